@@ -0,0 +1,189 @@
+//! Compression and decompression for programs using ouch as a library, instead of running it.
+//!
+//! Nothing is asked, the options answer the questions ouch would ask, and nothing is printed: the
+//! messages go to the `LogSink` of the options, and the progress to their `ProgressReporter`, when
+//! they are given. What is done with each entry, and the warnings, are sent to the `EventSink` of
+//! the options. Failures are returned as `Error`, ouch never exits the process from here.
+
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    archive::FileInArchive,
+    cli::{self, Command},
+    commands,
+    events::{Event, EventSink, NoEvents},
+    logger::{self, Level, LogSink, Message},
+    oof,
+    progress::{NoProgress, ProgressReporter},
+};
+
+/// What to do with the outputs that already exist
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Keep them, like `--no`, compression fails with `Error::OverwriteDeclined`
+    #[default]
+    Never,
+    /// Replace them, like `--yes`
+    Always,
+}
+
+#[derive(Default)]
+#[non_exhaustive]
+pub struct CompressOptions {
+    pub overwrite: Overwrite,
+    /// Same archive for the same files, like `--reproducible`
+    pub reproducible: bool,
+    /// Leave out what .gitignore files ignore, like `--gitignore`
+    pub gitignore: bool,
+    /// Add the files symbolic links point to, like `--follow-symlinks`
+    pub follow_symlinks: bool,
+    pub log: Option<Rc<dyn LogSink>>,
+    pub events: Option<Rc<dyn EventSink>>,
+}
+
+#[derive(Default)]
+#[non_exhaustive]
+pub struct DecompressOptions {
+    pub overwrite: Overwrite,
+    /// Only move the files into the output folder once all of them were extracted, like
+    /// `--atomic`
+    pub atomic: bool,
+    /// Skip the entries that fail to be extracted, like `--continue-on-error`
+    pub continue_on_error: bool,
+    pub progress: Option<Rc<dyn ProgressReporter>>,
+    pub log: Option<Rc<dyn LogSink>>,
+    pub events: Option<Rc<dyn EventSink>>,
+}
+
+#[derive(Default)]
+#[non_exhaustive]
+pub struct ListOptions {
+    pub log: Option<Rc<dyn LogSink>>,
+}
+
+/// What was done
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Report {
+    /// The files that were written: the archive, or the files that were extracted
+    pub files: Vec<PathBuf>,
+}
+
+/// Compress `inputs` into `output`, whose extensions give the formats, like "backup.tar.gz"
+pub fn compress(
+    inputs: &[PathBuf],
+    output: &Path,
+    options: CompressOptions,
+) -> crate::Result<Report> {
+    let mut flags = overwrite_flags(options.overwrite);
+    for (name, enabled) in [
+        ("reproducible", options.reproducible),
+        ("gitignore", options.gitignore),
+        ("follow-symlinks", options.follow_symlinks),
+    ] {
+        if enabled {
+            flags.boolean_flags.insert(name);
+        }
+    }
+
+    let events = options.events.unwrap_or_else(|| Rc::new(NoEvents));
+
+    with_log(options.log, events.clone(), || {
+        let files = cli::canonicalize_files(inputs)?;
+        let command = Command::Compress { files, output_path: output.to_path_buf() };
+        commands::run_with_events(command, &flags, events.as_ref())?;
+        Ok(Report { files: vec![output.to_path_buf()] })
+    })
+}
+
+/// Decompress `inputs` into `output_folder`, or into the current folder for archives and next to
+/// them for single files, like "notes.txt.gz"
+///
+/// Archives without a single folder at their root are extracted into a folder named after them,
+/// like `ouch decompress` does.
+pub fn decompress(
+    inputs: &[PathBuf],
+    output_folder: Option<&Path>,
+    options: DecompressOptions,
+) -> crate::Result<Report> {
+    let mut flags = overwrite_flags(options.overwrite);
+    for (name, enabled) in
+        [("atomic", options.atomic), ("continue-on-error", options.continue_on_error)]
+    {
+        if enabled {
+            flags.boolean_flags.insert(name);
+        }
+    }
+    let progress = options.progress.unwrap_or_else(|| Rc::new(NoProgress));
+    let events = options.events.unwrap_or_else(|| Rc::new(NoEvents));
+
+    with_log(options.log, events.clone(), || {
+        let files = cli::canonicalize_files(inputs)?;
+        let files =
+            commands::decompress_files(&files, output_folder, progress, events.as_ref(), &flags)?;
+        Ok(Report { files })
+    })
+}
+
+/// The entries of the archive at `archive`, an error ends them when it can't be read
+pub fn list(
+    archive: &Path,
+    options: ListOptions,
+) -> impl Iterator<Item = crate::Result<FileInArchive>> {
+    let flags = oof::Flags::default();
+    let entries = with_log(options.log, Rc::new(NoEvents), || {
+        let archive = cli::canonicalize_files(&[archive])?.remove(0);
+        crate::list::archive_entries(&archive, &flags)
+    });
+
+    let (entries, error) = match entries {
+        Ok(entries) => (entries, None),
+        Err(err) => (vec![], Some(err)),
+    };
+    entries.into_iter().map(Ok).chain(error.map(Err))
+}
+
+/// The flags that answer the questions about overwriting
+fn overwrite_flags(overwrite: Overwrite) -> oof::Flags {
+    let mut flags = oof::Flags::default();
+    match overwrite {
+        Overwrite::Never => flags.boolean_flags.insert("no"),
+        Overwrite::Always => flags.boolean_flags.insert("yes"),
+    };
+    flags
+}
+
+/// Run `f` with the messages going to `log`, or nowhere, and the warnings to `events` too
+fn with_log<T>(
+    log: Option<Rc<dyn LogSink>>,
+    events: Rc<dyn EventSink>,
+    f: impl FnOnce() -> T,
+) -> T {
+    let log = log.unwrap_or_else(|| Rc::new(Silent));
+    logger::with_sink(Rc::new(Warnings { log, events }), f)
+}
+
+/// Drops the messages when no `LogSink` was given
+struct Silent;
+
+impl LogSink for Silent {
+    fn log(&self, _message: &Message) {}
+}
+
+/// Sends the warnings to `events` as well as to `log`
+struct Warnings {
+    log: Rc<dyn LogSink>,
+    events: Rc<dyn EventSink>,
+}
+
+impl LogSink for Warnings {
+    fn log(&self, message: &Message) {
+        if message.level() == Level::Warning {
+            self.events.event(Event::Warning { message: message.message().to_string() });
+        }
+        self.log.log(message);
+    }
+}
@@ -0,0 +1,211 @@
+//! POSIX ACLs kept with `--acl`, stored in .tar as the `SCHILY.acl.access` and
+//! `SCHILY.acl.default` PAX records that GNU tar and star use.
+//!
+//! The records hold the short text form, like "user::rw-,user:1000:r--,group::r--,mask::r--,
+//! other::r--", with numeric IDs. On Linux, ACLs are read from and written to the
+//! `system.posix_acl_access` and `system.posix_acl_default` extended attributes.
+
+use std::{cell::Cell, io, path::Path};
+
+use crate::{
+    archive::xattrs::{get_xattr, is_unsupported, set_xattr},
+    utils::{logger, to_utf},
+};
+
+pub const ACCESS_KEY: &str = "SCHILY.acl.access";
+pub const DEFAULT_KEY: &str = "SCHILY.acl.default";
+
+const ACCESS_XATTR: &str = "system.posix_acl_access";
+const DEFAULT_XATTR: &str = "system.posix_acl_default";
+
+/// The ACLs of a file, in the text form of the PAX records. `None` when the file has none beyond
+/// its mode.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Acls {
+    pub access: Option<String>,
+    /// Inherited by what is created inside of a directory
+    pub default: Option<String>,
+}
+
+impl Acls {
+    /// The PAX records to store before the entry of the file
+    pub fn pax_records(&self) -> Vec<(&'static str, &[u8])> {
+        let access = self.access.as_ref().map(|acl| (ACCESS_KEY, acl.as_bytes()));
+        let default = self.default.as_ref().map(|acl| (DEFAULT_KEY, acl.as_bytes()));
+        access.into_iter().chain(default).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.access.is_none() && self.default.is_none()
+    }
+}
+
+/// Reads and writes ACLs, warning once when the system or the filesystem can't
+#[derive(Debug, Default)]
+pub struct AclHandler {
+    warned: Cell<bool>,
+}
+
+impl AclHandler {
+    /// The ACLs of the file at `path`, empty when they can't be read
+    pub fn read(&self, path: &Path) -> crate::Result<Acls> {
+        let read = |name| match get_xattr(path, name) {
+            Ok(Some(bytes)) => Ok(Some(to_text(&bytes)?)),
+            Ok(None) => Ok(None),
+            Err(err) => Err(err),
+        };
+        match read(ACCESS_XATTR)
+            .and_then(|access| Ok(Acls { access, default: read(DEFAULT_XATTR)? }))
+        {
+            Ok(acls) => Ok(acls),
+            Err(err) if is_unsupported(&err) => {
+                self.warn_unsupported(path, &err, "ACLs not stored")?;
+                Ok(Acls::default())
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Give the file at `path` the ACLs that were archived with it
+    pub fn apply(&self, path: &Path, acls: &Acls) -> crate::Result<()> {
+        let write = |name, acl: &Option<String>| match acl {
+            Some(acl) => set_xattr(path, name, &from_text(acl)?),
+            None => Ok(()),
+        };
+        match write(ACCESS_XATTR, &acls.access).and_then(|()| write(DEFAULT_XATTR, &acls.default)) {
+            Ok(()) => Ok(()),
+            Err(err) if is_unsupported(&err) => {
+                self.warn_unsupported(path, &err, "ACLs not restored")
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn warn_unsupported(
+        &self,
+        path: &Path,
+        err: &io::Error,
+        category: &'static str,
+    ) -> crate::Result<()> {
+        if self.warned.replace(true) {
+            return Ok(());
+        }
+        logger::warning(format!(
+            "Could not keep the ACLs of '{}' ({}), they are left out.",
+            to_utf(path),
+            err
+        ))
+        .path(path)
+        .category(category)
+        .warn()
+    }
+}
+
+// Tags of the entries, as in <linux/posix_acl.h>
+const USER_OBJ: u16 = 0x01;
+const USER: u16 = 0x02;
+const GROUP_OBJ: u16 = 0x04;
+const GROUP: u16 = 0x08;
+const MASK: u16 = 0x10;
+const OTHER: u16 = 0x20;
+
+/// Version of the format of the extended attribute, followed by entries of 8 bytes
+const XATTR_VERSION: u32 = 2;
+/// ID of the entries that are not about a named user or group
+const UNDEFINED_ID: u32 = u32::MAX;
+
+fn invalid_acl(reason: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid ACL: {}", reason.to_string()))
+}
+
+/// The text form of the extended attribute `bytes`
+fn to_text(bytes: &[u8]) -> io::Result<String> {
+    let (version, entries) = bytes.split_at(4.min(bytes.len()));
+    if version != XATTR_VERSION.to_le_bytes() || entries.len() % 8 != 0 {
+        return Err(invalid_acl("unknown extended attribute format"));
+    }
+
+    let entries: Vec<String> = entries
+        .chunks(8)
+        .map(|entry| {
+            let tag = u16::from_le_bytes([entry[0], entry[1]]);
+            let perm = u16::from_le_bytes([entry[2], entry[3]]);
+            let id = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            let (tag, qualifier) = match tag {
+                USER_OBJ => ("user", String::new()),
+                USER => ("user", id.to_string()),
+                GROUP_OBJ => ("group", String::new()),
+                GROUP => ("group", id.to_string()),
+                MASK => ("mask", String::new()),
+                OTHER => ("other", String::new()),
+                _ => return Err(invalid_acl(format!("unknown tag {}", tag))),
+            };
+            let perms: String = [(4, 'r'), (2, 'w'), (1, 'x')]
+                .iter()
+                .map(|&(bit, letter)| if perm & bit != 0 { letter } else { '-' })
+                .collect();
+            Ok(format!("{}:{}:{}", tag, qualifier, perms))
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(entries.join(","))
+}
+
+/// The extended attribute of the text form `text`, as written by ouch, GNU tar or star
+fn from_text(text: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = XATTR_VERSION.to_le_bytes().to_vec();
+    for entry in text.split([',', '\n']).map(str::trim).filter(|entry| !entry.is_empty()) {
+        // star appends the numeric ID to named entries, as in "user:bob:r--:1000"
+        let fields: Vec<&str> = entry.split(':').collect();
+        let (tag, qualifier, perms) = match fields.as_slice() {
+            [tag, qualifier, perms] => (*tag, *qualifier, *perms),
+            [tag, _name, perms, id] => (*tag, *id, *perms),
+            // "other::r--" can also be written "other:r--"
+            [tag, perms] => (*tag, "", *perms),
+            _ => return Err(invalid_acl(entry)),
+        };
+
+        let (tag, id) = match (tag, qualifier) {
+            ("user" | "u", "") => (USER_OBJ, UNDEFINED_ID),
+            ("user" | "u", id) => (USER, id.parse().map_err(|_| invalid_acl(entry))?),
+            ("group" | "g", "") => (GROUP_OBJ, UNDEFINED_ID),
+            ("group" | "g", id) => (GROUP, id.parse().map_err(|_| invalid_acl(entry))?),
+            ("mask" | "m", "") => (MASK, UNDEFINED_ID),
+            ("other" | "o", "") => (OTHER, UNDEFINED_ID),
+            _ => return Err(invalid_acl(entry)),
+        };
+        let mut perm = 0;
+        for letter in perms.chars() {
+            perm |= match letter {
+                'r' => 4,
+                'w' => 2,
+                'x' => 1,
+                '-' => 0,
+                _ => return Err(invalid_acl(entry)),
+            };
+        }
+
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&(perm as u16).to_le_bytes());
+        bytes.extend_from_slice(&id.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acl_text_round_trip() {
+        let text = "user::rw-,user:1000:r--,group::r-x,group:27:rwx,mask::rwx,other::---";
+        assert_eq!(to_text(&from_text(text).unwrap()).unwrap(), text);
+
+        // As written by star, with names and short tags
+        let star = "u::rw-,user:bob:r--:1000,g::r-x,group:sudo:rwx:27,m::rwx,o::---";
+        assert_eq!(from_text(star).unwrap(), from_text(text).unwrap());
+
+        assert!(from_text("user:bob:r--").is_err());
+        assert!(from_text("user::rwz").is_err());
+        assert!(to_text(&[1, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Entries whose paths only differ in case, like "README" and "readme", are the same file on
+//! case-insensitive filesystems, like the default ones of macOS and Windows, where the later one
+//! would overwrite the earlier one. They are asked about, renamed or skipped instead, as chosen
+//! with `--conflict`.
+//!
+//! With `--flat`, entries in different folders collide too when they have the same name, like
+//! "a/README" and "b/README", on any filesystem. The same policy applies to them.
+//!
+//! Whether the filesystem is case-insensitive is probed once per output folder, by creating a file
+//! in it and looking for it with its name upper-cased.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    merge::numbered_path,
+    oof,
+    utils::{self, logger, to_utf},
+};
+
+/// What to do with a file whose path only differs in case from one that was extracted before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CasePolicy {
+    /// Ask whether to overwrite it, the default, answered by `--yes` and `--no`
+    Ask,
+    /// Extract it under a free name, like "readme (1)"
+    Rename,
+    /// Keep the one extracted before, with a warning
+    Skip,
+}
+
+impl CasePolicy {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        match flags.arg("conflict").map(|policy| policy.to_str()) {
+            None | Some(Some("ask")) => Ok(Self::Ask),
+            Some(Some("rename")) => Ok(Self::Rename),
+            Some(Some("skip")) => Ok(Self::Skip),
+            Some(_) => Err(crate::Error::InvalidInput),
+        }
+    }
+}
+
+/// The files extracted into an output folder so far, by their case-folded path
+pub struct CaseCollisions {
+    policy: CasePolicy,
+    output_folder: PathBuf,
+    /// Probed when the first file is claimed
+    is_insensitive: Option<bool>,
+    /// Whether entries are extracted without their folders, with `--flat`
+    flat: bool,
+    extracted: HashMap<String, PathBuf>,
+}
+
+impl CaseCollisions {
+    pub fn new(output_folder: &Path, flags: &oof::Flags) -> crate::Result<Self> {
+        Ok(Self {
+            policy: CasePolicy::from_flags(flags)?,
+            output_folder: output_folder.to_path_buf(),
+            is_insensitive: None,
+            flat: flags.is_present("flat"),
+            extracted: HashMap::new(),
+        })
+    }
+
+    /// The path the file at `file_path` is extracted at, `None` when it is skipped.
+    ///
+    /// Folders are never claimed, the entries of folders that only differ in case are merged.
+    pub fn claim(
+        &mut self,
+        file_path: &Path,
+        flags: &oof::Flags,
+    ) -> crate::Result<Option<PathBuf>> {
+        if !self.is_insensitive()? && !self.flat {
+            return Ok(Some(file_path.to_path_buf()));
+        }
+
+        let key = self.key(file_path);
+        let previous = match self.extracted.get(&key) {
+            Some(previous) if self.flat || previous != file_path => previous.clone(),
+            _ => {
+                self.extracted.insert(key, file_path.to_path_buf());
+                return Ok(Some(file_path.to_path_buf()));
+            },
+        };
+
+        let is_same_name = previous == file_path;
+        let message = |outcome: &str| match is_same_name {
+            true => format!("'{}' was extracted before, {}.", to_utf(file_path), outcome),
+            false => format!(
+                "'{}' only differs in case from '{}', {}.",
+                to_utf(file_path),
+                to_utf(&previous),
+                outcome
+            ),
+        };
+        let category = |same_name: &'static str, differing_case: &'static str| match is_same_name {
+            true => same_name,
+            false => differing_case,
+        };
+        match self.policy {
+            CasePolicy::Ask if utils::user_wants_to_overwrite(&previous, flags)? => {
+                // Removed first, so that the file takes the case of the later entry
+                if previous.symlink_metadata().is_ok() {
+                    fs::remove_file(&previous)?;
+                }
+                logger::warning(message("it was overwritten"))
+                    .path(file_path)
+                    .category(category(
+                        "entries with the same name overwritten",
+                        "entries differing only in case overwritten",
+                    ))
+                    .log();
+                self.extracted.insert(key, file_path.to_path_buf());
+                Ok(Some(file_path.to_path_buf()))
+            },
+            CasePolicy::Ask => {
+                logger::warning(message("it was skipped"))
+                    .path(file_path)
+                    .category(category(
+                        "entries with the same name skipped",
+                        "entries differing only in case skipped",
+                    ))
+                    .log();
+                Ok(None)
+            },
+            CasePolicy::Skip => {
+                logger::warning(message("it was skipped"))
+                    .path(file_path)
+                    .category(category(
+                        "entries with the same name skipped",
+                        "entries differing only in case skipped",
+                    ))
+                    .warn()?;
+                Ok(None)
+            },
+            CasePolicy::Rename => {
+                let renamed = (1..)
+                    .map(|number| numbered_path(file_path, number))
+                    .find(|candidate| {
+                        !self.extracted.contains_key(&self.key(candidate))
+                            && candidate.symlink_metadata().is_err()
+                    })
+                    .unwrap();
+                logger::warning(message(&format!("it was extracted as '{}'", to_utf(&renamed))))
+                    .path(&renamed)
+                    .category(category(
+                        "entries with the same name renamed",
+                        "entries differing only in case renamed",
+                    ))
+                    .warn()?;
+                self.extracted.insert(self.key(&renamed), renamed.clone());
+                Ok(Some(renamed))
+            },
+        }
+    }
+
+    fn is_insensitive(&mut self) -> crate::Result<bool> {
+        if let Some(is_insensitive) = self.is_insensitive {
+            return Ok(is_insensitive);
+        }
+
+        fs::create_dir_all(&self.output_folder)?;
+        // Removed when dropped
+        let probe =
+            tempfile::Builder::new().prefix(".ouch-case-probe").tempfile_in(&self.output_folder)?;
+        // Safe unwrap: temporary files always have a file name
+        let upper_name = probe.path().file_name().unwrap().to_string_lossy().to_uppercase();
+        let is_insensitive = probe.path().with_file_name(upper_name).exists();
+
+        logger::debug(format!(
+            "'{}' is on a case-{} filesystem.",
+            to_utf(&self.output_folder),
+            if is_insensitive { "insensitive" } else { "sensitive" }
+        ))
+        .path(&self.output_folder)
+        .log();
+        self.is_insensitive = Some(is_insensitive);
+        Ok(is_insensitive)
+    }
+
+    /// Case-folded on case-insensitive filesystems
+    fn key(&self, path: &Path) -> String {
+        match self.is_insensitive {
+            Some(true) => path.to_string_lossy().to_lowercase(),
+            _ => path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collisions(policy: CasePolicy) -> CaseCollisions {
+        CaseCollisions {
+            policy,
+            output_folder: PathBuf::from("out"),
+            is_insensitive: Some(true),
+            flat: false,
+            extracted: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_claim() {
+        let flags = oof::Flags::default();
+
+        let mut renaming = collisions(CasePolicy::Rename);
+        let claim = |collisions: &mut CaseCollisions, path: &str| {
+            collisions.claim(Path::new(path), &flags).unwrap()
+        };
+        assert_eq!(claim(&mut renaming, "out/README").unwrap(), Path::new("out/README"));
+        assert_eq!(claim(&mut renaming, "out/README").unwrap(), Path::new("out/README"));
+        assert_eq!(claim(&mut renaming, "out/readme").unwrap(), Path::new("out/readme (1)"));
+        assert_eq!(claim(&mut renaming, "out/Readme").unwrap(), Path::new("out/Readme (2)"));
+        assert_eq!(claim(&mut renaming, "out/DOCS/a.txt").unwrap(), Path::new("out/DOCS/a.txt"));
+        assert_eq!(
+            claim(&mut renaming, "out/docs/A.txt").unwrap(),
+            Path::new("out/docs/A (1).txt")
+        );
+
+        let mut skipping = collisions(CasePolicy::Skip);
+        assert_eq!(claim(&mut skipping, "out/README").unwrap(), Path::new("out/README"));
+        assert!(claim(&mut skipping, "out/readme").is_none());
+        assert_eq!(claim(&mut skipping, "out/README.md").unwrap(), Path::new("out/README.md"));
+
+        // With --flat, the same name is a collision too, on any filesystem
+        let mut flat = CaseCollisions {
+            flat: true,
+            is_insensitive: Some(false),
+            ..collisions(CasePolicy::Rename)
+        };
+        assert_eq!(claim(&mut flat, "out/kick.wav").unwrap(), Path::new("out/kick.wav"));
+        assert_eq!(claim(&mut flat, "out/kick.wav").unwrap(), Path::new("out/kick (1).wav"));
+        assert_eq!(claim(&mut flat, "out/Kick.wav").unwrap(), Path::new("out/Kick.wav"));
+    }
+}
@@ -0,0 +1,562 @@
+//! Archives in the "newc" format of cpio, the one used by Linux initramfs images.
+//!
+//! Each entry is a 110 bytes header of ASCII hexadecimal fields, followed by the NUL terminated
+//! name and the contents, both padded to 4 bytes. The archive ends with an entry named
+//! "TRAILER!!!".
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    env, fs,
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    archive::{
+        self,
+        collisions::CaseCollisions,
+        directories::DeferredDirectories,
+        permissions::Permissions,
+        special::{self, SpecialFileKind, SpecialFilePolicy},
+        transform::Transforms,
+        windows::{self, SkippedSymlinks},
+        ArchiveInfo, FileInArchive,
+    },
+    events::{Event, EventSink},
+    extension::CompressionFormat,
+    oof,
+    stats::CompressionStats,
+    utils,
+    utils::{
+        io::{EntryLimit, OutputLimit},
+        ArchiveMtime,
+    },
+};
+
+const MAGIC: &[u8] = b"070701";
+/// Same layout, with a checksum of the contents in the last field
+const MAGIC_WITH_CRC: &[u8] = b"070702";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// The file type bits of `mode`, as in `stat`
+const FILE_TYPE_MASK: u32 = 0o170000;
+const SOCKET: u32 = 0o140000;
+const SYMLINK: u32 = 0o120000;
+const REGULAR_FILE: u32 = 0o100000;
+const BLOCK_DEVICE: u32 = 0o060000;
+const DIRECTORY: u32 = 0o040000;
+const CHAR_DEVICE: u32 = 0o020000;
+const FIFO: u32 = 0o010000;
+
+#[derive(Debug, Default)]
+struct Header {
+    /// Whether the last field holds a checksum of the contents
+    has_checksum: bool,
+    ino: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    mtime: u32,
+    file_size: u32,
+    dev_major: u32,
+    dev_minor: u32,
+    rdev_major: u32,
+    rdev_minor: u32,
+}
+
+impl Header {
+    fn read(reader: &mut impl Read) -> crate::Result<Option<(Self, String)>> {
+        let mut raw = [0; HEADER_SIZE];
+        reader.read_exact(&mut raw).map_err(|_| corrupt("truncated header"))?;
+        if &raw[..6] != MAGIC && &raw[..6] != MAGIC_WITH_CRC {
+            return Err(corrupt("invalid header, only the newc format is supported"));
+        }
+
+        let mut fields = [0; 13];
+        for (index, field) in fields.iter_mut().enumerate() {
+            let start = 6 + index * 8;
+            let text = std::str::from_utf8(&raw[start..start + 8]).ok();
+            *field = text
+                .and_then(|text| u32::from_str_radix(text, 16).ok())
+                .ok_or_else(|| corrupt("invalid header field"))?;
+        }
+        // The last field is the checksum, left unchecked
+        let name_size = fields[11];
+
+        let mut name = vec![0; name_size as usize];
+        reader.read_exact(&mut name).map_err(|_| corrupt("truncated entry name"))?;
+        skip(reader, padding(HEADER_SIZE as u64 + name_size as u64))?;
+        // The size counts the terminating NUL
+        if name.pop() != Some(0) {
+            return Err(corrupt("entry name isn't NUL terminated"));
+        }
+
+        let name = String::from_utf8_lossy(&name).into_owned();
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        let header = Self {
+            has_checksum: &raw[..6] == MAGIC_WITH_CRC,
+            ino: fields[0],
+            mode: fields[1],
+            uid: fields[2],
+            gid: fields[3],
+            nlink: fields[4],
+            mtime: fields[5],
+            file_size: fields[6],
+            dev_major: fields[7],
+            dev_minor: fields[8],
+            rdev_major: fields[9],
+            rdev_minor: fields[10],
+        };
+        Ok(Some((header, name)))
+    }
+
+    fn write(&self, writer: &mut impl Write, name: &[u8]) -> io::Result<()> {
+        let name_size = name.len() as u32 + 1;
+        let fields = [
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.nlink,
+            self.mtime,
+            self.file_size,
+            self.dev_major,
+            self.dev_minor,
+            self.rdev_major,
+            self.rdev_minor,
+            name_size,
+            0,
+        ];
+
+        writer.write_all(MAGIC)?;
+        for field in &fields {
+            write!(writer, "{:08X}", field)?;
+        }
+        writer.write_all(name)?;
+        writer.write_all(&[0])?;
+        write_padding(writer, HEADER_SIZE as u64 + name_size as u64)
+    }
+
+    fn file_type(&self) -> u32 {
+        self.mode & FILE_TYPE_MASK
+    }
+}
+
+fn corrupt(reason: &str) -> crate::Error {
+    crate::Error::CorruptArchive {
+        format: CompressionFormat::Cpio,
+        reason: reason.to_string(),
+        offset: None,
+        compressed_offset: None,
+        entry: None,
+    }
+}
+
+/// Bytes needed after `size` bytes to reach a multiple of 4
+fn padding(size: u64) -> u64 {
+    (4 - size % 4) % 4
+}
+
+fn skip(reader: &mut impl Read, bytes: u64) -> crate::Result<()> {
+    let skipped = io::copy(&mut reader.take(bytes), &mut io::sink())?;
+    if skipped < bytes {
+        return Err(corrupt("unexpected end of archive"));
+    }
+    Ok(())
+}
+
+fn write_padding(writer: &mut impl Write, size: u64) -> io::Result<()> {
+    writer.write_all(&[0; 3][..padding(size) as usize])
+}
+
+fn special_kind(file_type: u32) -> Option<SpecialFileKind> {
+    match file_type {
+        FIFO => Some(SpecialFileKind::Fifo),
+        SOCKET => Some(SpecialFileKind::Socket),
+        CHAR_DEVICE => Some(SpecialFileKind::CharDevice),
+        BLOCK_DEVICE => Some(SpecialFileKind::BlockDevice),
+        _ => None,
+    }
+}
+
+/// Names like "./init" are relative to the output folder, `None` for names that could escape it
+fn relative_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {},
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+pub fn unpack_archive(
+    mut reader: Box<dyn Read>,
+    output_folder: &Path,
+    limit: &mut OutputLimit,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<PathBuf>> {
+    let special_files = SpecialFilePolicy::from_flags(flags)?;
+    let permissions = Permissions::from_flags(flags)?;
+    let transforms = Transforms::from_flags(flags)?;
+    // Hard links share an inode number, only the last one of them holds the contents
+    let mut pending_links: HashMap<(u32, u32, u32), Vec<PathBuf>> = HashMap::new();
+    let mut symlinks = SkippedSymlinks::default();
+    let mut collisions = CaseCollisions::new(output_folder, flags)?;
+    let mut directories = DeferredDirectories::default();
+    let mut files_unpacked = vec![];
+
+    while let Some((header, name)) = Header::read(&mut reader)? {
+        limit.add_entry()?;
+        let entry = PathBuf::from(&name);
+        let size = (header.file_type() == REGULAR_FILE).then_some(header.file_size as u64);
+        events.event(Event::EntryStarted { entry: entry.clone(), size });
+        let data_padding = padding(header.file_size as u64);
+        let renamed = if header.file_type() == SYMLINK && transforms.skips_symlink(&entry)? {
+            None
+        } else {
+            transforms.apply(&entry, header.file_type() == DIRECTORY)?
+        };
+        let relative = match renamed.and_then(|name| relative_path(&name.to_string_lossy())) {
+            Some(relative) if relative != Path::new("") => relative,
+            _ => {
+                skip(&mut reader, header.file_size as u64 + data_padding)?;
+                continue;
+            },
+        };
+        let file_path = output_folder.join(windows::adapt_path(&relative)?);
+        utils::validate_entry_path(&file_path, output_folder)?;
+
+        if let Some(kind) = special_kind(header.file_type()) {
+            if special_files.keep(&file_path, kind, kind.extraction_limitation())? {
+                let device = (header.rdev_major, header.rdev_minor);
+                special::create(&file_path, kind, permissions.mode(header.mode), device)?;
+                events.event(Event::EntryFinished { entry, path: file_path.clone(), bytes: 0 });
+                files_unpacked.push(file_path);
+            }
+            skip(&mut reader, header.file_size as u64 + data_padding)?;
+            continue;
+        }
+
+        if header.file_type() == DIRECTORY {
+            fs::create_dir_all(&file_path)?;
+            // Like those of files, modification times aren't restored
+            directories.defer(&file_path, permissions.mode(header.mode), None)?;
+            skip(&mut reader, header.file_size as u64 + data_padding)?;
+            events.event(Event::EntryFinished { entry, path: file_path, bytes: 0 });
+            continue;
+        }
+
+        let file_path = match collisions.claim(&file_path, flags)? {
+            Some(file_path) => file_path,
+            None => {
+                skip(&mut reader, header.file_size as u64 + data_padding)?;
+                continue;
+            },
+        };
+
+        if file_path.symlink_metadata().is_ok() {
+            if !utils::user_wants_to_overwrite(&file_path, flags)? {
+                skip(&mut reader, header.file_size as u64 + data_padding)?;
+                continue;
+            }
+            fs::remove_file(&file_path)?;
+        }
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        limit.add(header.file_size as u64, &file_path)?;
+        let mut contents = (&mut reader).take(header.file_size as u64);
+        match header.file_type() {
+            SYMLINK => {
+                let mut target = String::new();
+                contents.read_to_string(&mut target)?;
+                symlinks.create(Path::new(&target), &file_path)?;
+            },
+            REGULAR_FILE if header.nlink > 1 && header.file_size == 0 => {
+                let key = (header.dev_major, header.dev_minor, header.ino);
+                pending_links.entry(key).or_default().push(file_path);
+                continue;
+            },
+            _ => {
+                io::copy(&mut contents, &mut fs::File::create(&file_path)?)?;
+                set_permissions(&file_path, permissions.mode(header.mode))?;
+                let key = (header.dev_major, header.dev_minor, header.ino);
+                for link_path in pending_links.remove(&key).unwrap_or_default() {
+                    fs::hard_link(&file_path, &link_path)
+                        .or_else(|_| fs::copy(&file_path, &link_path).map(|_| ()))?;
+                    files_unpacked.push(link_path);
+                }
+            },
+        }
+        if contents.limit() > 0 {
+            return Err(corrupt("unexpected end of archive"));
+        }
+        skip(&mut reader, data_padding)?;
+
+        events.event(Event::EntryFinished {
+            entry,
+            path: file_path.clone(),
+            bytes: header.file_size as u64,
+        });
+        files_unpacked.push(file_path);
+    }
+
+    // Links to a file that turned out to be empty
+    for link_path in pending_links.into_values().flatten() {
+        fs::File::create(&link_path)?;
+        files_unpacked.push(link_path);
+    }
+
+    symlinks.warn()?;
+    directories.restore()?;
+    Ok(files_unpacked)
+}
+
+pub fn list_archive(
+    reader: Box<dyn Read>,
+    entries: &mut EntryLimit,
+) -> crate::Result<Vec<FileInArchive>> {
+    let mut files = vec![];
+    visit_archive(reader, entries, |file| {
+        files.push(file);
+        Ok(true)
+    })?;
+    Ok(files)
+}
+
+/// Like `list_archive`, calling `visit` with the entries instead, and only reading the archive
+/// until it returns `false`
+pub fn visit_archive(
+    mut reader: Box<dyn Read>,
+    entries: &mut EntryLimit,
+    mut visit: impl FnMut(FileInArchive) -> crate::Result<bool>,
+) -> crate::Result<()> {
+    while let Some((header, name)) = Header::read(&mut reader)? {
+        entries.add()?;
+        skip(&mut reader, header.file_size as u64 + padding(header.file_size as u64))?;
+        let is_dir = header.file_type() == DIRECTORY;
+        let file = FileInArchive {
+            path: name.into(),
+            is_dir,
+            size: header.file_size as u64,
+            modified: Some(header.mtime as i64),
+        };
+        if !visit(file)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Call `visit` with the path and the contents of every regular file, for `ouch contents-hash`
+pub fn for_each_file(
+    mut reader: Box<dyn Read>,
+    mut visit: impl FnMut(PathBuf, &mut dyn Read) -> crate::Result<()>,
+) -> crate::Result<()> {
+    while let Some((header, name)) = Header::read(&mut reader)? {
+        let size = header.file_size as u64;
+        if header.file_type() == REGULAR_FILE {
+            let mut contents = (&mut reader).take(size);
+            visit(name.into(), &mut contents)?;
+            // Whatever `visit` didn't read, failing if the archive ends before it
+            let rest = contents.limit();
+            skip(&mut reader, rest)?;
+        } else {
+            skip(&mut reader, size)?;
+        }
+        skip(&mut reader, padding(size))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_permissions(file_path: &Path, mode: u32) -> crate::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(file_path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_permissions(file_path: &Path, mode: u32) -> crate::Result<()> {
+    windows::set_readonly(file_path, mode)
+}
+
+/// What `ouch info` shows about the archive
+pub fn archive_info(
+    mut reader: Box<dyn Read>,
+    entries: &mut EntryLimit,
+) -> crate::Result<ArchiveInfo> {
+    let mut info = ArchiveInfo::default();
+    let mut has_checksums = false;
+    while let Some((header, _)) = Header::read(&mut reader)? {
+        entries.add()?;
+        skip(&mut reader, header.file_size as u64 + padding(header.file_size as u64))?;
+        info.entries += 1;
+        has_checksums |= header.has_checksum;
+        if header.file_type() != DIRECTORY {
+            info.uncompressed_size += header.file_size as u64;
+        }
+    }
+
+    let version = if has_checksums { "newc, with checksums" } else { "newc" };
+    info.version = Some(version.to_string());
+    Ok(info)
+}
+
+/// Directories are walked up to `max_depth` levels deep, 1 only includes their direct children.
+///
+/// Symbolic links are stored as links, and special files as entries of their type when
+/// `--special-files store` is given, so the archive can be used as an initramfs.
+pub fn build_archive_from_paths<W>(
+    input_filenames: &[PathBuf],
+    max_depth: usize,
+    mut writer: W,
+    stats: &mut CompressionStats,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<W>
+where
+    W: Write,
+{
+    let reproducible = flags.is_present("reproducible");
+    let mtime = utils::archive_mtime(flags)?;
+    let special_files = SpecialFilePolicy::from_flags(flags)?;
+    let mut next_ino = 1;
+
+    for filename in utils::archive_order(input_filenames, flags) {
+        let previous_location = utils::cd_into_same_dir_as(&filename)?;
+
+        // Safe unwrap, input shall be treated before
+        let filename = filename.file_name().unwrap();
+
+        for entry in utils::walk_dir(filename.as_ref(), max_depth, flags) {
+            let entry = entry?;
+            let path = entry.path();
+            archive::start_adding(&entry, events);
+
+            // Symlinks are stored as links, unless they are followed with --follow-symlinks
+            let metadata = entry.metadata()?;
+            if let Some(kind) = SpecialFileKind::of(&metadata) {
+                if !special_files.keep(path, kind, None)? {
+                    continue;
+                }
+            }
+
+            let mut header = entry_header(&metadata, reproducible, mtime);
+            header.ino = next_ino;
+            next_ino += 1;
+
+            let (mut contents, size): (Box<dyn Read>, u64) = match header.file_type() {
+                REGULAR_FILE => (Box::new(fs::File::open(path)?), metadata.len()),
+                SYMLINK => {
+                    // The contents of a link entry are its target
+                    let target = name_bytes(&fs::read_link(path)?);
+                    let size = target.len() as u64;
+                    (Box::new(io::Cursor::new(target)), size)
+                },
+                _ => (Box::new(io::empty()), 0),
+            };
+            header.file_size = u32::try_from(size).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("'{}' is too big for .cpio, 4GiB at most", utils::to_utf(path)),
+                )
+            })?;
+
+            if header.file_type() == REGULAR_FILE {
+                stats.start(path, size);
+            }
+            header.write(&mut writer, &name_bytes(path))?;
+            let copied = io::copy(&mut (&mut contents).take(size), &mut writer)?;
+            if copied < size {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("'{}' shrank while being compressed", utils::to_utf(path)),
+                )
+                .into());
+            }
+            write_padding(&mut writer, size)?;
+            stats.finish(&mut writer)?;
+            archive::finish_adding(path, size, events);
+        }
+
+        env::set_current_dir(previous_location)?;
+    }
+
+    let trailer = Header { nlink: 1, ..Header::default() };
+    trailer.write(&mut writer, TRAILER.as_bytes())?;
+    Ok(writer)
+}
+
+/// Header with the metadata of an entry, without its inode number and size
+#[cfg(unix)]
+fn entry_header(
+    metadata: &fs::Metadata,
+    reproducible: bool,
+    mtime: Option<ArchiveMtime>,
+) -> Header {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut header = Header {
+        mode: metadata.mode(),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        nlink: if metadata.is_dir() { 2 } else { 1 },
+        mtime: metadata.mtime().clamp(0, u32::MAX as i64) as u32,
+        rdev_major: libc::major(metadata.rdev()) as u32,
+        rdev_minor: libc::minor(metadata.rdev()) as u32,
+        ..Header::default()
+    };
+
+    if reproducible {
+        // Same normalization as .tar, see `tar::HeaderMode::Deterministic`
+        let permissions =
+            if metadata.is_dir() || metadata.mode() & 0o100 != 0 { 0o755 } else { 0o644 };
+        header.mode = header.file_type() | permissions;
+        header.uid = 0;
+        header.gid = 0;
+    }
+    if let Some(mtime) = mtime {
+        header.mtime = mtime.of(metadata).min(u32::MAX as u64) as u32;
+    }
+    header
+}
+
+#[cfg(not(unix))]
+fn entry_header(
+    metadata: &fs::Metadata,
+    _reproducible: bool,
+    _mtime: Option<ArchiveMtime>,
+) -> Header {
+    let mode = if metadata.is_dir() {
+        DIRECTORY | 0o755
+    } else if metadata.file_type().is_symlink() {
+        SYMLINK | 0o777
+    } else {
+        REGULAR_FILE | 0o644
+    };
+    Header { mode, nlink: if metadata.is_dir() { 2 } else { 1 }, ..Header::default() }
+}
+
+#[cfg(unix)]
+fn name_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn name_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().replace('\\', "/").into_bytes()
+}
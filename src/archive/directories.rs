@@ -0,0 +1,81 @@
+//! The permissions and modification times of extracted folders are restored once everything
+//! inside of them was written, deepest first, like GNU tar does. A read-only folder, like one with
+//! mode 0555, would otherwise keep its entries from being written into it, and writing them would
+//! change its modification time.
+
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::utils::logger;
+
+#[derive(Debug)]
+struct Directory {
+    path: PathBuf,
+    mode: u32,
+    mtime: Option<SystemTime>,
+}
+
+/// The folders extracted so far, with what they are given once the archive was extracted
+#[derive(Debug, Default)]
+pub struct DeferredDirectories(Vec<Directory>);
+
+impl DeferredDirectories {
+    /// Keep the folder at `path` writable until [`Self::restore`], which gives it `mode` and
+    /// `mtime`
+    pub fn defer(
+        &mut self,
+        path: &Path,
+        mode: u32,
+        mtime: Option<SystemTime>,
+    ) -> crate::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::{fs, os::unix::fs::PermissionsExt};
+
+            fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777 | 0o700))?;
+        }
+        self.0.push(Directory { path: path.to_path_buf(), mode, mtime });
+        Ok(())
+    }
+
+    /// Give the folders their permissions and modification times, those of subfolders first, so
+    /// that a parent that can't be searched doesn't keep them from being reached
+    pub fn restore(mut self) -> crate::Result<()> {
+        self.0.sort_by_key(|directory| Reverse(directory.path.components().count()));
+        for directory in self.0 {
+            if let Err(err) = restore(&directory) {
+                logger::warning(format!(
+                    "Could not restore the permissions of '{}' to {:o} ({}).",
+                    directory.path.display(),
+                    directory.mode,
+                    err
+                ))
+                .path(&directory.path)
+                .category("permissions not restored")
+                .warn()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The modification time comes first, opening the folder to set it takes reading it
+#[cfg(unix)]
+fn restore(directory: &Directory) -> std::io::Result<()> {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    if let Some(mtime) = directory.mtime {
+        fs::File::open(&directory.path)?.set_modified(mtime)?;
+    }
+    fs::set_permissions(&directory.path, fs::Permissions::from_mode(directory.mode & 0o7777))
+}
+
+/// Folders keep the permissions and times they were given
+#[cfg(not(unix))]
+fn restore(directory: &Directory) -> std::io::Result<()> {
+    let _ = directory.mtime;
+    Ok(())
+}
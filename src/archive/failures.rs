@@ -0,0 +1,78 @@
+//! `--continue-on-error`: an entry that can't be extracted, like one with a bad CRC or whose file
+//! can't be created, is reported and skipped instead of stopping the extraction, which fails once
+//! the other entries were extracted, with an exit code of its own.
+//!
+//! Errors after which nothing else can be extracted, like a full disk, a damaged .tar stream or
+//! going over `--max-output-size`, still stop it right away.
+
+use std::path::{Path, PathBuf};
+
+use crate::{oof, utils::logger, Error};
+
+/// The entries that failed to be extracted, only left aside with `--continue-on-error`
+#[derive(Debug, Default)]
+pub struct EntryFailures {
+    enabled: bool,
+    /// Entries read from the archive so far
+    entries: usize,
+    extracted: usize,
+    failed: Vec<PathBuf>,
+}
+
+impl EntryFailures {
+    pub fn from_flags(flags: &oof::Flags) -> Self {
+        Self { enabled: flags.is_present("continue-on-error"), ..Self::default() }
+    }
+
+    /// `result` of extracting the entry at `entry`, the path of the extracted file or `None` when
+    /// it was skipped. Its error is logged and left aside with `--continue-on-error`, as skipped.
+    pub fn check(
+        &mut self,
+        entry: &Path,
+        result: crate::Result<Option<PathBuf>>,
+    ) -> crate::Result<Option<PathBuf>> {
+        self.entries += 1;
+        match result {
+            Ok(file_path) => {
+                self.extracted += file_path.is_some() as usize;
+                Ok(file_path)
+            },
+            Err(err) if self.enabled && is_recoverable(&err) => {
+                logger::error(format!("Failed to extract {:?}, skipping it:", entry))
+                    .entry(entry)
+                    .log();
+                err.display();
+                self.failed.push(entry.to_path_buf());
+                Ok(None)
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Called once every entry was read, fails if any of them failed
+    pub fn finish(self) -> crate::Result<()> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        Err(Error::EntriesFailed {
+            extracted: self.extracted,
+            total: self.entries,
+            failed: self.failed,
+        })
+    }
+}
+
+/// Whether the entries after one that failed with `err` can still be extracted
+fn is_recoverable(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::IoError { .. }
+            | Error::PermissionDenied
+            | Error::AlreadyExists
+            | Error::FileNotFound(_)
+            | Error::CorruptArchive { .. }
+            | Error::UnsupportedZipArchive(_)
+            | Error::SpecialFile { .. }
+            | Error::PathTraversal { .. }
+    )
+}
@@ -0,0 +1,228 @@
+//! `--create-tar-index`: OUTPUT.idx, next to a .tar or .tar.gz, maps the path of every entry to
+//! the byte where its headers start in the archive, so that `ouch list --use-index` reads the
+//! headers alone instead of going through the whole archive.
+//!
+//! Every entry of a .tar.gz starts a new gzip member, that can be decompressed on its own. gzip and
+//! ouch decompress the members one after the other, as a single .tar.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fs,
+    io::{self, prelude::*, BufReader, SeekFrom},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use flate2::write::GzEncoder;
+
+use crate::{
+    archive::FileInArchive,
+    oof,
+    utils::{
+        io::{CountingWriter, EntryLimit},
+        logger, to_utf,
+    },
+};
+
+/// The entries of an index, by path
+type Entries = BTreeMap<String, u64>;
+
+/// The index of the archive being created, only recorded with `--create-tar-index`
+#[derive(Debug, Default)]
+pub struct TarIndex {
+    /// Shared with the `IndexWriter` under the tar builder
+    state: Option<Rc<RefCell<State>>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: Entries,
+    /// The entry whose headers are written next, it starts at the next byte written
+    next_entry: Option<String>,
+}
+
+impl TarIndex {
+    pub fn from_flags(flags: &oof::Flags) -> Self {
+        let state = flags.is_present("create-tar-index").then(Rc::default);
+        Self { state }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// `output`, the archive file, for the tar builder to write to, compressed with `gzip` for
+    /// .tar.gz
+    pub fn writer<W: Write>(&self, output: W, gzip: bool) -> IndexWriter<W> {
+        let written = Rc::default();
+        let output = CountingWriter::new(output, Rc::clone(&written));
+        let output = if gzip {
+            Output::Gzip(Some(GzEncoder::new(output, Default::default())))
+        } else {
+            Output::Plain(output)
+        };
+        IndexWriter { state: self.state.clone(), output, written, member_is_empty: true }
+    }
+
+    /// Called before the headers of the entry at `path` are written
+    pub fn start(&self, path: &Path) {
+        if let Some(state) = &self.state {
+            state.borrow_mut().next_entry = Some(to_utf(path));
+        }
+    }
+
+    /// Write the index next to the archive at `archive_path`
+    pub fn write(&self, archive_path: &Path) -> crate::Result<()> {
+        if let Some(state) = &self.state {
+            // Safe unwrap: the index only holds strings and numbers
+            let json = serde_json::to_vec(&state.borrow().entries).unwrap();
+            fs::write(index_path(archive_path), json)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the index of the archive at `archive_path` is, like "backup.tar.gz.idx"
+pub fn index_path(archive_path: &Path) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(".idx");
+    PathBuf::from(path)
+}
+
+/// Writer of the archive, that notes where each entry starts and, for .tar.gz, starts a new gzip
+/// member there
+pub struct IndexWriter<W: Write> {
+    state: Option<Rc<RefCell<State>>>,
+    output: Output<W>,
+    /// Bytes written to the archive file so far
+    written: Rc<Cell<u64>>,
+    member_is_empty: bool,
+}
+
+enum Output<W: Write> {
+    Plain(CountingWriter<W>),
+    /// `None` only while a member is replaced by the next one
+    Gzip(Option<GzEncoder<CountingWriter<W>>>),
+}
+
+impl<W: Write> IndexWriter<W> {
+    /// The archive file, once the last gzip member was finished
+    pub fn into_inner(self) -> io::Result<W> {
+        match self.output {
+            Output::Plain(output) => Ok(output.into_inner()),
+            // Safe unwrap: only `None` within `start_member`
+            Output::Gzip(encoder) => Ok(encoder.unwrap().finish()?.into_inner()),
+        }
+    }
+
+    /// Where the next entry starts, after the gzip member before it was finished
+    fn start_member(&mut self) -> io::Result<u64> {
+        if let Output::Gzip(encoder) = &mut self.output {
+            if !self.member_is_empty {
+                // Safe unwrap: put back right away
+                let output = encoder.take().unwrap().finish()?;
+                *encoder = Some(GzEncoder::new(output, Default::default()));
+                self.member_is_empty = true;
+            }
+        }
+        Ok(self.written.get())
+    }
+}
+
+impl<W: Write> Write for IndexWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let next_entry = self.state.as_ref().and_then(|state| state.borrow_mut().next_entry.take());
+        if let Some(path) = next_entry {
+            let offset = self.start_member()?;
+            // Safe unwrap: `next_entry` is only set with an index
+            self.state.as_ref().unwrap().borrow_mut().entries.insert(path, offset);
+        }
+
+        self.member_is_empty &= buf.is_empty();
+        match &mut self.output {
+            Output::Plain(output) => output.write(buf),
+            // Safe unwrap: only `None` within `start_member`
+            Output::Gzip(encoder) => encoder.as_mut().unwrap().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Output::Plain(output) => output.flush(),
+            // Safe unwrap: only `None` within `start_member`
+            Output::Gzip(encoder) => encoder.as_mut().unwrap().flush(),
+        }
+    }
+}
+
+/// The entries of the .tar or .tar.gz (`gzip`) at `archive_path`, read from where its index says
+/// they are. `None`, with a warning, when it has no index or the index doesn't match it, for the
+/// archive to be listed as a whole.
+pub fn list_archive(
+    archive_path: &Path,
+    gzip: bool,
+    limit: &mut EntryLimit,
+) -> crate::Result<Option<Vec<FileInArchive>>> {
+    let index_path = index_path(archive_path);
+    let entries: Entries = match fs::read(&index_path) {
+        Ok(json) => match serde_json::from_slice(&json) {
+            Ok(entries) => entries,
+            Err(_) => return not_used(&index_path, "it isn't an index of ouch").map(|_| None),
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return not_used(&index_path, "it doesn't exist").map(|_| None);
+        },
+        Err(err) => return Err(err.into()),
+    };
+    let mut entries: Vec<(String, u64)> = entries.into_iter().collect();
+    // Listed in the order of the archive
+    entries.sort_by_key(|(_, offset)| *offset);
+
+    let mut archive = fs::File::open(archive_path)?;
+    let mut files = vec![];
+    for (path, offset) in entries {
+        archive.seek(SeekFrom::Start(offset))?;
+        match read_entry(&archive, gzip) {
+            Ok(Some(file)) if to_utf(&file.path) == path => files.push(file),
+            _ => {
+                let reason = format!("'{}' isn't at byte {} of the archive", path, offset);
+                return not_used(&index_path, &reason).map(|_| None);
+            },
+        }
+    }
+    // Counted once the index was found to match, the archive is listed as a whole otherwise
+    for _ in &files {
+        limit.add()?;
+    }
+    Ok(Some(files))
+}
+
+/// The first entry of the archive from the current position of `archive`
+fn read_entry(archive: &fs::File, gzip: bool) -> io::Result<Option<FileInArchive>> {
+    let reader: Box<dyn Read + '_> = if gzip {
+        Box::new(flate2::read::GzDecoder::new(BufReader::new(archive)))
+    } else {
+        Box::new(BufReader::new(archive))
+    };
+    let mut archive = tar::Archive::new(reader);
+    let file = match archive.entries()?.next() {
+        Some(file) => file?,
+        None => return Ok(None),
+    };
+    let path = file.path()?.into_owned();
+    let is_dir = file.header().entry_type().is_dir();
+    let modified = file.header().mtime().ok().map(|mtime| mtime as i64);
+    Ok(Some(FileInArchive { path, is_dir, size: file.size(), modified }))
+}
+
+fn not_used(index_path: &Path, reason: &str) -> crate::Result<()> {
+    logger::warning(format!(
+        "Not using the index '{}', {}, the whole archive is read instead.",
+        to_utf(index_path),
+        reason
+    ))
+    .path(index_path)
+    .category("tar indexes not used")
+    .warn()
+}
@@ -0,0 +1,405 @@
+//! Read-only support for ISO 9660 disk images.
+//!
+//! Long file names are read from the Joliet supplementary volume descriptor or from Rock Ridge
+//! `NM` entries when present, falling back to the plain ISO 9660 names otherwise.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, prelude::*, SeekFrom},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    archive::{
+        collisions::CaseCollisions, transform::Transforms, windows, ArchiveInfo, FileInArchive,
+    },
+    events::{Event, EventSink},
+    extension::CompressionFormat,
+    oof,
+    progress::{ProgressReporter, ProgressUnit},
+    utils::{
+        self,
+        io::{EntryLimit, OutputLimit},
+        sparse::{self, SparseWriter},
+    },
+};
+
+const SECTOR_SIZE: u64 = 2048;
+/// Volume descriptors start after the 16 sectors of the system area
+const FIRST_DESCRIPTOR_SECTOR: u64 = 16;
+
+const PRIMARY_DESCRIPTOR: u8 = 1;
+const SUPPLEMENTARY_DESCRIPTOR: u8 = 2;
+const DESCRIPTOR_SET_TERMINATOR: u8 = 255;
+
+const FLAG_DIRECTORY: u8 = 0b10;
+
+/// A directory record, pointing to the extent of a file or directory
+#[derive(Debug, Clone)]
+pub struct DirectoryRecord {
+    pub name: String,
+    pub extent_sector: u32,
+    pub size: u32,
+    pub is_dir: bool,
+}
+
+/// A file or directory of the image, with its path relative to the image root
+#[derive(Debug, Clone)]
+pub struct IsoEntry {
+    pub path: PathBuf,
+    pub record: DirectoryRecord,
+}
+
+pub struct IsoImage<R> {
+    reader: R,
+    root: DirectoryRecord,
+    joliet: bool,
+    /// The application identifier of the primary volume descriptor, the program that made it
+    application: Option<String>,
+}
+
+impl<R: Read + Seek> IsoImage<R> {
+    pub fn new(mut reader: R) -> crate::Result<Self> {
+        let mut primary_root = None;
+        let mut joliet_root = None;
+        let mut application = None;
+
+        for sector in FIRST_DESCRIPTOR_SECTOR.. {
+            let descriptor = read_sector(&mut reader, sector)
+                .map_err(|_| corrupt("missing volume descriptors"))?;
+
+            if &descriptor[1..6] != b"CD001" {
+                return Err(corrupt("invalid volume descriptor"));
+            }
+
+            match descriptor[0] {
+                PRIMARY_DESCRIPTOR => {
+                    primary_root = Some(parse_record(&descriptor[156..], false));
+                    // Padded with spaces, or starting with '_' when it names a file of the image
+                    let identifier = String::from_utf8_lossy(&descriptor[574..702]);
+                    let identifier = identifier.trim();
+                    if !identifier.is_empty() && !identifier.starts_with('_') {
+                        application = Some(identifier.to_string());
+                    }
+                },
+                // Joliet is a supplementary descriptor with one of the UCS-2 escape sequences
+                SUPPLEMENTARY_DESCRIPTOR
+                    if matches!(&descriptor[88..91], b"%/@" | b"%/C" | b"%/E") =>
+                {
+                    joliet_root = Some(parse_record(&descriptor[156..], true));
+                },
+                DESCRIPTOR_SET_TERMINATOR => break,
+                _ => {},
+            }
+        }
+
+        let (root, joliet) = match (joliet_root, primary_root) {
+            (Some(Some(root)), _) => (root, true),
+            (_, Some(Some(root))) => (root, false),
+            _ => return Err(corrupt("missing primary volume descriptor")),
+        };
+
+        Ok(Self { reader, root, joliet, application })
+    }
+
+    /// Every file and directory in the image, parents come before their children
+    pub fn entries(&mut self) -> crate::Result<Vec<IsoEntry>> {
+        let mut entries = vec![];
+        let mut visited = HashSet::new();
+        let root = self.root.clone();
+        self.walk(&root, Path::new(""), &mut entries, &mut visited)?;
+        Ok(entries)
+    }
+
+    fn walk(
+        &mut self,
+        directory: &DirectoryRecord,
+        parent: &Path,
+        entries: &mut Vec<IsoEntry>,
+        visited: &mut HashSet<u32>,
+    ) -> crate::Result<()> {
+        // Protects against malicious images where directories point at their ancestors
+        if !visited.insert(directory.extent_sector) {
+            return Err(corrupt("directory loop detected"));
+        }
+
+        for record in self.read_directory(directory)? {
+            let path = parent.join(&record.name);
+            let is_dir = record.is_dir;
+            entries.push(IsoEntry { path: path.clone(), record: record.clone() });
+            if is_dir {
+                self.walk(&record, &path, entries, visited)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: &DirectoryRecord,
+    ) -> crate::Result<Vec<DirectoryRecord>> {
+        let mut data = vec![0; directory.size as usize];
+        self.reader.seek(SeekFrom::Start(directory.extent_sector as u64 * SECTOR_SIZE))?;
+        self.reader.read_exact(&mut data)?;
+
+        let mut records = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            let length = data[offset] as usize;
+
+            // Records don't cross sector boundaries, zeroes pad the rest of the sector
+            if length == 0 {
+                offset = (offset / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+                continue;
+            }
+            if offset + length > data.len() {
+                return Err(corrupt("truncated directory record"));
+            }
+
+            // Skips "." and "..", which point to the directory itself and its parent
+            match parse_record(&data[offset..offset + length], self.joliet) {
+                Some(record) if !record.name.is_empty() => records.push(record),
+                _ => {},
+            }
+            offset += length;
+        }
+
+        Ok(records)
+    }
+
+    /// Reader over the contents of a file
+    pub fn file_reader(&mut self, record: &DirectoryRecord) -> crate::Result<impl Read + '_> {
+        self.reader.seek(SeekFrom::Start(record.extent_sector as u64 * SECTOR_SIZE))?;
+        Ok((&mut self.reader).take(record.size as u64))
+    }
+}
+
+fn corrupt(reason: &str) -> crate::Error {
+    crate::Error::CorruptArchive {
+        format: CompressionFormat::Iso,
+        reason: reason.to_string(),
+        offset: None,
+        compressed_offset: None,
+        entry: None,
+    }
+}
+
+fn read_sector(reader: &mut (impl Read + Seek), sector: u64) -> io::Result<[u8; 2048]> {
+    let mut buffer = [0; SECTOR_SIZE as usize];
+    reader.seek(SeekFrom::Start(sector * SECTOR_SIZE))?;
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Parses a directory record, returning `None` for ".." or malformed records
+fn parse_record(data: &[u8], joliet: bool) -> Option<DirectoryRecord> {
+    if data.len() < 34 {
+        return None;
+    }
+
+    let extent_sector = read_u32_le(&data[2..6]);
+    let size = read_u32_le(&data[10..14]);
+    let is_dir = data[25] & FLAG_DIRECTORY != 0;
+    let name_length = data[32] as usize;
+    let raw_name = data.get(33..33 + name_length)?;
+
+    // "." (and the root record of a volume descriptor) is named "\0", ".." is named "\1"
+    let name = match raw_name {
+        [0] => String::new(),
+        [1] => return None,
+        _ if joliet => decode_ucs2(raw_name),
+        _ => {
+            // Padding byte after even-length names
+            let system_use_start = 33 + name_length + (1 - name_length % 2);
+            let system_use = data.get(system_use_start..).unwrap_or(&[]);
+            rock_ridge_name(system_use)
+                .unwrap_or_else(|| String::from_utf8_lossy(raw_name).into_owned())
+        },
+    };
+
+    Some(DirectoryRecord { name: strip_version(&name, is_dir), extent_sector, size, is_dir })
+}
+
+fn decode_ucs2(bytes: &[u8]) -> String {
+    let units: Vec<u16> =
+        bytes.chunks_exact(2).map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Looks for a Rock Ridge alternate name ("NM" entry) in the system use area of a record
+fn rock_ridge_name(mut system_use: &[u8]) -> Option<String> {
+    let mut name = Vec::new();
+
+    while system_use.len() >= 4 {
+        let signature = &system_use[0..2];
+        let length = system_use[2] as usize;
+        if length < 4 || length > system_use.len() {
+            break;
+        }
+
+        if signature == b"NM" && length >= 5 {
+            name.extend_from_slice(&system_use[5..length]);
+        }
+        system_use = &system_use[length..];
+    }
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(&name).into_owned())
+    }
+}
+
+/// Plain ISO 9660 file names end with a version number, like "README.TXT;1"
+fn strip_version(name: &str, is_dir: bool) -> String {
+    if is_dir {
+        return name.to_string();
+    }
+    let name = name.split(';').next().unwrap_or(name);
+    // Names without an extension end with a dot, like "README."
+    name.strip_suffix('.').unwrap_or(name).to_string()
+}
+
+/// Only plain names are accepted, so entries can't be written outside of the output folder
+fn is_safe_path(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+pub fn list_archive<R>(
+    mut image: IsoImage<R>,
+    entries: &mut EntryLimit,
+) -> crate::Result<Vec<FileInArchive>>
+where
+    R: Read + Seek,
+{
+    image
+        .entries()?
+        .into_iter()
+        .map(|entry| {
+            entries.add()?;
+            Ok(FileInArchive {
+                path: entry.path,
+                is_dir: entry.record.is_dir,
+                size: entry.record.size as u64,
+                // The recording dates of the records aren't read
+                modified: None,
+            })
+        })
+        .collect()
+}
+
+/// What `ouch info` shows about the image
+pub fn archive_info<R>(
+    mut image: IsoImage<R>,
+    entries: &mut EntryLimit,
+) -> crate::Result<ArchiveInfo>
+where
+    R: Read + Seek,
+{
+    let mut info = ArchiveInfo {
+        version: Some(if image.joliet { "ISO 9660, Joliet" } else { "ISO 9660" }.to_string()),
+        created_by: image.application.take(),
+        ..ArchiveInfo::default()
+    };
+    for entry in image.entries()? {
+        entries.add()?;
+        info.entries += 1;
+        if !entry.record.is_dir {
+            info.uncompressed_size += entry.record.size as u64;
+        }
+    }
+    Ok(info)
+}
+
+/// Call `visit` with the path and the contents of every file, for `ouch contents-hash`
+pub fn for_each_file<R>(
+    mut image: IsoImage<R>,
+    mut visit: impl FnMut(PathBuf, &mut dyn Read) -> crate::Result<()>,
+) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    for entry in image.entries()? {
+        if !entry.record.is_dir {
+            visit(entry.path, &mut image.file_reader(&entry.record)?)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn unpack_archive<R>(
+    mut image: IsoImage<R>,
+    output_folder: &Path,
+    limit: &mut OutputLimit,
+    progress: &dyn ProgressReporter,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<PathBuf>>
+where
+    R: Read + Seek,
+{
+    let entries = image.entries()?;
+    if !limit.is_unlimited() {
+        let declared_size = entries.iter().map(|entry| entry.record.size as u64).sum();
+        utils::io::warn_if_low_on_space(declared_size, output_folder);
+    }
+
+    let sparse = sparse::from_flags(flags)?;
+    let transforms = Transforms::from_flags(flags)?;
+    let mut collisions = CaseCollisions::new(output_folder, flags)?;
+
+    progress.set_total(entries.len() as u64, ProgressUnit::Files);
+    let mut unpacked_files = vec![];
+
+    for entry in entries {
+        limit.add_entry()?;
+        progress.advance(1);
+        let size = (!entry.record.is_dir).then_some(entry.record.size as u64);
+        events.event(Event::EntryStarted { entry: entry.path.clone(), size });
+        let path = match transforms.apply(&entry.path, entry.record.is_dir)? {
+            Some(path) if is_safe_path(&path) => path,
+            _ => continue,
+        };
+
+        let file_path = output_folder.join(windows::adapt_path(&path)?);
+        utils::validate_entry_path(&file_path, output_folder)?;
+        if entry.record.is_dir {
+            fs::create_dir_all(&file_path)?;
+            events.event(Event::EntryFinished { entry: entry.path, path: file_path, bytes: 0 });
+            continue;
+        }
+
+        let file_path = match collisions.claim(&file_path, flags)? {
+            Some(file_path) => file_path,
+            None => continue,
+        };
+
+        if file_path.exists() && !utils::user_wants_to_overwrite(&file_path, flags)? {
+            continue;
+        }
+
+        let mut output_file = SparseWriter::new(fs::File::create(&file_path)?, sparse);
+        utils::io::copy_limited(
+            &mut image.file_reader(&entry.record)?,
+            &mut output_file,
+            &file_path,
+            limit,
+        )?;
+        output_file.finish()?;
+
+        events.event(Event::EntryFinished {
+            entry: entry.path,
+            path: file_path.clone(),
+            bytes: entry.record.size as u64,
+        });
+        unpacked_files.push(file_path);
+    }
+
+    Ok(unpacked_files)
+}
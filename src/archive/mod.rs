@@ -1,2 +1,100 @@
+pub mod acl;
+pub mod collisions;
+pub mod cpio;
+pub mod directories;
+pub mod failures;
+pub mod index;
+pub mod iso;
+pub mod ownership;
+pub mod permissions;
+pub mod special;
 pub mod tar;
+pub mod transform;
+pub mod windows;
+pub mod xattrs;
 pub mod zip;
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use ignore::DirEntry;
+
+use crate::{
+    events::{Event, EventSink},
+    oof,
+};
+
+/// An entry of an archive, as shown by `ouch list`
+#[derive(Debug, Serialize)]
+pub struct FileInArchive {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Uncompressed size of the contents
+    pub size: u64,
+    /// Modification time, in seconds since the Unix epoch, when the format stores one
+    pub modified: Option<i64>,
+}
+
+/// What `ouch info` shows about an archive as a whole, where `ouch list` shows its entries
+#[derive(Debug, Default, Serialize)]
+pub struct ArchiveInfo {
+    /// Variant of the format, like "GNU" or "PAX" for .tar
+    pub version: Option<String>,
+    /// How the entries are compressed, for formats that compress each of them, like .zip
+    pub methods: Vec<String>,
+    /// Whether any of the entries is encrypted
+    pub encrypted: bool,
+    pub comment: Option<String>,
+    /// The program that made the archive, for formats that store it, like .iso
+    pub created_by: Option<String>,
+    pub entries: u64,
+    /// Total size of the files, once extracted
+    pub uncompressed_size: u64,
+}
+
+/// What `--preserve` keeps of the files in .tar archives, given as "ownership", "xattrs", "atime"
+/// or "all", separated by commas
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Preserve {
+    /// The names of the owners, when compressing, and the owners, when extracting
+    pub ownership: bool,
+    pub xattrs: bool,
+    /// The access times, in PAX records
+    pub atime: bool,
+}
+
+impl Preserve {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let mut preserve = Self::default();
+        let what = match flags.arg("preserve") {
+            Some(what) => what.to_str().ok_or(crate::Error::InvalidUnicode)?,
+            None => return Ok(preserve),
+        };
+        for part in what.split(',') {
+            match part.trim() {
+                "ownership" => preserve.ownership = true,
+                "xattrs" => preserve.xattrs = true,
+                "atime" => preserve.atime = true,
+                "all" => preserve = Self { ownership: true, xattrs: true, atime: true },
+                _ => return Err(crate::Error::InvalidInput),
+            }
+        }
+        Ok(preserve)
+    }
+}
+
+/// Report the file at `entry` as it starts being added to an archive
+pub(crate) fn start_adding(entry: &DirEntry, events: &dyn EventSink) {
+    let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file());
+    let size = metadata.map(|metadata| metadata.len());
+    events.event(Event::EntryStarted { entry: entry.path().to_path_buf(), size });
+}
+
+/// Report the file at `path` as added to an archive, with `bytes` bytes of contents
+pub(crate) fn finish_adding(path: &Path, bytes: u64, events: &dyn EventSink) {
+    events.event(Event::EntryFinished {
+        entry: path.to_path_buf(),
+        path: path.to_path_buf(),
+        bytes,
+    });
+}
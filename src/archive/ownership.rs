@@ -0,0 +1,259 @@
+//! Owners of extracted files, restored with `--preserve ownership` or with their IDs rewritten by
+//! `--uid-map` and `--gid-map`.
+//!
+//! Like GNU tar, the owners are found by the names stored in the archive first, as the IDs of the
+//! same user can differ between systems, and by the stored IDs when there is no user or group with
+//! that name, or with `--numeric-owner`. IDs that are mapped are always rewritten.
+//!
+//! Changing the owner of a file takes root or `CAP_CHOWN`, without them the files keep belonging
+//! to the user running ouch.
+
+use std::{cell::Cell, collections::HashMap, io, path::Path};
+
+use crate::{
+    archive::Preserve,
+    oof,
+    utils::{logger, to_utf},
+};
+
+/// IDs to rewrite, parsed from lists like "1000:1001,0:1001"
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct OwnershipMap {
+    uids: HashMap<u64, u64>,
+    gids: HashMap<u64, u64>,
+    /// Whether names are left out, with `--numeric-owner`
+    numeric: bool,
+    /// Whether the missing permission to change owners was already reported
+    warned: Cell<bool>,
+}
+
+impl OwnershipMap {
+    /// The maps given with `--uid-map` and `--gid-map`, `None` when neither of them nor
+    /// `--preserve ownership` was given and owners aren't restored
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Option<Self>> {
+        let (uid_map, gid_map) = (flags.arg("uid-map"), flags.arg("gid-map"));
+        if uid_map.is_none() && gid_map.is_none() && !Preserve::from_flags(flags)?.ownership {
+            return Ok(None);
+        }
+
+        let parse = |map: Option<&std::ffi::OsString>| match map {
+            Some(map) => parse_id_map(map.to_str().ok_or(crate::Error::InvalidUnicode)?),
+            None => Ok(HashMap::new()),
+        };
+        Ok(Some(Self {
+            uids: parse(uid_map)?,
+            gids: parse(gid_map)?,
+            numeric: flags.is_present("numeric-owner"),
+            warned: Cell::new(false),
+        }))
+    }
+
+    /// The (uid, gid) a file archived with these IDs and names is given, the mapped IDs, else
+    /// those of the user and group with these names on this system, else the archived IDs
+    pub fn owner(&self, uid: u64, gid: u64, user: Option<&str>, group: Option<&str>) -> (u64, u64) {
+        let find = |name: Option<&str>, find_id: fn(&str) -> Option<u64>| {
+            name.filter(|name| !self.numeric && !name.is_empty()).and_then(find_id)
+        };
+        let uid = match self.uids.get(&uid) {
+            Some(mapped) => *mapped,
+            None => find(user, user_id).unwrap_or(uid),
+        };
+        let gid = match self.gids.get(&gid) {
+            Some(mapped) => *mapped,
+            None => find(group, group_id).unwrap_or(gid),
+        };
+        (uid, gid)
+    }
+
+    /// Give the file at `path` (not the target, for symlinks) to its owner, see [`Self::owner`]
+    pub fn apply(
+        &self,
+        path: &Path,
+        uid: u64,
+        gid: u64,
+        user: Option<&str>,
+        group: Option<&str>,
+    ) -> crate::Result<()> {
+        let (uid, gid) = self.owner(uid, gid, user, group);
+        match lchown(path, uid, gid) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                if !self.warned.replace(true) {
+                    logger::warning(format!(
+                        "Could not change the owner of '{}', changing owners takes root or \
+                         CAP_CHOWN. Extracted files keep the current user as owner.",
+                        to_utf(path)
+                    ))
+                    .path(path)
+                    .category("owners not restored")
+                    .warn()?;
+                }
+                Ok(())
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Parse "OLD:NEW" pairs separated by commas
+fn parse_id_map(text: &str) -> crate::Result<HashMap<u64, u64>> {
+    text.split(',')
+        .map(|pair| {
+            let (old, new) = pair.split_once(':').ok_or(crate::Error::InvalidInput)?;
+            match (old.trim().parse(), new.trim().parse()) {
+                (Ok(old), Ok(new)) => Ok((old, new)),
+                _ => Err(crate::Error::InvalidInput),
+            }
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn lchown(path: &Path, uid: u64, gid: u64) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // Safety: `c_path` is a valid C string
+    let result = unsafe { libc::lchown(c_path.as_ptr(), uid as libc::uid_t, gid as libc::gid_t) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn lchown(_path: &Path, _uid: u64, _gid: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// Look up a user or group with `call`, one of the reentrant functions of libc like `getpwnam_r`,
+/// and read what is asked for from it with `read`
+#[cfg(unix)]
+fn lookup<T, R>(
+    call: impl Fn(*mut T, *mut libc::c_char, libc::size_t, *mut *mut T) -> libc::c_int,
+    read: impl FnOnce(&T) -> Option<R>,
+) -> Option<R> {
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    loop {
+        // Safety: `passwd` and `group` are plain C structs, read only once filled in by `call`
+        let mut record: T = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        match call(&mut record, buffer.as_mut_ptr(), buffer.len(), &mut result) {
+            // The strings of the record didn't fit
+            libc::ERANGE => buffer.resize(buffer.len() * 2, 0),
+            0 if !result.is_null() => return read(&record),
+            _ => return None,
+        }
+    }
+}
+
+/// The name that `pointer`, into the buffer of a record, points to
+#[cfg(unix)]
+fn read_name(pointer: *const libc::c_char) -> Option<String> {
+    // Safety: filled in by libc, the names of records are valid C strings
+    let name = unsafe { std::ffi::CStr::from_ptr(pointer) };
+    name.to_str().ok().map(str::to_string)
+}
+
+#[cfg(unix)]
+fn user_id(name: &str) -> Option<u64> {
+    let name = std::ffi::CString::new(name).ok()?;
+    lookup(
+        // Safety: the pointers are valid for the sizes given
+        |record, buffer, size, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), record, buffer, size, result)
+        },
+        |user: &libc::passwd| Some(user.pw_uid as u64),
+    )
+}
+
+#[cfg(unix)]
+fn group_id(name: &str) -> Option<u64> {
+    let name = std::ffi::CString::new(name).ok()?;
+    lookup(
+        // Safety: the pointers are valid for the sizes given
+        |record, buffer, size, result| unsafe {
+            libc::getgrnam_r(name.as_ptr(), record, buffer, size, result)
+        },
+        |group: &libc::group| Some(group.gr_gid as u64),
+    )
+}
+
+/// The name of the user with ID `uid` on this system, stored with `--preserve ownership`
+#[cfg(unix)]
+pub fn user_name(uid: u64) -> Option<String> {
+    lookup(
+        // Safety: the pointers are valid for the sizes given
+        |record, buffer, size, result| unsafe {
+            libc::getpwuid_r(uid as libc::uid_t, record, buffer, size, result)
+        },
+        |user: &libc::passwd| read_name(user.pw_name),
+    )
+}
+
+/// The name of the group with ID `gid` on this system, stored with `--preserve ownership`
+#[cfg(unix)]
+pub fn group_name(gid: u64) -> Option<String> {
+    lookup(
+        // Safety: the pointers are valid for the sizes given
+        |record, buffer, size, result| unsafe {
+            libc::getgrgid_r(gid as libc::gid_t, record, buffer, size, result)
+        },
+        |group: &libc::group| read_name(group.gr_name),
+    )
+}
+
+#[cfg(not(unix))]
+fn user_id(_name: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(not(unix))]
+fn group_id(_name: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_map_parsing() {
+        let map = parse_id_map("1000:1001, 0:65534").unwrap();
+        assert_eq!(map.get(&1000), Some(&1001));
+        assert_eq!(map.get(&0), Some(&65534));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(parse_id_map("1000").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(parse_id_map("1000:bob").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(parse_id_map("").unwrap_err(), crate::Error::InvalidInput);
+
+        let mut flags = oof::Flags::default();
+        assert_eq!(OwnershipMap::from_flags(&flags).unwrap(), None);
+        flags.argument_flags.insert("uid-map", "1000:1001".into());
+        let map = OwnershipMap::from_flags(&flags).unwrap().unwrap();
+        // Unmapped IDs pass through unchanged
+        assert_eq!(map.owner(1000, 1000, None, None), (1001, 1000));
+        assert_eq!(map.owner(42, 7, None, None), (42, 7));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_owner_names() {
+        assert_eq!(user_name(0).as_deref(), Some("root"));
+        assert_eq!(user_id("root"), Some(0));
+
+        let mut flags = oof::Flags::default();
+        flags.argument_flags.insert("preserve", "ownership".into());
+        flags.argument_flags.insert("gid-map", "7:8".into());
+        let owners = OwnershipMap::from_flags(&flags).unwrap().unwrap();
+        // Names come before the archived IDs, but not before the maps
+        assert_eq!(owners.owner(1000, 100, Some("root"), Some("no group of that name")), (0, 100));
+        assert_eq!(owners.owner(1000, 7, Some(""), Some("root")), (1000, 8));
+
+        flags.boolean_flags.insert("numeric-owner");
+        let owners = OwnershipMap::from_flags(&flags).unwrap().unwrap();
+        assert_eq!(owners.owner(1000, 100, Some("root"), Some("root")), (1000, 100));
+    }
+}
@@ -0,0 +1,100 @@
+//! The permissions extracted files and folders get, the same for every format.
+//!
+//! By default, and with `--same-permissions`, they get the modes stored in the archive, setuid,
+//! setgid and sticky bits included. With `--no-same-permissions`, those bits and the ones the umask
+//! clears are left out, like for files created by other programs, so that an untrusted archive
+//! can't leave world-writable or setuid files behind, even when extracted as root.
+
+use crate::oof;
+
+/// The setuid, setgid and sticky bits
+const SPECIAL_BITS: u32 = 0o7000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    /// The bits left out of the stored modes
+    mask: u32,
+}
+
+impl Permissions {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        match (flags.is_present("same-permissions"), flags.is_present("no-same-permissions")) {
+            (true, true) => {
+                Err(crate::Error::ConflictingFlags("same-permissions", "no-same-permissions"))
+            },
+            (_, true) => Ok(Self { mask: SPECIAL_BITS | umask() }),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// The mode an entry stored with `mode` is extracted with, without its file type bits
+    pub fn mode(self, mode: u32) -> u32 {
+        mode & 0o7777 & !self.mask
+    }
+
+    /// The bits left out of the stored modes, for the tar crate, which applies them itself
+    pub fn mask(self) -> u32 {
+        self.mask
+    }
+}
+
+/// Read the umask ahead of time, for ouch to do it at startup, before any other thread runs:
+/// without the "Umask:" line of /proc/self/status, `libc::umask` is the only way to read it, and
+/// it sets the mask for a moment, which files created by other threads meanwhile would get
+pub fn read_umask() {
+    umask();
+}
+
+/// The umask, read once
+#[cfg(unix)]
+fn umask() -> u32 {
+    use std::sync::OnceLock;
+
+    static UMASK: OnceLock<u32> = OnceLock::new();
+    *UMASK.get_or_init(|| {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+        status_umask(&status).unwrap_or_else(|| {
+            // Safety: umask can't fail, and the previous value is set back right away
+            let umask = unsafe { libc::umask(0o022) };
+            unsafe { libc::umask(umask) };
+            umask as u32
+        })
+    })
+}
+
+/// The "Umask:" line of /proc/self/status, there since Linux 4.7
+#[cfg(unix)]
+fn status_umask(status: &str) -> Option<u32> {
+    let umask = status.lines().find_map(|line| line.strip_prefix("Umask:"))?;
+    u32::from_str_radix(umask.trim(), 8).ok()
+}
+
+/// Windows has no umask, the folders files are created in decide their permissions
+#[cfg(not(unix))]
+fn umask() -> u32 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode() {
+        let same = Permissions::default();
+        assert_eq!(same.mode(0o100777), 0o777);
+        assert_eq!(same.mode(0o4755), 0o4755);
+
+        let umasked = Permissions { mask: SPECIAL_BITS | 0o022 };
+        assert_eq!(umasked.mode(0o100777), 0o755);
+        assert_eq!(umasked.mode(0o4755), 0o755);
+        assert_eq!(umasked.mode(0o640), 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_status_umask() {
+        assert_eq!(status_umask("Name:\touch\nUmask:\t0027\nState:\tR (running)\n"), Some(0o027));
+        assert_eq!(status_umask("Name:\touch\n"), None);
+    }
+}
@@ -0,0 +1,197 @@
+//! FIFOs, sockets and device nodes, handled as chosen with `--special-files store|skip|fail`.
+//!
+//! They are never opened: reading from a FIFO blocks until something writes to it.
+
+use std::{fmt, fs, io, path::Path};
+
+use crate::{
+    oof,
+    utils::{logger, to_utf},
+};
+
+/// What to do with special files while compressing or extracting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFilePolicy {
+    /// Keep them as entries of their own type, only .tar and .cpio have those
+    Store,
+    /// Leave them out with a warning, the default
+    Skip,
+    /// Stop with an error
+    Fail,
+}
+
+impl SpecialFilePolicy {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        match flags.arg("special-files").map(|policy| policy.to_str()) {
+            None | Some(Some("skip")) => Ok(Self::Skip),
+            Some(Some("store")) => Ok(Self::Store),
+            Some(Some("fail")) => Ok(Self::Fail),
+            Some(_) => Err(crate::Error::InvalidInput),
+        }
+    }
+
+    /// Whether the special file at `path` should be kept, warning when it is left out.
+    ///
+    /// `limitation` explains why it can't be kept, even with `--special-files store`.
+    pub fn keep(
+        self,
+        path: &Path,
+        kind: SpecialFileKind,
+        limitation: Option<&str>,
+    ) -> crate::Result<bool> {
+        let reason = match (self, limitation) {
+            (Self::Fail, _) => {
+                return Err(crate::Error::SpecialFile { path: path.to_path_buf(), kind })
+            },
+            (_, Some(limitation)) => limitation,
+            (Self::Store, None) => return Ok(true),
+            (Self::Skip, None) => "use `--special-files store` to keep it",
+        };
+
+        logger::warning(format!("Skipping the {} '{}', {}.", kind, to_utf(path), reason))
+            .path(path)
+            .category("special files skipped")
+            .warn()?;
+        Ok(false)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFileKind {
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+}
+
+impl SpecialFileKind {
+    /// The kind of special file of `metadata`, `None` for regular files, directories and symlinks
+    #[cfg(unix)]
+    pub fn of(metadata: &fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        let file_type = metadata.file_type();
+        if file_type.is_fifo() {
+            Some(Self::Fifo)
+        } else if file_type.is_socket() {
+            Some(Self::Socket)
+        } else if file_type.is_char_device() {
+            Some(Self::CharDevice)
+        } else if file_type.is_block_device() {
+            Some(Self::BlockDevice)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn of(_metadata: &fs::Metadata) -> Option<Self> {
+        None
+    }
+
+    /// The kind of special file a tar entry stands for, tar has no entry type for sockets
+    pub fn of_tar_entry(entry_type: tar::EntryType) -> Option<Self> {
+        match entry_type {
+            tar::EntryType::Fifo => Some(Self::Fifo),
+            tar::EntryType::Char => Some(Self::CharDevice),
+            tar::EntryType::Block => Some(Self::BlockDevice),
+            _ => None,
+        }
+    }
+
+    /// Why this kind of file can't be stored in .tar, if it can't
+    pub fn tar_limitation(self) -> Option<&'static str> {
+        match self {
+            Self::Socket => Some("sockets can't be stored in .tar"),
+            _ => None,
+        }
+    }
+
+    /// Why this kind of file can't be created by the current user, if it can't
+    pub fn extraction_limitation(self) -> Option<&'static str> {
+        if cfg!(not(unix)) {
+            return Some("special files can only be created on Unix");
+        }
+
+        let is_device = matches!(self, Self::CharDevice | Self::BlockDevice);
+        if is_device && !is_root() {
+            Some("device nodes can only be created by root")
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for SpecialFileKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Fifo => "FIFO",
+            Self::Socket => "socket",
+            Self::CharDevice => "character device",
+            Self::BlockDevice => "block device",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Create a FIFO or device node at `path` with the permissions of `mode`, replacing what was there
+#[cfg(unix)]
+pub fn create(
+    path: &Path,
+    kind: SpecialFileKind,
+    mode: u32,
+    (major, minor): (u32, u32),
+) -> crate::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(path)?;
+    }
+
+    let mode = mode as libc::mode_t & 0o7777;
+    let device = libc::makedev(major as _, minor as _);
+    let c_path =
+        CString::new(path.as_os_str().as_bytes()).map_err(|_| crate::Error::InvalidInput)?;
+    // Safety: `c_path` is a valid C string
+    let result = unsafe {
+        match kind {
+            SpecialFileKind::Fifo => libc::mkfifo(c_path.as_ptr(), mode),
+            SpecialFileKind::Socket => libc::mknod(c_path.as_ptr(), libc::S_IFSOCK | mode, 0),
+            SpecialFileKind::CharDevice => {
+                libc::mknod(c_path.as_ptr(), libc::S_IFCHR | mode, device)
+            },
+            SpecialFileKind::BlockDevice => {
+                libc::mknod(c_path.as_ptr(), libc::S_IFBLK | mode, device)
+            },
+        }
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn create(
+    _path: &Path,
+    _kind: SpecialFileKind,
+    _mode: u32,
+    _device: (u32, u32),
+) -> crate::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // Safety: geteuid has no preconditions and always succeeds
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
@@ -1,71 +1,934 @@
 use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
     env, fs,
-    io::prelude::*,
-    path::{Path, PathBuf},
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use filetime::FileTime;
 use tar;
-use utils::colors;
-use walkdir::WalkDir;
+use utils::logger;
 
-use crate::{oof, utils};
+use crate::{
+    archive::{
+        self,
+        acl::{self, AclHandler, Acls},
+        collisions::CaseCollisions,
+        directories::DeferredDirectories,
+        failures::EntryFailures,
+        index::TarIndex,
+        ownership::{self, OwnershipMap},
+        permissions::Permissions,
+        special::{self, SpecialFileKind, SpecialFilePolicy},
+        transform::Transforms,
+        windows::{self, SkippedSymlinks},
+        xattrs::{self, XattrHandler, Xattrs},
+        ArchiveInfo, FileInArchive, Preserve,
+    },
+    events::{Event, EventSink},
+    extension::CompressionFormat,
+    oof,
+    stats::CompressionStats,
+    utils,
+    utils::{
+        io::{EntryLimit, OutputLimit},
+        to_utf, ArchiveMtime,
+    },
+};
 
 pub fn unpack_archive(
     reader: Box<dyn Read>,
     output_folder: &Path,
+    limit: &mut OutputLimit,
+    events: &dyn EventSink,
     flags: &oof::Flags,
 ) -> crate::Result<Vec<PathBuf>> {
     let mut archive = tar::Archive::new(reader);
+    let permissions = Permissions::from_flags(flags)?;
+    archive.set_preserve_permissions(true);
+    archive.set_mask(permissions.mask());
+    let special_files = SpecialFilePolicy::from_flags(flags)?;
+    let ownership = OwnershipMap::from_flags(flags)?;
+    let acls = flags.is_present("acl").then(AclHandler::default);
+    let preserve = Preserve::from_flags(flags)?;
+    let xattrs = preserve.xattrs.then(XattrHandler::default);
+    let transforms = Transforms::from_flags(flags)?;
+    let mut collisions = CaseCollisions::new(output_folder, flags)?;
+    let mut directories = DeferredDirectories::default();
 
     let mut files_unpacked = vec![];
-    for file in archive.entries()? {
-        let mut file = file?;
+    // Where the entries that were extracted are, by their path in the archive, for hard links
+    let mut extracted_paths = HashMap::new();
+    let mut symlinks = SkippedSymlinks::default();
+    let mut failures = EntryFailures::from_flags(flags);
+    // The last entry read, reported along with the damaged data that follows it
+    let mut last_entry = None;
+    // Extracts the entry at `entry`, the path of its file or `None` when it was skipped
+    let mut unpack_entry = |file: &mut tar::Entry<_>, entry: &Path| -> crate::Result<_> {
+        limit.add_entry()?;
+        let entry_type = file.header().entry_type();
+        let size = entry_type.is_file().then(|| file.size());
+        events.event(Event::EntryStarted { entry: entry.to_path_buf(), size });
+        logger::trace(format!("{:?} is of type {:?}.", entry, entry_type)).entry(entry).log();
+        if entry_type == tar::EntryType::Symlink && transforms.skips_symlink(entry)? {
+            return Ok(None);
+        }
+        let renamed = match transforms.apply(entry, entry_type.is_dir())? {
+            Some(renamed) => windows::adapt_path(&renamed)?.into_owned(),
+            None => return Ok(None),
+        };
+        let mut file_path = output_folder.join(&renamed);
+        if !entry_type.is_dir() {
+            file_path = match collisions.claim(&file_path, flags)? {
+                Some(file_path) => file_path,
+                None => return Ok(None),
+            };
+        }
+        utils::validate_entry_path(&file_path, output_folder)?;
+        let special_kind = SpecialFileKind::of_tar_entry(entry_type);
+        if let Some(kind) = special_kind {
+            if !special_files.keep(&file_path, kind, kind.extraction_limitation())? {
+                return Ok(None);
+            }
+        }
 
-        let file_path = output_folder.join(file.path()?);
         if file_path.exists() && !utils::user_wants_to_overwrite(&file_path, flags)? {
-            continue;
+            return Ok(None);
+        }
+
+        // Read before the contents, which come after the PAX records
+        let records = archived_records(file)?;
+
+        // The tar crate never reads past the size declared in the header
+        limit.add(file.size(), &file_path)?;
+        if entry_type == tar::EntryType::Link {
+            if !unpack_hardlink(file, &extracted_paths, &file_path)? {
+                return Ok(None);
+            }
+        } else if let Some(kind) = special_kind {
+            // The tar crate would write them out as regular files
+            unpack_special(file, kind, &file_path, permissions)?;
+        } else if entry_type.is_gnu_sparse() && flags.is_present("no-sparse") {
+            unpack_dense(file, &file_path, permissions)?;
+        } else if entry_type == tar::EntryType::Symlink && cfg!(windows) {
+            // The tar crate fails on the links Windows refuses to create, these are skipped
+            let target = file.link_name()?.map(Cow::into_owned).unwrap_or_default();
+            if !unpack_symlink(&mut symlinks, &target, output_folder, &file_path)? {
+                return Ok(None);
+            }
+        } else if file_path == output_folder.join(entry) {
+            // Sparse entries are recreated with holes, by seeking over them. The paths it skips
+            // were refused by `validate_entry_path` already
+            file.unpack_in(output_folder)?;
+        } else {
+            unpack_renamed(file, output_folder, &file_path)?;
+        }
+
+        // The tar crate gives folders their permissions right away, not their modification time
+        if entry_type.is_dir() {
+            let header = file.header();
+            let mode = header.mode().map_err(header_error)?;
+            let mtime = header.mtime().ok().map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+            directories.defer(&file_path, permissions.mode(mode), records.mtime.or(mtime))?;
+        }
+
+        // Entries with unsafe paths are never written out
+        if let (Some(ownership), Ok(_)) = (&ownership, file_path.symlink_metadata()) {
+            let header = file.header();
+            ownership.apply(
+                &file_path,
+                header.uid().map_err(header_error)?,
+                header.gid().map_err(header_error)?,
+                records.user.as_deref(),
+                records.group.as_deref(),
+            )?;
+        }
+        // After the owner, changing it could drop the permissions that the ACLs give
+        if let Some(acls) = &acls {
+            if !records.acls.is_empty() && entry_type != tar::EntryType::Symlink {
+                acls.apply(&file_path, &records.acls)?;
+            }
+        }
+        // After the owner too, changing it drops attributes like "security.capability"
+        if let Some(xattrs) = &xattrs {
+            if !records.xattrs.is_empty() && entry_type != tar::EntryType::Symlink {
+                xattrs.apply(&file_path, &records.xattrs)?;
+            }
+        }
+        // Last, as setting attributes doesn't change the modification time but writing does. Hard
+        // links share the times of the file they link to
+        let atime = records.atime.filter(|_| preserve.atime);
+        if !entry_type.is_dir() && entry_type != tar::EntryType::Link {
+            let is_symlink = entry_type == tar::EntryType::Symlink;
+            restore_times(&file_path, records.mtime, atime, is_symlink)?;
+        }
+
+        events.event(Event::EntryFinished {
+            entry: entry.to_path_buf(),
+            path: file_path.clone(),
+            bytes: file.size(),
+        });
+
+        extracted_paths.insert(archived_path(entry), file_path.clone());
+        Ok(Some(file_path))
+    };
+    let mut unpack_entries = || -> crate::Result<()> {
+        // The tar crate skips what wasn't read of an entry, like that of one that failed, once the
+        // next one is read
+        for file in archive.entries().map_err(header_error)? {
+            let mut file = file.map_err(header_error)?;
+            let entry = file.path()?.into_owned();
+            last_entry = Some(entry.clone());
+            let result = unpack_entry(&mut file, &entry);
+            if let Some(file_path) = failures.check(&entry, result)? {
+                files_unpacked.push(file_path);
+            }
+        }
+        Ok(())
+    };
+    unpack_entries().map_err(|err| after_entry(err, last_entry))?;
+
+    symlinks.warn()?;
+    directories.restore()?;
+    failures.finish()?;
+    Ok(files_unpacked)
+}
+
+pub fn list_archive(
+    reader: Box<dyn Read>,
+    entries: &mut EntryLimit,
+) -> crate::Result<Vec<FileInArchive>> {
+    let mut archive = tar::Archive::new(reader);
+    let files = archive.entries().map_err(header_error)?;
+    list_entries(files, entries)
+}
+
+/// Like `list_archive`, seeking over the contents of the entries instead of reading them
+pub fn list_seekable_archive<R: Read + Seek>(
+    reader: R,
+    entries: &mut EntryLimit,
+) -> crate::Result<Vec<FileInArchive>> {
+    let mut archive = tar::Archive::new(reader);
+    let files = archive.entries_with_seek().map_err(header_error)?;
+    list_entries(files, entries)
+}
+
+/// Like `list_archive`, calling `visit` with the entries instead, and only reading the archive
+/// until it returns `false`
+pub fn visit_archive(
+    reader: Box<dyn Read>,
+    entries: &mut EntryLimit,
+    visit: impl FnMut(FileInArchive) -> crate::Result<bool>,
+) -> crate::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    let files = archive.entries().map_err(header_error)?;
+    visit_entries(files, entries, visit)
+}
+
+fn list_entries<R: Read>(
+    archive_entries: tar::Entries<R>,
+    entries: &mut EntryLimit,
+) -> crate::Result<Vec<FileInArchive>> {
+    let mut files = vec![];
+    visit_entries(archive_entries, entries, |file| {
+        files.push(file);
+        Ok(true)
+    })?;
+    Ok(files)
+}
+
+fn visit_entries<R: Read>(
+    archive_entries: tar::Entries<R>,
+    entries: &mut EntryLimit,
+    mut visit: impl FnMut(FileInArchive) -> crate::Result<bool>,
+) -> crate::Result<()> {
+    // The last entry listed, reported along with the damaged data that follows it
+    let mut last_path = None;
+    let list = || -> crate::Result<()> {
+        for file in archive_entries {
+            let file = file.map_err(header_error)?;
+            entries.add()?;
+            let path = file.path()?.into_owned();
+            last_path = Some(path.clone());
+            let is_dir = file.header().entry_type().is_dir();
+            let modified = file.header().mtime().ok().map(|mtime| mtime as i64);
+            if !visit(FileInArchive { path, is_dir, size: file.size(), modified })? {
+                break;
+            }
+        }
+        Ok(())
+    };
+    list().map_err(|err| after_entry(err, last_path))
+}
+
+/// What `ouch info` shows about the archive, its variant is the newest one that its headers use
+pub fn archive_info(reader: Box<dyn Read>, entries: &mut EntryLimit) -> crate::Result<ArchiveInfo> {
+    let mut archive = tar::Archive::new(reader);
+
+    let mut info = ArchiveInfo::default();
+    let (mut is_pax, mut is_gnu, mut is_ustar) = (false, false, false);
+    for file in archive.entries().map_err(header_error)? {
+        let mut file = file.map_err(header_error)?;
+        entries.add()?;
+        info.entries += 1;
+
+        // The tar crate reads the PAX records of an entry itself, instead of returning them
+        let entry_type = file.header().entry_type();
+        is_pax |= entry_type == tar::EntryType::XGlobalHeader || file.pax_extensions()?.is_some();
+        is_gnu |= file.header().as_gnu().is_some();
+        is_ustar |= file.header().as_ustar().is_some();
+        if !entry_type.is_dir() {
+            info.uncompressed_size += file.size();
+        }
+    }
+
+    let version = match (is_pax, is_gnu, is_ustar) {
+        (true, ..) => "PAX",
+        (_, true, _) => "GNU",
+        (_, _, true) => "ustar",
+        _ => "v7",
+    };
+    info.version = Some(version.to_string());
+    Ok(info)
+}
+
+/// Call `visit` with the path and the contents of every regular file, for `ouch contents-hash`
+pub fn for_each_file(
+    reader: Box<dyn Read>,
+    mut visit: impl FnMut(PathBuf, &mut dyn Read) -> crate::Result<()>,
+) -> crate::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for file in archive.entries().map_err(header_error)? {
+        let mut file = file.map_err(header_error)?;
+        let entry_type = file.header().entry_type();
+        // Sparse entries are read with their holes filled with zeroes
+        if entry_type.is_file() || entry_type.is_contiguous() || entry_type.is_gnu_sparse() {
+            let path = file.path()?.into_owned();
+            visit(path, &mut file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `unpack_in` for entries extracted under another path than their archived one, which is the only
+/// one it knows of, like those renamed by `--transform`.
+/// Like it, refuses to write through symlinks that lead out of `output_folder`.
+fn unpack_renamed(
+    file: &mut tar::Entry<Box<dyn Read>>,
+    output_folder: &Path,
+    file_path: &Path,
+) -> crate::Result<()> {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+        if !parent.canonicalize()?.starts_with(output_folder.canonicalize()?) {
+            return Err(crate::Error::PathTraversal { entry: to_utf(file_path) });
+        }
+    }
+    file.unpack(file_path)?;
+    Ok(())
+}
+
+/// Create a symbolic link entry at `file_path`, inside of `output_folder`, `false` when it was
+/// skipped
+fn unpack_symlink(
+    symlinks: &mut SkippedSymlinks,
+    target: &Path,
+    output_folder: &Path,
+    file_path: &Path,
+) -> crate::Result<bool> {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+        if !parent.canonicalize()?.starts_with(output_folder.canonicalize()?) {
+            return Err(crate::Error::PathTraversal { entry: to_utf(file_path) });
         }
+    }
+    if file_path.symlink_metadata().is_ok() {
+        fs::remove_file(file_path)?;
+    }
+    symlinks.create(target, file_path)
+}
+
+/// Unpack a sparse entry writing its holes out as zeroes
+fn unpack_dense<R: Read>(
+    entry: &mut tar::Entry<R>,
+    file_path: &Path,
+    permissions: Permissions,
+) -> crate::Result<()> {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut output_file = fs::File::create(file_path)?;
+    io::copy(entry, &mut output_file)?;
+
+    if let Ok(mode) = entry.header().mode() {
+        let mode = permissions.mode(mode);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(file_path, fs::Permissions::from_mode(mode))?;
+        }
+        windows::set_readonly(file_path, mode)?;
+    }
+
+    Ok(())
+}
+
+/// Create the FIFO or device node of a special entry
+fn unpack_special<R: Read>(
+    entry: &tar::Entry<R>,
+    kind: SpecialFileKind,
+    file_path: &Path,
+    permissions: Permissions,
+) -> crate::Result<()> {
+    if !entry.path()?.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Ok(());
+    }
+
+    let header = entry.header();
+    let mode = header.mode().map_err(header_error)?;
+    let device = match (header.device_major(), header.device_minor()) {
+        (Ok(Some(major)), Ok(Some(minor))) => (major, minor),
+        _ => (0, 0),
+    };
+
+    special::create(file_path, kind, permissions.mode(mode), device)
+}
+
+/// Recreate a hard link entry, copying the file instead if linking fails
+/// "./dir/file" and "dir/file" are the same entry
+fn archived_path(path: &Path) -> PathBuf {
+    path.components().filter(|component| *component != Component::CurDir).collect()
+}
+
+/// Link `file_path` to the file extracted from the entry the link points to, returning false if
+/// that entry wasn't extracted before it
+fn unpack_hardlink<R: Read>(
+    entry: &tar::Entry<R>,
+    extracted_paths: &HashMap<PathBuf, PathBuf>,
+    file_path: &Path,
+) -> crate::Result<bool> {
+    let link_name = match entry.link_name().map_err(header_error)? {
+        Some(link_name) => archived_path(&link_name),
+        None => return Ok(false),
+    };
+    // Never a file that was already in the output folder, or outside of it, only the ones that
+    // were extracted from the archive
+    let target = match extracted_paths.get(&link_name) {
+        Some(target) => target,
+        None => {
+            logger::warning(format!(
+                "Skipping the hard link {:?}, its target {:?} was not extracted.",
+                file_path, link_name
+            ))
+            .path(file_path)
+            .category("hard links skipped")
+            .warn()?;
+            return Ok(false);
+        },
+    };
+
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if file_path.exists() {
+        fs::remove_file(file_path)?;
+    }
 
-        file.unpack_in(output_folder)?;
+    if let Err(err) = fs::hard_link(target, file_path) {
+        logger::warning(format!(
+            "Could not hard link {:?} to {:?}, copying it instead ({}).",
+            file_path, target, err
+        ))
+        .path(file_path)
+        .category("hard links copied")
+        .warn()?;
+        fs::copy(target, file_path)?;
+    }
+
+    Ok(true)
+}
+
+/// If `path` is a hard link to a file that was already archived, the path it was archived at
+#[cfg(unix)]
+fn archived_hardlink_target(
+    path: &Path,
+    archived_inodes: &mut HashMap<(u64, u64), PathBuf>,
+) -> crate::Result<Option<PathBuf>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_file() || metadata.nlink() < 2 {
+        return Ok(None);
+    }
+
+    match archived_inodes.entry((metadata.dev(), metadata.ino())) {
+        Entry::Occupied(entry) => Ok(Some(entry.get().clone())),
+        Entry::Vacant(entry) => {
+            entry.insert(path.to_path_buf());
+            Ok(None)
+        },
+    }
+}
+
+#[cfg(not(unix))]
+fn archived_hardlink_target(
+    _path: &Path,
+    _archived_inodes: &mut HashMap<(u64, u64), PathBuf>,
+) -> crate::Result<Option<PathBuf>> {
+    Ok(None)
+}
+
+/// Errors while reading the entries come from broken headers, unless they were already reported
+/// by one of the decoders
+fn header_error(err: io::Error) -> crate::Error {
+    match crate::Error::from(err) {
+        crate::Error::IoError { reason } => crate::Error::CorruptArchive {
+            format: CompressionFormat::Tar,
+            reason,
+            offset: None,
+            compressed_offset: None,
+            entry: None,
+        },
+        err => err,
+    }
+}
+
+/// Damaged data found once the entry at `last_entry` was read is reported along with it
+fn after_entry(err: crate::Error, last_entry: Option<PathBuf>) -> crate::Error {
+    match err {
+        crate::Error::CorruptArchive { format, reason, offset, compressed_offset, entry: None } => {
+            crate::Error::CorruptArchive {
+                format,
+                reason,
+                offset,
+                compressed_offset,
+                entry: last_entry,
+            }
+        },
+        err => err,
+    }
+}
+
+/// Append the FIFO or device node at `path`, without opening it
+fn append_special<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    metadata: &fs::Metadata,
+    format: TarFormat,
+    reproducible: bool,
+    mtime: Option<ArchiveMtime>,
+) -> crate::Result<()> {
+    // The entry type comes from the metadata, but not the device numbers
+    let mut header = file_header(path, format, reproducible, mtime)?;
+    header.set_size(0);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let device = metadata.rdev();
+        header.set_device_major(libc::major(device) as u32)?;
+        header.set_device_minor(libc::minor(device) as u32)?;
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
 
-        println!(
-            "{}[INFO]{} {:?} extracted. ({})",
-            colors::yellow(),
-            colors::reset(),
-            output_folder.join(file.path()?),
-            utils::Bytes::new(file.size())
+    builder.append_data(&mut header, path, io::empty())?;
+    Ok(())
+}
+
+/// What an entry stores about its file beyond its mode, times and owner IDs
+#[derive(Debug, Default)]
+struct Records {
+    acls: Acls,
+    xattrs: Xattrs,
+    /// Names of the owner and group
+    user: Option<String>,
+    group: Option<String>,
+    /// To the nanosecond, the header only has whole seconds
+    mtime: Option<SystemTime>,
+    atime: Option<SystemTime>,
+}
+
+/// The ACLs, extended attributes, owner names and times stored in the PAX records of `entry`, or
+/// the owner names of its header when they aren't
+fn archived_records<R: Read>(entry: &mut tar::Entry<R>) -> crate::Result<Records> {
+    let header = entry.header();
+    let name = |name: Result<Option<&str>, std::str::Utf8Error>| {
+        name.ok().flatten().filter(|name| !name.is_empty()).map(str::to_string)
+    };
+    let mut records = Records {
+        user: name(header.username()),
+        group: name(header.groupname()),
+        ..Records::default()
+    };
+
+    let extensions = match entry.pax_extensions().map_err(header_error)? {
+        Some(extensions) => extensions,
+        None => return Ok(records),
+    };
+    for extension in extensions {
+        let extension = extension.map_err(header_error)?;
+        let value =
+            || extension.value().map(str::to_string).map_err(|_| crate::Error::InvalidUnicode);
+        match extension.key() {
+            Ok(acl::ACCESS_KEY) => records.acls.access = Some(value()?),
+            Ok(acl::DEFAULT_KEY) => records.acls.default = Some(value()?),
+            Ok("uname") => records.user = Some(value()?),
+            Ok("gname") => records.group = Some(value()?),
+            Ok("mtime") => records.mtime = parse_pax_time(&value()?),
+            Ok("atime") => records.atime = parse_pax_time(&value()?),
+            Ok(key) => {
+                if let Some(name) = key.strip_prefix(xattrs::KEY_PREFIX) {
+                    records.xattrs.push((name.to_string(), extension.value_bytes().to_vec()));
+                }
+            },
+            _ => {},
+        }
+    }
+    Ok(records)
+}
+
+/// The PAX records of the file at `path`, with its ACLs, its extended attributes, the names of its
+/// owners and its times, for those that are kept
+fn pax_records(
+    path: &Path,
+    metadata: &fs::Metadata,
+    acls: Option<&AclHandler>,
+    xattrs: Option<&XattrHandler>,
+    owner_names: bool,
+    times: PaxTimes,
+) -> crate::Result<Vec<(String, Vec<u8>)>> {
+    let mut records = vec![];
+    // The modification time is only needed when the header can't hold it, with a fraction of a
+    // second
+    if let (true, Ok(mtime)) = (times.mtime, metadata.modified()) {
+        if let Some(mtime) = pax_time(mtime).filter(|mtime| mtime.contains('.')) {
+            records.push(("mtime".to_string(), mtime.into_bytes()));
+        }
+    }
+    if let (true, Ok(atime)) = (times.atime, metadata.accessed()) {
+        if let Some(atime) = pax_time(atime) {
+            records.push(("atime".to_string(), atime.into_bytes()));
+        }
+    }
+    if let Some(acls) = acls {
+        let file_acls = acls.read(path)?;
+        let acl_records = file_acls.pax_records().into_iter();
+        records.extend(acl_records.map(|(key, value)| (key.to_string(), value.to_vec())));
+    }
+    if let Some(xattrs) = xattrs {
+        let file_xattrs = xattrs.read(path)?.into_iter();
+        records.extend(
+            file_xattrs.map(|(name, value)| (format!("{}{}", xattrs::KEY_PREFIX, name), value)),
         );
+    }
+    #[cfg(unix)]
+    if owner_names {
+        use std::os::unix::fs::MetadataExt;
 
-        files_unpacked.push(file_path);
+        if let Some(user) = ownership::user_name(metadata.uid() as u64) {
+            records.push(("uname".to_string(), user.into_bytes()));
+        }
+        if let Some(group) = ownership::group_name(metadata.gid() as u64) {
+            records.push(("gname".to_string(), group.into_bytes()));
+        }
     }
+    #[cfg(not(unix))]
+    let _ = (metadata, owner_names);
+    Ok(records)
+}
 
-    Ok(files_unpacked)
+/// Which times of the files are stored in PAX records
+#[derive(Debug, Clone, Copy, Default)]
+struct PaxTimes {
+    mtime: bool,
+    atime: bool,
+}
+
+/// `time` as PAX records store it, the seconds since the Unix epoch and their fraction, like
+/// "1700000000.123456789"
+fn pax_time(time: SystemTime) -> Option<String> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+    Some(match since_epoch.subsec_nanos() {
+        0 => since_epoch.as_secs().to_string(),
+        nanos => format!("{}.{:09}", since_epoch.as_secs(), nanos),
+    })
+}
+
+/// The time of a PAX record, `None` for those before the Unix epoch. Digits past the nanoseconds
+/// are left out
+fn parse_pax_time(value: &str) -> Option<SystemTime> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let seconds = seconds.parse().ok()?;
+    if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{:0<9}", &fraction[..fraction.len().min(9)]).parse().ok()?;
+    UNIX_EPOCH.checked_add(Duration::new(seconds, nanos))
+}
+
+/// Give the file at `file_path` the times of its PAX records, to the nanosecond. Filesystems that
+/// keep coarser times round them, those that refuse them get whole seconds instead.
+fn restore_times(
+    file_path: &Path,
+    mtime: Option<SystemTime>,
+    atime: Option<SystemTime>,
+    is_symlink: bool,
+) -> crate::Result<()> {
+    if mtime.is_none() && atime.is_none() {
+        return Ok(());
+    }
+    let metadata = file_path.symlink_metadata()?;
+    let mtime = mtime.map_or_else(
+        || FileTime::from_last_modification_time(&metadata),
+        FileTime::from_system_time,
+    );
+    let atime = atime
+        .map_or_else(|| FileTime::from_last_access_time(&metadata), FileTime::from_system_time);
+
+    let set = |atime, mtime| {
+        if is_symlink {
+            filetime::set_symlink_file_times(file_path, atime, mtime)
+        } else {
+            filetime::set_file_times(file_path, atime, mtime)
+        }
+    };
+    if let Err(err) = set_times_with(set, atime, mtime) {
+        logger::warning(format!("Could not restore the times of {:?} ({}).", file_path, err))
+            .path(file_path)
+            .category("times not restored")
+            .warn()?;
+    }
+    Ok(())
+}
+
+/// Set the times with `set`, falling back to whole seconds when it fails
+fn set_times_with(
+    set: impl Fn(FileTime, FileTime) -> io::Result<()>,
+    atime: FileTime,
+    mtime: FileTime,
+) -> io::Result<()> {
+    let whole_seconds = |time: FileTime| FileTime::from_unix_time(time.unix_seconds(), 0);
+    set(atime, mtime).or_else(|_| set(whole_seconds(atime), whole_seconds(mtime)))
+}
+
+/// Flavor of the archives written, chosen with `--format-tar`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarFormat {
+    /// GNU headers with PAX records, which store times to the nanosecond. GNU headers are kept
+    /// for sparse files and long names, tools without PAX support ignore the records.
+    Pax,
+    /// GNU headers, times in whole seconds
+    Gnu,
+    /// POSIX ustar headers only, for old tools: no sparse files, ACLs, extended attributes, owner
+    /// names or times below the second
+    Ustar,
+}
+
+impl TarFormat {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let format = match flags.arg("format-tar").map(|format| format.to_str()) {
+            None | Some(Some("pax")) => Self::Pax,
+            Some(Some("gnu")) => Self::Gnu,
+            Some(Some("ustar")) => Self::Ustar,
+            Some(_) => return Err(crate::Error::InvalidInput),
+        };
+        if format == Self::Ustar {
+            if flags.is_present("acl") {
+                return Err(crate::Error::ConflictingFlags("format-tar", "acl"));
+            }
+            if flags.is_present("preserve") {
+                return Err(crate::Error::ConflictingFlags("format-tar", "preserve"));
+            }
+        }
+        Ok(format)
+    }
+
+    fn header(self) -> tar::Header {
+        match self {
+            Self::Ustar => tar::Header::new_ustar(),
+            Self::Pax | Self::Gnu => tar::Header::new_gnu(),
+        }
+    }
+}
+
+/// Header with the metadata of `path` and the modification time of `mtime` when there is one. With
+/// `--reproducible` it has no owners and a fixed mode.
+fn file_header(
+    path: &Path,
+    format: TarFormat,
+    reproducible: bool,
+    mtime: Option<ArchiveMtime>,
+) -> crate::Result<tar::Header> {
+    let metadata = fs::metadata(path)?;
+    let mut header = format.header();
+    if reproducible {
+        header.set_metadata_in_mode(&metadata, tar::HeaderMode::Deterministic);
+    } else {
+        header.set_metadata(&metadata);
+    }
+    if let Some(mtime) = mtime {
+        header.set_mtime(mtime.of(&metadata));
+    }
+    Ok(header)
 }
 
-pub fn build_archive_from_paths<W>(input_filenames: &[PathBuf], writer: W) -> crate::Result<W>
+/// Directories are walked up to `max_depth` levels deep, 1 only includes their direct children
+pub fn build_archive_from_paths<W>(
+    input_filenames: &[PathBuf],
+    max_depth: usize,
+    writer: W,
+    stats: &mut CompressionStats,
+    index: &TarIndex,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<W>
 where
     W: Write,
 {
     let mut builder = tar::Builder::new(writer);
+    let format = TarFormat::from_flags(flags)?;
+    // Holes of sparse files are found with SEEK_HOLE/SEEK_DATA and stored as GNU sparse entries,
+    // filesystems without hole support are read as regular files. Left out with --reproducible,
+    // as whether holes are found depends on the filesystem
+    let reproducible = flags.is_present("reproducible");
+    let mtime = utils::archive_mtime(flags)?;
+    builder.sparse(format != TarFormat::Ustar && !reproducible && !flags.is_present("no-sparse"));
+    let dereference_hardlinks = flags.is_present("dereference-hardlinks");
+    let special_files = SpecialFilePolicy::from_flags(flags)?;
+    let acls = flags.is_present("acl").then(AclHandler::default);
+    let preserve = Preserve::from_flags(flags)?;
+    let xattrs = preserve.xattrs.then(XattrHandler::default);
+    // With --reproducible, files have no owners
+    let owner_names = preserve.ownership && !reproducible;
+    // Times given with --mtime are in whole seconds, and reproducible archives have no access
+    // times that change as the files are read
+    let pax_times = match format {
+        TarFormat::Pax => {
+            PaxTimes { mtime: mtime.is_none(), atime: preserve.atime && !reproducible }
+        },
+        _ => PaxTimes::default(),
+    };
+    // Path each file with multiple hard links was first archived at, by (device, inode)
+    let mut archived_inodes = HashMap::new();
 
-    for filename in input_filenames {
-        let previous_location = utils::cd_into_same_dir_as(filename)?;
+    for filename in utils::archive_order(input_filenames, flags) {
+        let previous_location = utils::cd_into_same_dir_as(&filename)?;
 
         // Safe unwrap, input shall be treated before
         let filename = filename.file_name().unwrap();
 
-        for entry in WalkDir::new(&filename) {
+        for entry in utils::walk_dir(filename.as_ref(), max_depth, flags) {
             let entry = entry?;
             let path = entry.path();
 
-            println!("Compressing '{}'.", utils::to_utf(path));
-            if !path.is_dir() {
-                let mut file = fs::File::open(path)?;
+            archive::start_adding(&entry, events);
+            // Folders have no entries of their own, only their contents are archived
+            if path.is_dir() {
+                archive::finish_adding(path, 0, events);
+                continue;
+            }
+
+            let metadata = fs::metadata(path)?;
+            if let Some(kind) = SpecialFileKind::of(&metadata) {
+                if special_files.keep(path, kind, kind.tar_limitation())? {
+                    index.start(path);
+                    append_special(&mut builder, path, &metadata, format, reproducible, mtime)?;
+                    archive::finish_adding(path, 0, events);
+                }
+                continue;
+            }
+
+            if !dereference_hardlinks {
+                if let Some(target) = archived_hardlink_target(path, &mut archived_inodes)? {
+                    let mut header = file_header(path, format, reproducible, mtime)?;
+                    header.set_entry_type(tar::EntryType::Link);
+                    header.set_size(0);
+                    header.set_link_name(&target)?;
+                    index.start(path);
+                    builder.append_data(&mut header, path, io::empty())?;
+                    archive::finish_adding(path, 0, events);
+                    continue;
+                }
+            }
+
+            // All in one set of records, only the last set before an entry applies to it
+            let records = pax_records(
+                path,
+                &metadata,
+                acls.as_ref(),
+                xattrs.as_ref(),
+                owner_names,
+                pax_times,
+            )?;
+            index.start(path);
+            if !records.is_empty() {
+                let records = records.iter().map(|(key, value)| (key.as_str(), value.as_slice()));
+                builder.append_pax_extensions(records)?;
+            }
+
+            let mut file = fs::File::open(path)?;
+            stats.start(path, metadata.len());
+            // `append_file` always writes GNU headers, with the metadata of the file. It is the one
+            // that finds holes, so files are stored whole with --mtime
+            if reproducible || mtime.is_some() || format == TarFormat::Ustar {
+                let mut header = file_header(path, format, reproducible, mtime)?;
+                builder.append_data(&mut header, path, &mut file)?;
+            } else {
                 builder.append_file(path, &mut file)?;
             }
+            stats.finish(builder.get_mut())?;
+            archive::finish_adding(path, metadata.len(), events);
         }
         env::set_current_dir(previous_location)?;
     }
 
     Ok(builder.into_inner()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pax_time_parsing() {
+        let time = |secs, nanos| Some(UNIX_EPOCH + Duration::new(secs, nanos));
+        assert_eq!(parse_pax_time("1700000000"), time(1_700_000_000, 0));
+        assert_eq!(parse_pax_time("1700000000.5"), time(1_700_000_000, 500_000_000));
+        assert_eq!(parse_pax_time("1700000000.123456789"), time(1_700_000_000, 123_456_789));
+        assert_eq!(parse_pax_time("1700000000.1234567891"), time(1_700_000_000, 123_456_789));
+        assert_eq!(parse_pax_time("-1.5"), None);
+        assert_eq!(parse_pax_time("1.5e3"), None);
+        assert_eq!(parse_pax_time(""), None);
+
+        let stored = UNIX_EPOCH + Duration::new(1_700_000_000, 42);
+        assert_eq!(pax_time(stored).as_deref(), Some("1700000000.000000042"));
+        assert_eq!(parse_pax_time(&pax_time(stored).unwrap()), Some(stored));
+    }
+
+    #[test]
+    fn test_whole_seconds_fallback() {
+        use std::cell::RefCell;
+
+        // A filesystem refusing times with a fraction of a second
+        let set_times = RefCell::new(vec![]);
+        let set = |atime: FileTime, mtime: FileTime| {
+            if atime.nanoseconds() != 0 || mtime.nanoseconds() != 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "no fractions"));
+            }
+            set_times.borrow_mut().push((atime, mtime));
+            Ok(())
+        };
+        set_times_with(set, FileTime::from_unix_time(10, 5), FileTime::from_unix_time(20, 7))
+            .unwrap();
+        assert_eq!(
+            set_times.into_inner(),
+            [(FileTime::from_unix_time(10, 0), FileTime::from_unix_time(20, 0))]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! `--transform REGEX:REPLACEMENT`, renaming the entries of archives as they are extracted and
+//! listed, like `--transform 's/REGEX/REPLACEMENT/g'` of GNU tar.
+//!
+//! Every match of REGEX is replaced, and REPLACEMENT refers to groups with `$1` or `${name}`.
+//! Repeated, the transforms are applied in the order they were given.
+//!
+//! `--flat` renames entries too, after the transforms: files are extracted without the folders
+//! they are in, and folders aren't extracted at all. Neither are symlinks, whose targets no longer
+//! lead where they did.
+
+use std::{
+    borrow::Cow,
+    path::{Component, Path, PathBuf},
+};
+
+use regex::Regex;
+
+use crate::{oof, utils::logger};
+
+#[derive(Debug)]
+struct Transform {
+    regex: Regex,
+    replacement: String,
+}
+
+/// The transforms of `--transform`, none when it wasn't given
+#[derive(Debug, Default)]
+pub struct Transforms {
+    transforms: Vec<Transform>,
+    flat: bool,
+}
+
+impl Transforms {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let transforms = flags
+            .args("transform")
+            .iter()
+            .map(|expression| parse(&expression.to_string_lossy()))
+            .collect::<crate::Result<_>>()?;
+        Ok(Self { transforms, flat: flags.is_present("flat") })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty() && !self.flat
+    }
+
+    /// The path `entry`, a folder when `is_dir`, is renamed to. `None` when it is skipped: with a
+    /// warning when the transforms leave it empty or lead it out of the output folder, and
+    /// quietly for folders with `--flat`
+    pub fn apply(&self, entry: &Path, is_dir: bool) -> crate::Result<Option<PathBuf>> {
+        if self.flat && is_dir {
+            return Ok(None);
+        }
+        let renamed = self.rename(entry)?;
+        if !self.flat {
+            return Ok(renamed);
+        }
+        Ok(renamed.and_then(|renamed| renamed.file_name().map(PathBuf::from)))
+    }
+
+    /// Whether the symlink `entry` is skipped, with a warning, as it is with `--flat`
+    pub fn skips_symlink(&self, entry: &Path) -> crate::Result<bool> {
+        if self.flat {
+            logger::warning(format!(
+                "Skipping {:?}, symlinks aren't extracted with --flat.",
+                entry
+            ))
+            .entry(entry)
+            .category("symlinks skipped by --flat")
+            .warn()?;
+        }
+        Ok(self.flat)
+    }
+
+    fn rename(&self, entry: &Path) -> crate::Result<Option<PathBuf>> {
+        let name = entry.to_string_lossy();
+        let mut renamed = Cow::Borrowed(name.as_ref());
+        for transform in &self.transforms {
+            if let Cow::Owned(replaced) =
+                transform.regex.replace_all(&renamed, transform.replacement.as_str())
+            {
+                renamed = Cow::Owned(replaced);
+            }
+        }
+
+        // Untouched names keep their bytes, even those that aren't valid UTF-8
+        let renamed = match renamed {
+            Cow::Borrowed(_) => return Ok(Some(entry.to_path_buf())),
+            Cow::Owned(renamed) => PathBuf::from(renamed),
+        };
+
+        let is_enclosed = renamed
+            .components()
+            .all(|part| matches!(part, Component::Normal(_) | Component::CurDir));
+        let is_empty = !renamed.components().any(|part| matches!(part, Component::Normal(_)));
+        let reason = if !is_enclosed {
+            format!("--transform renames it to {:?}, outside of the folder", renamed)
+        } else if is_empty {
+            "--transform renames it to an empty path".to_string()
+        } else {
+            logger::trace(format!("{:?} renamed to {:?}.", entry, renamed)).entry(entry).log();
+            return Ok(Some(renamed));
+        };
+
+        logger::warning(format!("Skipping {:?}, {}.", entry, reason))
+            .entry(entry)
+            .category("entries renamed to invalid paths skipped")
+            .warn()?;
+        Ok(None)
+    }
+}
+
+/// Split "REGEX:REPLACEMENT" at the first ':' that isn't escaped as "\:"
+fn parse(expression: &str) -> crate::Result<Transform> {
+    let invalid = |reason: String| crate::Error::InvalidTransform {
+        expression: expression.to_string(),
+        reason,
+    };
+
+    let mut escaped = false;
+    let separator = expression.char_indices().find_map(|(index, char)| match char {
+        ':' if !escaped => Some(index),
+        '\\' => {
+            escaped = !escaped;
+            None
+        },
+        _ => {
+            escaped = false;
+            None
+        },
+    });
+    let separator = separator.ok_or_else(|| invalid("There is no ':' after REGEX".into()))?;
+
+    // The regex crate takes "\:" as a ':'
+    let regex = Regex::new(&expression[..separator]).map_err(|err| invalid(err.to_string()))?;
+    Ok(Transform { regex, replacement: expression[separator + 1..].to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transforms(expressions: &[&str]) -> crate::Result<Transforms> {
+        let mut flags = oof::Flags::new();
+        let values = expressions.iter().map(|expression| expression.into()).collect();
+        flags.repeated_flags.insert("transform", values);
+        Transforms::from_flags(&flags)
+    }
+
+    fn apply(expressions: &[&str], entry: &str) -> Option<PathBuf> {
+        transforms(expressions).unwrap().apply(Path::new(entry), false).unwrap()
+    }
+
+    #[test]
+    fn test_apply_in_order() {
+        let renamed = apply(&["^src/:lib/", "\\.rs$:.txt"], "src/main.rs");
+        assert_eq!(renamed.unwrap(), Path::new("lib/main.txt"));
+        assert_eq!(apply(&["o:0"], "foo/boo").unwrap(), Path::new("f00/b00"));
+        assert_eq!(apply(&["^(\\w+)-v1:$1"], "ouch-v1/a").unwrap(), Path::new("ouch/a"));
+        assert_eq!(apply(&["a\\:b:c"], "a:b").unwrap(), Path::new("c"));
+        assert_eq!(apply(&[], "a/b").unwrap(), Path::new("a/b"));
+    }
+
+    #[test]
+    fn test_skip_invalid_paths() {
+        assert!(apply(&["^.*$:"], "file").is_none());
+        assert!(apply(&["^dir:.."], "dir/file").is_none());
+        assert!(apply(&["^:/"], "file").is_none());
+        assert_eq!(apply(&["^dir:."], "dir/file").unwrap(), Path::new("./file"));
+    }
+
+    #[test]
+    fn test_flat() {
+        let mut flags = oof::Flags::new();
+        flags.boolean_flags.insert("flat");
+        let flat = Transforms::from_flags(&flags).unwrap();
+        let apply = |entry, is_dir| flat.apply(Path::new(entry), is_dir).unwrap();
+        assert_eq!(apply("firmware/v2/boot.bin", false).unwrap(), Path::new("boot.bin"));
+        assert_eq!(apply("boot.bin", false).unwrap(), Path::new("boot.bin"));
+        assert_eq!(apply("firmware/v2", true), None);
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        let is_invalid = |expression| {
+            matches!(transforms(&[expression]), Err(crate::Error::InvalidTransform { .. }))
+        };
+        assert!(is_invalid("no separator"));
+        assert!(is_invalid("a\\:b"));
+        assert!(is_invalid("(unclosed:x"));
+        assert!(!is_invalid("a:"));
+    }
+}
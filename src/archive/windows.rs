@@ -0,0 +1,183 @@
+//! Extracting on Windows: names it can't create, like "CON" or "notes.", are renamed, long paths
+//! get the `\\?\` prefix that lifts the MAX_PATH limit of 260 characters, files that no one may
+//! write to are marked read-only, and symbolic links it refuses to create are skipped.
+//!
+//! Everywhere else, these leave paths and files as they are.
+
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::utils::logger;
+
+/// Device names, reserved by Windows even with an extension, like "aux.txt"
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Windows refuses to create symbolic links without Developer Mode or administrator rights
+const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+/// `name` changed so that Windows can create it, `None` if it already can.
+///
+/// Reserved names get a '_' after their stem, "CON" becomes "CON_" and "aux.txt" "aux_.txt", and
+/// names ending in dots or spaces, which Windows drops, get a '_' at the end.
+fn windows_name(name: &str) -> Option<String> {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    let mut renamed = name.to_string();
+    if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        renamed.insert(stem.len(), '_');
+    }
+    if renamed.ends_with('.') || renamed.ends_with(' ') {
+        renamed.push('_');
+    }
+    (renamed != name).then_some(renamed)
+}
+
+/// On Windows, `relative`, the path of an entry inside of the output folder, with the names that
+/// Windows can't create renamed, with a warning.
+///
+/// Its separators also become '\', which paths with the `\\?\` prefix of [`long_path`] need.
+pub fn adapt_path(relative: &Path) -> crate::Result<Cow<'_, Path>> {
+    if !cfg!(windows) {
+        return Ok(Cow::Borrowed(relative));
+    }
+
+    let mut adapted = PathBuf::new();
+    let mut is_renamed = false;
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => match name.to_str().and_then(windows_name) {
+                Some(renamed) => {
+                    adapted.push(renamed);
+                    is_renamed = true;
+                },
+                None => adapted.push(name),
+            },
+            other => adapted.push(other),
+        }
+    }
+
+    if is_renamed {
+        logger::warning(format!(
+            "Renaming {:?} to {:?}, Windows can't create its name as it is.",
+            relative, adapted
+        ))
+        .entry(relative)
+        .category("entries renamed for Windows")
+        .warn()?;
+    }
+    Ok(Cow::Owned(adapted))
+}
+
+/// On Windows, the absolute `path` with the `\\?\` prefix, so that the paths of entries joined to
+/// it can be longer than 260 characters
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(path);
+    }
+
+    let absolute = match std::path::absolute(path) {
+        Ok(absolute) => absolute.to_string_lossy().into_owned(),
+        Err(_) => return Cow::Borrowed(path),
+    };
+    let prefixed = if absolute.starts_with(r"\\?\") {
+        absolute
+    } else if let Some(share) = absolute.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", absolute)
+    };
+    Cow::Owned(PathBuf::from(prefixed))
+}
+
+/// On Windows, which only has a read-only attribute, set it on the file at `path` when `mode`
+/// lets no one write to it
+pub fn set_readonly(path: &Path, mode: u32) -> crate::Result<()> {
+    if cfg!(windows) && mode & 0o222 == 0 {
+        let mut permissions = fs::metadata(path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+/// The symbolic links of an archive that Windows refused to create, warned about once
+#[derive(Debug, Default)]
+pub struct SkippedSymlinks(usize);
+
+impl SkippedSymlinks {
+    /// Create the symbolic link `link` to `target`, `false` when it was skipped
+    pub fn create(&mut self, target: &Path, link: &Path) -> crate::Result<bool> {
+        match symlink(target, link) {
+            Err(err) if err.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) && cfg!(windows) => {
+                self.0 += 1;
+                Ok(false)
+            },
+            result => {
+                result?;
+                Ok(true)
+            },
+        }
+    }
+
+    /// Warn about the links that were skipped, if any
+    pub fn warn(self) -> crate::Result<()> {
+        if self.0 == 0 {
+            return Ok(());
+        }
+        logger::warning(format!(
+            "Skipped {} symbolic link(s), Windows only creates them in Developer Mode or as an \
+             administrator.",
+            self.0
+        ))
+        .category("symbolic links skipped")
+        .warn()
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+
+    // Archived targets are separated by '/'
+    let target: PathBuf = target.components().collect();
+    let is_dir = link.parent().map_or(false, |parent| parent.join(&target).is_dir());
+    if is_dir {
+        symlink_dir(target, link)
+    } else {
+        symlink_file(target, link)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symbolic links aren't supported"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_name() {
+        assert_eq!(windows_name("CON").unwrap(), "CON_");
+        assert_eq!(windows_name("aux.txt").unwrap(), "aux_.txt");
+        assert_eq!(windows_name("nul .tar.gz").unwrap(), "nul_ .tar.gz");
+        assert_eq!(windows_name("Com1").unwrap(), "Com1_");
+        assert_eq!(windows_name("notes.").unwrap(), "notes._");
+        assert_eq!(windows_name("prn ").unwrap(), "prn_ _");
+        assert_eq!(windows_name("console"), None);
+        assert_eq!(windows_name("COM0"), None);
+        assert_eq!(windows_name(".gitignore"), None);
+        assert_eq!(windows_name("file.txt"), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Extended attributes kept with `--preserve xattrs`, stored in .tar as the `SCHILY.xattr.NAME`
+//! PAX records that GNU tar and star use.
+//!
+//! They are read and written on Linux. The `system.posix_acl_access` and
+//! `system.posix_acl_default` attributes, that hold ACLs, are left to `--acl`.
+
+use std::{cell::Cell, io, path::Path};
+
+use crate::utils::{logger, to_utf};
+
+pub const KEY_PREFIX: &str = "SCHILY.xattr.";
+
+/// Names of the attributes that hold ACLs, see `acl`
+const ACL_PREFIX: &str = "system.posix_acl_";
+
+/// The extended attributes of a file, as (name, value), sorted by name
+pub type Xattrs = Vec<(String, Vec<u8>)>;
+
+/// Reads and writes extended attributes, warning once when the system or the filesystem can't, or
+/// when writing some of them takes privileges, like those named "trusted." or "security."
+#[derive(Debug, Default)]
+pub struct XattrHandler {
+    warned: Cell<bool>,
+}
+
+impl XattrHandler {
+    /// The extended attributes of the file at `path`, empty when they can't be read
+    pub fn read(&self, path: &Path) -> crate::Result<Xattrs> {
+        let read = || -> io::Result<Xattrs> {
+            let mut xattrs = vec![];
+            for name in list_xattrs(path)? {
+                if name.starts_with(ACL_PREFIX) {
+                    continue;
+                }
+                // Unless it was removed in between
+                if let Some(value) = get_xattr(path, &name)? {
+                    xattrs.push((name, value));
+                }
+            }
+            xattrs.sort();
+            Ok(xattrs)
+        };
+        match read() {
+            Ok(xattrs) => Ok(xattrs),
+            Err(err) if is_unsupported(&err) => {
+                self.warn(path, &err, "stored", "extended attributes not stored")?;
+                Ok(Xattrs::new())
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Give the file at `path` the extended attributes that were archived with it
+    pub fn apply(&self, path: &Path, xattrs: &Xattrs) -> crate::Result<()> {
+        for (name, value) in xattrs {
+            match set_xattr(path, name, value) {
+                Ok(()) => {},
+                Err(err)
+                    if is_unsupported(&err) || err.kind() == io::ErrorKind::PermissionDenied =>
+                {
+                    self.warn(path, &err, "restored", "extended attributes not restored")?;
+                },
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn warn(
+        &self,
+        path: &Path,
+        err: &io::Error,
+        action: &str,
+        category: &'static str,
+    ) -> crate::Result<()> {
+        if self.warned.replace(true) {
+            return Ok(());
+        }
+        logger::warning(format!(
+            "Could not keep the extended attributes of '{}' ({}), those that can't be {} are \
+             left out.",
+            to_utf(path),
+            err,
+            action
+        ))
+        .path(path)
+        .category(category)
+        .warn()
+    }
+}
+
+pub(super) fn is_unsupported(err: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    if err.raw_os_error() == Some(libc::EOPNOTSUPP) {
+        return true;
+    }
+    err.kind() == io::ErrorKind::Unsupported
+}
+
+/// The names of the extended attributes of the file at `path`, leaving out those that aren't
+/// valid UTF-8
+#[cfg(target_os = "linux")]
+fn list_xattrs(path: &Path) -> io::Result<Vec<String>> {
+    let names = xattr::list_deref(path)?;
+    Ok(names.filter_map(|name| name.into_string().ok()).collect())
+}
+
+/// The extended attribute `name` of the file at `path`, `None` if it has none
+#[cfg(target_os = "linux")]
+pub(super) fn get_xattr(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    xattr::get_deref(path, name)
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn set_xattr(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+    xattr::set_deref(path, name, value)
+}
+
+#[cfg(not(target_os = "linux"))]
+const UNSUPPORTED: &str = "extended attributes are only supported on Linux";
+
+#[cfg(not(target_os = "linux"))]
+fn list_xattrs(_path: &Path) -> io::Result<Vec<String>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, UNSUPPORTED))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn get_xattr(_path: &Path, _name: &str) -> io::Result<Option<Vec<u8>>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, UNSUPPORTED))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, UNSUPPORTED))
+}
@@ -1,80 +1,645 @@
 use std::{
+    convert::TryFrom,
     env, fs,
-    io::{self, prelude::*},
-    path::{Path, PathBuf},
+    io::{self, prelude::*, SeekFrom},
+    path::{Component, Path, PathBuf},
+    str,
 };
 
-use walkdir::WalkDir;
-use zip::{self, read::ZipFile, ZipArchive};
+use encoding_rs::Encoding;
+use zip::{self, read::ZipFile, result::ZipError, write::FileOptions, ZipArchive};
 
 use crate::{
+    archive::{
+        self,
+        collisions::CaseCollisions,
+        directories::DeferredDirectories,
+        failures::EntryFailures,
+        permissions::Permissions,
+        special::{SpecialFileKind, SpecialFilePolicy},
+        transform::Transforms,
+        windows, ArchiveInfo, FileInArchive,
+    },
+    events::{Event, EventSink},
+    extension::CompressionFormat,
     oof,
-    utils::{self, colors},
+    progress::{ProgressReporter, ProgressUnit},
+    stats::CompressionStats,
+    utils::{
+        self,
+        io::{EntryLimit, OutputLimit},
+        logger,
+        sparse::{self, SparseWriter},
+        to_utf,
+    },
 };
 
+/// Header ID of the Info-ZIP Unicode Path extra field, which stores the name in UTF-8 next to
+/// the one in a legacy encoding
+const UNICODE_PATH_ID: u16 = 0x7075;
+
+/// Encoding of the entry names that aren't marked as UTF-8, chosen with `--charset` (or its
+/// other name, `--zip-encoding`)
+#[derive(Debug, Clone, Copy)]
+pub enum Charset {
+    /// The zip specification's default
+    Cp437,
+    /// UTF-8 when the name is valid UTF-8, CP437 otherwise
+    Auto,
+    Other(&'static Encoding),
+}
+
+impl Charset {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let label = match (flags.arg("charset"), flags.arg("zip-encoding")) {
+            (Some(_), Some(_)) => {
+                return Err(crate::Error::ConflictingFlags("charset", "zip-encoding"))
+            },
+            (Some(label), None) | (None, Some(label)) => {
+                label.to_str().ok_or(crate::Error::InvalidUnicode)?
+            },
+            (None, None) => return Ok(Self::Cp437),
+        };
+
+        match label.to_ascii_lowercase().as_str() {
+            "cp437" | "ibm437" | "437" => Ok(Self::Cp437),
+            "auto" => Ok(Self::Auto),
+            // Labels like "shift_jis", "gbk" or "windows-1252"
+            _ => Encoding::for_label(label.as_bytes())
+                .map(Self::Other)
+                .ok_or_else(|| crate::Error::UnknownCharset(label.to_string())),
+        }
+    }
+}
+
+/// Name of the entry, decoded from `charset` unless the archive marked it as UTF-8 or stored it
+/// in UTF-8 in a Unicode Path extra field
+pub fn decode_name(file: &ZipFile, charset: Charset) -> String {
+    let raw_name = file.name_raw();
+
+    // The zip crate decodes names as UTF-8 when they are marked as such, and as CP437 otherwise,
+    // ASCII names look the same either way
+    let is_utf8 = str::from_utf8(raw_name) == Ok(file.name());
+    if is_utf8 && !raw_name.is_ascii() {
+        return file.name().to_string();
+    }
+    if let Some(name) = unicode_path(file.extra_data(), raw_name) {
+        return name;
+    }
+    if raw_name.is_ascii() {
+        return file.name().to_string();
+    }
+
+    match charset {
+        Charset::Cp437 => file.name().to_string(),
+        Charset::Auto => match str::from_utf8(raw_name) {
+            Ok(name) => name.to_string(),
+            Err(_) => file.name().to_string(),
+        },
+        Charset::Other(encoding) => {
+            match encoding.decode_without_bom_handling_and_without_replacement(raw_name) {
+                Some(name) if !name.contains('\0') => name.into_owned(),
+                _ => percent_escape(raw_name),
+            }
+        },
+    }
+}
+
+/// The name in the Unicode Path extra field of `extra`, if it was written for `raw_name`. The
+/// field stores the CRC-32 of the name it goes with, tools that renamed the entry without
+/// updating it leave a stale one behind.
+fn unicode_path(extra: &[u8], raw_name: &[u8]) -> Option<String> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let id = u16::from_le_bytes([rest[0], rest[1]]);
+        let size = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let data = rest.get(4..4 + size)?;
+        rest = &rest[4 + size..];
+
+        // Version 1 is the only one there is
+        if id != UNICODE_PATH_ID || data.len() < 5 || data[0] != 1 {
+            continue;
+        }
+        let mut crc = flate2::Crc::new();
+        crc.update(raw_name);
+        if data[1..5] == crc.sum().to_le_bytes() {
+            return String::from_utf8(data[5..].to_vec()).ok();
+        }
+    }
+    None
+}
+
+/// Escape the bytes that aren't printable ASCII, like "caf%E9.txt", for names that can't be
+/// decoded
+fn percent_escape(raw_name: &[u8]) -> String {
+    raw_name
+        .iter()
+        .map(|&byte| match byte {
+            b'%' => "%25".to_string(),
+            0x21..=0x7e | b' ' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Like `ZipFile::enclosed_name`, ignores names that could be used to write outside of the
+/// output folder
+fn enclosed_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    let is_enclosed = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if is_enclosed {
+        Some(path.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// Signature of the end of central directory record, the last record of an archive
+const END_RECORD_SIGNATURE: u32 = 0x0605_4b50;
+/// Size of the end of central directory record, without its comment
+const END_RECORD_SIZE: usize = 22;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+/// Size of a central directory file header, without its name, extra field and comment
+const CENTRAL_HEADER_SIZE: usize = 46;
+
+/// A split .zip archive (APPNOTE.TXT section 8.5), "archive.z01", "archive.z02", ... and
+/// "archive.zip", read as one archive.
+///
+/// The offsets of split archives are relative to the start of the volume they point into, which
+/// the zip crate doesn't support. The volumes are read one after the other, followed by a copy of
+/// the central directory with offsets from the start of the first volume, which the zip crate
+/// finds instead of the original one. Archives that aren't split are read as they are.
+pub struct SplitZipReader {
+    volumes: Vec<Volume>,
+    /// The rewritten central directory and end of central directory record, after the volumes
+    tail: Vec<u8>,
+    position: u64,
+    /// The volume whose file is at `position`, none after seeking
+    current: Option<usize>,
+}
+
+struct Volume {
+    file: fs::File,
+    /// Position of its first byte, in all of the volumes
+    start: u64,
+    len: u64,
+}
+
+impl SplitZipReader {
+    /// Open the archive at `path` along with the volumes before it, if it is the last volume of a
+    /// split archive
+    pub fn open(path: &Path) -> crate::Result<Self> {
+        let file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut reader = Self {
+            volumes: vec![Volume { file, start: 0, len }],
+            tail: vec![],
+            position: 0,
+            current: None,
+        };
+
+        // Not an archive, which the zip crate reports
+        let end_record = match find_end_record(&mut reader.volumes[0].file, len)? {
+            Some(end_record) => end_record,
+            None => return Ok(reader),
+        };
+        let last_volume = read_u16(&end_record, 4);
+        if last_volume == 0 {
+            return Ok(reader);
+        }
+
+        let paths: Vec<PathBuf> =
+            (1..=last_volume).map(|number| volume_path(path, number)).collect();
+        let missing: Vec<PathBuf> = paths.iter().filter(|path| !path.is_file()).cloned().collect();
+        if !missing.is_empty() {
+            return Err(crate::Error::MissingZipVolumes { archive: path.to_path_buf(), missing });
+        }
+        logger::debug(format!("'{}' is split in {} volumes.", to_utf(path), paths.len() + 1))
+            .path(path)
+            .log();
+
+        let mut volumes = vec![];
+        let mut start = 0;
+        for path in &paths {
+            let file = fs::File::open(path)?;
+            let len = file.metadata()?.len();
+            volumes.push(Volume { file, start, len });
+            start += len;
+        }
+        // Safe unwrap: the volume of `path` was added first
+        let mut last = reader.volumes.pop().unwrap();
+        last.start = start;
+        volumes.push(last);
+        reader.volumes = volumes;
+
+        reader.tail = reader.joined_central_directory(&end_record)?;
+        Ok(reader)
+    }
+
+    /// The central directory, and an end record after it, with the offsets into the volumes
+    /// turned into offsets into all of them and the volume numbers set to the first one
+    fn joined_central_directory(&mut self, end_record: &[u8]) -> crate::Result<Vec<u8>> {
+        let directory_volume = read_u16(end_record, 6) as usize;
+        let entries = read_u16(end_record, 10);
+        let directory_size = read_u32(end_record, 12);
+        let directory_offset = read_u32(end_record, 16);
+        if entries == u16::MAX || directory_size == u32::MAX || directory_offset == u32::MAX {
+            return Err(crate::Error::UnsupportedZipArchive(
+                "Split archives in the ZIP64 format are not supported",
+            ));
+        }
+
+        let directory_start = match self.volumes.get(directory_volume) {
+            Some(volume) => volume.start + directory_offset as u64,
+            None => return Err(corrupt("the central directory is in a volume that doesn't exist")),
+        };
+        let mut directory = vec![0; directory_size as usize];
+        self.seek(SeekFrom::Start(directory_start))?;
+        self.read_exact(&mut directory)?;
+
+        // Offsets past 4 GiB take ZIP64 records
+        let too_large =
+            |_| crate::Error::UnsupportedZipArchive("Split archives over 4 GiB are not supported");
+        let mut position = 0;
+        for _ in 0..entries {
+            let header = match directory.get_mut(position..position + CENTRAL_HEADER_SIZE) {
+                Some(header) if read_u32(header, 0) == CENTRAL_HEADER_SIGNATURE => header,
+                _ => return Err(corrupt("invalid central directory")),
+            };
+            let volume = read_u16(header, 34) as usize;
+            let offset = read_u32(header, 42);
+            let joined_offset = self
+                .volumes
+                .get(volume)
+                .map(|volume| volume.start + offset as u64)
+                .ok_or_else(|| corrupt("an entry is in a volume that doesn't exist"))?;
+            let joined_offset = u32::try_from(joined_offset).map_err(too_large)?;
+            header[34..36].copy_from_slice(&0_u16.to_le_bytes());
+            header[42..46].copy_from_slice(&joined_offset.to_le_bytes());
+
+            // Followed by the name, the extra field and the comment, of these lengths
+            let variable_size: usize =
+                [28, 30, 32].iter().map(|&at| read_u16(header, at) as usize).sum();
+            position += CENTRAL_HEADER_SIZE + variable_size;
+        }
+
+        let tail_start = u32::try_from(self.volumes_len()).map_err(too_large)?;
+        let mut tail = directory;
+        tail.extend_from_slice(&END_RECORD_SIGNATURE.to_le_bytes());
+        // This volume, the one with the central directory, and the entries in each
+        for value in [0, 0, entries, entries] {
+            tail.extend_from_slice(&value.to_le_bytes());
+        }
+        tail.extend_from_slice(&directory_size.to_le_bytes());
+        tail.extend_from_slice(&tail_start.to_le_bytes());
+        // The comment, and its length before it
+        tail.extend_from_slice(&end_record[20..]);
+        Ok(tail)
+    }
+
+    fn volumes_len(&self) -> u64 {
+        self.volumes.last().map_or(0, |volume| volume.start + volume.len)
+    }
+}
+
+impl Read for SplitZipReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.position;
+        let index = self.volumes.iter().position(|volume| position < volume.start + volume.len);
+        let read = match index {
+            Some(index) => {
+                let volume = &mut self.volumes[index];
+                if self.current != Some(index) {
+                    volume.file.seek(SeekFrom::Start(position - volume.start))?;
+                    self.current = Some(index);
+                }
+                // Reads stop at the end of the volume, the next one continues from the next one
+                let left = (volume.start + volume.len - position).min(buf.len() as u64) as usize;
+                volume.file.read(&mut buf[..left])?
+            },
+            None => {
+                let start = (position - self.volumes_len()).min(self.tail.len() as u64) as usize;
+                (&self.tail[start..]).read(buf)?
+            },
+        };
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SplitZipReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let len = self.volumes_len() + self.tail.len() as u64;
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        let position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position")
+        })?;
+        if position != self.position {
+            self.position = position;
+            self.current = None;
+        }
+        Ok(position)
+    }
+}
+
+/// The path of volume `number` of the split archive whose last volume is at `path`, like
+/// "archive.z01" for the first volume of "archive.zip"
+fn volume_path(path: &Path, number: u16) -> PathBuf {
+    let is_upper = path.extension().is_some_and(|extension| extension == "ZIP");
+    let extension = format!("{}{:02}", if is_upper { 'Z' } else { 'z' }, number);
+    path.with_extension(extension)
+}
+
+/// The other volumes of the split archive at `path`, those next to it, for the sandbox to let
+/// them be read
+pub fn split_volumes(path: &Path) -> Vec<PathBuf> {
+    let is_zip = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    if !is_zip {
+        return vec![];
+    }
+    (1..=u16::MAX)
+        .map(|number| volume_path(path, number))
+        .take_while(|path| path.is_file())
+        .collect()
+}
+
+/// The end of central directory record of the archive `file` of `len` bytes, with its comment
+fn find_end_record(file: &mut fs::File, len: u64) -> io::Result<Option<Vec<u8>>> {
+    // The comment is at most u16::MAX bytes long
+    let search_len = len.min((END_RECORD_SIZE + u16::MAX as usize) as u64);
+    let mut end = vec![0; search_len as usize];
+    file.seek(SeekFrom::Start(len - search_len))?;
+    file.read_exact(&mut end)?;
+
+    let signature = END_RECORD_SIGNATURE.to_le_bytes();
+    let start = (0..end.len().saturating_sub(END_RECORD_SIZE - 1)).rev().find(|&start| {
+        end[start..].starts_with(&signature)
+            && END_RECORD_SIZE + read_u16(&end[start..], 20) as usize <= end.len() - start
+    });
+    Ok(start.map(|start| {
+        // Without what some tools add after the comment
+        let mut end_record = end.split_off(start);
+        end_record.truncate(END_RECORD_SIZE + read_u16(&end_record, 20) as usize);
+        end_record
+    }))
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn corrupt(reason: &str) -> crate::Error {
+    crate::Error::CorruptArchive {
+        format: CompressionFormat::Zip,
+        reason: reason.to_string(),
+        offset: None,
+        compressed_offset: None,
+        entry: None,
+    }
+}
+
 pub fn unpack_archive<R>(
     mut archive: ZipArchive<R>,
     into: &Path,
+    limit: &mut OutputLimit,
+    progress: &dyn ProgressReporter,
+    events: &dyn EventSink,
     flags: &oof::Flags,
 ) -> crate::Result<Vec<PathBuf>>
 where
     R: Read + Seek,
 {
-    let mut unpacked_files = vec![];
-    for idx in 0..archive.len() {
+    if !limit.is_unlimited() {
+        let mut declared_size = 0;
+        for idx in 0..archive.len() {
+            declared_size += archive.by_index(idx)?.size();
+        }
+        utils::io::warn_if_low_on_space(declared_size, into);
+    }
+
+    let charset = Charset::from_flags(flags)?;
+    let sparse = sparse::from_flags(flags)?;
+    let permissions = Permissions::from_flags(flags)?;
+    let transforms = Transforms::from_flags(flags)?;
+    let mut collisions = CaseCollisions::new(into, flags)?;
+    let mut directories = DeferredDirectories::default();
+
+    // The central directory gives the number of entries upfront
+    progress.set_total(archive.len() as u64, ProgressUnit::Files);
+
+    let mut failures = EntryFailures::from_flags(flags);
+    // Extracts the entry at `idx`, the path of its file or `None` when it was skipped
+    let mut unpack_entry = |archive: &mut ZipArchive<R>, idx: usize| -> crate::Result<_> {
         let mut file = archive.by_index(idx)?;
-        let file_path = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
+        limit.add_entry()?;
+        progress.advance(1);
+        let entry = PathBuf::from(decode_name(&file, charset));
+        let size = (!file.is_dir()).then(|| file.size());
+        events.event(Event::EntryStarted { entry: entry.clone(), size });
+        // Symbolic links are stored as files holding the target
+        let is_symlink = file.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+        if is_symlink && transforms.skips_symlink(&entry)? {
+            return Ok(None);
+        }
+        let name = match transforms.apply(&entry, file.is_dir())? {
+            Some(renamed) => renamed.to_string_lossy().into_owned(),
+            None => return Ok(None),
         };
+        let file_path = enclosed_path(&name)
+            .ok_or_else(|| crate::Error::PathTraversal { entry: to_utf(into.join(&name)) })?;
+        logger::trace(format!(
+            "{:?} is compressed with {:?}, to {:.2}% of its size.",
+            file_path,
+            file.compression(),
+            file.compressed_size() as f64 / file.size().max(1) as f64 * 100.0
+        ))
+        .entry(&file_path)
+        .log();
 
-        let file_path = into.join(file_path);
+        let mut file_path = into.join(windows::adapt_path(&file_path)?);
+        if !file.is_dir() {
+            file_path = match collisions.claim(&file_path, flags)? {
+                Some(file_path) => file_path,
+                None => return Ok(None),
+            };
+        }
+        utils::validate_entry_path(&file_path, into)?;
         if file_path.exists() && !utils::user_wants_to_overwrite(&file_path, flags)? {
-            continue;
+            return Ok(None);
         }
 
         check_for_comments(&file);
 
-        match (&*file.name()).ends_with('/') {
+        match file.name().ends_with('/') {
             _is_dir @ true => {
-                println!("File {} extracted to \"{}\"", idx, file_path.display());
                 fs::create_dir_all(&file_path)?;
             },
             _is_file @ false => {
                 if let Some(path) = file_path.parent() {
                     if !path.exists() {
-                        fs::create_dir_all(&path)?;
+                        fs::create_dir_all(path)?;
                     }
                 }
-                println!(
-                    "{}[INFO]{} \"{}\" extracted. ({})",
-                    colors::yellow(),
-                    colors::reset(),
-                    file_path.display(),
-                    utils::Bytes::new(file.size())
-                );
-
-                let mut output_file = fs::File::create(&file_path)?;
-                io::copy(&mut file, &mut output_file)?;
+                let mut output_file = SparseWriter::new(fs::File::create(&file_path)?, sparse);
+                utils::io::copy_limited(&mut file, &mut output_file, &file_path, limit)?;
+                output_file.finish()?;
             },
         }
 
-        #[cfg(unix)]
-        __unix_set_permissions(&file_path, &file);
+        let mode = file.unix_mode().map(|mode| permissions.mode(mode));
+        if file.is_dir() {
+            if let Some(mode) = mode {
+                directories.defer(&file_path, mode, None)?;
+            }
+        } else if let Some(mode) = mode {
+            #[cfg(unix)]
+            __unix_set_permissions(&file_path, mode)?;
+            windows::set_readonly(&file_path, mode)?;
+        }
+
+        let file_path = fs::canonicalize(file_path)?;
+        events.event(Event::EntryFinished { entry, path: file_path.clone(), bytes: file.size() });
+        Ok(Some(file_path))
+    };
 
-        let file_path = fs::canonicalize(file_path.clone())?;
-        unpacked_files.push(file_path);
+    let mut unpacked_files = vec![];
+    for idx in 0..archive.len() {
+        // Named without being decompressed, for the entries that fail to be
+        let entry = PathBuf::from(archive.by_index_raw(idx)?.name());
+        let result = unpack_entry(&mut archive, idx);
+        if let Some(file_path) = failures.check(&entry, result)? {
+            unpacked_files.push(file_path);
+        }
     }
 
+    directories.restore()?;
+    failures.finish()?;
     Ok(unpacked_files)
 }
 
-pub fn build_archive_from_paths<W>(input_filenames: &[PathBuf], writer: W) -> crate::Result<W>
+pub fn list_archive<R>(
+    mut archive: ZipArchive<R>,
+    entries: &mut EntryLimit,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<FileInArchive>>
+where
+    R: Read + Seek,
+{
+    let charset = Charset::from_flags(flags)?;
+
+    let mut files = vec![];
+    for idx in 0..archive.len() {
+        let file = archive.by_index(idx)?;
+        entries.add()?;
+        let is_dir = file.name().ends_with('/');
+        let path = decode_name(&file, charset).into();
+        let modified = modified_time(file.last_modified());
+        files.push(FileInArchive { path, is_dir, size: file.size(), modified });
+    }
+
+    Ok(files)
+}
+
+/// `time` in seconds since the Unix epoch. .zip stores times without a time zone, in the one of
+/// the computer that made the archive, taken to be the local one.
+fn modified_time(time: zip::DateTime) -> Option<i64> {
+    let date = chrono::NaiveDate::from_ymd_opt(
+        time.year().into(),
+        time.month().into(),
+        time.day().into(),
+    )?;
+    let time = date.and_hms_opt(time.hour().into(), time.minute().into(), time.second().into())?;
+    time.and_local_timezone(chrono::Local).earliest().map(|time| time.timestamp())
+}
+
+/// What `ouch info` shows about the archive, its version is the newest version of the zip
+/// specification that the programs which added its entries followed
+pub fn archive_info<R>(
+    mut archive: ZipArchive<R>,
+    entries: &mut EntryLimit,
+) -> crate::Result<ArchiveInfo>
+where
+    R: Read + Seek,
+{
+    let comment = String::from_utf8_lossy(archive.comment()).trim().to_string();
+    let mut info =
+        ArchiveInfo { comment: (!comment.is_empty()).then_some(comment), ..ArchiveInfo::default() };
+
+    let mut version = None;
+    for idx in 0..archive.len() {
+        entries.add()?;
+        // Encrypted entries can only be opened with their password, but not their metadata
+        if let Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) =
+            archive.by_index(idx)
+        {
+            info.encrypted = true;
+        }
+        let file = archive.by_index_raw(idx)?;
+        info.entries += 1;
+        info.uncompressed_size += file.size();
+        version = version.max(Some(file.version_made_by()));
+
+        let method = file.compression().to_string();
+        if !info.methods.contains(&method) {
+            info.methods.push(method);
+        }
+    }
+
+    info.version = version.map(|(major, minor)| format!("APPNOTE {}.{}", major, minor));
+    Ok(info)
+}
+
+/// Call `visit` with the path and the contents of every regular file, for `ouch contents-hash`
+pub fn for_each_file<R>(
+    mut archive: ZipArchive<R>,
+    flags: &oof::Flags,
+    mut visit: impl FnMut(PathBuf, &mut dyn Read) -> crate::Result<()>,
+) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    let charset = Charset::from_flags(flags)?;
+
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        // Symbolic links are stored as files holding the target
+        let is_symlink = file.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+        if file.name().ends_with('/') || is_symlink {
+            continue;
+        }
+        let path = decode_name(&file, charset).into();
+        visit(path, &mut file)?;
+    }
+
+    Ok(())
+}
+
+/// Directories are walked up to `max_depth` levels deep, 1 only includes their direct children
+pub fn build_archive_from_paths<W>(
+    input_filenames: &[PathBuf],
+    max_depth: usize,
+    writer: W,
+    stats: &mut CompressionStats,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<W>
 where
     W: Write + Seek,
 {
     let mut writer = zip::ZipWriter::new(writer);
     let options = zip::write::FileOptions::default();
+    let reproducible = flags.is_present("reproducible");
+    let mtime = utils::archive_mtime(flags)?;
 
     // Vec of any filename that failed the UTF-8 check
     let invalid_unicode_filenames: Vec<PathBuf> = input_filenames
@@ -91,26 +656,46 @@ where
         );
     }
 
-    for filename in input_filenames {
-        let previous_location = utils::cd_into_same_dir_as(filename)?;
+    let special_files = SpecialFilePolicy::from_flags(flags)?;
+
+    for filename in utils::archive_order(input_filenames, flags) {
+        let previous_location = utils::cd_into_same_dir_as(&filename)?;
 
         // Safe unwrap, input shall be treated before
         let filename = filename.file_name().unwrap();
 
-        for entry in WalkDir::new(filename) {
+        for entry in utils::walk_dir(filename.as_ref(), max_depth, flags) {
             let entry = entry?;
             let path = &entry.path();
 
-            println!("Compressing '{}'.", utils::to_utf(path));
+            archive::start_adding(&entry, events);
+            let options = match mtime {
+                Some(mtime) => {
+                    let mtime = mtime.of(&fs::metadata(path)?);
+                    options.last_modified_time(reproducible_time(mtime))
+                },
+                None => options,
+            };
             if path.is_dir() {
+                // Directories get their own entries, otherwise empty ones would be lost
+                let options = directory_options(path, options, reproducible)?;
+                writer.add_directory(path.to_str().unwrap().to_owned(), options)?;
+                archive::finish_adding(path, 0, events);
+                continue;
+            }
+            if let Some(kind) = SpecialFileKind::of(&fs::metadata(path)?) {
+                special_files.keep(path, kind, Some("special files can only be stored in .tar"))?;
                 continue;
             }
 
-            writer.start_file(path.to_str().unwrap().to_owned(), options)?;
             // TODO: check if isn't there a function that already does this for us......
             // TODO: better error messages
             let file_bytes = fs::read(entry.path())?;
-            writer.write_all(&*file_bytes)?;
+            stats.start(path, file_bytes.len() as u64);
+            writer.start_file(path.to_str().unwrap().to_owned(), options)?;
+            writer.write_all(&file_bytes)?;
+            stats.finish(&mut writer)?;
+            archive::finish_adding(path, file_bytes.len() as u64, events);
         }
 
         env::set_current_dir(previous_location)?;
@@ -120,24 +705,111 @@ where
     Ok(bytes)
 }
 
+/// Options for the entry of the directory at `path`, keeping its unix permissions, which are the
+/// same for every directory with `--reproducible`
+fn directory_options(
+    path: &Path,
+    options: FileOptions,
+    reproducible: bool,
+) -> crate::Result<FileOptions> {
+    if reproducible {
+        return Ok(options.unix_permissions(0o755));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)?.permissions().mode();
+        Ok(options.unix_permissions(mode))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(options)
+    }
+}
+
+/// The time `seconds` after the Unix epoch as .zip stores it, in UTC to be the same everywhere.
+/// Times before 1980, the earliest one .zip can store, are stored as 1980.
+fn reproducible_time(seconds: u64) -> zip::DateTime {
+    use chrono::{Datelike, Timelike};
+
+    let time = i64::try_from(seconds)
+        .ok()
+        .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0));
+    time.and_then(|time| {
+        zip::DateTime::from_date_and_time(
+            time.year() as u16,
+            time.month() as u8,
+            time.day() as u8,
+            time.hour() as u8,
+            time.minute() as u8,
+            time.second() as u8,
+        )
+        .ok()
+    })
+    .unwrap_or_default()
+}
+
 fn check_for_comments(file: &ZipFile) {
     let comment = file.comment();
     if !comment.is_empty() {
-        println!(
-            "{}[INFO]{} Comment in {}: {}",
-            colors::yellow(),
-            colors::reset(),
-            file.name(),
-            comment
-        );
+        logger::info(format!("Comment in {}: {}", file.name(), comment)).entry(file.name()).log();
     }
 }
 
 #[cfg(unix)]
-fn __unix_set_permissions(file_path: &Path, file: &ZipFile) {
+fn __unix_set_permissions(file_path: &Path, mode: u32) -> crate::Result<()> {
     use std::os::unix::fs::PermissionsExt;
 
-    if let Some(mode) = file.unix_mode() {
-        fs::set_permissions(&file_path, fs::Permissions::from_mode(mode)).unwrap();
+    if let Err(err) = fs::set_permissions(file_path, fs::Permissions::from_mode(mode)) {
+        logger::warning(format!(
+            "Could not set the permissions of '{}' to {:o} ({}).",
+            file_path.display(),
+            mode,
+            err
+        ))
+        .path(file_path)
+        .category("permissions not restored")
+        .warn()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unicode Path extra field for `name`, written for the name whose CRC-32 is `crc`
+    fn unicode_path_field(name: &str, crc: u32) -> Vec<u8> {
+        let mut field = UNICODE_PATH_ID.to_le_bytes().to_vec();
+        field.extend_from_slice(&(5 + name.len() as u16).to_le_bytes());
+        field.push(1);
+        field.extend_from_slice(&crc.to_le_bytes());
+        field.extend_from_slice(name.as_bytes());
+        field
+    }
+
+    #[test]
+    fn test_unicode_path() {
+        let raw_name = b"caf\x82.txt";
+        let mut crc = flate2::Crc::new();
+        crc.update(raw_name);
+
+        // After another extra field, like the extended timestamp one
+        let mut extra = vec![0x55, 0x54, 1, 0, 0];
+        extra.extend(unicode_path_field("café.txt", crc.sum()));
+        assert_eq!(unicode_path(&extra, raw_name).as_deref(), Some("café.txt"));
+
+        // Stale, written for another name
+        let extra = unicode_path_field("café.txt", crc.sum() ^ 1);
+        assert_eq!(unicode_path(&extra, raw_name), None);
+
+        // Truncated
+        let extra = unicode_path_field("café.txt", crc.sum());
+        assert_eq!(unicode_path(&extra[..extra.len() - 1], raw_name), None);
+        assert_eq!(unicode_path(&[], raw_name), None);
     }
 }
@@ -0,0 +1,120 @@
+//! Compression and decompression of streams for async programs, like servers that send archives
+//! to and from object storage, with the `async` feature.
+//!
+//! The formats are the ones of the sync path, given by the extensions of a name, and only the
+//! compression formats around an archive are applied: the .tar of "backup.tar.gz" is what the
+//! reader gives, or what the writer receives, like `ouch convert` passes it through. The codecs
+//! themselves are blocking, so they run in the blocking threads of tokio, reading and writing the
+//! async streams through `SyncIoBridge`.
+
+use std::{
+    cell::RefCell,
+    io::{self, prelude::*},
+    path::Path,
+    rc::Rc,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::io::SyncIoBridge;
+
+use crate::{
+    commands::{chain_reader_decoder, chain_writer_encoder},
+    error::FinalError,
+    extension::{self, CompressionFormat},
+    utils::to_utf,
+    zstandard,
+};
+
+/// Compress what `reader` gives into `writer`, with the formats of `name`, like "notes.txt.gz" or
+/// "backup.tar.zst". `writer` is shut down once everything was written.
+pub async fn compress_async<R, W>(reader: R, writer: W, name: &Path) -> crate::Result<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let codecs = codecs(name)?;
+    let mut reader = SyncIoBridge::new(reader);
+    let writer = SyncIoBridge::new(writer);
+
+    blocking(move || {
+        let writer = Rc::new(RefCell::new(writer));
+        let mut encoder: Box<dyn Write> = Box::new(SharedWriter(writer.clone()));
+        let zstd_options = zstandard::Options::default();
+        for format in codecs.iter().rev() {
+            encoder = chain_writer_encoder(format, encoder, &zstd_options)?;
+        }
+        io::copy(&mut reader, &mut encoder)?;
+        encoder.flush()?;
+        // The encoders write what ends their formats once dropped
+        drop(encoder);
+
+        let mut writer = writer.borrow_mut();
+        writer.flush()?;
+        writer.shutdown()?;
+        Ok(())
+    })
+    .await
+}
+
+/// Decompress what `reader` gives, compressed with the formats of `name`, like "notes.txt.gz" or
+/// "backup.tar.zst", into `writer`. `writer` is shut down once everything was written.
+pub async fn decompress_async<R, W>(reader: R, writer: W, name: &Path) -> crate::Result<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let codecs = codecs(name)?;
+    let reader = SyncIoBridge::new(reader);
+    let mut writer = SyncIoBridge::new(writer);
+
+    blocking(move || {
+        let mut decoder: Box<dyn Read> = Box::new(reader);
+        let zstd_options = zstandard::Options::default();
+        for format in codecs.iter().rev() {
+            decoder = chain_reader_decoder(format, decoder, &zstd_options)?;
+        }
+        io::copy(&mut decoder, &mut writer)?;
+        writer.flush()?;
+        writer.shutdown()?;
+        Ok(())
+    })
+    .await
+}
+
+/// The compression formats of `name`, without the archive they may hold
+fn codecs(name: &Path) -> crate::Result<Vec<CompressionFormat>> {
+    let mut formats = extension::extensions_from_path(name);
+    if formats.first().is_some_and(CompressionFormat::is_archive) {
+        formats.remove(0);
+    }
+    if formats.is_empty() {
+        return Err(FinalError::with_title(format!("Cannot stream '{}'.", to_utf(name)))
+            .detail("Its extensions have no compression format to apply.")
+            .hint("Run 'ouch formats' for the full list of supported formats")
+            .into());
+    }
+    Ok(formats)
+}
+
+/// Run `job` in the blocking threads of tokio
+async fn blocking<F>(job: F) -> crate::Result<()>
+where
+    F: FnOnce() -> crate::Result<()> + Send + 'static,
+{
+    tokio::task::spawn_blocking(job)
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+}
+
+/// Writer to the end of a chain of encoders, which is still reachable once they were dropped
+struct SharedWriter<W>(Rc<RefCell<W>>);
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
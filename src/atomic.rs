@@ -0,0 +1,160 @@
+//! `--atomic` extraction: archives are extracted into a staging folder that only takes the place
+//! of the output folder once everything was extracted, a failure leaves nothing behind.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    events::{Event, EventSink},
+    oof,
+    utils::{self, logger},
+};
+
+/// Run `extract` on a staging folder, then move what it extracted into `destination`.
+///
+/// New destinations are staged next to them, in the same parent folder, and renamed into place.
+/// Existing ones are staged inside of themselves, and the extracted files are moved in one by
+/// one, asking before overwriting anything that was already there.
+pub fn extract(
+    destination: &Path,
+    flags: &oof::Flags,
+    extract: impl FnOnce(&Path) -> crate::Result<Vec<PathBuf>>,
+) -> crate::Result<Vec<PathBuf>> {
+    let staging_folder = create_staging_folder(destination)?;
+
+    let result = extract(&staging_folder)
+        .and_then(|files| move_into_place(&staging_folder, destination, files, flags));
+
+    // After failures, and files that were not moved because the user declined overwriting them.
+    // What is left over is only warned about, not to hide why the extraction failed
+    if staging_folder.exists() {
+        if let Err(err) = fs::remove_dir_all(&staging_folder) {
+            logger::warning(format!(
+                "Could not remove the staging folder '{}': {}",
+                utils::to_utf(&staging_folder),
+                err
+            ))
+            .path(&staging_folder)
+            .log();
+        }
+    }
+    result
+}
+
+/// Events of the entries extracted into `staging_folder`, reported with the paths they are moved
+/// to in `destination`
+pub struct StagedEvents<'a> {
+    pub inner: &'a dyn EventSink,
+    pub staging_folder: &'a Path,
+    pub destination: &'a Path,
+}
+
+impl EventSink for StagedEvents<'_> {
+    fn event(&self, event: Event) {
+        let event = match event {
+            Event::EntryFinished { entry, path, bytes } => {
+                let path = match path.strip_prefix(self.staging_folder) {
+                    Ok(relative) => self.destination.join(relative),
+                    Err(_) => path,
+                };
+                Event::EntryFinished { entry, path, bytes }
+            },
+            event => event,
+        };
+        self.inner.event(event);
+    }
+}
+
+/// A new empty folder for staging, on the same filesystem as `destination` so that it can be
+/// renamed into it
+fn create_staging_folder(destination: &Path) -> crate::Result<PathBuf> {
+    let (parent, name) = if destination.exists() {
+        (destination.to_path_buf(), ".ouch".into())
+    } else {
+        let parent = match destination.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(parent)?;
+        // Safe unwrap: a folder that doesn't exist can't be "." or "/"
+        (parent.to_path_buf(), utils::to_utf(destination.file_name().unwrap()))
+    };
+
+    // Unique enough to not clash with the staging folders of other ouch processes
+    let mut suffix =
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.subsec_nanos());
+    loop {
+        let staging_folder = parent.join(format!("{}.tmp-{:x}{:x}", name, process::id(), suffix));
+        match fs::create_dir(&staging_folder) {
+            Ok(()) => return Ok(staging_folder),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                suffix = suffix.wrapping_add(1)
+            },
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Move the contents of `staging_folder` into `destination`, returning where `files` ended up
+fn move_into_place(
+    staging_folder: &Path,
+    destination: &Path,
+    files: Vec<PathBuf>,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<PathBuf>> {
+    let mut declined = vec![];
+    if destination.exists() {
+        merge_folder(staging_folder, destination, flags, &mut declined)?;
+    } else {
+        fs::rename(staging_folder, destination)?;
+    }
+
+    let files = files
+        .into_iter()
+        .filter_map(|file| {
+            let relative = file.strip_prefix(staging_folder).ok()?;
+            Some(destination.join(relative))
+        })
+        // Files that weren't overwritten are not the extracted ones
+        .filter(|file| !declined.iter().any(|declined| file.starts_with(declined)))
+        .collect();
+    Ok(files)
+}
+
+/// Move the entries of `from` into `to`, merging the folders both of them have
+fn merge_folder(
+    from: &Path,
+    to: &Path,
+    flags: &oof::Flags,
+    declined: &mut Vec<PathBuf>,
+) -> crate::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let existing = match target.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                fs::rename(entry.path(), &target)?;
+                continue;
+            },
+        };
+
+        if entry.file_type()?.is_dir() && existing.is_dir() {
+            merge_folder(&entry.path(), &target, flags, declined)?;
+        } else if utils::user_wants_to_overwrite(&target, flags)? {
+            if existing.is_dir() {
+                fs::remove_dir_all(&target)?;
+            } else {
+                fs::remove_file(&target)?;
+            }
+            fs::rename(entry.path(), &target)?;
+        } else {
+            declined.push(target);
+        }
+    }
+    Ok(())
+}
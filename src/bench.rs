@@ -0,0 +1,213 @@
+//! Internal benchmark mode, reached through the hidden `ouch bench <file>` subcommand.
+//!
+//! Compresses the input with every supported codec and level, measuring wall-clock time and
+//! compression ratio. Everything runs in the same process, so no fork overhead is measured.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::FinalError,
+    extension::CompressionFormat::{self, *},
+    utils::{self, to_utf},
+};
+
+/// Output style of the results table
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BenchOutput {
+    Markdown,
+    Json,
+}
+
+impl BenchOutput {
+    pub fn from_flag(value: &str) -> Option<Self> {
+        match value {
+            "markdown" | "md" => Some(Self::Markdown),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+struct BenchResult {
+    format: CompressionFormat,
+    level: u32,
+    elapsed: Duration,
+    compressed_size: u64,
+}
+
+impl BenchResult {
+    fn ratio(&self, original_size: u64) -> f64 {
+        if self.compressed_size == 0 {
+            return 0.0;
+        }
+        original_size as f64 / self.compressed_size as f64
+    }
+
+    /// Throughput in bytes of input per second
+    fn throughput(&self, original_size: u64) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        original_size as f64 / secs
+    }
+}
+
+/// Writer that discards everything, only keeping track of how many bytes went through it
+#[derive(Default)]
+struct CountingSink {
+    count: u64,
+}
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.count += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Every codec paired with the range of levels it accepts
+fn codecs() -> Vec<(CompressionFormat, std::ops::RangeInclusive<u32>)> {
+    // Snappy and LZ4 have no levels
+    vec![
+        (Gzip, 0..=9),
+        (Bzip, 1..=9),
+        (Lzma, 0..=9),
+        (Snappy, 0..=0),
+        (Zstd, 1..=19),
+        (Lz4, 0..=0),
+        (Lz4Block, 0..=0),
+    ]
+}
+
+fn compress_with(format: &CompressionFormat, level: u32, input: &[u8]) -> io::Result<u64> {
+    let mut sink = CountingSink::default();
+    match format {
+        Gzip => {
+            let level = flate2::Compression::new(level);
+            let mut encoder = flate2::write::GzEncoder::new(&mut sink, level);
+            encoder.write_all(input)?;
+            encoder.finish()?;
+        },
+        Bzip => {
+            let level = bzip2::Compression::new(level);
+            let mut encoder = bzip2::write::BzEncoder::new(&mut sink, level);
+            encoder.write_all(input)?;
+            encoder.finish()?;
+        },
+        Lzma => {
+            let mut encoder = xz2::write::XzEncoder::new(&mut sink, level);
+            encoder.write_all(input)?;
+            encoder.finish()?;
+        },
+        Snappy => {
+            let mut encoder = snap::write::FrameEncoder::new(&mut sink);
+            encoder.write_all(input)?;
+            encoder.flush()?;
+        },
+        Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(&mut sink, level as i32)?;
+            encoder.write_all(input)?;
+            encoder.finish()?;
+        },
+        Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(&mut sink);
+            encoder.write_all(input)?;
+            encoder.finish()?;
+        },
+        Lz4Block => sink.write_all(&lz4_flex::block::compress_prepend_size(input))?,
+        Tar | Zip | Cpio | Iso => unreachable!("archive formats are not benchmarked"),
+    }
+    Ok(sink.count)
+}
+
+pub fn run(input_path: &Path, output: BenchOutput) -> crate::Result<()> {
+    let input = fs::read(input_path)?;
+    let original_size = input.len() as u64;
+
+    if input.is_empty() {
+        return Err(FinalError::with_title(format!("Cannot benchmark '{}'.", to_utf(input_path)))
+            .detail("The file is empty.")
+            .into());
+    }
+
+    let mut results = vec![];
+    for (format, levels) in codecs() {
+        for level in levels {
+            let start = Instant::now();
+            let compressed_size = compress_with(&format, level, &input)?;
+            let elapsed = start.elapsed();
+            results.push(BenchResult { format: format.clone(), level, elapsed, compressed_size });
+        }
+    }
+
+    let mut stdout = vec![];
+    match output {
+        BenchOutput::Markdown => print_markdown(&mut stdout, input_path, original_size, &results)?,
+        BenchOutput::Json => print_json(&mut stdout, input_path, original_size, &results)?,
+    }
+    utils::write_stdout(stdout)
+}
+
+fn print_markdown(
+    out: &mut impl Write,
+    input_path: &Path,
+    original_size: u64,
+    results: &[BenchResult],
+) -> io::Result<()> {
+    writeln!(out, "Benchmark of '{}' ({})", to_utf(input_path), utils::Bytes::new(original_size))?;
+    writeln!(out)?;
+    writeln!(out, "| Format | Level | Time (ms) | Throughput | Compressed size | Ratio |")?;
+    writeln!(out, "|--------|------:|----------:|-----------:|----------------:|------:|")?;
+    for result in results {
+        writeln!(
+            out,
+            "| {} | {} | {:.2} | {}/s | {} | {:.3} |",
+            result.format,
+            result.level,
+            result.elapsed.as_secs_f64() * 1000.0,
+            utils::Bytes::new(result.throughput(original_size) as u64),
+            utils::Bytes::new(result.compressed_size),
+            result.ratio(original_size),
+        )?;
+    }
+    Ok(())
+}
+
+fn print_json(
+    out: &mut impl Write,
+    input_path: &Path,
+    original_size: u64,
+    results: &[BenchResult],
+) -> io::Result<()> {
+    let entries: Vec<String> = results
+        .iter()
+        .map(|result| {
+            format!(
+                "{{\"format\":\"{}\",\"level\":{},\"time_ms\":{:.3},\"compressed_size\":{},\"ratio\":{:.4}}}",
+                result.format.to_string().trim_start_matches('.'),
+                result.level,
+                result.elapsed.as_secs_f64() * 1000.0,
+                result.compressed_size,
+                result.ratio(original_size),
+            )
+        })
+        .collect();
+
+    writeln!(
+        out,
+        "{{\"file\":{:?},\"original_size\":{},\"results\":[{}]}}",
+        to_utf(input_path),
+        original_size,
+        entries.join(",")
+    )
+}
@@ -0,0 +1,86 @@
+//! Ctrl-C handling. The first one cancels the running operation: it stops at the next read or
+//! write, and the partially written output is removed. A second one exits right away.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Exit code of processes stopped by SIGINT, 128 + 2
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Cancel on SIGINT instead of being killed by it
+#[cfg(unix)]
+pub fn install_handler() {
+    extern "C" fn handle_sigint(_signal: libc::c_int) {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            // Safety: _exit is async-signal-safe
+            unsafe { libc::_exit(EXIT_INTERRUPTED) };
+        }
+    }
+
+    // Safety: the handler only touches an atomic and calls async-signal-safe functions
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install_handler() {}
+
+/// Cancel the running operation, like Ctrl-C does
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+fn check() -> io::Result<()> {
+    if is_cancelled() {
+        Err(io::Error::other("cancelled by the user"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reader or writer that fails once the operation was cancelled
+pub struct Cancellable<T> {
+    inner: T,
+}
+
+impl<T> Cancellable<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        check()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<W: Write> Write for Cancellable<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        check()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: Seek> Seek for Cancellable<S> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(position)
+    }
+}
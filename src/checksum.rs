@@ -0,0 +1,176 @@
+//! `--checksum-file ALGORITHM`: a file next to the created archive, like "backup.tar.gz.sha256",
+//! with its digest in the format of `sha256sum` and `shasum`, so that `sha256sum -c` can check it,
+//! or "backup.tar.gz.b3" for `b3sum -c`. `--verify-checksum` checks it before extracting.
+//!
+//! .zip archives are written with seeks, so the digest is taken from the finished archive instead
+//! of while it is being written.
+
+use std::{
+    fs,
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use sha2::{
+    digest::{consts::U32, DynDigest, FixedOutput, FixedOutputReset, Output, OutputSizeUser, Reset},
+    Sha256, Sha512,
+};
+
+use crate::{
+    cancel::Cancellable,
+    oof,
+    utils::{io::HashingWriter, logger, to_utf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Algorithm {
+    const ALL: [Algorithm; 3] = [Algorithm::Sha256, Algorithm::Sha512, Algorithm::Blake3];
+
+    /// The algorithm of `--checksum-file`, if given
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Option<Self>> {
+        let name = match flags.arg("checksum-file") {
+            Some(name) => name.to_string_lossy(),
+            None => return Ok(None),
+        };
+        match Self::ALL.iter().copied().find(|algorithm| algorithm.name() == name.to_lowercase()) {
+            Some(algorithm) => Ok(Some(algorithm)),
+            None => Err(crate::Error::UnknownChecksumAlgorithm(name.into_owned())),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// The extension of the checksum file, the one `b3sum` users expect for BLAKE3
+    fn extension(self) -> &'static str {
+        match self {
+            Algorithm::Blake3 => "b3",
+            algorithm => algorithm.name(),
+        }
+    }
+
+    fn hasher(self) -> Box<dyn DynDigest> {
+        match self {
+            Algorithm::Sha256 => Box::new(Sha256::default()),
+            Algorithm::Sha512 => Box::new(Sha512::default()),
+            Algorithm::Blake3 => Box::new(Blake3::default()),
+        }
+    }
+}
+
+/// `blake3::Hasher` through the traits of the version of `digest` that the SHA-2 hashers
+/// implement, which isn't the one blake3 implements
+#[derive(Clone, Default)]
+struct Blake3(blake3::Hasher);
+
+impl sha2::digest::Update for Blake3 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+}
+
+impl OutputSizeUser for Blake3 {
+    type OutputSize = U32;
+}
+
+impl FixedOutput for Blake3 {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        out.copy_from_slice(self.0.finalize().as_bytes());
+    }
+}
+
+impl FixedOutputReset for Blake3 {
+    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+        out.copy_from_slice(self.0.finalize().as_bytes());
+        self.0.reset();
+    }
+}
+
+impl Reset for Blake3 {
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+/// "backup.tar.gz" becomes "backup.tar.gz.sha256"
+fn checksum_path(archive_path: &Path, algorithm: Algorithm) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(algorithm.extension());
+    PathBuf::from(path)
+}
+
+/// The checksum files `--verify-checksum` looks for next to the archive at `archive_path`
+pub fn checksum_paths(archive_path: &Path) -> Vec<PathBuf> {
+    Algorithm::ALL.iter().map(|algorithm| checksum_path(archive_path, *algorithm)).collect()
+}
+
+/// The hex digest of the file at `path`
+fn digest_of(path: &Path, algorithm: Algorithm) -> crate::Result<String> {
+    let mut reader = Cancellable::new(BufReader::new(fs::File::open(path)?));
+    let mut hasher = HashingWriter::new(io::sink(), algorithm.hasher());
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.hex_digest())
+}
+
+/// Write the checksum file of the archive at `archive_path`
+pub fn write_checksum_file(archive_path: &Path, algorithm: Algorithm) -> crate::Result<()> {
+    let digest = digest_of(archive_path, algorithm)?;
+    let checksum_path = checksum_path(archive_path, algorithm);
+    // Next to the archive, so only its name is written, like `sha256sum` run from its folder would
+    let name = archive_path.file_name().unwrap_or(archive_path.as_os_str());
+
+    let mut file = fs::File::create(&checksum_path)?;
+    writeln!(file, "{}  {}", digest, to_utf(Path::new(name)))?;
+    logger::info(format!(
+        "Wrote the {} of the archive to '{}'.",
+        algorithm.name(),
+        to_utf(&checksum_path)
+    ))
+    .path(&checksum_path)
+    .log();
+    Ok(())
+}
+
+/// With `--verify-checksum`, check the archive at `archive_path` against its checksum file,
+/// failing if it has none
+pub fn verify(archive_path: &Path, flags: &oof::Flags) -> crate::Result<()> {
+    if !flags.is_present("verify-checksum") {
+        return Ok(());
+    }
+
+    let (algorithm, checksum_path) = Algorithm::ALL
+        .iter()
+        .copied()
+        .map(|algorithm| (algorithm, checksum_path(archive_path, algorithm)))
+        .find(|(_, path)| path.exists())
+        .ok_or_else(|| crate::Error::MissingChecksumFile(archive_path.to_path_buf()))?;
+
+    let contents = fs::read_to_string(&checksum_path)?;
+    // The digest comes first, followed by the name of the file
+    let expected = contents.split_whitespace().next().unwrap_or_default().to_lowercase();
+    let actual = digest_of(archive_path, algorithm)?;
+    if expected != actual {
+        return Err(crate::Error::ChecksumMismatch {
+            path: archive_path.to_path_buf(),
+            expected,
+            actual,
+        });
+    }
+
+    logger::debug(format!("The {} of '{}' matches.", algorithm.name(), to_utf(archive_path)))
+        .path(archive_path)
+        .log();
+    Ok(())
+}
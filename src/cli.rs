@@ -7,7 +7,14 @@ use std::{
 
 use strsim::normalized_damerau_levenshtein;
 
-use crate::{arg_flag, flag, oof};
+use crate::{
+    arg_flag,
+    bench::BenchOutput,
+    completions::Shell,
+    config::{self, Config},
+    extension, flag, logger, oof,
+    utils::colors,
+};
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum Command {
@@ -16,32 +23,132 @@ pub enum Command {
         files: Vec<PathBuf>,
         output_path: PathBuf,
     },
+    /// Files to be compressed into one archive each, named after the file plus `extension`
+    CompressEach {
+        files: Vec<PathBuf>,
+        extension: String,
+        output_folder: Option<PathBuf>,
+    },
     /// Files to be decompressed and their extensions
     Decompress {
         files: Vec<PathBuf>,
         output_folder: Option<PathBuf>,
     },
+    /// Hidden benchmark mode, compresses `file` with every codec and level
+    Bench {
+        file: PathBuf,
+        output: BenchOutput,
+    },
+    /// Archives whose entries are listed, or compressed files whose headers are shown
+    List {
+        archives: Vec<PathBuf>,
+    },
+    /// Archives whose format, compression and other metadata are shown
+    Info {
+        archives: Vec<PathBuf>,
+    },
+    /// Archives whose files are hashed together, in an order that doesn't depend on the format
+    ContentsHash {
+        archives: Vec<PathBuf>,
+    },
+    /// Archives whose entries are copied into one at `output_path`
+    Merge {
+        archives: Vec<PathBuf>,
+        output_path: PathBuf,
+    },
+    /// File compressed again into `output_path`, with the compression formats of its extension
+    Convert {
+        file: PathBuf,
+        output_path: PathBuf,
+    },
+    /// File encrypted with a passphrase into `output_path`, an age file
+    Encrypt {
+        file: PathBuf,
+        output_path: PathBuf,
+    },
+    /// Age file decrypted with a passphrase into `output_path`
+    Decrypt {
+        file: PathBuf,
+        output_path: PathBuf,
+    },
+    /// Zstandard dictionary trained on `files` into `output_path`
+    DictTrain {
+        files: Vec<PathBuf>,
+        output_path: PathBuf,
+    },
+    /// Print the supported formats and what can be done with them
+    ShowFormats,
+    /// Print the completion script of `shell`
+    ShowCompletions {
+        shell: Shell,
+    },
+    /// Print where the config file is read from
+    ShowConfigPath,
+    /// Print the settings in effect, from the config file and the flags
+    ShowConfig,
     ShowHelp,
     ShowVersion,
 }
 
+/// Flags for every command, given before the ones of the command line, like `--yes --quiet`
+const OPTS_VAR: &str = "OUCH_OPTS";
+
 /// Calls parse_args_and_flags_from using argv (std::env::args_os)
 ///
 /// This function is also responsible for treating and checking the cli input
 /// Like calling canonicale, checking if it exists.
 pub fn parse_args() -> crate::Result<ParsedArgs> {
+    colors::set_from_env();
+
     // From argv, but ignoring empty arguments
-    let args = env::args_os().skip(1).filter(|arg| !arg.is_empty()).collect();
-    let mut parsed_args = parse_args_from(args)?;
+    let args: Vec<OsString> = env::args_os().skip(1).filter(|arg| !arg.is_empty()).collect();
+    let defaults: Vec<OsString> = env::var_os(OPTS_VAR)
+        .unwrap_or_default()
+        .to_string_lossy()
+        .split_whitespace()
+        .map(OsString::from)
+        .collect();
+    // Parsed without the defaults too, to tell where the flags come from
+    let given_flags = flag_names(&parse_args_from(args.clone())?.flags);
+    let mut parsed_args = parse_args_with_defaults(args, &defaults)?;
+    logger::set_format(&parsed_args.flags)?;
+    logger::set_verbosity(&parsed_args.flags)?;
+    logger::set_strict(&parsed_args.flags);
+    logger::set_log_file(&parsed_args.flags)?;
 
     // If has a list of files, canonicalize them, reporting error if they do not exist
     match &mut parsed_args.command {
-        Command::Compress { files, .. } | Command::Decompress { files, .. } => {
+        Command::Compress { files, .. }
+        | Command::CompressEach { files, .. }
+        | Command::Decompress { files, .. }
+        | Command::List { archives: files }
+        | Command::Info { archives: files }
+        | Command::ContentsHash { archives: files }
+        | Command::Merge { archives: files, .. }
+        | Command::DictTrain { files, .. } => {
             *files = canonicalize_files(files)?;
         },
+        Command::Bench { file, .. }
+        | Command::Convert { file, .. }
+        | Command::Encrypt { file, .. }
+        | Command::Decrypt { file, .. } => {
+            *file = canonicalize(&file)?;
+        },
         _ => {},
     }
 
+    // A broken config file shouldn't stop the user from finding it
+    if parsed_args.command != Command::ShowConfigPath {
+        let env_flags = flag_names(&parsed_args.flags);
+        let mut config = match parsed_args.flags.is_present("no-config") {
+            true => Config::default(),
+            false => Config::load()?,
+        };
+        config.apply_env()?;
+        config.apply_to(&mut parsed_args.flags);
+        log_flag_sources(&parsed_args.flags, &given_flags, &env_flags);
+    }
+
     if parsed_args.flags.is_present("yes") && parsed_args.flags.is_present("no") {
         todo!("conflicting flags, better error message.");
     }
@@ -70,7 +177,7 @@ fn is_typo(path: impl AsRef<Path>) -> bool {
 }
 
 fn canonicalize(path: impl AsRef<Path>) -> crate::Result<PathBuf> {
-    match std::fs::canonicalize(&path.as_ref()) {
+    match std::fs::canonicalize(path.as_ref()) {
         Ok(abs_path) => Ok(abs_path),
         Err(io_err) => {
             if !path.as_ref().exists() {
@@ -82,11 +189,270 @@ fn canonicalize(path: impl AsRef<Path>) -> crate::Result<PathBuf> {
     }
 }
 
-fn canonicalize_files(files: &[impl AsRef<Path>]) -> crate::Result<Vec<PathBuf>> {
+fn flag_names(flags: &oof::Flags) -> Vec<&'static str> {
+    let argument_flags = flags.argument_flags.keys().chain(flags.repeated_flags.keys());
+    flags.boolean_flags.iter().chain(argument_flags).copied().collect()
+}
+
+/// Say where the flags in effect come from, with `--verbose`
+fn log_flag_sources(flags: &oof::Flags, given_flags: &[&str], env_flags: &[&str]) {
+    for name in flag_names(flags) {
+        let source = if given_flags.contains(&name) {
+            "the command line"
+        } else if env_flags.contains(&name) {
+            OPTS_VAR
+        } else if matches!(name, "yes" | "no") && env::var_os(config::OVERWRITE_VAR).is_some() {
+            config::OVERWRITE_VAR
+        } else {
+            "the config file"
+        };
+        let flag = match (flags.arg(name), flags.args(name)) {
+            (Some(value), _) => format!("--{} {}", name, value.to_string_lossy()),
+            (None, []) => format!("--{}", name),
+            (None, values) => values
+                .iter()
+                .map(|value| format!("--{} {}", name, value.to_string_lossy()))
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        logger::debug(format!("Using {}, from {}.", flag, source)).log();
+    }
+}
+
+/// `oof::filter_flags`, plus the flags of `defaults` that weren't given in `args`
+fn filter_flags(
+    args: Vec<OsString>,
+    flags_info: &[oof::Flag],
+    defaults: &[OsString],
+) -> crate::Result<(Vec<OsString>, oof::Flags)> {
+    let (args, mut flags) = oof::filter_flags(args, flags_info)?;
+    let (_, default_flags) = oof::filter_flags(accepted_flags(defaults, flags_info), flags_info)?;
+
+    let is_overridden = |flags: &oof::Flags, name: &str| {
+        opposite_flag(name).is_some_and(|opposite| flags.is_present(opposite))
+    };
+    for name in default_flags.boolean_flags {
+        if !is_overridden(&flags, name) {
+            flags.boolean_flags.insert(name);
+        }
+    }
+    for (name, value) in default_flags.argument_flags {
+        flags.argument_flags.entry(name).or_insert(value);
+    }
+    // Repeated in the defaults too, but replaced as a whole by the ones in `args`
+    for (name, values) in default_flags.repeated_flags {
+        flags.repeated_flags.entry(name).or_insert(values);
+    }
+    Ok((args, flags))
+}
+
+/// The flags of `defaults` found in `flags_info`, the first of them wins over later repetitions,
+/// unless the flag can be repeated, and opposites. The others are left out with their values,
+/// defaults are shared by every subcommand.
+fn accepted_flags(defaults: &[OsString], flags_info: &[oof::Flag]) -> Vec<OsString> {
+    let find_flag = |arg: &OsString| {
+        let arg = arg.to_str()?;
+        flags_info.iter().find(|flag| match arg.strip_prefix("--") {
+            Some(long) => flag.long == long.split_once('=').map_or(long, |(name, _)| name),
+            None => flag.short.is_some_and(|short| arg == format!("-{}", short)),
+        })
+    };
+
+    let mut accepted = vec![];
+    let mut taken: Vec<&str> = vec![];
+    let mut defaults = defaults.iter().peekable();
+    while let Some(arg) = defaults.next() {
+        match find_flag(arg) {
+            Some(flag) => {
+                let value = if flag.takes_value { defaults.next() } else { None };
+                let is_taken = (taken.contains(&flag.long) && !flag.repeated)
+                    || opposite_flag(flag.long).is_some_and(|opposite| taken.contains(&opposite));
+                if !is_taken {
+                    taken.push(flag.long);
+                    accepted.push(arg.clone());
+                    accepted.extend(value.cloned());
+                }
+            },
+            // Skipped along with its values, if it has any
+            None => {
+                while defaults.next_if(|next| !next.to_string_lossy().starts_with('-')).is_some() {}
+            },
+        }
+    }
+    accepted
+}
+
+/// Flags that undo each other
+fn opposite_flag(name: &str) -> Option<&'static str> {
+    const OPPOSITES: &[(&str, &str)] = &[
+        ("yes", "no"),
+        ("quiet", "verbose"),
+        ("gitignore", "no-gitignore"),
+        ("exclude-vcs", "no-exclude-vcs"),
+        ("sparse", "no-sparse"),
+        ("same-permissions", "no-same-permissions"),
+        ("keep-archive", "remove-archive"),
+    ];
+    OPPOSITES.iter().find_map(|&(first, second)| match name {
+        _ if name == first => Some(second),
+        _ if name == second => Some(first),
+        _ => None,
+    })
+}
+
+pub(crate) fn canonicalize_files(files: &[impl AsRef<Path>]) -> crate::Result<Vec<PathBuf>> {
     files.iter().map(canonicalize).collect()
 }
 
-pub fn parse_args_from(mut args: Vec<OsString>) -> crate::Result<ParsedArgs> {
+/// The subcommands, by the name shown in the help and in the completions, and their aliases.
+/// `ouch bench` is hidden from both.
+pub(crate) const SUBCOMMANDS: [(&str, &[&str]); 12] = [
+    ("compress", &["c"]),
+    ("list", &["l"]),
+    ("info", &[]),
+    ("contents-hash", &[]),
+    ("merge", &[]),
+    ("convert", &[]),
+    ("encrypt", &[]),
+    ("decrypt", &[]),
+    ("dict-train", &[]),
+    ("formats", &[]),
+    ("completions", &[]),
+    ("config", &[]),
+];
+
+/// The flags every subcommand takes
+pub(crate) fn global_flags() -> Vec<oof::Flag> {
+    vec![
+        flag!('y', "yes"),
+        flag!('n', "no"),
+        flag!('q', "quiet"),
+        flag!('v', "verbose"),
+        flag!("no-summary"),
+        flag!("strict"),
+        arg_flag!("log-format"),
+        arg_flag!("log-file"),
+        arg_flag!("question-timeout"),
+        flag!("no-config"),
+        flag!("no-sandbox"),
+        arg_flag!("sandbox"),
+    ]
+}
+
+/// The flags `subcommand` takes besides the global ones, those of decompression for `None`
+pub(crate) fn subcommand_flags(subcommand: Option<&str>) -> Vec<oof::Flag> {
+    match subcommand {
+        Some("c" | "compress") => vec![
+            arg_flag!("each"),
+            arg_flag!('o', "output"),
+            flag!("no-recursion"),
+            arg_flag!("depth"),
+            flag!("dereference-hardlinks"),
+            flag!("no-sparse"),
+            flag!("reproducible"),
+            arg_flag!("mtime"),
+            arg_flag!("format-tar"),
+            flag!("acl"),
+            arg_flag!("preserve"),
+            arg_flag!("special-files"),
+            flag!("gitignore"),
+            flag!("no-gitignore"),
+            flag!("follow-symlinks"),
+            flag!("exclude-vcs"),
+            flag!("no-exclude-vcs"),
+            flag!("allow-weird-extension"),
+            flag!("try-formats"),
+            flag!("zstd-long").optional_value(),
+            arg_flag!("dict"),
+            arg_flag!("checksum-file"),
+            flag!("compression-stats"),
+            flag!("json"),
+            flag!("low-memory"),
+            arg_flag!("temp-dir"),
+            flag!("create-tar-index"),
+        ],
+        Some("l" | "list") => vec![
+            flag!("json"),
+            flag!("print0"),
+            flag!("bytes"),
+            arg_flag!("date-format"),
+            arg_flag!("max-files"),
+            arg_flag!("charset"),
+            arg_flag!("zip-encoding"),
+            arg_flag!("transform").repeated(),
+            arg_flag!("temp-dir"),
+            flag!("use-index"),
+            flag!("zstd-long").optional_value(),
+            arg_flag!("dict"),
+        ],
+        Some("info") => {
+            vec![flag!("json"), flag!("bytes"), arg_flag!("max-files"), arg_flag!("temp-dir")]
+        },
+        Some("contents-hash") => {
+            vec![arg_flag!("charset"), arg_flag!("zip-encoding"), arg_flag!("temp-dir")]
+        },
+        Some("merge") => vec![arg_flag!('o', "output"), arg_flag!("conflict")],
+        Some("convert") => vec![flag!("zstd-long").optional_value(), arg_flag!("dict")],
+        Some("encrypt" | "decrypt") => vec![arg_flag!('o', "output")],
+        Some("dict-train") => vec![arg_flag!("max-dict-size")],
+        Some("bench") => vec![arg_flag!("format")],
+        Some("formats") => vec![flag!("json")],
+        Some("completions") => vec![],
+        // `--show-path` is the older spelling of `ouch config path`
+        Some("config") => vec![flag!("show-path")],
+        // Decompression
+        None => vec![
+            arg_flag!('o', "output"),
+            flag!('r', "recursive"),
+            flag!("extract-nested"),
+            arg_flag!("max-nested-depth"),
+            flag!("keep-nested"),
+            arg_flag!("max-output-size"),
+            flag!("no-size-limit"),
+            arg_flag!("max-ratio"),
+            arg_flag!("max-files"),
+            arg_flag!("charset"),
+            arg_flag!("zip-encoding"),
+            arg_flag!("transform").repeated(),
+            arg_flag!("temp-dir"),
+            arg_flag!("conflict"),
+            flag!("no-smart-unpack"),
+            flag!("flat"),
+            flag!("sparse"),
+            arg_flag!("sparse-threshold"),
+            flag!("no-sparse"),
+            arg_flag!("special-files"),
+            flag!("use-gzip-name"),
+            flag!("no-mtime"),
+            flag!("keep-archive"),
+            flag!("remove-archive"),
+            flag!("atomic"),
+            flag!("continue-on-error"),
+            flag!("verify-checksum"),
+            arg_flag!("uid-map"),
+            arg_flag!("gid-map"),
+            flag!("acl"),
+            arg_flag!("preserve"),
+            flag!("numeric-owner"),
+            flag!("same-permissions"),
+            flag!("no-same-permissions"),
+            flag!("zstd-long").optional_value(),
+            arg_flag!("dict"),
+        ],
+        Some(subcommand) => unreachable!("No flags for the subcommand {}", subcommand),
+    }
+}
+
+pub fn parse_args_from(args: Vec<OsString>) -> crate::Result<ParsedArgs> {
+    parse_args_with_defaults(args, &[])
+}
+
+/// Parse `args`, taking the flags of `defaults` that the subcommand accepts and that `args`
+/// doesn't override
+pub fn parse_args_with_defaults(
+    mut args: Vec<OsString>,
+    defaults: &[OsString],
+) -> crate::Result<ParsedArgs> {
     if oof::matches_any_arg(&args, &["--help", "-h"]) || args.is_empty() {
         return Ok(ParsedArgs { command: Command::ShowHelp, flags: oof::Flags::default() });
     }
@@ -95,15 +461,51 @@ pub fn parse_args_from(mut args: Vec<OsString>) -> crate::Result<ParsedArgs> {
         return Ok(ParsedArgs { command: Command::ShowVersion, flags: oof::Flags::default() });
     }
 
-    let subcommands = &["c", "compress"];
-    let mut flags_info = vec![flag!('y', "yes"), flag!('n', "no")];
+    let subcommands = SUBCOMMANDS
+        .iter()
+        .flat_map(|(name, aliases)| aliases.iter().chain([name]))
+        .chain([&"bench"]);
+    let subcommand = oof::pop_subcommand(&mut args, subcommands).copied();
+    let mut flags_info = global_flags();
+    flags_info.extend(subcommand_flags(subcommand));
+
+    let parsed_args = match subcommand {
+        Some("c" | "compress") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            // Files ignored by git are archived unless asked otherwise
+            if flags.is_present("gitignore") && flags.is_present("no-gitignore") {
+                return Err(crate::Error::InvalidInput);
+            }
 
-    let parsed_args = match oof::pop_subcommand(&mut args, subcommands) {
-        Some(&"c") | Some(&"compress") => {
-            // `ouch compress` subcommand
-            let (args, flags) = oof::filter_flags(args, &flags_info)?;
             let mut files: Vec<PathBuf> = args.into_iter().map(PathBuf::from).collect();
 
+            if let Some(extension) = flags.arg("each") {
+                // Every argument is an input, each one is compressed into its own archive
+                let extension = extension.to_str().ok_or(crate::Error::InvalidUnicode)?;
+                let extension = extension.trim_start_matches('.').to_string();
+
+                match files.last() {
+                    None => return Err(crate::Error::MissingArgumentsForCompression),
+                    // Looks like an output path was given, it can't be combined with `--each`
+                    Some(last)
+                        if !last.exists() && !extension::extensions_from_path(last).is_empty() =>
+                    {
+                        return Err(crate::Error::EachWithOutputPath(last.clone()));
+                    },
+                    Some(_) => {},
+                }
+
+                let output_folder = flags.arg("output").map(PathBuf::from);
+                let command = Command::CompressEach { files, extension, output_folder };
+                return Ok(ParsedArgs { command, flags });
+            }
+
+            // The output folder is only used when compressing each input separately
+            if flags.is_present("output") {
+                return Err(crate::Error::InvalidInput);
+            }
+
             if files.len() < 2 {
                 return Err(crate::Error::MissingArgumentsForCompression);
             }
@@ -114,10 +516,150 @@ pub fn parse_args_from(mut args: Vec<OsString>) -> crate::Result<ParsedArgs> {
             let command = Command::Compress { files, output_path };
             ParsedArgs { command, flags }
         },
+        Some("l" | "list") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            if args.is_empty() {
+                return Err(crate::Error::InvalidInput);
+            }
+
+            let archives = args.into_iter().map(PathBuf::from).collect();
+            ParsedArgs { command: Command::List { archives }, flags }
+        },
+        Some("info") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            if args.is_empty() {
+                return Err(crate::Error::InvalidInput);
+            }
+
+            let archives = args.into_iter().map(PathBuf::from).collect();
+            ParsedArgs { command: Command::Info { archives }, flags }
+        },
+        Some("contents-hash") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            if args.is_empty() {
+                return Err(crate::Error::InvalidInput);
+            }
+
+            let archives = args.into_iter().map(PathBuf::from).collect();
+            ParsedArgs { command: Command::ContentsHash { archives }, flags }
+        },
+        Some("merge") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            let output_path = match flags.arg("output") {
+                Some(output_path) if args.len() >= 2 => PathBuf::from(output_path),
+                _ => return Err(crate::Error::InvalidInput),
+            };
+
+            let archives = args.into_iter().map(PathBuf::from).collect();
+            ParsedArgs { command: Command::Merge { archives, output_path }, flags }
+        },
+        Some("convert") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            let (file, output_path) = match args.as_slice() {
+                [file, output_path] => (PathBuf::from(file), PathBuf::from(output_path)),
+                _ => return Err(crate::Error::InvalidInput),
+            };
+            ParsedArgs { command: Command::Convert { file, output_path }, flags }
+        },
+        Some(subcommand @ ("encrypt" | "decrypt")) => {
+            let encrypt = subcommand == "encrypt";
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            let file = match args.as_slice() {
+                [file] => PathBuf::from(file),
+                _ => return Err(crate::Error::InvalidInput),
+            };
+
+            // "data.tar.gz" is encrypted into "data.tar.gz.age", and decrypted back
+            let output_path = match flags.arg("output") {
+                Some(output_path) => PathBuf::from(output_path),
+                None if encrypt => {
+                    let mut output_path = file.clone().into_os_string();
+                    output_path.push(".age");
+                    PathBuf::from(output_path)
+                },
+                None if file.extension() == Some("age".as_ref()) => file.with_extension(""),
+                None => return Err(crate::Error::InvalidInput),
+            };
+
+            let command = if encrypt {
+                Command::Encrypt { file, output_path }
+            } else {
+                Command::Decrypt { file, output_path }
+            };
+            ParsedArgs { command, flags }
+        },
+        Some("dict-train") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            let mut files: Vec<PathBuf> = args.into_iter().map(PathBuf::from).collect();
+            if files.len() < 2 {
+                return Err(crate::Error::InvalidInput);
+            }
+            // `ouch dict-train <output.dict> <inputs...>`, the dictionary comes first
+            let output_path = files.remove(0);
+
+            ParsedArgs { command: Command::DictTrain { files, output_path }, flags }
+        },
+        // Hidden `ouch bench` subcommand, not shown in the help message
+        Some("bench") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            let file = match args.as_slice() {
+                [file] => PathBuf::from(file),
+                _ => return Err(crate::Error::InvalidInput),
+            };
+
+            let output = match flags.arg("format") {
+                None => BenchOutput::Markdown,
+                Some(format) => format
+                    .to_str()
+                    .and_then(BenchOutput::from_flag)
+                    .ok_or(crate::Error::InvalidInput)?,
+            };
+
+            let command = Command::Bench { file, output };
+            ParsedArgs { command, flags }
+        },
+        Some("formats") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            if !args.is_empty() {
+                return Err(crate::Error::InvalidInput);
+            }
+
+            ParsedArgs { command: Command::ShowFormats, flags }
+        },
+        Some("completions") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            let shell = match args.as_slice() {
+                [shell] => shell.to_str().and_then(Shell::from_name),
+                _ => None,
+            };
+            let shell = shell.ok_or(crate::Error::InvalidInput)?;
+
+            ParsedArgs { command: Command::ShowCompletions { shell }, flags }
+        },
+        Some("config") => {
+            let (args, flags) = filter_flags(args, &flags_info, defaults)?;
+
+            let command = match (args.as_slice(), flags.is_present("show-path")) {
+                ([], true) => Command::ShowConfigPath,
+                ([action], false) if action == "path" => Command::ShowConfigPath,
+                ([action], false) if action == "show" => Command::ShowConfig,
+                _ => return Err(crate::Error::InvalidInput),
+            };
+
+            ParsedArgs { command, flags }
+        },
         // Defaults to decompression when there is no subcommand
         None => {
-            flags_info.push(arg_flag!('o', "output"));
-
             if let Some(first_arg) = args.first() {
                 if is_typo(first_arg) {
                     return Err(crate::Error::CompressionTypo);
@@ -127,9 +669,13 @@ pub fn parse_args_from(mut args: Vec<OsString>) -> crate::Result<ParsedArgs> {
             }
 
             // Parse flags
-            let (files, flags) = oof::filter_flags(args, &flags_info)?;
+            let (files, flags) = filter_flags(args, &flags_info, defaults)?;
             let files = files.into_iter().map(PathBuf::from).collect();
 
+            if flags.is_present("keep-archive") && flags.is_present("remove-archive") {
+                return Err(crate::Error::InvalidInput);
+            }
+
             let output_folder = flags.arg("output").map(PathBuf::from);
 
             // TODO: ensure all files are decompressible
@@ -158,6 +704,28 @@ mod tests {
         parse_args_from(args)
     }
 
+    #[test]
+    fn test_cli_defaults() {
+        let parse = |args: &str, defaults: &str| {
+            parse_args_with_defaults(gen_args(args), &gen_args(defaults)).unwrap().flags
+        };
+
+        let flags = parse("c a b.zip --no", "--yes --exclude-vcs --charset cp437 -q --depth 2");
+        assert!(flags.is_present("no") && !flags.is_present("yes"));
+        assert!(flags.is_present("exclude-vcs") && flags.is_present("quiet"));
+        // --charset is not taken when compressing, its value isn't mistaken for a file
+        assert_eq!(flags.arg("charset"), None);
+        assert_eq!(flags.arg("depth").unwrap(), "2");
+
+        let flags = parse("list a.zip --charset gbk", "--charset cp437 --exclude-vcs");
+        assert_eq!(flags.arg("charset").unwrap(), "gbk");
+        assert!(!flags.is_present("exclude-vcs"));
+
+        // The first of the opposite defaults wins
+        let flags = parse("a.zip", "--yes --no --yes");
+        assert!(flags.is_present("yes") && !flags.is_present("no"));
+    }
+
     #[test]
     fn test_cli_commands() {
         assert_eq!(test_cli("--help").unwrap().command, Command::ShowHelp);
@@ -167,11 +735,86 @@ mod tests {
             files: vec!["foo.zip".into(), "bar.zip".into()],
             output_folder: None
         });
+        assert_eq!(
+            test_cli("foo.zip --keep-archive --remove-archive").unwrap_err(),
+            crate::Error::InvalidInput
+        );
         assert_eq!(test_cli("compress foo bar baz.zip").unwrap().command, Command::Compress {
             files: vec!["foo".into(), "bar".into()],
             output_path: "baz.zip".into()
         });
         assert_eq!(test_cli("compress").unwrap_err(), crate::Error::MissingArgumentsForCompression);
+        assert_eq!(test_cli("compress a b --each .tar.gz -o out").unwrap().command, Command::CompressEach {
+            files: vec!["a".into(), "b".into()],
+            extension: "tar.gz".into(),
+            output_folder: Some("out".into())
+        });
+        assert_eq!(
+            test_cli("compress a b out.zip --each zip").unwrap_err(),
+            crate::Error::EachWithOutputPath("out.zip".into())
+        );
+        assert_eq!(test_cli("compress a out.zip -o folder").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(
+            test_cli("compress a out.zip --gitignore --no-gitignore").unwrap_err(),
+            crate::Error::InvalidInput
+        );
+        assert_eq!(test_cli("list a.tar.gz b.gz --json").unwrap().command, Command::List {
+            archives: vec!["a.tar.gz".into(), "b.gz".into()]
+        });
+        assert_eq!(test_cli("l").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("info a.tar.gz b.zip --json").unwrap().command, Command::Info {
+            archives: vec!["a.tar.gz".into(), "b.zip".into()]
+        });
+        assert_eq!(test_cli("info").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("contents-hash a.tar.gz b.zip").unwrap().command, Command::ContentsHash {
+            archives: vec!["a.tar.gz".into(), "b.zip".into()]
+        });
+        assert_eq!(test_cli("contents-hash").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("merge a.tar b.tar.gz -o c.tar.xz").unwrap().command, Command::Merge {
+            archives: vec!["a.tar".into(), "b.tar.gz".into()],
+            output_path: "c.tar.xz".into()
+        });
+        assert_eq!(test_cli("merge a.tar -o c.tar").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("merge a.tar b.tar").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("convert a.tar.gz b.tar.xz").unwrap().command, Command::Convert {
+            file: "a.tar.gz".into(),
+            output_path: "b.tar.xz".into()
+        });
+        assert_eq!(test_cli("convert a.tar.gz").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("encrypt data.tar.gz").unwrap().command, Command::Encrypt {
+            file: "data.tar.gz".into(),
+            output_path: "data.tar.gz.age".into()
+        });
+        assert_eq!(test_cli("decrypt data.tar.gz.age").unwrap().command, Command::Decrypt {
+            file: "data.tar.gz.age".into(),
+            output_path: "data.tar.gz".into()
+        });
+        assert_eq!(test_cli("decrypt secret -o plain").unwrap().command, Command::Decrypt {
+            file: "secret".into(),
+            output_path: "plain".into()
+        });
+        assert_eq!(test_cli("decrypt secret").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("dict-train logs.dict logs a.json").unwrap().command, Command::DictTrain {
+            files: vec!["logs".into(), "a.json".into()],
+            output_path: "logs.dict".into()
+        });
+        assert_eq!(test_cli("dict-train logs.dict").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("bench foo --format json").unwrap().command, Command::Bench {
+            file: "foo".into(),
+            output: BenchOutput::Json
+        });
+        assert_eq!(test_cli("bench foo --format yaml").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("formats --json").unwrap().command, Command::ShowFormats);
+        assert_eq!(test_cli("formats zip").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("completions zsh").unwrap().command, Command::ShowCompletions {
+            shell: Shell::Zsh
+        });
+        assert_eq!(test_cli("completions tcsh").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("config --show-path").unwrap().command, Command::ShowConfigPath);
+        assert_eq!(test_cli("config path").unwrap().command, Command::ShowConfigPath);
+        assert_eq!(test_cli("config show --no-config").unwrap().command, Command::ShowConfig);
+        assert_eq!(test_cli("config").unwrap_err(), crate::Error::InvalidInput);
+        assert_eq!(test_cli("config path --show-path").unwrap_err(), crate::Error::InvalidInput);
     }
 
     #[test]
@@ -183,6 +826,7 @@ mod tests {
         assert_eq!(test_cli("foo --yes bar --output folder").unwrap().flags, oof::Flags {
             boolean_flags: vec!["yes"].into_iter().collect(),
             argument_flags: vec![("output", OsString::from("folder"))].into_iter().collect(),
+            repeated_flags: Default::default(),
         });
     }
 }
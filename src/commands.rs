@@ -1,41 +1,125 @@
 use std::{
     fs,
-    io::{self, BufReader, BufWriter, Read, Write},
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
     path::{Path, PathBuf},
+    rc::Rc,
 };
 
-use utils::colors;
+use walkdir::WalkDir;
 
 use crate::{
-    archive,
+    archive::{self, index::TarIndex},
+    atomic, bench,
+    cancel::Cancellable,
+    checksum,
     cli::Command,
+    completions, config, contents_hash, convert, encryption,
     error::FinalError,
+    events::{Event, EventSink, LogEvents, Phase},
     extension::{
         self,
         CompressionFormat::{self, *},
     },
-    oof, utils,
-    utils::to_utf,
+    formats, gzip, info, list, lz4,
+    members::MultiMemberDecoder,
+    merge, oof,
+    progress::{Progress, ProgressReader, ProgressReporter, ProgressUnit},
+    seekable::SeekableDecoder,
+    stats::CompressionStats,
+    try_formats, unpack, utils,
+    utils::{
+        io::{DecodingReader, EntryLimit, OutputLimit, PositionReader},
+        logger,
+        sparse::{self, SparseWriter},
+        to_utf,
+    },
+    zstandard,
 };
 
+/// Capacity of the buffers around the input and output files. Compression and decompression stream
+/// through them, so only this much of the data is held in memory at once (except for creating
+/// .zip inside of other formats, without `--low-memory`)
+const BUFFER_CAPACITY: usize = 64 * 1024;
+
 pub fn run(command: Command, flags: &oof::Flags) -> crate::Result<()> {
+    run_with_events(command, flags, &LogEvents::default())
+}
+
+/// `run`, reporting what is done with each entry to `events`
+pub(crate) fn run_with_events(
+    command: Command,
+    flags: &oof::Flags,
+    events: &dyn EventSink,
+) -> crate::Result<()> {
     match command {
         Command::Compress { files, output_path } => {
             // Formats from path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Lzma]
-            let formats = extension::extensions_from_path(&output_path);
+            let mut formats = extension::extensions_from_path(&output_path);
 
             if formats.is_empty() {
-                FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
-                    .detail("You shall supply the compression format via the extension.")
+                let mut error = FinalError::with_title(format!(
+                    "Cannot compress to '{}'.",
+                    to_utf(&output_path)
+                ));
+                error.detail("You shall supply the compression format via the extension.");
+                if let Some(suggestion) = extension::suggest_extension(&output_path) {
+                    error.hint(format!("Did you mean '{}'?", to_utf(&suggestion))).hint("");
+                }
+                return Err(error
                     .hint("Try adding something like .tar.gz or .zip to the output file.")
                     .hint("")
                     .hint("Examples:")
                     .hint(format!("  ouch compress ... {}.tar.gz", to_utf(&output_path)))
                     .hint(format!("  ouch compress ... {}.zip", to_utf(&output_path)))
-                    .display_and_crash();
+                    .into());
+            }
+
+            if let Some(format) = formats.iter().find(|format| !format.capabilities().compress) {
+                return Err(crate::Error::UnsupportedFormat(format.to_string()));
             }
 
-            if matches!(&formats[0], Bzip | Gzip | Lzma) && files.len() > 1 {
+            if let Some(reordered) = extension::reordered_formats(&formats) {
+                let extensions: String =
+                    reordered.iter().map(|format| format.to_string()).collect();
+                let (name, _) = extension::separate_known_extensions_from_name(&output_path);
+                let suggested_output_path =
+                    output_path.with_file_name(format!("{}{}", to_utf(name), extensions));
+
+                if !flags.is_present("allow-weird-extension") {
+                    let archives = formats.iter().filter(|format| format.is_archive()).count();
+                    let detail = if archives > 1 {
+                        "Archives can't be put inside of each other, only one of them is used."
+                    } else {
+                        "Archive formats have to come first, followed by the compression formats."
+                    };
+                    return Err(FinalError::with_title(format!(
+                        "Cannot compress to '{}'.",
+                        to_utf(&output_path)
+                    ))
+                    .detail("Other tools would not tell its format apart by its name.")
+                    .detail(detail)
+                    .hint(format!("Did you mean '{}'?", to_utf(&suggested_output_path)))
+                    .hint(format!(
+                        "Use --allow-weird-extension to write it as {} anyway, under this name.",
+                        extensions
+                    ))
+                    .into());
+                }
+
+                logger::warning(format!(
+                    "Writing '{}' as {}, other tools won't tell its format apart by its name.",
+                    to_utf(&output_path),
+                    extensions
+                ))
+                .path(&output_path)
+                .category("archives with reordered extensions")
+                .log();
+                formats = reordered;
+            }
+
+            if matches!(&formats[0], Bzip | Gzip | Lzma | Snappy | Zstd | Lz4 | Lz4Block)
+                && files.len() > 1
+            {
                 // This piece of code creates a sugestion for compressing multiple files
                 // It says:
                 // Change from file.bz.xz
@@ -53,7 +137,7 @@ pub fn run(command: Command, flags: &oof::Flags) -> crate::Result<()> {
                 let mut suggested_output_path = output_path.clone();
                 suggested_output_path.replace_range(empty_range, ".tar");
 
-                FinalError::with_title(format!(
+                return Err(FinalError::with_title(format!(
                     "Cannot compress to '{}'.",
                     to_utf(&output_path)
                 ))
@@ -69,192 +153,701 @@ pub fn run(command: Command, flags: &oof::Flags) -> crate::Result<()> {
                 ))
                 .hint(format!("From: {}", output_path))
                 .hint(format!(" To : {}", suggested_output_path))
-                .display_and_crash();
+                .into());
             }
 
-            if let Some(format) =
-                formats.iter().skip(1).position(|format| matches!(format, Tar | Zip))
-            {
-                FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
-                    .detail(format!("Found the format '{}' in an incorrect position.", format))
-                    .detail(format!(
-                        "{} can only be used at the start of the file extension.",
-                        format
-                    ))
-                    .hint(format!(
-                        "If you wish to compress multiple files, start the extension with {}.",
-                        format
+            let checksum_algorithm = checksum::Algorithm::from_flags(flags)?;
+
+            if flags.is_present("create-tar-index") && !matches!(*formats, [Tar] | [Tar, Gzip]) {
+                return Err(FinalError::with_title(format!(
+                    "Cannot index '{}'.",
+                    to_utf(&output_path)
+                ))
+                .detail("--create-tar-index indexes .tar and .tar.gz archives.")
+                .hint("Try an output ending in .tar or .tar.gz, like 'backup.tar.gz'.")
+                .into());
+            }
+
+            if flags.is_present("try-formats") {
+                if flags.is_present("compression-stats") {
+                    return Err(crate::Error::ConflictingFlags("try-formats", "compression-stats"));
+                }
+                if flags.is_present("create-tar-index") {
+                    return Err(crate::Error::ConflictingFlags("try-formats", "create-tar-index"));
+                }
+                if formats != [Tar] {
+                    return Err(FinalError::with_title(format!(
+                        "Cannot compress to '{}'.",
+                        to_utf(&output_path)
                     ))
-                    .hint(format!("Otherwise, remove {} from '{}'.", format, to_utf(&output_path)))
-                    .display_and_crash();
+                    .detail("--try-formats picks the compression format of a .tar archive.")
+                    .hint("Try an output ending in .tar, like 'backup.tar'.")
+                    .into());
+                }
+                return try_formats::compress(files, &output_path, events, flags);
             }
+            zstandard::warn_unused_flags(&formats, flags);
 
             if output_path.exists() && !utils::user_wants_to_overwrite(&output_path, flags)? {
-                return Ok(());
+                return Err(crate::Error::OverwriteDeclined(output_path));
             }
 
-            let output_file = fs::File::create(&output_path).unwrap_or_else(|err| {
-                FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+            // Also read, for the sizes of .zip entries with --compression-stats
+            let output_file = fs::File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&output_path);
+            let output_file = match output_file {
+                Ok(output_file) => output_file,
+                Err(err) => {
+                    return Err(FinalError::with_title(format!(
+                        "Cannot compress to '{}'.",
+                        to_utf(&output_path)
+                    ))
                     .detail(format!("Could not open file '{}' for writing.", to_utf(&output_path)))
                     .detail(format!("Error: {}.", err))
-                    .display_and_crash()
-            });
-            let compress_result = compress_files(files, formats, output_file, flags);
+                    .into());
+                },
+            };
+            let compress_result =
+                compress_files(files, formats, &output_path, output_file, events, flags);
 
             // If any error occurred, delete incomplete file
             if compress_result.is_err() {
                 // Print an extra alert message pointing out that we left a possibly
                 // CORRUPTED FILE at `output_path`
                 if let Err(err) = fs::remove_file(&output_path) {
-                    eprintln!("{red}FATAL ERROR:\n", red = colors::red());
-                    eprintln!("  Please manually delete '{}'.", to_utf(&output_path));
-                    eprintln!(
-                        "  Compression failed and we could not delete '{}'.",
+                    logger::error(format!(
+                        "Compression failed and we could not delete '{}', please delete it \
+                         manually. Error: {}.",
                         to_utf(&output_path),
-                    );
-                    eprintln!(
-                        "  Error:{reset} {}{red}.{reset}\n",
-                        err,
-                        reset = colors::reset(),
-                        red = colors::red()
-                    );
+                        err
+                    ))
+                    .path(&output_path)
+                    .log();
                 }
             } else {
-                println!(
-                    "{}[INFO]{} Successfully compressed '{}'.",
-                    colors::yellow(),
-                    colors::reset(),
-                    to_utf(output_path),
-                );
+                logger::info(format!("Successfully compressed '{}'.", to_utf(&output_path)))
+                    .path(&output_path)
+                    .log();
             }
 
-            compress_result?;
+            let (stats, index) = compress_result?;
+            stats.print(&output_path, flags)?;
+            index.write(&output_path)?;
+            if let Some(algorithm) = checksum_algorithm {
+                checksum::write_checksum_file(&output_path, algorithm)?;
+            }
+        },
+        Command::CompressEach { files, extension, output_folder } => {
+            compress_each(files, &extension, output_folder.as_deref(), events, flags)?;
         },
         Command::Decompress { files, output_folder } => {
-            let mut output_paths = vec![];
-            let mut formats = vec![];
-
-            for path in files.iter() {
-                let (file_output_path, file_formats) =
-                    extension::separate_known_extensions_from_name(path);
-                output_paths.push(file_output_path);
-                formats.push(file_formats);
-            }
+            // Checked before anything is extracted, they are parsed again for each archive
+            archive::transform::Transforms::from_flags(flags)?;
 
-            let files_missing_format: Vec<PathBuf> = files
-                .iter()
-                .zip(&formats)
-                .filter(|(_, formats)| formats.is_empty())
-                .map(|(input_path, _)| PathBuf::from(input_path))
-                .collect();
-
-            // Error
-            if !files_missing_format.is_empty() {
-                eprintln!("Some file you asked ouch to decompress lacks a supported extension.");
-                eprintln!("Could not decompress {}.", to_utf(&files_missing_format[0]));
-                todo!(
-                    "Dev note: add this error variant and pass the Vec to it, all the files \
-                     lacking extension shall be shown: {:#?}.",
-                    files_missing_format
-                );
+            if flags.is_present("recursive") {
+                return decompress_recursively(&files, output_folder.as_deref(), flags);
             }
 
-            // From Option<PathBuf> to Option<&Path>
-            let output_folder = output_folder.as_ref().map(|path| path.as_ref());
-
-            for ((input_path, formats), file_name) in files.iter().zip(formats).zip(output_paths) {
-                decompress_file(input_path, formats, output_folder, file_name, flags)?;
-            }
+            let progress = Rc::new(Progress::new());
+            decompress_files(&files, output_folder.as_deref(), progress, events, flags)?;
+        },
+        Command::List { archives } => list::list_files(&archives, flags)?,
+        Command::Info { archives } => info::show_info(&archives, flags)?,
+        Command::ContentsHash { archives } => contents_hash::hash_archives(&archives, flags)?,
+        Command::Merge { archives, output_path } => {
+            merge::merge_archives(&archives, &output_path, flags)?
+        },
+        Command::Convert { file, output_path } => {
+            convert::convert_file(&file, &output_path, flags)?
+        },
+        Command::Encrypt { file, output_path } => {
+            encryption::encrypt_file(&file, &output_path, flags)?
         },
-        Command::ShowHelp => crate::help_command(),
-        Command::ShowVersion => crate::version_command(),
+        Command::Decrypt { file, output_path } => {
+            encryption::decrypt_file(&file, &output_path, flags)?
+        },
+        Command::DictTrain { files, output_path } => {
+            zstandard::train_dictionary(&files, &output_path, flags)?
+        },
+        Command::Bench { file, output } => bench::run(&file, output)?,
+        Command::ShowFormats => formats::show_formats(flags)?,
+        Command::ShowCompletions { shell } => completions::print_completions(shell)?,
+        Command::ShowConfigPath => config::show_path()?,
+        Command::ShowConfig => config::show(flags)?,
+        Command::ShowHelp => crate::help_command()?,
+        Command::ShowVersion => crate::version_command()?,
     }
     Ok(())
 }
 
-fn compress_files(
+/// Decompress `files` into `output_folder`, or next to them, returning the files that were
+/// extracted
+pub(crate) fn decompress_files(
+    files: &[PathBuf],
+    output_folder: Option<&Path>,
+    progress: Rc<dyn ProgressReporter>,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<PathBuf>> {
+    let mut output_paths = vec![];
+    let mut formats = vec![];
+
+    for path in files.iter() {
+        let (file_output_path, file_formats) = extension::separate_known_extensions_from_name(path);
+        output_paths.push(file_output_path);
+        formats.push(file_formats);
+    }
+
+    let files_missing_format: Vec<PathBuf> = files
+        .iter()
+        .zip(&formats)
+        .filter(|(_, formats)| formats.is_empty())
+        .map(|(input_path, _)| PathBuf::from(input_path))
+        .collect();
+
+    if !files_missing_format.is_empty() {
+        let mut error = FinalError::with_title("Cannot decompress files without a known extension");
+        for path in &files_missing_format {
+            error.detail(to_utf(path));
+        }
+        return Err(error.hint("Run 'ouch formats' for the full list of supported formats").into());
+    }
+
+    let mut extracted = vec![];
+    for ((input_path, formats), file_name) in files.iter().zip(formats).zip(output_paths) {
+        checksum::verify(input_path, flags)?;
+        let archive_folder =
+            unpack::archive_folder(input_path, &formats, output_folder, file_name, flags)?;
+        let progress = Rc::clone(&progress);
+        // Shared with the archives found inside of it
+        let mut limit = output_limit(input_path, flags)?;
+        let files = decompress_file(
+            input_path,
+            formats,
+            archive_folder.as_deref().or(output_folder),
+            file_name,
+            &mut limit,
+            progress,
+            events,
+            flags,
+        )?;
+        extracted.extend(files.iter().cloned());
+        extract_nested_archives(input_path, files, &mut limit, flags)?;
+        remove_source_archive(input_path, flags)?;
+    }
+    Ok(extracted)
+}
+
+pub(crate) fn compress_files(
     files: Vec<PathBuf>,
     formats: Vec<CompressionFormat>,
+    output_path: &Path,
     output_file: fs::File,
-    _flags: &oof::Flags,
-) -> crate::Result<()> {
-    let file_writer = BufWriter::new(output_file);
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<(CompressionStats, TarIndex)> {
+    let mut stats = CompressionStats::from_flags(flags);
+    let index = TarIndex::from_flags(flags);
+    // The sizes of .zip entries are read back from the output, see `read_zip_sizes`
+    let output_reader = output_file.try_clone()?;
+    let file_writer =
+        BufWriter::with_capacity(BUFFER_CAPACITY, stats.counting(Cancellable::new(output_file)));
+    let max_depth = walk_max_depth(flags)?;
+    events.event(Event::Phase(Phase::Compressing { formats: formats.clone() }));
+    if max_depth != usize::MAX {
+        logger::debug(format!("Going {} levels deep into folders.", max_depth)).log();
+    }
+
+    // Only .tar and .tar.gz, checked before, every entry of the .tar.gz gets a gzip member
+    if index.is_enabled() {
+        let writer = index.writer(file_writer, formats == [Tar, Gzip]);
+        let writer = archive::tar::build_archive_from_paths(
+            &files, max_depth, writer, &mut stats, &index, events, flags,
+        )?;
+        writer.into_inner()?.flush()?;
+        return Ok((stats, index));
+    }
 
-    if formats.len() == 1 {
-        let build_archive_from_paths = match formats[0] {
-            Tar => archive::tar::build_archive_from_paths,
-            Zip => archive::zip::build_archive_from_paths,
+    // Archives written straight to the file, anything else goes through the chain of encoders
+    if let [Tar] | [Zip] | [Cpio] = *formats.as_slice() {
+        let stats = &mut stats;
+        let mut bufwriter = match formats[0] {
+            Tar => archive::tar::build_archive_from_paths(
+                &files,
+                max_depth,
+                file_writer,
+                stats,
+                &index,
+                events,
+                flags,
+            )?,
+            Zip => archive::zip::build_archive_from_paths(
+                &files,
+                max_depth,
+                file_writer,
+                stats,
+                events,
+                flags,
+            )?,
+            Cpio => archive::cpio::build_archive_from_paths(
+                &files,
+                max_depth,
+                file_writer,
+                stats,
+                events,
+                flags,
+            )?,
             _ => unreachable!(),
         };
-
-        let mut bufwriter = build_archive_from_paths(&files, file_writer)?;
         bufwriter.flush()?;
+        if formats[0] == Zip {
+            stats.read_zip_sizes(output_reader)?;
+        }
     } else {
         let mut writer: Box<dyn Write> = Box::new(file_writer);
-
-        // Grab previous encoder and wrap it inside of a new one
-        let chain_writer_encoder = |format: &CompressionFormat, encoder: Box<dyn Write>| {
-            let encoder: Box<dyn Write> = match format {
-                Gzip => Box::new(flate2::write::GzEncoder::new(encoder, Default::default())),
-                Bzip => Box::new(bzip2::write::BzEncoder::new(encoder, Default::default())),
-                Lzma => Box::new(xz2::write::XzEncoder::new(encoder, 6)),
-                _ => unreachable!(),
-            };
-            encoder
-        };
+        let zstd_options = zstandard::Options::from_flags(flags)?;
 
         for format in formats.iter().skip(1).rev() {
-            writer = chain_writer_encoder(format, writer);
+            writer = chain_writer_encoder(format, writer, &zstd_options)?;
         }
 
         match formats[0] {
-            Gzip | Bzip | Lzma => {
-                writer = chain_writer_encoder(&formats[0], writer);
-                let mut reader = fs::File::open(&files[0]).unwrap();
+            Gzip | Bzip | Lzma | Snappy | Zstd | Lz4 | Lz4Block => {
+                writer = match formats[0] {
+                    // Stores the name and modification time of the file
+                    Gzip => Box::new(gzip::encoder(&files[0], writer, flags)?),
+                    _ => chain_writer_encoder(&formats[0], writer, &zstd_options)?,
+                };
+                let mut reader = Cancellable::new(fs::File::open(&files[0]).unwrap());
+                // Safe unwrap: inputs are canonicalized, so they always have a file name
+                let entry = Path::new(files[0].file_name().unwrap());
+                let size = fs::metadata(&files[0])?.len();
+                events.event(Event::EntryStarted { entry: entry.to_path_buf(), size: Some(size) });
+                stats.start(entry, size);
                 io::copy(&mut reader, &mut writer)?;
+                // The encoders write what they buffered out when dropped
+                drop(writer);
+                stats.finish(&mut io::sink())?;
+                events.event(Event::EntryFinished {
+                    entry: entry.to_path_buf(),
+                    path: files[0].clone(),
+                    bytes: size,
+                });
             },
             Tar => {
-                let mut writer = archive::tar::build_archive_from_paths(&files, writer)?;
+                let mut writer = archive::tar::build_archive_from_paths(
+                    &files, max_depth, writer, &mut stats, &index, events, flags,
+                )?;
+                writer.flush()?;
+            },
+            Cpio => {
+                let mut writer = archive::cpio::build_archive_from_paths(
+                    &files, max_depth, writer, &mut stats, events, flags,
+                )?;
                 writer.flush()?;
             },
+            Zip if flags.is_present("low-memory") => {
+                // .zip seeks back into what it wrote, the encoders can't, it is built in a
+                // temporary file next to the output and copied into them once finished
+                let output_folder = match output_path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                let temp_dir = utils::io::temp_dir(flags, Some(output_folder));
+                let mut file = utils::io::temp_file(&temp_dir)?;
+                archive::zip::build_archive_from_paths(
+                    &files, max_depth, &mut file, &mut stats, events, flags,
+                )?;
+                file.rewind()?;
+                stats.read_zip_sizes(&mut file)?;
+                file.rewind()?;
+                io::copy(&mut file, &mut writer)?;
+            },
             Zip => {
-                eprintln!(
-                    "{yellow}Warning:{reset}",
-                    yellow = colors::yellow(),
-                    reset = colors::reset()
-                );
-                eprintln!("\tCompressing .zip entirely in memory.");
-                eprintln!("\tIf the file is too big, your pc might freeze!");
-                eprintln!(
-                    "\tThis is a limitation for formats like '{}'.",
+                logger::warning(format!(
+                    "Compressing .zip entirely in memory. If the file is too big, your pc might \
+                     freeze! This is a limitation for formats like '{}'. The design of .zip \
+                     makes it impossible to compress via stream, use --low-memory to build it \
+                     in a temporary file instead.",
                     formats.iter().map(|format| format.to_string()).collect::<String>()
-                );
-                eprintln!("\tThe design of .zip makes it impossible to compress via stream.");
+                ))
+                .category("archives built in memory")
+                .log();
 
                 let mut vec_buffer = io::Cursor::new(vec![]);
-                archive::zip::build_archive_from_paths(&files, &mut vec_buffer)?;
+                archive::zip::build_archive_from_paths(
+                    &files,
+                    max_depth,
+                    &mut vec_buffer,
+                    &mut stats,
+                    events,
+                    flags,
+                )?;
                 let vec_buffer = vec_buffer.into_inner();
+                stats.read_zip_sizes(io::Cursor::new(&vec_buffer))?;
                 io::copy(&mut vec_buffer.as_slice(), &mut writer)?;
             },
+            Iso => unreachable!("creating .iso images is rejected before compression"),
         }
     }
 
+    Ok((stats, index))
+}
+
+/// Grab previous encoder and wrap it inside of a new one
+pub(crate) fn chain_writer_encoder(
+    format: &CompressionFormat,
+    encoder: Box<dyn Write>,
+    zstd_options: &zstandard::Options,
+) -> crate::Result<Box<dyn Write>> {
+    let encoder: Box<dyn Write> = match format {
+        Gzip => Box::new(flate2::write::GzEncoder::new(encoder, Default::default())),
+        Bzip => Box::new(bzip2::write::BzEncoder::new(encoder, Default::default())),
+        Lzma => Box::new(xz2::write::XzEncoder::new(encoder, 6)),
+        // The framed format of `snzip` and Hadoop, with checksums, not raw Snappy blocks
+        Snappy => Box::new(snap::write::FrameEncoder::new(encoder)),
+        Zstd => zstd_options.encoder(encoder)?,
+        // With the checksum of the contents, like the lz4 command writes
+        Lz4 => {
+            let frame_info = lz4_flex::frame::FrameInfo::new().content_checksum(true);
+            Box::new(
+                lz4_flex::frame::FrameEncoder::with_frame_info(frame_info, encoder).auto_finish(),
+            )
+        },
+        Lz4Block => Box::new(lz4::BlockEncoder::new(encoder)),
+        _ => unreachable!(),
+    };
+    Ok(encoder)
+}
+
+// Compresses every file in `files` into its own archive, named after the file plus `extension`.
+//
+// Archives are placed in `output_folder`, or next to each input if none was given.
+fn compress_each(
+    files: Vec<PathBuf>,
+    extension: &str,
+    output_folder: Option<&Path>,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    let output_paths = each_output_paths(&files, extension, output_folder);
+
+    // Refuse to start if two inputs would end up being compressed to the same archive
+    for (i, output_path) in output_paths.iter().enumerate() {
+        if let Some(j) = output_paths[..i].iter().position(|other| other == output_path) {
+            return Err(FinalError::with_title(format!(
+                "Cannot compress to '{}'.",
+                to_utf(output_path)
+            ))
+            .detail(format!(
+                "Both '{}' and '{}' would be compressed to it.",
+                to_utf(&files[j]),
+                to_utf(&files[i])
+            ))
+            .hint("Rename one of the inputs, or compress them separately.")
+            .into());
+        }
+    }
+
+    if let Some(output_folder) = output_folder {
+        utils::create_dir_if_non_existent(output_folder)?;
+    }
+
+    let total = files.len();
+    let mut failures = vec![];
+
+    for (i, (file, output_path)) in files.into_iter().zip(output_paths).enumerate() {
+        logger::info(format!(
+            "({}/{}) Compressing '{}' into '{}'.",
+            i + 1,
+            total,
+            to_utf(&file),
+            to_utf(&output_path)
+        ))
+        .path(&file)
+        .log();
+
+        let command = Command::Compress { files: vec![file.clone()], output_path };
+        match run_with_events(command, flags, events) {
+            Err(crate::Error::Cancelled) => return Err(crate::Error::Cancelled),
+            Err(err) => {
+                logger::error(format!("Failed to compress '{}':", to_utf(&file))).path(&file).log();
+                err.display();
+                failures.push(file);
+            },
+            Ok(()) => {},
+        }
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    Err(crate::Error::BatchFailed { failed: failures, total })
+}
+
+/// The archive paths created by `--each`: each file's name plus `extension`, inside of
+/// `output_folder` or next to the file.
+pub fn each_output_paths(
+    files: &[PathBuf],
+    extension: &str,
+    output_folder: Option<&Path>,
+) -> Vec<PathBuf> {
+    files
+        .iter()
+        .map(|file| {
+            // Safe unwrap: inputs are canonicalized, so they always have a file name
+            let mut file_name = file.file_name().unwrap().to_os_string();
+            file_name.push(".");
+            file_name.push(extension);
+
+            match output_folder {
+                Some(output_folder) => output_folder.join(file_name),
+                None => file.with_file_name(file_name),
+            }
+        })
+        .collect()
+}
+
+// Walks every directory in `inputs` looking for files with known extensions, and decompresses
+// each one of them into its own folder (named after the archive) under `output_folder`.
+//
+// A failure in one archive does not stop the others, failures are reported at the end.
+fn decompress_recursively(
+    inputs: &[PathBuf],
+    output_folder: Option<&Path>,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    let mut total = 0;
+    let mut failures = vec![];
+
+    for input in inputs {
+        // Archives are placed relative to the directory that was given
+        let (walk_root, entries): (&Path, Vec<PathBuf>) = if input.is_dir() {
+            let mut entries = vec![];
+            for entry in WalkDir::new(input).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    entries.push(entry.into_path());
+                }
+            }
+            (input, entries)
+        } else {
+            (input.parent().unwrap_or_else(|| Path::new("")), vec![input.clone()])
+        };
+
+        for archive_path in entries {
+            let (file_name, formats) =
+                extension::separate_known_extensions_from_name(&archive_path);
+            if formats.is_empty() {
+                continue;
+            }
+            total += 1;
+
+            // Safe unwrap: `archive_path` was found inside of `walk_root`
+            let relative_parent = archive_path.parent().unwrap().strip_prefix(walk_root).unwrap();
+            let mut archive_output_folder = match output_folder {
+                Some(output_folder) => output_folder.join(relative_parent),
+                None => walk_root.join(relative_parent),
+            };
+
+            // Bundles get their own folder, so that archives don't mix their contents
+            if formats[0].is_archive() {
+                // Safe unwrap: `file_name` was obtained from a file path
+                archive_output_folder.push(file_name.file_name().unwrap());
+            }
+
+            let file_name = Path::new(file_name.file_name().unwrap()).to_path_buf();
+            let result = checksum::verify(&archive_path, flags)
+                .and_then(|_| utils::create_dir_if_non_existent(&archive_output_folder))
+                .and_then(|_| output_limit(&archive_path, flags))
+                .and_then(|mut limit| {
+                    decompress_file(
+                        &archive_path,
+                        formats,
+                        Some(&archive_output_folder),
+                        &file_name,
+                        &mut limit,
+                        Rc::new(Progress::new()),
+                        &LogEvents::default(),
+                        flags,
+                    )
+                    .and_then(|files| {
+                        extract_nested_archives(&archive_path, files, &mut limit, flags)
+                    })
+                    .and_then(|_| remove_source_archive(&archive_path, flags))
+                });
+
+            if result == Err(crate::Error::Cancelled) {
+                return result;
+            }
+            if let Err(err) = result {
+                logger::error(format!("Failed to decompress '{}':", to_utf(&archive_path)))
+                    .path(&archive_path)
+                    .log();
+                err.display();
+                failures.push(archive_path);
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    Err(crate::Error::BatchFailed { failed: failures, total })
+}
+
+/// Delete the archive at `archive_path` with `--remove-archive`, only called once everything in
+/// it was extracted successfully. Archives are kept by default (`--keep-archive`).
+fn remove_source_archive(archive_path: &Path, flags: &oof::Flags) -> crate::Result<()> {
+    if !flags.is_present("remove-archive") {
+        return Ok(());
+    }
+
+    fs::remove_file(archive_path)?;
+    logger::info(format!("Removed the archive '{}'.", to_utf(archive_path)))
+        .path(archive_path)
+        .log();
     Ok(())
 }
 
+/// How many levels deep compression walks into the given directories, `--no-recursion` only
+/// includes their direct children and `--depth N` goes N levels deep.
+fn walk_max_depth(flags: &oof::Flags) -> crate::Result<usize> {
+    if flags.is_present("no-recursion") {
+        return Ok(1);
+    }
+
+    match flags.arg("depth") {
+        Some(depth) => {
+            depth.to_str().and_then(|depth| depth.parse().ok()).ok_or(crate::Error::InvalidInput)
+        },
+        // Same as walkdir's default, no limit
+        None => Ok(usize::MAX),
+    }
+}
+
+/// Limit on how much can be extracted from the file at `input_file_path`, and from the archives
+/// inside of it with `--extract-nested`, set by `--max-output-size SIZE` and disabled by
+/// `--no-size-limit`, plus the ratio limit of `--max-ratio N` and the entry limit of
+/// `--max-files N`.
+fn output_limit(input_file_path: &Path, flags: &oof::Flags) -> crate::Result<OutputLimit> {
+    let input_size = fs::metadata(input_file_path)?.len();
+
+    let limit = if flags.is_present("no-size-limit") {
+        OutputLimit::unlimited()
+    } else {
+        match flags.arg("max-output-size") {
+            Some(size) => {
+                let size =
+                    size.to_str().and_then(utils::parse_size).ok_or(crate::Error::InvalidInput)?;
+                OutputLimit::new(size)
+            },
+            None => OutputLimit::default_for_input_size(input_size),
+        }
+    };
+
+    let max_ratio = match flags.arg("max-ratio") {
+        Some(max_ratio) => max_ratio
+            .to_str()
+            .and_then(|max_ratio| max_ratio.parse().ok())
+            .ok_or(crate::Error::InvalidInput)?,
+        None => 0,
+    };
+    let limit = limit.with_entry_limit(EntryLimit::from_flags(flags)?);
+    // 0, the default, is no ratio limit
+    if max_ratio == 0 {
+        return Ok(limit);
+    }
+    Ok(limit.with_max_ratio(max_ratio, input_size))
+}
+
 // File at input_file_path is opened for reading, example: "archive.tar.gz"
 // formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
 // output_folder it's where the file will be decompressed to
 // file_name is only used when extracting single file formats, no archive formats like .tar or .zip
+#[allow(clippy::too_many_arguments)]
 fn decompress_file(
+    input_file_path: &Path,
+    mut formats: Vec<extension::CompressionFormat>,
+    output_folder: Option<&Path>,
+    file_name: &Path,
+    limit: &mut OutputLimit,
+    progress: Rc<dyn ProgressReporter>,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<PathBuf>> {
+    // Like "out.gz.tar", written in the order that makes sense with --allow-weird-extension
+    if let Some(reordered) = extension::reordered_formats(&formats) {
+        formats = reordered;
+    }
+    events.event(Event::Phase(Phase::Decompressing {
+        input: input_file_path.to_path_buf(),
+        formats: formats.clone(),
+    }));
+    let (is_archive, is_image) = (formats[0].is_archive(), formats[0] == Iso);
+
+    // With --atomic, archives only reach the output folder once they were completely extracted
+    let files = if is_archive && flags.is_present("atomic") {
+        let destination = output_folder.unwrap_or_else(|| Path::new("."));
+        atomic::extract(destination, flags, |staging_folder| {
+            // Logged with the paths the entries are moved to
+            let events = atomic::StagedEvents { inner: events, staging_folder, destination };
+            unpack_file(
+                input_file_path,
+                formats,
+                Some(staging_folder),
+                file_name,
+                limit,
+                progress,
+                &events,
+                flags,
+            )
+        })?
+    } else {
+        unpack_file(
+            input_file_path,
+            formats,
+            output_folder,
+            file_name,
+            limit,
+            progress,
+            events,
+            flags,
+        )?
+    };
+
+    if is_archive {
+        let output_folder = output_folder.unwrap_or_else(|| Path::new("."));
+        let message = if is_image {
+            format!("Successfully extracted image at '{}'.", to_utf(output_folder))
+        } else {
+            format!("Successfully uncompressed bundle at '{}'.", to_utf(output_folder))
+        };
+        logger::info(message).path(output_folder).log();
+    }
+
+    Ok(files)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn unpack_file(
     input_file_path: &Path,
     formats: Vec<extension::CompressionFormat>,
     output_folder: Option<&Path>,
     file_name: &Path,
+    limit: &mut OutputLimit,
+    progress: Rc<dyn ProgressReporter>,
+    events: &dyn EventSink,
     flags: &oof::Flags,
-) -> crate::Result<()> {
+) -> crate::Result<Vec<PathBuf>> {
     // TODO: improve error message
-    let reader = fs::File::open(&input_file_path)?;
+    let reader = Cancellable::new(fs::File::open(input_file_path)?);
 
     // Output path is used by single file formats
     let output_path = if let Some(output_folder) = output_folder {
@@ -263,75 +856,293 @@ fn decompress_file(
         file_name.to_path_buf()
     };
 
-    // Output folder is used by archive file formats (zip and tar)
-    let output_folder = output_folder.unwrap_or_else(|| Path::new("."));
+    // Output folder is used by archive file formats (zip and tar), on Windows with the prefix that
+    // lets the paths of their entries be longer than 260 characters
+    let output_folder =
+        archive::windows::long_path(output_folder.unwrap_or_else(|| Path::new(".")));
+    let output_folder = output_folder.as_ref();
 
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
     //
-    // This is the only case where we can read and unpack it directly, without having to do
-    // in-memory decompression/copying first.
-    //
-    // Any other Zip decompression done can take up the whole RAM and freeze ouch.
+    // This is the only case where we can read and unpack it directly, without having to copy
+    // the decompressed archive to a temporary file first. Split archives are read from all of
+    // their volumes.
     if let [Zip] = *formats.as_slice() {
+        let volumes = Cancellable::new(archive::zip::SplitZipReader::open(input_file_path)?);
         utils::create_dir_if_non_existent(output_folder)?;
-        let zip_archive = zip::ZipArchive::new(reader)?;
-        let _files = crate::archive::zip::unpack_archive(zip_archive, output_folder, flags)?;
-        println!("[INFO]: Successfully uncompressed bundle at '{}'.", to_utf(output_folder));
-        return Ok(());
+        let zip_archive = zip::ZipArchive::new(volumes)?;
+        let files = crate::archive::zip::unpack_archive(
+            zip_archive,
+            output_folder,
+            limit,
+            &progress,
+            events,
+            flags,
+        )?;
+        progress.finish();
+        return Ok(files);
     }
 
+    // ISO images need io::Seek too, and are usually distributed uncompressed
+    if let [Iso] = *formats.as_slice() {
+        utils::create_dir_if_non_existent(output_folder)?;
+        let image = archive::iso::IsoImage::new(reader)?;
+        let files =
+            archive::iso::unpack_archive(image, output_folder, limit, &progress, events, flags)?;
+        progress.finish();
+        return Ok(files);
+    }
+
+    // In a seekable .zst, they are read in place instead of being copied to a temporary file
+    if let [Zip | Iso, Zstd] = *formats.as_slice() {
+        let input = Cancellable::new(fs::File::open(input_file_path)?);
+        if let Some(decoder) = SeekableDecoder::new(input)? {
+            utils::create_dir_if_non_existent(output_folder)?;
+            let files = if formats[0] == Zip {
+                let zip_archive = zip::ZipArchive::new(decoder)?;
+                archive::zip::unpack_archive(
+                    zip_archive,
+                    output_folder,
+                    limit,
+                    &progress,
+                    events,
+                    flags,
+                )?
+            } else {
+                let image = archive::iso::IsoImage::new(decoder)?;
+                archive::iso::unpack_archive(image, output_folder, limit, &progress, events, flags)?
+            };
+            progress.finish();
+            return Ok(files);
+        }
+    }
+
+    // Streams can't be counted in entries before reaching their end, so the progress is measured
+    // in bytes of the input file
+    progress.set_total(fs::metadata(input_file_path)?.len(), ProgressUnit::Bytes);
+    let reader = ProgressReader::new(reader, progress.clone());
+
     // Will be used in decoder chaining
-    let reader = BufReader::new(reader);
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
     let mut reader: Box<dyn Read> = Box::new(reader);
-
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder = |format: &CompressionFormat, decoder: Box<dyn Read>| {
-        let decoder: Box<dyn Read> = match format {
-            Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-            Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-            Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-            _ => unreachable!(),
-        };
-        decoder
-    };
+    let zstd_options = zstandard::Options::from_flags(flags)?;
 
     for format in formats.iter().skip(1).rev() {
-        reader = chain_reader_decoder(format, reader);
+        reader = chain_reader_decoder(format, reader, &zstd_options)?;
     }
 
-    match formats[0] {
-        Gzip | Bzip | Lzma => {
-            reader = chain_reader_decoder(&formats[0], reader);
+    let files = match formats[0] {
+        Gzip | Bzip | Lzma | Snappy | Zstd | Lz4 | Lz4Block => {
+            let mut output_path = output_path;
+            let mut stored = gzip::StoredMetadata::default();
+            reader = match formats[0] {
+                Gzip => {
+                    let input = PositionReader::new(BufReader::new(reader));
+                    let position = input.position();
+                    let decoder = MultiMemberDecoder::new(input, Gzip);
+                    if let Some(header) = decoder.header() {
+                        stored = gzip::StoredMetadata::from_header(header);
+                    }
+                    Box::new(DecodingReader::new(decoder, Gzip, position))
+                },
+                _ => chain_reader_decoder(&formats[0], reader, &zstd_options)?,
+            };
+            if flags.is_present("use-gzip-name") {
+                if let Some(name) = stored.name {
+                    output_path.set_file_name(name);
+                }
+            }
+
+            // The file is its only entry, named after what it is decompressed to
+            // Safe unwrap: `output_path` ends with `file_name`, or the name stored in the .gz
+            let entry = PathBuf::from(output_path.file_name().unwrap());
+            events.event(Event::EntryStarted { entry: entry.clone(), size: None });
 
             // TODO: improve error treatment
-            let mut writer = fs::File::create(&output_path)?;
+            let mut writer =
+                SparseWriter::new(fs::File::create(&output_path)?, sparse::from_flags(flags)?);
 
-            io::copy(&mut reader, &mut writer)?;
-            println!("[INFO]: Successfully uncompressed file at '{}'.", to_utf(output_path));
+            let copied = utils::io::copy_limited(&mut reader, &mut writer, &output_path, limit);
+            // A file cut short by Ctrl-C would look like a complete one
+            if copied == Err(crate::Error::Cancelled) {
+                fs::remove_file(&output_path)?;
+            }
+            let bytes = copied?;
+            writer.finish()?;
+            if !flags.is_present("no-mtime") {
+                if let Some(mtime) = stored.mtime {
+                    fs::File::options().write(true).open(&output_path)?.set_modified(mtime)?;
+                }
+            }
+            events.event(Event::EntryFinished { entry, path: output_path.clone(), bytes });
+            logger::info(format!("Successfully uncompressed file at '{}'.", to_utf(&output_path)))
+                .path(&output_path)
+                .log();
+            vec![output_path]
         },
         Tar => {
             utils::create_dir_if_non_existent(output_folder)?;
-            let _ = crate::archive::tar::unpack_archive(reader, output_folder, flags)?;
-            println!("[INFO]: Successfully uncompressed bundle at '{}'.", to_utf(output_folder));
+            crate::archive::tar::unpack_archive(reader, output_folder, limit, events, flags)?
+        },
+        Cpio => {
+            utils::create_dir_if_non_existent(output_folder)?;
+            crate::archive::cpio::unpack_archive(reader, output_folder, limit, events, flags)?
         },
         Zip => {
             utils::create_dir_if_non_existent(output_folder)?;
 
-            eprintln!("Compressing first into .zip.");
-            eprintln!("Warning: .zip archives with extra extensions have a downside.");
-            eprintln!("The only way is loading everything into the RAM while compressing, and then write everything down.");
-            eprintln!("this means that by compressing .zip with extra compression formats, you can run out of RAM if the file is too large!");
+            // .zip needs to seek, which the decoders in front of it can't do
+            let temp_dir = utils::io::temp_dir(flags, Some(output_folder));
+            logger::debug(format!(
+                "Copying the decompressed .zip to a temporary file in '{}' first.",
+                to_utf(&temp_dir)
+            ))
+            .path(input_file_path)
+            .log();
+            let zip_archive = zip::ZipArchive::new(utils::io::spool(&mut reader, &temp_dir)?)?;
+
+            crate::archive::zip::unpack_archive(
+                zip_archive,
+                output_folder,
+                limit,
+                &progress,
+                events,
+                flags,
+            )?
+        },
+        Iso => {
+            utils::create_dir_if_non_existent(output_folder)?;
+
+            // Same limitation as .zip, the whole image is decompressed into a temporary file first
+            let temp_dir = utils::io::temp_dir(flags, Some(output_folder));
+            let image = archive::iso::IsoImage::new(utils::io::spool(&mut reader, &temp_dir)?)?;
+            archive::iso::unpack_archive(image, output_folder, limit, &progress, events, flags)?
+        },
+    };
+    progress.finish();
+
+    Ok(files)
+}
+
+/// Grab previous decoder and wrap it inside of a new one
+pub(crate) fn chain_reader_decoder(
+    format: &CompressionFormat,
+    decoder: Box<dyn Read>,
+    zstd_options: &zstandard::Options,
+) -> crate::Result<Box<dyn Read>> {
+    let input = PositionReader::new(BufReader::new(decoder));
+    let position = input.position();
+    let decoder: Box<dyn Read> = match format {
+        Gzip | Bzip => Box::new(MultiMemberDecoder::new(input, format.clone())),
+        // xz reads the streams that follow the first one itself, and fails on trailing data, like
+        // `xz -d` does
+        Lzma => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(input)),
+        Snappy => Box::new(snap::read::FrameDecoder::new(input)),
+        Zstd => zstd_options.decoder(input)?,
+        // Frames or a block, whichever the magic bytes say
+        Lz4 | Lz4Block => lz4::decoder(input)?,
+        _ => unreachable!(),
+    };
+    // Decoding errors are reported as a corrupted archive, with how far into its input it got
+    Ok(Box::new(DecodingReader::new(decoder, format.clone(), position)))
+}
 
-            let mut vec = vec![];
-            io::copy(&mut reader, &mut vec)?;
-            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
+/// An archive that was found inside of another one and extracted, along with the archives found
+/// inside of it.
+struct NestedArchive {
+    path: PathBuf,
+    children: Vec<NestedArchive>,
+}
 
-            let _ = crate::archive::zip::unpack_archive(zip_archive, output_folder, flags)?;
+/// If `--extract-nested` was passed, extract the archives found among the freshly extracted
+/// `files`, up to `--max-nested-depth` levels deep (1 by default), and print what was expanded.
+fn extract_nested_archives(
+    archive_path: &Path,
+    files: Vec<PathBuf>,
+    limit: &mut OutputLimit,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    if !flags.is_present("extract-nested") {
+        return Ok(());
+    }
 
-            println!("[INFO]: Successfully uncompressed bundle at '{}'.", to_utf(output_folder));
+    let max_depth = match flags.arg("max-nested-depth") {
+        Some(depth) => {
+            depth.to_str().and_then(|depth| depth.parse().ok()).ok_or(crate::Error::InvalidInput)?
         },
+        None => 1,
+    };
+
+    let nested = extract_nested_level(files, max_depth, limit, flags)?;
+    if nested.is_empty() {
+        return Ok(());
     }
 
+    let mut tree = to_utf(archive_path);
+    write_nested_tree(&mut tree, &nested, 1);
+    logger::info(format!("Nested archives extracted:\n{}", tree)).path(archive_path).log();
     Ok(())
 }
+
+/// The archives found in `files` are extracted within `limit`, the one of the outermost archive,
+/// so that nesting doesn't multiply how much can be extracted
+fn extract_nested_level(
+    files: Vec<PathBuf>,
+    depth_left: usize,
+    limit: &mut OutputLimit,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<NestedArchive>> {
+    let mut nested = vec![];
+    if depth_left == 0 {
+        return Ok(nested);
+    }
+
+    for path in files.into_iter().filter(|path| path.is_file()) {
+        let (file_name, formats) = extension::separate_known_extensions_from_name(&path);
+        if formats.is_empty() {
+            continue;
+        }
+
+        // The inner archive is replaced by a folder with its name, single file formats are
+        // decompressed right next to it
+        // Safe unwraps: `path` is a file, so it has a parent and a file name
+        let parent = path.parent().unwrap();
+        let file_name = Path::new(file_name.file_name().unwrap());
+        let output_folder = match formats[0] {
+            Tar | Zip | Cpio | Iso => parent.join(file_name),
+            Gzip | Bzip | Lzma | Snappy | Zstd | Lz4 | Lz4Block => parent.to_path_buf(),
+        };
+
+        let progress = Rc::new(Progress::new());
+        let events = LogEvents::default();
+        let inner_files = decompress_file(
+            &path,
+            formats,
+            Some(&output_folder),
+            file_name,
+            limit,
+            progress,
+            &events,
+            flags,
+        )?;
+        let children = extract_nested_level(inner_files, depth_left - 1, limit, flags)?;
+
+        if !flags.is_present("keep-nested") {
+            fs::remove_file(&path)?;
+        }
+
+        nested.push(NestedArchive { path, children });
+    }
+
+    Ok(nested)
+}
+
+fn write_nested_tree(tree: &mut String, nested: &[NestedArchive], indentation: usize) {
+    for archive in nested {
+        // Safe unwrap: only files are added to the tree
+        let name = archive.path.file_name().unwrap();
+        tree.push_str(&format!("\n{}└─ {}", "   ".repeat(indentation - 1), to_utf(name)));
+        write_nested_tree(tree, &archive.children, indentation + 1);
+    }
+}
@@ -0,0 +1,501 @@
+//! `ouch completions SHELL`: completion scripts, written from the same flags that
+//! `cli::parse_args_from` takes, so that a new flag is completed as soon as ouch accepts it.
+//!
+//! The values of flags that only take a few, like `--each` or `--charset`, are completed too, and
+//! the other arguments are completed as files.
+
+use std::fmt::Write;
+
+use crate::{cli, extension::FORMATS, oof, utils};
+
+/// The usual ones, `--charset` takes any label of the WHATWG Encoding Standard
+const CHARSETS: [&str; 8] =
+    ["cp437", "auto", "utf-8", "shift_jis", "gbk", "big5", "euc-kr", "windows-1252"];
+
+/// Older spellings of other flags, accepted but not completed
+const HIDDEN_FLAGS: [&str; 2] = ["zip-encoding", "show-path"];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Elvish,
+}
+
+impl Shell {
+    const NAMES: [&'static str; 5] = ["bash", "zsh", "fish", "powershell", "elvish"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            "powershell" => Some(Self::Powershell),
+            "elvish" => Some(Self::Elvish),
+            _ => None,
+        }
+    }
+}
+
+/// Print the completion script for `shell` to stdout
+pub fn print_completions(shell: Shell) -> crate::Result<()> {
+    utils::write_stdout(completion_script(shell))
+}
+
+fn completion_script(shell: Shell) -> String {
+    let contexts = contexts();
+    match shell {
+        Shell::Bash => bash(&contexts),
+        Shell::Zsh => zsh(&contexts),
+        Shell::Fish => fish(&contexts),
+        Shell::Powershell => powershell(&contexts),
+        Shell::Elvish => elvish(&contexts),
+    }
+}
+
+/// Decompression, named "ouch", or a subcommand, along with what can be given to it
+struct Context {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    flags: Vec<oof::Flag>,
+    /// What the arguments that aren't flags can be, files when there are none
+    arguments: Vec<String>,
+}
+
+impl Context {
+    /// The aliases and the name it is given by
+    fn names(&self) -> Vec<&'static str> {
+        self.aliases.iter().copied().chain([self.name]).collect()
+    }
+
+    /// Every name the flags are given by, like "-y" and "--yes"
+    fn flag_names(&self) -> Vec<String> {
+        self.flags.iter().flat_map(flag_names).collect()
+    }
+
+    /// The names of the flags whose values are completed, like "--each", along with the values
+    fn flag_values(&self) -> Vec<(String, Vec<String>)> {
+        self.flags
+            .iter()
+            .filter_map(|flag| Some((flag, flag_values(self.name, flag.long)?)))
+            .flat_map(|(flag, values)| {
+                flag_names(flag).into_iter().map(move |name| (name, values.clone()))
+            })
+            .collect()
+    }
+}
+
+fn contexts() -> Vec<Context> {
+    let subcommands =
+        cli::SUBCOMMANDS.iter().map(|&(name, aliases)| (name, aliases, Some(name)));
+    std::iter::once(("ouch", &[][..], None))
+        .chain(subcommands)
+        .map(|(name, aliases, subcommand)| {
+            let flags = cli::global_flags()
+                .into_iter()
+                .chain(cli::subcommand_flags(subcommand))
+                .filter(|flag| !HIDDEN_FLAGS.contains(&flag.long))
+                .collect();
+            let arguments = match subcommand {
+                Some("completions") => Shell::NAMES.map(String::from).to_vec(),
+                Some("config") => vec!["path".to_string(), "show".to_string()],
+                _ => vec![],
+            };
+            Context { name, aliases, flags, arguments }
+        })
+        .collect()
+}
+
+fn flag_names(flag: &oof::Flag) -> Vec<String> {
+    let short = flag.short.map(|short| format!("-{}", short));
+    short.into_iter().chain([format!("--{}", flag.long)]).collect()
+}
+
+/// The values of the flag `long` of the subcommand `context`, when it only takes a few
+fn flag_values(context: &str, long: &str) -> Option<Vec<String>> {
+    let values: &[&str] = match (context, long) {
+        (_, "each") => return Some(extension_chains()),
+        (_, "charset") => &CHARSETS,
+        (_, "log-format") => &["human", "json"],
+        (_, "sandbox") => &["strict", "normal", "off"],
+        (_, "special-files") => &["skip", "store", "fail"],
+        (_, "preserve") => &["ownership", "xattrs", "atime", "all"],
+        (_, "format-tar") => &["pax", "gnu", "ustar"],
+        (_, "checksum-file") => &["sha256", "sha512", "blake3"],
+        ("merge", "conflict") => &["rename", "skip", "error"],
+        (_, "conflict") => &["ask", "rename", "skip"],
+        _ => return None,
+    };
+    Some(values.iter().map(|value| value.to_string()).collect())
+}
+
+/// The extensions that can be compressed to, alone and with every compression format after the
+/// archive ones, like "tar.gz"
+fn extension_chains() -> Vec<String> {
+    let compressible = || FORMATS.iter().filter(|capabilities| capabilities.compress);
+    let compressor_extensions = compressible()
+        .filter(|capabilities| !capabilities.format.is_archive())
+        .flat_map(|capabilities| capabilities.extensions);
+
+    let mut chains: Vec<String> = compressible()
+        .flat_map(|capabilities| capabilities.extensions)
+        .map(|extension| extension.to_string())
+        .collect();
+    for archive in compressible().filter(|capabilities| capabilities.format.is_archive()) {
+        let archive_extension = archive.extensions[0];
+        chains.extend(
+            compressor_extensions
+                .clone()
+                .map(|extension| format!("{}.{}", archive_extension, extension)),
+        );
+    }
+    chains
+}
+
+/// The subcommands, the first argument completed
+fn subcommand_names(contexts: &[Context]) -> String {
+    contexts[1..].iter().map(|context| context.name).collect::<Vec<_>>().join(" ")
+}
+
+fn bash(contexts: &[Context]) -> String {
+    let mut script = String::new();
+    script.push_str(
+        r#"_ouch() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    local subcommand=ouch flags values arguments
+    if [[ $COMP_CWORD -gt 1 ]]; then
+        case "${COMP_WORDS[1]}" in
+"#,
+    );
+    for context in &contexts[1..] {
+        let names = context.names().join("|");
+        let _ = writeln!(script, "            {}) subcommand={} ;;", names, context.name);
+    }
+    script.push_str("        esac\n    fi\n\n    case \"$subcommand\" in\n");
+    for context in contexts {
+        let _ = writeln!(script, "        {})", context.name);
+        let _ = writeln!(script, "            flags=\"{}\"", context.flag_names().join(" "));
+        if !context.arguments.is_empty() {
+            let _ = writeln!(script, "            arguments=\"{}\"", context.arguments.join(" "));
+        }
+        script.push_str("            ;;\n");
+    }
+    script.push_str("    esac\n\n    case \"$subcommand $prev\" in\n");
+    for context in contexts {
+        for (name, values) in context.flag_values() {
+            let _ = writeln!(
+                script,
+                "        \"{} {}\") values=\"{}\" ;;",
+                context.name,
+                name,
+                values.join(" ")
+            );
+        }
+    }
+    let _ = write!(
+        script,
+        r#"    esac
+
+    if [[ -n "$values" ]]; then
+        COMPREPLY=($(compgen -W "$values" -- "$cur"))
+    elif [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "$flags" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{}" -- "$cur") $(compgen -f -- "$cur"))
+    elif [[ -n "$arguments" ]]; then
+        COMPREPLY=($(compgen -W "$arguments" -- "$cur"))
+    fi
+}}
+
+complete -o default -F _ouch ouch
+"#,
+        subcommand_names(contexts)
+    );
+    script
+}
+
+fn zsh(contexts: &[Context]) -> String {
+    let mut script = String::new();
+    script.push_str(
+        r#"#compdef ouch
+
+_ouch() {
+    local subcommand=ouch
+    local -a specs
+    if (( CURRENT > 2 )); then
+        case $words[2] in
+"#,
+    );
+    for context in &contexts[1..] {
+        let names = context.names().join("|");
+        let _ = writeln!(script, "            ({}) subcommand={} ;;", names, context.name);
+    }
+    // The subcommand is left out of the words the specs describe
+    script.push_str(
+        r#"        esac
+    fi
+    if [[ $subcommand != ouch ]]; then
+        shift words
+        (( CURRENT-- ))
+    fi
+
+    case $subcommand in
+"#,
+    );
+    for context in contexts {
+        let _ = writeln!(script, "        ({})\n            specs=(", context.name);
+        for flag in &context.flags {
+            let repeated = if flag.repeated { "*" } else { "" };
+            let argument = match flag_values(context.name, flag.long) {
+                _ if !flag.takes_value => String::new(),
+                Some(values) => format!(":{}:({})", flag.long, values.join(" ")),
+                None => format!(":{}:_files", flag.long),
+            };
+            for name in flag_names(flag) {
+                let _ = writeln!(script, "                '{}{}{}'", repeated, name, argument);
+            }
+        }
+        match context.name {
+            "ouch" => {
+                // The subcommands or the first archive
+                let subcommands =
+                    format!("subcommands:subcommand:({})", subcommand_names(contexts));
+                let _ = writeln!(
+                    script,
+                    "                '1: :{{_alternative \"{}\" \"files:archive:_files\"}}'",
+                    subcommands
+                );
+                script.push_str("                '*:archive:_files'\n");
+            },
+            _ if !context.arguments.is_empty() => {
+                let _ = writeln!(script, "                '1: :({})'", context.arguments.join(" "));
+            },
+            _ => script.push_str("                '*:file:_files'\n"),
+        }
+        script.push_str("            )\n            ;;\n");
+    }
+    script.push_str(
+        r#"    esac
+    _arguments -s $specs
+}
+
+if [ "$funcstack[1]" = "_ouch" ]; then
+    _ouch "$@"
+else
+    compdef _ouch ouch
+fi
+"#,
+    );
+    script
+}
+
+fn fish(contexts: &[Context]) -> String {
+    let mut script = String::new();
+    let every_subcommand: Vec<&str> =
+        contexts[1..].iter().flat_map(|context| context.names()).collect();
+    let _ = writeln!(
+        script,
+        "complete -c ouch -n \"__fish_use_subcommand\" -a \"{}\"",
+        subcommand_names(contexts)
+    );
+    for context in contexts {
+        let condition = match context.name {
+            "ouch" => format!("not __fish_seen_subcommand_from {}", every_subcommand.join(" ")),
+            _ => format!("__fish_seen_subcommand_from {}", context.names().join(" ")),
+        };
+        for flag in &context.flags {
+            let mut line = format!("complete -c ouch -n \"{}\"", condition);
+            if let Some(short) = flag.short {
+                let _ = write!(line, " -s {}", short);
+            }
+            let _ = write!(line, " -l {}", flag.long);
+            match flag_values(context.name, flag.long) {
+                _ if !flag.takes_value => {},
+                Some(values) => {
+                    let _ = write!(line, " -x -a \"{}\"", values.join(" "));
+                },
+                None => line.push_str(" -r"),
+            }
+            script.push_str(&line);
+            script.push('\n');
+        }
+        if !context.arguments.is_empty() {
+            let _ = writeln!(
+                script,
+                "complete -c ouch -n \"{}\" -f -a \"{}\"",
+                condition,
+                context.arguments.join(" ")
+            );
+        }
+    }
+    script
+}
+
+fn powershell(contexts: &[Context]) -> String {
+    let quoted = |words: &[String]| {
+        let words: Vec<String> = words.iter().map(|word| format!("'{}'", word)).collect();
+        format!("@({})", words.join(", "))
+    };
+    let mut script = String::new();
+    script.push_str(
+        r#"Register-ArgumentCompleter -Native -CommandName ouch -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+"#,
+    );
+    let aliases: Vec<String> = contexts
+        .iter()
+        .flat_map(|context| context.aliases.iter().map(move |alias| (alias, context.name)))
+        .map(|(alias, name)| format!("'{}' = '{}'", alias, name))
+        .collect();
+    let _ = writeln!(script, "    $aliases = @{{ {} }}", aliases.join("; "));
+    let subcommands: Vec<String> = contexts[1..].iter().map(|c| c.name.to_string()).collect();
+    let _ = writeln!(script, "    $subcommands = {}", quoted(&subcommands));
+    script.push_str("    $flags = @{\n");
+    for context in contexts {
+        let _ = writeln!(script, "        '{}' = {}", context.name, quoted(&context.flag_names()));
+    }
+    script.push_str("    }\n    $values = @{\n");
+    for context in contexts {
+        for (name, values) in context.flag_values() {
+            let _ = writeln!(script, "        '{} {}' = {}", context.name, name, quoted(&values));
+        }
+    }
+    script.push_str("    }\n    $arguments = @{\n");
+    for context in contexts.iter().filter(|context| !context.arguments.is_empty()) {
+        let _ = writeln!(script, "        '{}' = {}", context.name, quoted(&context.arguments));
+    }
+    script.push_str(
+        r#"    }
+
+    # The words before the one being completed
+    $words = @($commandAst.CommandElements |
+        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } |
+        ForEach-Object { $_.ToString() })
+    $subcommand = 'ouch'
+    if ($words.Count -gt 1) {
+        $name = $words[1]
+        if ($aliases.ContainsKey($name)) { $name = $aliases[$name] }
+        if ($flags.ContainsKey($name)) { $subcommand = $name }
+    }
+    $key = "$subcommand $($words[-1])"
+
+    $candidates = if ($values.ContainsKey($key)) {
+        $values[$key]
+    } elseif ($wordToComplete.StartsWith('-')) {
+        $flags[$subcommand]
+    } elseif ($words.Count -eq 1) {
+        $subcommands
+    } elseif ($arguments.ContainsKey($subcommand)) {
+        $arguments[$subcommand]
+    } else {
+        @()
+    }
+    # Nothing returned falls back to completing files
+    $candidates | Where-Object { $_ -like "$wordToComplete*" } | ForEach-Object {
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }
+}
+"#,
+    );
+    script
+}
+
+fn elvish(contexts: &[Context]) -> String {
+    let list = |words: &[String]| {
+        let words: Vec<String> = words.iter().map(|word| format!("'{}'", word)).collect();
+        format!("[{}]", words.join(" "))
+    };
+    let mut script = String::new();
+    script.push_str("use str\n\nset edit:completion:arg-completer[ouch] = {|@words|\n");
+    let aliases: Vec<String> = contexts
+        .iter()
+        .flat_map(|context| context.aliases.iter().map(move |alias| (alias, context.name)))
+        .map(|(alias, name)| format!("&'{}'='{}'", alias, name))
+        .collect();
+    let _ = writeln!(script, "    var aliases = [{}]", aliases.join(" "));
+    let subcommands: Vec<String> = contexts[1..].iter().map(|c| c.name.to_string()).collect();
+    let _ = writeln!(script, "    var subcommands = {}", list(&subcommands));
+    script.push_str("    var flags = [\n");
+    for context in contexts {
+        let _ = writeln!(script, "        &'{}'={}", context.name, list(&context.flag_names()));
+    }
+    script.push_str("    ]\n    var values = [\n");
+    for context in contexts {
+        for (name, values) in context.flag_values() {
+            let _ = writeln!(script, "        &'{} {}'={}", context.name, name, list(&values));
+        }
+    }
+    script.push_str("    ]\n    var arguments = [\n");
+    for context in contexts.iter().filter(|context| !context.arguments.is_empty()) {
+        let _ = writeln!(script, "        &'{}'={}", context.name, list(&context.arguments));
+    }
+    script.push_str(
+        r#"    ]
+
+    var subcommand = 'ouch'
+    if (> (count $words) 2) {
+        var name = $words[1]
+        if (has-key $aliases $name) {
+            set name = $aliases[$name]
+        }
+        if (has-key $flags $name) {
+            set subcommand = $name
+        }
+    }
+    var current = $words[-1]
+    var key = $subcommand' '$words[-2]
+
+    if (has-key $values $key) {
+        all $values[$key]
+    } elif (str:has-prefix $current '-') {
+        all $flags[$subcommand]
+    } elif (== (count $words) 2) {
+        all $subcommands
+        edit:complete-filename $current
+    } elif (has-key $arguments $subcommand) {
+        all $arguments[$subcommand]
+    } else {
+        edit:complete-filename $current
+    }
+}
+"#,
+    );
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_scripts() {
+        let chains = extension_chains();
+        let has = |chain: &str| chains.iter().any(|value| value == chain);
+        assert!(has("tar.gz") && has("tgz") && has("zip.xz") && has("cpio.bz2"));
+        assert!(!has("iso"));
+
+        // Every flag the parser takes is completed, along with the values of the few that have
+        // them, and hidden flags are left out
+        let bash = completion_script(Shell::Bash);
+        assert!(bash.contains("c|compress) subcommand=compress ;;"));
+        assert!(bash.contains("\"compress --each\") values=\"tar "));
+        assert!(bash.contains("\"merge --conflict\") values=\"rename skip error\""));
+        for flag in cli::subcommand_flags(None) {
+            assert!(bash.contains(&format!(" --{}", flag.long)) || flag.long == "zip-encoding");
+        }
+        assert!(!bash.contains("--show-path"));
+
+        let zsh = completion_script(Shell::Zsh);
+        assert!(zsh.contains("'*--transform:transform:_files'"));
+        assert!(zsh.contains("'--log-format:log-format:(human json)'"));
+        let fish = completion_script(Shell::Fish);
+        assert!(fish.contains("__fish_seen_subcommand_from c compress\" -l each -x -a \"tar "));
+        for shell in Shell::NAMES {
+            let shell = Shell::from_name(shell).unwrap();
+            assert!(completion_script(shell).contains("no-same-permissions"));
+        }
+    }
+}
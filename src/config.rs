@@ -0,0 +1,301 @@
+//! Default options read from `ouch.toml`, flags given in the command line take precedence.
+//!
+//! The file is looked up at `$XDG_CONFIG_HOME/ouch/ouch.toml` (or `~/.config/ouch/ouch.toml`) on
+//! Linux, `~/Library/Application Support/ouch/ouch.toml` on macOS and
+//! `%APPDATA%\ouch\ouch.toml` on Windows. `--no-config` skips it.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    oof,
+    utils::{self, logger, to_utf},
+};
+
+/// Overrides `question_policy`, for containers where environment variables are easier to set
+pub const OVERWRITE_VAR: &str = "OUCH_OVERWRITE";
+
+/// What to do when a file is about to be overwritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionPolicy {
+    Ask,
+    AlwaysYes,
+    AlwaysNo,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub default_threads: Option<usize>,
+    pub default_level: Option<u32>,
+    pub progress: Option<bool>,
+    pub accessible: Option<bool>,
+    pub question_policy: Option<QuestionPolicy>,
+    /// Seconds that questions wait for an answer before defaulting to no
+    pub question_timeout: Option<u64>,
+    /// Leave out the folders of version control systems when compressing, like `--exclude-vcs`
+    pub exclude_vcs: Option<bool>,
+}
+
+impl Config {
+    /// Location of the config file, even if it doesn't exist
+    pub fn path() -> Option<PathBuf> {
+        let config_dir = if cfg!(target_os = "macos") {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+        } else if cfg!(target_os = "windows") {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        };
+
+        config_dir.map(|dir| dir.join("ouch").join("ouch.toml"))
+    }
+
+    /// Load the config file, a missing file means that everything is left at its default
+    pub fn load() -> crate::Result<Self> {
+        match Self::path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> crate::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        toml::from_str(&text).map_err(|err| {
+            // Quoted as it was written, the error only tells where it is
+            let line = err.line_col().and_then(|(line, _)| {
+                text.lines().nth(line).map(|text| format!("{} | {}", line + 1, text))
+            });
+            crate::Error::InvalidConfig { path: path.to_path_buf(), reason: err.to_string(), line }
+        })
+    }
+
+    /// Override the file with the environment variables, which take precedence over it
+    pub fn apply_env(&mut self) -> crate::Result<()> {
+        self.apply_env_from(|name| env::var_os(name).filter(|value| !value.is_empty()))
+    }
+
+    fn apply_env_from(&mut self, var: impl Fn(&str) -> Option<OsString>) -> crate::Result<()> {
+        if let Some(value) = var(OVERWRITE_VAR) {
+            self.question_policy = Some(match value.to_str() {
+                Some("ask") => QuestionPolicy::Ask,
+                Some("yes" | "always_yes") => QuestionPolicy::AlwaysYes,
+                Some("no" | "always_no") => QuestionPolicy::AlwaysNo,
+                _ => {
+                    return Err(crate::Error::InvalidEnvVar {
+                        name: OVERWRITE_VAR,
+                        reason: format!("'{}' is not one of ask, yes or no", to_utf(&value)),
+                    })
+                },
+            });
+        }
+
+        for name in ["OUCH_LEVEL", "OUCH_THREADS"] {
+            if var(name).is_some() {
+                logger::warning(format!(
+                    "'{}' is not supported by this version of ouch, ignoring it.",
+                    name
+                ))
+                .category("unsupported config keys")
+                .log();
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in the defaults for what wasn't given in the command line
+    pub fn apply_to(&self, flags: &mut oof::Flags) {
+        let has_question_flag = flags.is_present("yes") || flags.is_present("no");
+        match self.question_policy {
+            Some(QuestionPolicy::AlwaysYes) if !has_question_flag => {
+                flags.boolean_flags.insert("yes");
+            },
+            Some(QuestionPolicy::AlwaysNo) if !has_question_flag => {
+                flags.boolean_flags.insert("no");
+            },
+            _ => {},
+        }
+        if let Some(seconds) = self.question_timeout {
+            if !flags.argument_flags.contains_key("question-timeout") {
+                flags.argument_flags.insert("question-timeout", seconds.to_string().into());
+            }
+        }
+        if self.exclude_vcs == Some(true) && !flags.is_present("no-exclude-vcs") {
+            flags.boolean_flags.insert("exclude-vcs");
+        }
+
+        let unsupported_keys = [
+            ("default_threads", self.default_threads.is_some()),
+            ("default_level", self.default_level.is_some()),
+            ("progress", self.progress.is_some()),
+            ("accessible", self.accessible.is_some()),
+        ];
+        for (key, _) in unsupported_keys.iter().filter(|(_, is_set)| *is_set) {
+            logger::warning(format!(
+                "'{}' is not supported by this version of ouch, ignoring it.",
+                key
+            ))
+            .category("unsupported config keys")
+            .log();
+        }
+    }
+}
+
+/// `ouch config path`, or `ouch config --show-path`
+pub fn show_path() -> crate::Result<()> {
+    let path = Config::path().ok_or(crate::Error::InvalidConfig {
+        path: PathBuf::new(),
+        reason: "could not find the configuration directory".into(),
+        line: None,
+    })?;
+
+    utils::write_stdout(format!("{}\n", to_utf(&path)))?;
+    if !path.exists() {
+        logger::info("The file does not exist yet.").path(&path).log();
+    }
+    Ok(())
+}
+
+/// `ouch config show`: the settings in effect, from the config file and the flags given with it
+pub fn show(flags: &oof::Flags) -> crate::Result<()> {
+    let loaded_from = match Config::path() {
+        _ if flags.is_present("no-config") => "not loaded, because of --no-config".to_string(),
+        Some(path) if path.exists() => format!("loaded from '{}'", to_utf(&path)),
+        Some(path) => format!("'{}' does not exist, using the defaults", to_utf(&path)),
+        None => "not found, using the defaults".to_string(),
+    };
+
+    utils::write_stdout(format!("# Config file {}\n{}", loaded_from, effective_settings(flags)))
+}
+
+/// The keys of the config file, with the values that `flags` ended up with
+fn effective_settings(flags: &oof::Flags) -> String {
+    let question_policy = match (flags.is_present("yes"), flags.is_present("no")) {
+        (true, _) => "always_yes",
+        (_, true) => "always_no",
+        _ => "ask",
+    };
+    let question_timeout = match flags.arg("question-timeout") {
+        Some(seconds) => format!("question_timeout = {}", to_utf(seconds)),
+        // Questions wait forever by default, which TOML can't write down
+        None => "# question_timeout is not set".to_string(),
+    };
+
+    format!(
+        "question_policy = \"{}\"\n{}\nexclude_vcs = {}\n",
+        question_policy,
+        question_timeout,
+        flags.is_present("exclude-vcs")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_parsing() {
+        let config: Config = toml::from_str("question_policy = \"always_yes\"").unwrap();
+        assert_eq!(config.question_policy, Some(QuestionPolicy::AlwaysYes));
+        assert_eq!(config.default_level, None);
+
+        assert!(toml::from_str::<Config>("question_policy = \"maybe\"").is_err());
+        assert!(toml::from_str::<Config>("unknown_key = 1").is_err());
+    }
+
+    #[test]
+    fn test_command_line_takes_precedence() {
+        let config =
+            Config { question_policy: Some(QuestionPolicy::AlwaysYes), ..Config::default() };
+
+        let mut flags = oof::Flags::default();
+        config.apply_to(&mut flags);
+        assert!(flags.is_present("yes"));
+
+        let mut flags = oof::Flags::default();
+        flags.boolean_flags.insert("no");
+        config.apply_to(&mut flags);
+        assert!(!flags.is_present("yes"));
+
+        let config = Config { question_timeout: Some(30), ..Config::default() };
+        let mut flags = oof::Flags::default();
+        config.apply_to(&mut flags);
+        assert_eq!(flags.arg("question-timeout").unwrap(), "30");
+
+        let mut flags = oof::Flags::default();
+        flags.argument_flags.insert("question-timeout", "5".into());
+        config.apply_to(&mut flags);
+        assert_eq!(flags.arg("question-timeout").unwrap(), "5");
+
+        let config = Config { exclude_vcs: Some(true), ..Config::default() };
+        let mut flags = oof::Flags::default();
+        flags.boolean_flags.insert("no-exclude-vcs");
+        config.apply_to(&mut flags);
+        assert!(!flags.is_present("exclude-vcs"));
+    }
+
+    #[test]
+    fn test_env_overrides_the_file() {
+        let mut config =
+            Config { question_policy: Some(QuestionPolicy::AlwaysYes), ..Config::default() };
+        config.apply_env_from(|name| (name == OVERWRITE_VAR).then(|| "ask".into())).unwrap();
+        assert_eq!(config.question_policy, Some(QuestionPolicy::Ask));
+
+        let mut flags = oof::Flags::default();
+        config.apply_to(&mut flags);
+        assert!(!flags.is_present("yes"));
+
+        assert!(config
+            .apply_env_from(|name| (name == OVERWRITE_VAR).then(|| "sometimes".into()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_config_quotes_the_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ouch.toml");
+        fs::write(&path, "question_policy = \"ask\"\nquestion_timeout = soon\n").unwrap();
+
+        match Config::load_from(&path) {
+            Err(crate::Error::InvalidConfig { line, .. }) => {
+                assert_eq!(line.as_deref(), Some("2 | question_timeout = soon"));
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_effective_settings() {
+        let config = Config {
+            question_policy: Some(QuestionPolicy::AlwaysNo),
+            exclude_vcs: Some(true),
+            ..Config::default()
+        };
+        let mut flags = oof::Flags::default();
+        flags.argument_flags.insert("question-timeout", "10".into());
+        config.apply_to(&mut flags);
+
+        let settings = effective_settings(&flags);
+        assert_eq!(
+            settings,
+            "question_policy = \"always_no\"\nquestion_timeout = 10\nexclude_vcs = true\n"
+        );
+        // What is shown can be read back as a config file
+        assert!(toml::from_str::<Config>(&settings).is_ok());
+    }
+}
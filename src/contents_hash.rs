@@ -0,0 +1,168 @@
+//! `ouch contents-hash`: a fingerprint of the files inside of archives, the same whatever format
+//! they were compressed with and whatever order their entries were stored in.
+//!
+//! Every regular file is hashed on its own, so only one of them is read at a time. The BLAKE3 of
+//! the archive is then taken over the sorted paths, each followed by a NUL byte and the BLAKE3 of
+//! the file. Folders, links and special files are left out, so are owners and permissions.
+
+use std::{
+    fs,
+    io::{self, prelude::*, BufReader},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    archive::{self, zip::SplitZipReader},
+    commands::chain_reader_decoder,
+    error::FinalError,
+    extension::{
+        self,
+        CompressionFormat::{self, *},
+    },
+    oof,
+    utils::{
+        self,
+        io::{spool, temp_dir},
+        to_utf,
+    },
+    zstandard,
+};
+
+/// A file of the archive: its path, with '/' separators, and the BLAKE3 of its contents
+type HashedFile = (String, [u8; 32]);
+
+pub fn hash_archives(archives: &[PathBuf], flags: &oof::Flags) -> crate::Result<()> {
+    for path in archives {
+        let formats = extension::extensions_from_path(path);
+        if !formats.first().is_some_and(CompressionFormat::is_archive) {
+            return Err(FinalError::with_title(format!(
+                "Cannot hash the contents of '{}'.",
+                to_utf(path)
+            ))
+            .detail("Only archives, like .tar.gz or .zip, have contents to hash.")
+            .hint("Run 'ouch formats' for the full list of supported formats")
+            .into());
+        }
+
+        let files = hash_files(path, &formats, flags)?;
+        utils::write_stdout(format!("{}  {}\n", contents_hash(files), to_utf(path)))?;
+    }
+
+    Ok(())
+}
+
+/// Hash every regular file of the archive at `path`
+fn hash_files(
+    path: &Path,
+    formats: &[CompressionFormat],
+    flags: &oof::Flags,
+) -> crate::Result<Vec<HashedFile>> {
+    let mut files = vec![];
+    let visit = |path: PathBuf, contents: &mut dyn Read| -> crate::Result<()> {
+        files.push((canonical_path(&path), blake3_of(contents)?));
+        Ok(())
+    };
+
+    let file = fs::File::open(path)?;
+    // .zip and .iso need io::Seek, compressed ones are decompressed into a temporary file first
+    match *formats {
+        [Zip] => {
+            let volumes = SplitZipReader::open(path)?;
+            archive::zip::for_each_file(zip::ZipArchive::new(volumes)?, flags, visit)?
+        },
+        [Iso] => archive::iso::for_each_file(archive::iso::IsoImage::new(file)?, visit)?,
+        _ => {
+            let mut reader: Box<dyn Read> = Box::new(BufReader::new(file));
+            let zstd_options = zstandard::Options::from_flags(flags)?;
+            for format in formats.iter().skip(1).rev() {
+                reader = chain_reader_decoder(format, reader, &zstd_options)?;
+            }
+
+            match formats[0] {
+                Tar => archive::tar::for_each_file(reader, visit)?,
+                Cpio => archive::cpio::for_each_file(reader, visit)?,
+                Zip | Iso => {
+                    let file = spool(&mut reader, &temp_dir(flags, None))?;
+                    if formats[0] == Zip {
+                        archive::zip::for_each_file(zip::ZipArchive::new(file)?, flags, visit)?
+                    } else {
+                        archive::iso::for_each_file(archive::iso::IsoImage::new(file)?, visit)?
+                    }
+                },
+                Gzip | Bzip | Lzma | Snappy | Zstd | Lz4 | Lz4Block => {
+                    unreachable!("checked to be an archive above")
+                },
+            }
+        },
+    }
+
+    Ok(files)
+}
+
+/// "./dir//file" and "dir/file" are the same file
+fn canonical_path(path: &Path) -> String {
+    let names: Vec<String> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    names.join("/")
+}
+
+fn blake3_of(contents: &mut dyn Read) -> io::Result<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(contents, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// The hex BLAKE3 of `files`, in order of their paths
+fn contents_hash(mut files: Vec<HashedFile>) -> String {
+    // When a path shows up several times, the last one wins, like when extracting
+    files.reverse();
+    files.sort_by(|(first, _), (second, _)| first.cmp(second));
+    files.dedup_by(|(first, _), (second, _)| first == second);
+
+    let mut hasher = blake3::Hasher::new();
+    for (path, file_hash) in files {
+        hasher.update(path.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(&file_hash);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contents_hash() {
+        let file = |path: &str, contents: &[u8]| {
+            (canonical_path(Path::new(path)), blake3_of(&mut &contents[..]).unwrap())
+        };
+
+        let hash = contents_hash(vec![file("a.txt", b"a"), file("dir/b.txt", b"b")]);
+        // Neither the order nor the spelling of the paths matter
+        assert_eq!(contents_hash(vec![file("./dir//b.txt", b"b"), file("a.txt", b"a")]), hash);
+        // The last of the entries with the same path is the one kept
+        assert_eq!(
+            contents_hash(vec![
+                file("a.txt", b"old"),
+                file("dir/b.txt", b"b"),
+                file("a.txt", b"a")
+            ]),
+            hash
+        );
+
+        assert_ne!(contents_hash(vec![file("a.txt", b"a"), file("dir/b.txt", b"c")]), hash);
+        assert_ne!(contents_hash(vec![file("a.txt", b"a"), file("dir/c.txt", b"b")]), hash);
+
+        // The BLAKE3 of nothing, cached hashes depend on the algorithm staying the same
+        assert_eq!(
+            contents_hash(vec![]),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+}
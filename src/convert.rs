@@ -0,0 +1,163 @@
+//! `ouch convert`: a file compressed with some formats compressed again with others, like
+//! "backup.tar.gz" into "backup.tar.xz", without writing what is in between to disk.
+//!
+//! Only the compression formats around the archive change, the archive itself, like the .tar, is
+//! passed through as it is. The input is decompressed in a thread of its own, which sends the
+//! data to the one compressing it through a bounded channel.
+
+use std::{
+    fs,
+    io::{prelude::*, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, SyncSender},
+    thread,
+};
+
+use crate::{
+    cancel::Cancellable,
+    commands::{chain_reader_decoder, chain_writer_encoder},
+    error::FinalError,
+    extension::{self, CompressionFormat},
+    oof,
+    utils::{self, logger, to_utf},
+    zstandard,
+};
+
+/// Chunks of decompressed data read ahead of the compression, at most
+const CHUNKS_IN_FLIGHT: usize = 16;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub fn convert_file(
+    input_path: &Path,
+    output_path: &Path,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    let input_formats = extension::extensions_from_path(input_path);
+    let output_formats = extension::extensions_from_path(output_path);
+    for (path, formats) in [(input_path, &input_formats), (output_path, &output_formats)] {
+        if formats.is_empty() {
+            return Err(FinalError::with_title(format!("Cannot convert '{}'.", to_utf(path)))
+                .detail("Its extension is not one of the supported formats.")
+                .hint("Run 'ouch formats' for the full list of supported formats")
+                .into());
+        }
+    }
+
+    // The archive, if any, is the innermost format, kept as it is
+    let archive = |formats: &[CompressionFormat]| {
+        formats.first().filter(|format| format.is_archive()).cloned()
+    };
+    if archive(&input_formats) != archive(&output_formats) {
+        let describe = |formats: &[CompressionFormat]| match archive(formats) {
+            Some(archive) => format!("a {} archive", archive),
+            None => "a single compressed file".to_string(),
+        };
+        return Err(FinalError::with_title(format!(
+            "Cannot convert '{}' to '{}'.",
+            to_utf(input_path),
+            to_utf(output_path)
+        ))
+        .detail(format!(
+            "It is {}, and the output would be {}.",
+            describe(&input_formats),
+            describe(&output_formats)
+        ))
+        .detail("Only the compression formats around an archive can be changed.")
+        .hint("Decompress it and compress what it holds into the output instead.")
+        .into());
+    }
+    let archived = archive(&input_formats).is_some() as usize;
+
+    // Creating the output would truncate the input
+    if output_path.canonicalize().is_ok_and(|output_path| output_path == input_path) {
+        return Err(FinalError::with_title(format!(
+            "Cannot convert '{}' to '{}'.",
+            to_utf(input_path),
+            to_utf(output_path)
+        ))
+        .detail("The input and the output are the same file.")
+        .hint("Write the output to another path.")
+        .into());
+    }
+
+    if output_path.exists() && !utils::user_wants_to_overwrite(output_path, flags)? {
+        return Err(crate::Error::OverwriteDeclined(output_path.to_path_buf()));
+    }
+
+    zstandard::warn_unused_flags(&output_formats, flags);
+    let zstd_options = zstandard::Options::from_flags(flags)?;
+    let mut writer: Box<dyn Write> =
+        Box::new(BufWriter::new(Cancellable::new(fs::File::create(output_path)?)));
+    for format in output_formats.iter().skip(archived).rev() {
+        writer = chain_writer_encoder(format, writer, &zstd_options)?;
+    }
+    let input_codecs = input_formats[archived..].to_vec();
+    let result = restream(input_path.to_path_buf(), input_codecs, writer, zstd_options);
+
+    // Don't leave a partial file behind
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    } else {
+        logger::info(format!(
+            "Successfully converted '{}' to '{}'.",
+            to_utf(input_path),
+            to_utf(output_path)
+        ))
+        .path(output_path)
+        .log();
+    }
+
+    result
+}
+
+/// Decompress the file at `input_path` from `codecs` in another thread, and write what comes out
+/// to `writer`
+fn restream(
+    input_path: PathBuf,
+    codecs: Vec<CompressionFormat>,
+    mut writer: Box<dyn Write>,
+    zstd_options: zstandard::Options,
+) -> crate::Result<()> {
+    let (sender, receiver) = mpsc::sync_channel(CHUNKS_IN_FLIGHT);
+    let decoder = thread::spawn(move || decode(&input_path, &codecs, &zstd_options, sender));
+
+    let mut write = || -> crate::Result<()> {
+        for chunk in &receiver {
+            writer.write_all(&chunk)?;
+        }
+        writer.flush()?;
+        Ok(())
+    };
+    let written = write();
+    // Stops the decoding thread if writing failed
+    drop(receiver);
+
+    let decoded = decoder.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    // Writing stops early once decoding failed, the reason is the decoding error
+    decoded.and(written)
+}
+
+fn decode(
+    input_path: &Path,
+    codecs: &[CompressionFormat],
+    zstd_options: &zstandard::Options,
+    sender: SyncSender<Vec<u8>>,
+) -> crate::Result<()> {
+    let mut reader: Box<dyn Read> = Box::new(BufReader::new(fs::File::open(input_path)?));
+    for format in codecs.iter().rev() {
+        reader = chain_reader_decoder(format, reader, zstd_options)?;
+    }
+
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let length = reader.read(&mut chunk)?;
+        if length == 0 {
+            return Ok(());
+        }
+        chunk.truncate(length);
+        // The writing thread stopped, with an error of its own
+        if sender.send(chunk).is_err() {
+            return Ok(());
+        }
+    }
+}
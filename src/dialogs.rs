@@ -1,21 +1,34 @@
-use std::io::{self, Write};
+use std::{
+    io,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
 
-use crate::utils::colors;
+use crate::utils::{self, colors, logger};
+
+/// Lines read from stdin by the thread started for the first question with a timeout
+static ANSWERS: OnceLock<Mutex<Receiver<io::Result<String>>>> = OnceLock::new();
 
 pub struct Confirmation<'a> {
     pub prompt: &'a str,
     pub placeholder: Option<&'a str>,
 }
 
-#[derive(Debug)]
-pub struct Error;
-
 impl<'a> Confirmation<'a> {
     pub const fn new(prompt: &'a str, pattern: Option<&'a str>) -> Self {
         Self { prompt, placeholder: pattern }
     }
 
-    pub fn ask(&self, substitute: Option<&'a str>) -> crate::Result<bool> {
+    /// Ask until the answer is yes or no, or until `timeout` goes by without one, which is a no
+    pub fn ask(
+        &self,
+        substitute: Option<&'a str>,
+        timeout: Option<Duration>,
+    ) -> crate::Result<bool> {
         let message = match (self.placeholder, substitute) {
             (None, _) => self.prompt.into(),
             (Some(_), None) => return Err(crate::Error::InternalError),
@@ -23,18 +36,30 @@ impl<'a> Confirmation<'a> {
         };
 
         loop {
-            print!(
+            utils::write_stdout(format!(
                 "{} [{}Y{}/{}n{}] ",
                 message,
                 colors::green(),
                 colors::reset(),
                 colors::red(),
                 colors::reset()
-            );
-            io::stdout().flush()?;
+            ))?;
 
-            let mut answer = String::new();
-            io::stdin().read_line(&mut answer)?;
+            let answer = match timeout {
+                Some(timeout) => match read_answer_within(timeout)? {
+                    Some(answer) => answer,
+                    None => {
+                        utils::write_stdout("\n")?;
+                        logger::info("No response, defaulting to No.").log();
+                        return Ok(false);
+                    },
+                },
+                None => {
+                    let mut answer = String::new();
+                    io::stdin().read_line(&mut answer)?;
+                    answer
+                },
+            };
             let trimmed_answer = answer.trim();
 
             if trimmed_answer.is_empty() {
@@ -49,3 +74,33 @@ impl<'a> Confirmation<'a> {
         }
     }
 }
+
+/// The next line of stdin, or None if it didn't come within `timeout`, or stdin ended.
+///
+/// Reading stdin can't be interrupted, so a thread keeps reading it in the background: an answer
+/// typed after a timeout goes to the next question instead of being lost with its thread.
+fn read_answer_within(timeout: Duration) -> io::Result<Option<String>> {
+    let answers = ANSWERS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut answer = String::new();
+            let result = io::stdin().read_line(&mut answer).map(|_| answer);
+            let is_done = !matches!(&result, Ok(answer) if !answer.is_empty());
+            if sender.send(result).is_err() || is_done {
+                break;
+            }
+        });
+        Mutex::new(receiver)
+    });
+
+    // Safe unwrap: the lock is only held here, by code that doesn't panic
+    match answers.lock().unwrap().recv_timeout(timeout) {
+        // Nobody can answer once stdin ended, like when it is empty, which is a no like a
+        // timeout, and not the empty line that stands for yes
+        Ok(Ok(answer)) if answer.is_empty() => Ok(None),
+        Ok(answer) => answer.map(Some),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        // The thread stopped at the end of stdin
+        Err(RecvTimeoutError::Disconnected) => Ok(None),
+    }
+}
@@ -0,0 +1,151 @@
+//! `ouch encrypt` and `ouch decrypt`: any file, archive or not, encrypted with a passphrase into
+//! the age format (https://age-encryption.org), readable by the `age` tool too.
+//!
+//! The passphrase is read from `OUCH_PASSPHRASE` if it is set, and asked for otherwise. A wrong
+//! passphrase typed in a terminal is asked for again, up to three times in all.
+
+use std::{
+    env, fs,
+    io::{self, BufReader, BufWriter, IsTerminal, Write},
+    iter,
+    path::Path,
+};
+
+use age::{secrecy::SecretString, stream::StreamReader};
+
+use crate::{
+    cancel::Cancellable,
+    error::FinalError,
+    oof,
+    utils::{self, logger, to_utf},
+};
+
+/// Environment variable holding the passphrase, for scripts
+const PASSPHRASE_VAR: &str = "OUCH_PASSPHRASE";
+
+/// How many times a passphrase typed in a terminal is asked for before giving up
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+type Decryptor = age::Decryptor<BufReader<fs::File>>;
+
+pub fn encrypt_file(input: &Path, output_path: &Path, flags: &oof::Flags) -> crate::Result<()> {
+    if output_path.exists() && !utils::user_wants_to_overwrite(output_path, flags)? {
+        return Err(crate::Error::OverwriteDeclined(output_path.to_path_buf()));
+    }
+
+    let passphrase = read_passphrase(true)?;
+    write_output(output_path, |output| {
+        let mut reader = Cancellable::new(BufReader::new(fs::File::open(input)?));
+        let encryptor = age::Encryptor::with_user_passphrase(passphrase);
+        let mut writer = encryptor.wrap_output(output)?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.finish()?.flush()?;
+        Ok(())
+    })?;
+
+    logger::info(format!("Successfully encrypted '{}'.", to_utf(output_path)))
+        .path(output_path)
+        .log();
+    Ok(())
+}
+
+pub fn decrypt_file(input: &Path, output_path: &Path, flags: &oof::Flags) -> crate::Result<()> {
+    let open = || -> crate::Result<Decryptor> {
+        age::Decryptor::new_buffered(BufReader::new(fs::File::open(input)?)).map_err(decrypt_error)
+    };
+    let decryptor = open()?;
+    if !decryptor.is_scrypt() {
+        return Err(FinalError::with_title(format!("Cannot decrypt '{}'.", to_utf(input)))
+            .detail("It was encrypted to age keys, not with a passphrase.")
+            .hint("Use the age tool with the matching identity file to decrypt it.")
+            .into());
+    }
+
+    if output_path.exists() && !utils::user_wants_to_overwrite(output_path, flags)? {
+        return Err(crate::Error::OverwriteDeclined(output_path.to_path_buf()));
+    }
+
+    let reader = decrypt(decryptor, open)?;
+    write_output(output_path, |mut output| {
+        io::copy(&mut Cancellable::new(reader), &mut output)?;
+        output.flush()?;
+        Ok(())
+    })?;
+
+    logger::info(format!("Successfully decrypted '{}'.", to_utf(output_path)))
+        .path(output_path)
+        .log();
+    Ok(())
+}
+
+/// Decrypt with the passphrase, asked for again if a wrong one was typed in a terminal, with the
+/// header read again by `open`, the decryptor only gets one attempt. Passphrases from
+/// `OUCH_PASSPHRASE` or that aren't typed fail right away, there is nothing to retry with.
+fn decrypt(
+    mut decryptor: Decryptor,
+    open: impl Fn() -> crate::Result<Decryptor>,
+) -> crate::Result<StreamReader<BufReader<fs::File>>> {
+    let interactive = env::var_os(PASSPHRASE_VAR).is_none() && io::stdin().is_terminal();
+    let mut attempts = 1;
+    loop {
+        let identity = age::scrypt::Identity::new(read_passphrase(false)?);
+        let result =
+            decryptor.decrypt(iter::once(&identity as &dyn age::Identity)).map_err(decrypt_error);
+        match result {
+            Err(crate::Error::WrongPassphrase) if interactive && attempts < PASSPHRASE_ATTEMPTS => {
+                // Part of the prompt, not a warning about the operation
+                logger::info("Wrong passphrase, try again.").log();
+                attempts += 1;
+                decryptor = open()?;
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Run `write` on the file at `output_path`, which is removed if writing fails halfway
+fn write_output(
+    output_path: &Path,
+    write: impl FnOnce(BufWriter<Cancellable<fs::File>>) -> crate::Result<()>,
+) -> crate::Result<()> {
+    let output = BufWriter::new(Cancellable::new(fs::File::create(output_path)?));
+    let result = write(output);
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    }
+    result
+}
+
+/// The passphrase from `OUCH_PASSPHRASE`, or typed by the user, twice if `confirm`
+fn read_passphrase(confirm: bool) -> crate::Result<SecretString> {
+    if let Some(passphrase) = env::var_os(PASSPHRASE_VAR) {
+        let passphrase = passphrase.into_string().map_err(|_| crate::Error::InvalidUnicode)?;
+        return non_empty(passphrase);
+    }
+
+    loop {
+        let passphrase = rpassword::prompt_password("Passphrase: ")?;
+        if !confirm || rpassword::prompt_password("Confirm passphrase: ")? == passphrase {
+            return non_empty(passphrase);
+        }
+        // Part of the prompt, not a warning about the operation
+        logger::info("The passphrases don't match, try again.").log();
+    }
+}
+
+fn non_empty(passphrase: String) -> crate::Result<SecretString> {
+    if passphrase.is_empty() {
+        return Err(crate::Error::EmptyPassphrase);
+    }
+    Ok(SecretString::from(passphrase))
+}
+
+fn decrypt_error(err: age::DecryptError) -> crate::Error {
+    match err {
+        age::DecryptError::DecryptionFailed
+        | age::DecryptError::KeyDecryptionFailed
+        | age::DecryptError::NoMatchingKeys => crate::Error::WrongPassphrase,
+        age::DecryptError::Io(err) => err.into(),
+        err => crate::Error::IoError { reason: err.to_string() },
+    }
+}
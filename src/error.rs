@@ -3,7 +3,12 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{oof, utils::colors::*};
+use crate::{
+    archive::special::SpecialFileKind,
+    extension::CompressionFormat,
+    oof,
+    utils::{colors::*, logger, to_utf, Bytes},
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
@@ -13,21 +18,128 @@ pub enum Error {
     InvalidUnicode,
     InvalidInput,
     IoError { reason: String },
+    /// The disk is full, `temp_dir` when it happened while spooling an archive there
+    NoSpaceLeft { temp_dir: Option<PathBuf> },
     FileNotFound(PathBuf),
     AlreadyExists,
-    InvalidZipArchive(&'static str),
     PermissionDenied,
     UnsupportedZipArchive(&'static str),
+    /// The volumes of the split .zip archive at `archive` that aren't next to it
+    MissingZipVolumes { archive: PathBuf, missing: Vec<PathBuf> },
+    /// The data doesn't follow `format`, `offset` is how many bytes were decoded before the error,
+    /// `compressed_offset` how many bytes of its compressed input were read, and `entry` the last
+    /// entry of the .tar that was read
+    CorruptArchive {
+        format: CompressionFormat,
+        reason: String,
+        offset: Option<u64>,
+        compressed_offset: Option<u64>,
+        entry: Option<PathBuf>,
+    },
+    UnknownCharset(String),
+    /// `line` is the offending line of the file, when known, with its number
+    InvalidConfig { path: PathBuf, reason: String, line: Option<String> },
+    /// An environment variable, like `OUCH_OVERWRITE`, has a value that isn't valid for it
+    InvalidEnvVar { name: &'static str, reason: String },
+    /// Extraction went over `--max-output-size`, `entry` is the file being written at that moment
+    OutputSizeExceeded { limit: u64, entry: PathBuf },
+    /// `--transform` was given something other than REGEX:REPLACEMENT with a valid REGEX
+    InvalidTransform { expression: String, reason: String },
+    /// `--checksum-file` was given an algorithm other than sha256 or sha512
+    UnknownChecksumAlgorithm(String),
+    /// `--verify-checksum` found no checksum file next to the archive at this path
+    MissingChecksumFile(PathBuf),
+    /// The digest in the checksum file of the archive at `path` isn't the digest of the archive
+    ChecksumMismatch { path: PathBuf, expected: String, actual: String },
+    /// Extraction went over `--max-ratio` times the size of the compressed input
+    RatioExceeded { max_ratio: u64, entry: PathBuf },
+    /// The archive has more than the `--max-files` entries
+    EntryLimitExceeded { max_files: u64 },
+    /// The format, like ".iso", can be extracted but not created
+    UnsupportedFormat(String),
+    /// A FIFO, socket or device node was found with `--special-files fail`
+    SpecialFile { path: PathBuf, kind: SpecialFileKind },
+    /// An entry would be written outside of the output folder, through the symbolic links or the
+    /// ".." in its path, `entry` being where it would be written
+    PathTraversal { entry: String },
+    /// An archive given to `ouch merge` doesn't have the `expected` format of the output
+    MergeFormatMismatch { path: PathBuf, expected: String },
+    /// Two of the merged archives have an entry at this path, with `--conflict error`
+    MergeConflict(PathBuf),
     InternalError,
     OofError(oof::OofError),
     CompressingRootFolder,
     MissingArgumentsForCompression,
+    EachWithOutputPath(PathBuf),
+    /// Flags that can't be given together, without their hyphens
+    ConflictingFlags(&'static str, &'static str),
+    /// The file of `--log-file` could not be opened
+    LogFileError { path: PathBuf, reason: String },
+    /// This many warnings were logged, with `--strict`
+    StrictWarnings(usize),
+    /// A warning that failed the operation as soon as it came up, with `--strict`
+    StrictWarning(String),
     CompressionTypo,
     WalkdirError { reason: String },
+    /// Some of the operations in a batch failed, `total` is the size of the batch
+    BatchFailed { failed: Vec<PathBuf>, total: usize },
+    /// Entries of the archive that failed to be extracted, with `--continue-on-error`, `extracted`
+    /// and `total` count the others too
+    EntriesFailed { extracted: usize, total: usize, failed: Vec<PathBuf> },
+    /// Stopped by Ctrl-C
+    Cancelled,
+    /// Whoever read the output stopped reading, like `head` does
+    BrokenPipe,
+    /// The output already exists, and the user answered no when asked to overwrite it
+    OverwriteDeclined(PathBuf),
+    EmptyPassphrase,
+    /// The passphrase doesn't decrypt the file
+    WrongPassphrase,
+    /// Any other failure, described by its title, details and hints
+    Custom { reason: FinalError },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Exit code for scripts to tell failures apart, see the constants in lib.rs
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::UnknownExtensionError(_)
+            | Error::MissingExtensionError(_)
+            | Error::InvalidInput
+            | Error::UnknownCharset(_)
+            | Error::UnknownChecksumAlgorithm(_)
+            | Error::InvalidTransform { .. }
+            | Error::InvalidConfig { .. }
+            | Error::InvalidEnvVar { .. }
+            | Error::UnsupportedFormat(_)
+            | Error::OofError(_)
+            | Error::CompressingRootFolder
+            | Error::MissingArgumentsForCompression
+            | Error::EachWithOutputPath(_)
+            | Error::ConflictingFlags(..)
+            | Error::CompressionTypo
+            | Error::EmptyPassphrase => crate::EXIT_USAGE,
+            Error::FileNotFound(_)
+            | Error::MissingChecksumFile(_)
+            | Error::MissingZipVolumes { .. } => crate::EXIT_NOT_FOUND,
+            Error::PermissionDenied => crate::EXIT_PERMISSION_DENIED,
+            Error::AlreadyExists | Error::OverwriteDeclined(_) => crate::EXIT_ALREADY_EXISTS,
+            Error::CorruptArchive { .. }
+            | Error::ChecksumMismatch { .. }
+            | Error::PathTraversal { .. } => crate::EXIT_CORRUPT_ARCHIVE,
+            Error::UnsupportedZipArchive(zip::result::ZipError::PASSWORD_REQUIRED)
+            | Error::WrongPassphrase => crate::EXIT_WRONG_PASSWORD,
+            Error::EntriesFailed { .. } => crate::EXIT_PARTIAL_FAILURE,
+            Error::Cancelled => crate::cancel::EXIT_INTERRUPTED,
+            Error::BrokenPipe => 0,
+            _ => crate::EXIT_FAILURE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FinalError {
     title: String,
     details: Vec<String>,
@@ -50,6 +162,23 @@ impl FinalError {
     }
 
     pub fn display(&self) {
+        if logger::has_sink() {
+            logger::error(self).log();
+            return;
+        }
+        logger::write_to_log_file(&format!("[ERROR] {}", self));
+
+        if logger::is_json() {
+            let error = serde_json::json!({
+                "level": "error",
+                "title": self.title,
+                "details": self.details,
+                "hints": self.hints,
+            });
+            eprintln!("{}", error);
+            return;
+        }
+
         // Title
         eprintln!("{}[ERROR]{} {}", red(), reset(), self.title);
 
@@ -70,25 +199,52 @@ impl FinalError {
         // Make sure to fix colors
         eprint!("{}", reset());
     }
+}
 
-    pub fn display_and_crash(&self) -> ! {
-        self.display();
-        std::process::exit(crate::EXIT_FAILURE)
+/// Ends the chains of `detail` and `hint` calls
+impl From<&mut FinalError> for FinalError {
+    fn from(error: &mut FinalError) -> Self {
+        error.clone()
     }
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+impl From<&mut FinalError> for Error {
+    fn from(error: &mut FinalError) -> Self {
+        Self::Custom { reason: error.clone() }
+    }
+}
+
+/// The error as plain text, like it is written to the log file
+impl fmt::Display for FinalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        for detail in &self.details {
+            write!(f, "\n - {}", detail)?;
+        }
+        for hint in &self.hints {
+            write!(f, "\nhint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Print the error to stderr, like ouch does when it fails
+    pub fn display(&self) {
+        self.final_error().display();
+    }
+
+    fn final_error(&self) -> FinalError {
         match self {
             Error::MissingExtensionError(filename) => {
                 FinalError::with_title(format!("Cannot compress to {:?}", filename))
                     .detail("Ouch could not detect the compression format")
                     .hint("Use a supported format extension, like '.zip' or '.tar.gz'")
-                    .hint("Check https://github.com/vrmiguel/ouch for a full list of supported formats")
-                    .display();
+                    .hint("Run 'ouch formats' for the full list of supported formats")
+                    .into()
             },
             Error::WalkdirError { reason } => {
-                FinalError::with_title(reason).display();
+                FinalError::with_title(reason)
             },
             Error::FileNotFound(file) => {
                 if file == Path::new("") {
@@ -96,13 +252,12 @@ impl fmt::Display for Error {
                 } else {
                     FinalError::with_title(format!("file {:?} not found!", file))
                 }
-                .display();
             },
             Error::CompressingRootFolder => {
                 FinalError::with_title("It seems you're trying to compress the root folder.")
                     .detail("This is unadvisable since ouch does compressions in-memory.")
                     .hint("Use a more appropriate tool for this, such as rsync.")
-                    .display();
+                    .into()
             },
             Error::MissingArgumentsForCompression => {
                 FinalError::with_title("Could not compress")
@@ -112,7 +267,42 @@ impl fmt::Display for Error {
                     .hint("  - The output argument.")
                     .hint("")
                     .hint("Example: `ouch compress image.png img.zip`")
-                    .display();
+                    .into()
+            },
+            Error::EachWithOutputPath(output_path) => {
+                FinalError::with_title("Could not compress")
+                    .detail(format!("'{}' looks like an output path", to_utf(output_path)))
+                    .detail("With --each, every argument is compressed into its own archive")
+                    .hint("Remove the output path, or drop --each to create a single archive")
+                    .hint("Use `-o FOLDER` to choose where the archives are placed")
+                    .into()
+            },
+            Error::ConflictingFlags(first, second) => {
+                FinalError::with_title(format!(
+                    "--{} and --{} can't be used together",
+                    first, second
+                ))
+                .hint("Remove one of them")
+                .into()
+            },
+            Error::LogFileError { path, reason } => {
+                FinalError::with_title(format!("Could not open the log file '{}'", to_utf(path)))
+                    .detail(reason)
+                    .hint("Choose another path with `--log-file`")
+                    .into()
+            },
+            Error::StrictWarnings(count) => {
+                let plural = if *count == 1 { "" } else { "s" };
+                FinalError::with_title(format!("{} warning{} with --strict", count, plural))
+                    .detail("With --strict, warnings are treated as errors")
+                    .hint("Drop --strict to only print warnings")
+                    .into()
+            },
+            Error::StrictWarning(message) => {
+                FinalError::with_title(message)
+                    .detail("With --strict, warnings are treated as errors")
+                    .hint("Drop --strict to only print warnings")
+                    .into()
             },
             Error::InternalError => {
                 FinalError::with_title("InternalError :(")
@@ -120,33 +310,311 @@ impl fmt::Display for Error {
                     .detail("It's probably our fault")
                     .detail("Please help us improve by reporting the issue at:")
                     .detail(format!("    {}https://github.com/vrmiguel/ouch/issues ", cyan()))
-                    .display();
+                    .into()
             },
             Error::OofError(err) => {
-                FinalError::with_title(err).display();
+                FinalError::with_title(err)
             },
             Error::IoError { reason } => {
-                FinalError::with_title(reason).display();
+                FinalError::with_title(reason)
+            },
+            Error::NoSpaceLeft { temp_dir: None } => {
+                FinalError::with_title("No space left on the device")
+            },
+            Error::NoSpaceLeft { temp_dir: Some(dir) } => {
+                FinalError::with_title(format!(
+                    "No space left in the temporary folder '{}'",
+                    to_utf(dir)
+                ))
+                .detail("Archives that need to seek, like a .zip inside of a .gz, are copied there")
+                .hint("Use `--temp-dir` to pick a folder with more space")
+                .into()
+            },
+            Error::BatchFailed { failed, total } => {
+                let mut error = FinalError::with_title(format!(
+                    "Failed to process {} out of {} files",
+                    failed.len(),
+                    total
+                ));
+                for path in failed {
+                    error.detail(to_utf(path));
+                }
+                error
+            },
+            Error::EntriesFailed { extracted, total, failed } => {
+                let mut error = FinalError::with_title(format!(
+                    "Extracted {} of {} entries, {} failed",
+                    extracted,
+                    total,
+                    failed.len()
+                ));
+                for path in failed {
+                    error.detail(to_utf(path));
+                }
+                error
+            },
+            Error::InvalidInput => {
+                FinalError::with_title("Invalid input")
+                    .detail("The arguments given could not be understood")
+                    .hint("Run `ouch --help` to see the usage")
+                    .into()
             },
             Error::CompressionTypo => {
                 FinalError::with_title("Possible typo detected")
                     .hint(format!("Did you mean '{}ouch compress{}'?", magenta(), reset()))
-                    .display();
+                    .into()
+            },
+            Error::UnknownExtensionError(extension) => {
+                FinalError::with_title(format!("Unknown extension '{}'", extension))
+            },
+            Error::InvalidUnicode => {
+                FinalError::with_title("Invalid Unicode")
+            },
+            Error::AlreadyExists => {
+                FinalError::with_title("File already exists")
+            },
+            Error::PermissionDenied => {
+                FinalError::with_title("Permission denied")
+            },
+            Error::UnsupportedZipArchive(reason) => {
+                FinalError::with_title("Unsupported zip archive").detail(reason).into()
+            },
+            Error::MissingZipVolumes { archive, missing } => {
+                let mut error = FinalError::with_title(format!(
+                    "Cannot read the split archive '{}'",
+                    to_utf(archive)
+                ));
+                for path in missing {
+                    error.detail(format!("The volume '{}' is missing", to_utf(path)));
+                }
+                error
+                    .hint("All of the volumes, like 'archive.z01', must be next to the .zip")
+                    .into()
+            },
+            Error::InvalidConfig { path, reason, line } => {
+                let mut error =
+                    FinalError::with_title(format!("Invalid config file '{}'", to_utf(path)));
+                error.detail(reason);
+                if let Some(line) = line {
+                    error.detail(line);
+                }
+                error
+                    .hint("Run `ouch config path` to find the config file")
+                    .hint("Use --no-config to run without it")
+                    .into()
+            },
+            Error::InvalidEnvVar { name, reason } => {
+                FinalError::with_title(format!("Invalid value of {}", name))
+                    .detail(reason)
+                    .hint(format!("Fix or unset {} in the environment", name))
+                    .into()
             },
-            _err => {
-                todo!();
+            Error::UnknownCharset(charset) => {
+                FinalError::with_title(format!("Unknown charset '{}'", charset))
+                    .hint("Use 'auto', 'cp437', or an encoding label like 'shift_jis' or 'gbk'")
+                    .into()
             },
+            Error::InvalidTransform { expression, reason } => {
+                FinalError::with_title(format!("Invalid --transform '{}'", expression))
+                    .detail(reason)
+                    .hint("Use REGEX:REPLACEMENT, like '^old/:new/', with \\: for a ':' in REGEX")
+                    .into()
+            },
+            Error::UnknownChecksumAlgorithm(algorithm) => {
+                FinalError::with_title(format!("Unknown checksum algorithm '{}'", algorithm))
+                    .hint("Use 'sha256', 'sha512' or 'blake3'")
+                    .into()
+            },
+            Error::MissingChecksumFile(path) => {
+                FinalError::with_title(format!("Cannot verify '{}'", to_utf(path)))
+                    .detail(format!(
+                        "Neither '{0}.sha256' nor '{0}.sha512' was found next to it",
+                        to_utf(path)
+                    ))
+                    .hint("Create it when compressing, with `--checksum-file sha256`")
+                    .hint("Or extract it without --verify-checksum")
+                    .into()
+            },
+            Error::ChecksumMismatch { path, expected, actual } => {
+                FinalError::with_title(format!("The checksum of '{}' doesn't match", to_utf(path)))
+                    .detail(format!("Expected {}", expected))
+                    .detail(format!("Found    {}", actual))
+                    .hint("The file may be truncated or damaged, try getting a new copy of it")
+                    .into()
+            },
+            Error::CorruptArchive { format, reason, offset, compressed_offset, entry } => {
+                let mut error = FinalError::with_title(format!("Corrupted '{}' data", format));
+                error.detail(reason);
+                if let Some(compressed_offset) = compressed_offset {
+                    error.detail(format!(
+                        "Found after reading {} bytes of the {} data",
+                        compressed_offset, format
+                    ));
+                }
+                if let Some(offset) = offset {
+                    error.detail(format!("Found after {} bytes of decompressed data", offset));
+                }
+                if let Some(entry) = entry {
+                    error.detail(format!("The last entry read was '{}'", to_utf(entry)));
+                }
+                error.hint("The file may be truncated or damaged, try getting a new copy of it");
+                error
+            },
+            Error::OutputSizeExceeded { limit, entry } => {
+                FinalError::with_title(format!("Extraction stopped at the {} limit", Bytes::new(*limit)))
+                    .detail(format!("The limit was exceeded while writing '{}'", to_utf(entry)))
+                    .detail("The archive might be a decompression bomb")
+                    .hint("Raise the limit with `--max-output-size SIZE`, like `--max-output-size 50G`")
+                    .hint("Or disable it with `--no-size-limit`")
+                    .into()
+            },
+            Error::RatioExceeded { max_ratio, entry } => {
+                FinalError::with_title("Decompression ratio limit exceeded")
+                    .detail(format!(
+                        "The output grew past {} times the size of the compressed file",
+                        max_ratio
+                    ))
+                    .detail(format!("The limit was exceeded while writing '{}'", to_utf(entry)))
+                    .detail("The archive might be a decompression bomb")
+                    .hint("Raise the limit with `--max-ratio N`, or use `--max-ratio 0` to disable it")
+                    .into()
+            },
+            Error::EntryLimitExceeded { max_files } => {
+                FinalError::with_title("Archive entry limit exceeded")
+                    .detail(format!("The archive has more than {} entries", max_files))
+                    .detail("The archive might be a decompression bomb")
+                    .hint("Raise the limit with `--max-files N`, or use `--max-files 0` to disable it")
+                    .into()
+            },
+            Error::SpecialFile { path, kind } => {
+                FinalError::with_title(format!("Found the {} '{}'", kind, to_utf(path)))
+                    .detail("FIFOs, sockets and device nodes were set to fail with --special-files")
+                    .hint("Use `--special-files skip` to leave them out")
+                    .hint("Or `--special-files store` to keep them as entries of their type in .tar")
+                    .into()
+            },
+            Error::PathTraversal { entry } => {
+                FinalError::with_title(format!("Refusing to write '{}'", entry))
+                    .detail("It is outside of the folder the archive is extracted to")
+                    .detail("The archive may have been crafted to overwrite files elsewhere")
+                    .hint("Use `--continue-on-error` to extract the other entries")
+                    .into()
+            },
+            Error::MergeFormatMismatch { path, expected } => {
+                FinalError::with_title(format!("Cannot merge '{}'", to_utf(path)))
+                    .detail(format!("All of the merged archives must be '{}' archives", expected))
+                    .hint("Decompress the archives first, then compress their contents together")
+                    .into()
+            },
+            Error::MergeConflict(path) => {
+                FinalError::with_title("Cannot merge the archives")
+                    .detail(format!("More than one archive has an entry at '{}'", to_utf(path)))
+                    .hint("Use `--conflict rename` to keep all of them")
+                    .hint("Use `--conflict skip` to keep the first one")
+                    .into()
+            },
+            Error::OverwriteDeclined(path) => {
+                FinalError::with_title(format!("'{}' already exists", to_utf(path)))
+                    .detail("It was not overwritten")
+                    .hint("Use --yes to overwrite it without asking")
+                    .into()
+            },
+            Error::EmptyPassphrase => {
+                FinalError::with_title("The passphrase is empty")
+                    .detail("Files can't be encrypted or decrypted without a passphrase")
+                    .into()
+            },
+            Error::WrongPassphrase => {
+                FinalError::with_title("Wrong passphrase")
+                    .detail("The file could not be decrypted with the given passphrase")
+                    .into()
+            },
+            Error::BrokenPipe => {
+                FinalError::with_title("The output was closed before ouch finished writing it")
+            },
+            Error::Cancelled => {
+                FinalError::with_title("Interrupted")
+                    .detail("Partially written outputs were removed")
+                    .hint("Archives that were being extracted may be left incomplete,")
+                    .hint("use --atomic to extract them only once they are complete")
+                    .into()
+            },
+            Error::UnsupportedFormat(format) => {
+                FinalError::with_title(format!("Cannot create '{}' files", format))
+                    .detail(format!("ouch can extract '{}' files, but not create them", format))
+                    .hint("Use an archive format like '.tar.gz' or '.zip' instead")
+                    .hint("Run 'ouch formats' to see what can be done with each format")
+                    .into()
+            },
+            Error::Custom { reason } => reason.clone(),
         }
-        Ok(())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.final_error())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decoding error tagged with the format it comes from, it travels inside of an `io::Error`
+/// through the chain of decoders until it is turned into `Error::CorruptArchive`
+#[derive(Debug, Clone)]
+pub struct CorruptData {
+    pub format: CompressionFormat,
+    pub reason: String,
+    pub offset: Option<u64>,
+    pub compressed_offset: Option<u64>,
+}
+
+impl fmt::Display for CorruptData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "corrupted {} data: {}", self.format, self.reason)
+    }
+}
+
+impl std::error::Error for CorruptData {}
+
+impl From<CorruptData> for Error {
+    fn from(CorruptData { format, reason, offset, compressed_offset }: CorruptData) -> Self {
+        Self::CorruptArchive { format, reason, offset, compressed_offset, entry: None }
+    }
+}
+
+/// The `CorruptData` in `err`, also when other errors were wrapped around it, like the tar crate
+/// does with the errors met while unpacking an entry
+fn corrupt_data(err: &std::io::Error) -> Option<&CorruptData> {
+    let mut source: &(dyn std::error::Error + 'static) = err.get_ref()?;
+    loop {
+        if let Some(corrupt) = source.downcast_ref::<CorruptData>() {
+            return Some(corrupt);
+        }
+        // `io::Error::source` skips the error it wraps
+        source = match source.downcast_ref::<std::io::Error>() {
+            Some(err) => err.get_ref()?,
+            None => source.source()?,
+        };
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
+        // Whatever failed after Ctrl-C failed because of it
+        if crate::cancel::is_cancelled() {
+            return Self::Cancelled;
+        }
+        if let Some(corrupt) = corrupt_data(&err) {
+            return corrupt.clone().into();
+        }
+
         match err.kind() {
             std::io::ErrorKind::NotFound => panic!("{}", err),
             std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
             std::io::ErrorKind::AlreadyExists => Self::AlreadyExists,
+            std::io::ErrorKind::BrokenPipe => Self::BrokenPipe,
+            std::io::ErrorKind::StorageFull => Self::NoSpaceLeft { temp_dir: None },
             _other => Self::IoError { reason: err.to_string() },
         }
     }
@@ -157,7 +625,13 @@ impl From<zip::result::ZipError> for Error {
         use zip::result::ZipError::*;
         match err {
             Io(io_err) => Self::from(io_err),
-            InvalidArchive(filename) => Self::InvalidZipArchive(filename),
+            InvalidArchive(reason) => Self::CorruptArchive {
+                format: CompressionFormat::Zip,
+                reason: reason.to_string(),
+                offset: None,
+                compressed_offset: None,
+                entry: None,
+            },
             FileNotFound => Self::FileNotFound("".into()),
             UnsupportedArchive(filename) => Self::UnsupportedZipArchive(filename),
         }
@@ -170,6 +644,12 @@ impl From<walkdir::Error> for Error {
     }
 }
 
+impl From<ignore::Error> for Error {
+    fn from(err: ignore::Error) -> Self {
+        Self::WalkdirError { reason: err.to_string() }
+    }
+}
+
 impl From<oof::OofError> for Error {
     fn from(err: oof::OofError) -> Self {
         Self::OofError(err)
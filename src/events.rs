@@ -0,0 +1,118 @@
+//! What ouch does with each entry, as it does it: the archive formats report their entries as
+//! events, that the CLI logs with `--verbose` and that programs using ouch as a library observe.
+//!
+//! Warnings are still logged where they happen, for their summary and `--strict`, programs using
+//! ouch as a library get them as events too.
+
+use std::{
+    cell::Cell,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use crate::{
+    extension::CompressionFormat,
+    utils::{logger, to_utf, Bytes},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Phase {
+    /// Files start being added to an archive, or a single file being compressed
+    Compressing { formats: Vec<CompressionFormat> },
+    /// The file at `input` starts being decompressed
+    Decompressing { input: PathBuf, formats: Vec<CompressionFormat> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Phase(Phase),
+    /// An entry starts being added or extracted, by its path in the archive, `size` bytes long
+    /// when it is a file
+    EntryStarted {
+        entry: PathBuf,
+        size: Option<u64>,
+    },
+    /// The entry that started last was added from, or extracted to, `path`, with `bytes` bytes of
+    /// contents. Entries that were skipped have no such event.
+    EntryFinished {
+        entry: PathBuf,
+        path: PathBuf,
+        bytes: u64,
+    },
+    /// A warning was logged, only sent to programs using ouch as a library
+    Warning {
+        message: String,
+    },
+}
+
+pub trait EventSink {
+    fn event(&self, event: Event);
+}
+
+/// Events nobody observes
+pub struct NoEvents;
+
+impl EventSink for NoEvents {
+    fn event(&self, _event: Event) {}
+}
+
+/// Events sent to a channel, to be observed from another thread
+pub struct ChannelEvents {
+    sender: Sender<Event>,
+}
+
+impl ChannelEvents {
+    /// The sink, and where its events are received
+    pub fn new() -> (Self, Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self { sender }, receiver)
+    }
+}
+
+impl EventSink for ChannelEvents {
+    fn event(&self, event: Event) {
+        // Nobody is listening anymore
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Events logged as the messages of the CLI, shown with `--verbose`
+#[derive(Default)]
+pub struct LogEvents {
+    compressing: Cell<bool>,
+}
+
+impl EventSink for LogEvents {
+    fn event(&self, event: Event) {
+        match event {
+            Event::Phase(Phase::Compressing { formats }) => {
+                self.compressing.set(true);
+                logger::debug(format!("Compressing as {:?}.", formats)).log();
+            },
+            Event::Phase(Phase::Decompressing { input, formats }) => {
+                self.compressing.set(false);
+                logger::debug(format!("Decompressing '{}' as {:?}.", to_utf(&input), formats))
+                    .path(&input)
+                    .log();
+            },
+            Event::EntryStarted { entry, size } if self.compressing.get() => {
+                let message = match size {
+                    Some(size) => {
+                        format!("Compressing '{}' ({}).", to_utf(&entry), Bytes::new(size))
+                    },
+                    None => format!("Compressing '{}'.", to_utf(&entry)),
+                };
+                logger::debug(message).path(&entry).log();
+            },
+            Event::EntryFinished { entry, path, bytes } if !self.compressing.get() => {
+                logger::debug(format!("{:?} extracted. ({})", path, Bytes::new(bytes)))
+                    .path(&path)
+                    .entry(&entry)
+                    .log();
+            },
+            // Already logged
+            Event::Warning { .. } => {},
+            Event::EntryStarted { .. } | Event::EntryFinished { .. } => {},
+        }
+    }
+}
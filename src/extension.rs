@@ -1,25 +1,186 @@
-use std::{fmt, path::Path};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
 
+use serde::Serialize;
 use CompressionFormat::*;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 /// Accepted extensions for input and output
 pub enum CompressionFormat {
-    Gzip, // .gz
-    Bzip, // .bz
-    Lzma, // .lzma
-    Tar,  // .tar (technically not a compression extension, but will do for now)
-    Zip,  // .zip
+    Gzip,   // .gz
+    Bzip,   // .bz
+    Lzma,   // .lzma
+    Snappy, // .sz (framed)
+    Zstd,   // .zst
+    Lz4,      // .lz4 (frame)
+    Lz4Block, // .lz4block (size-prefixed block)
+    Tar,    // .tar (technically not a compression extension, but will do for now)
+    Zip,    // .zip
+    Cpio,   // .cpio (newc)
+    Iso,    // .iso (read only)
 }
 
+impl CompressionFormat {
+    /// Archive formats bundle several files, the other ones compress a single stream
+    pub fn is_archive(&self) -> bool {
+        matches!(self, Tar | Zip | Cpio | Iso)
+    }
+
+    /// What this build of ouch can do with the format
+    pub fn capabilities(&self) -> &'static FormatCapabilities {
+        // Safe unwrap: every format has a row
+        FORMATS.iter().find(|capabilities| capabilities.format == *self).unwrap()
+    }
+}
+
+/// A row of the table shown by `ouch formats`, also read by the errors about unsupported formats
+#[derive(Debug, Serialize)]
+pub struct FormatCapabilities {
+    #[serde(skip)]
+    pub format: CompressionFormat,
+    pub name: &'static str,
+    /// Without the dot, the first one is the main one
+    pub extensions: &'static [&'static str],
+    pub compress: bool,
+    pub decompress: bool,
+    /// Entries are listed, or for single file formats, what their header stores
+    pub list: bool,
+    /// Password protected files can be read
+    pub password: bool,
+    /// Modification times or permissions are stored and restored
+    pub metadata: bool,
+}
+
+/// Every format, in the order they are shown
+pub const FORMATS: &[FormatCapabilities] = &[
+    FormatCapabilities {
+        format: Tar,
+        name: "tar",
+        extensions: &["tar", "tgz", "tbz", "tbz2", "txz", "tlz", "tsnap", "tzst"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: true,
+    },
+    FormatCapabilities {
+        format: Zip,
+        name: "zip",
+        extensions: &["zip"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: true,
+    },
+    FormatCapabilities {
+        format: Gzip,
+        name: "gzip",
+        extensions: &["gz"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: true,
+    },
+    FormatCapabilities {
+        format: Lzma,
+        name: "xz",
+        extensions: &["xz", "lzma", "lz"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: false,
+    },
+    FormatCapabilities {
+        format: Bzip,
+        name: "bzip2",
+        extensions: &["bz2", "bz"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: false,
+    },
+    FormatCapabilities {
+        format: Snappy,
+        name: "snappy",
+        extensions: &["sz"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: false,
+    },
+    FormatCapabilities {
+        format: Zstd,
+        name: "zstd",
+        extensions: &["zst"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: false,
+    },
+    FormatCapabilities {
+        format: Lz4,
+        name: "lz4",
+        extensions: &["lz4"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: false,
+    },
+    FormatCapabilities {
+        format: Lz4Block,
+        name: "lz4block",
+        extensions: &["lz4block"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: false,
+    },
+    FormatCapabilities {
+        format: Cpio,
+        name: "cpio",
+        extensions: &["cpio"],
+        compress: true,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: true,
+    },
+    FormatCapabilities {
+        format: Iso,
+        name: "iso",
+        extensions: &["iso"],
+        compress: false,
+        decompress: true,
+        list: true,
+        password: false,
+        metadata: false,
+    },
+];
+
 impl fmt::Display for CompressionFormat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", match self {
             Gzip => ".gz",
             Bzip => ".bz",
             Lzma => ".lz",
+            Snappy => ".sz",
+            Zstd => ".zst",
+            Lz4 => ".lz4",
+            Lz4Block => ".lz4block",
             Tar => ".tar",
             Zip => ".zip",
+            Cpio => ".cpio",
+            Iso => ".iso",
         })
     }
 }
@@ -35,18 +196,35 @@ pub fn separate_known_extensions_from_name(mut path: &Path) -> (&Path, Vec<Compr
 
     let mut extensions = vec![];
 
+    // "file.zip." is taken as "file.zip"
+    if let (Some(name), Some(full_path)) = (path.file_name(), path.to_str()) {
+        if !name.to_string_lossy().trim_end_matches('.').is_empty() {
+            path = Path::new(full_path.trim_end_matches('.'));
+        }
+    }
+
     // While there is known extensions at the tail, grab them
     while let Some(extension) = path.extension() {
-        let extension = match () {
-            _ if extension == "tar" => Tar,
-            _ if extension == "zip" => Zip,
-            _ if extension == "bz" => Bzip,
-            _ if extension == "gz" || extension == "bz2" => Gzip,
-            _ if extension == "xz" || extension == "lzma" || extension == "lz" => Lzma,
-            _ => break,
-        };
-
-        extensions.push(extension);
+        // Uppercase extensions, like ".ZIP", are common on Windows
+        let extension = extension.to_string_lossy().to_ascii_lowercase();
+        match tar_alias(&extension) {
+            // Pushed in reverse, like the rest
+            Some(format) => extensions.extend([format, Tar]),
+            None => extensions.push(match extension.as_str() {
+                "tar" => Tar,
+                "zip" => Zip,
+                "cpio" => Cpio,
+                "iso" => Iso,
+                "bz" | "bz2" => Bzip,
+                "gz" => Gzip,
+                "xz" | "lzma" | "lz" => Lzma,
+                "sz" => Snappy,
+                "zst" => Zstd,
+                "lz4" => Lz4,
+                "lz4block" => Lz4Block,
+                _ => break,
+            }),
+        }
 
         // Update for the next iteration
         path = if let Some(stem) = path.file_stem() { Path::new(stem) } else { Path::new("") };
@@ -57,7 +235,140 @@ pub fn separate_known_extensions_from_name(mut path: &Path) -> (&Path, Vec<Compr
     (path, extensions)
 }
 
+/// The compression format of the single extensions that stand for a compressed .tar, like ".tgz"
+fn tar_alias(extension: &str) -> Option<CompressionFormat> {
+    match extension {
+        "tgz" => Some(Gzip),
+        "tbz" | "tbz2" => Some(Bzip),
+        "txz" | "tlz" => Some(Lzma),
+        "tsnap" => Some(Snappy),
+        "tzst" => Some(Zstd),
+        _ => None,
+    }
+}
+
 pub fn extensions_from_path(path: &Path) -> Vec<CompressionFormat> {
     let (_, extensions) = separate_known_extensions_from_name(path);
     extensions
 }
+
+/// For chains that other tools wouldn't detect by name, like ".gz.tar" or ".tar.zip", the order
+/// that makes sense: the first archive format, followed by the compression formats as given.
+///
+/// `None` when the chain is fine as it is.
+pub fn reordered_formats(formats: &[CompressionFormat]) -> Option<Vec<CompressionFormat>> {
+    let archives: Vec<&CompressionFormat> =
+        formats.iter().filter(|format| format.is_archive()).collect();
+    match archives.as_slice() {
+        [] => None,
+        [_] if formats[0].is_archive() => None,
+        [first_archive, ..] => {
+            let compressions = formats.iter().filter(|format| !format.is_archive());
+            Some(std::iter::once(*first_archive).chain(compressions).cloned().collect())
+        },
+    }
+}
+
+/// `path` with its unknown extension replaced by the closest known one, like "out.zpi" ->
+/// "out.zip", if any is close enough to likely be a typo of it
+pub fn suggest_extension(path: &Path) -> Option<PathBuf> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    // One typo in short extensions, two in longer ones
+    let (known, _) = FORMATS
+        .iter()
+        .flat_map(|capabilities| capabilities.extensions)
+        .map(|known| (known, strsim::damerau_levenshtein(&extension, known)))
+        .filter(|&(known, distance)| distance <= if known.len() <= 3 { 1 } else { 2 })
+        .min_by_key(|&(_, distance)| distance)?;
+    Some(path.with_extension(known))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extensions_case_and_trailing_dot() {
+        let (name, formats) = separate_known_extensions_from_name(Path::new("file.TAR.Gz"));
+        assert_eq!((name, formats), (Path::new("file"), vec![Tar, Gzip]));
+        let (name, formats) = separate_known_extensions_from_name(Path::new("file.zip."));
+        assert_eq!((name, formats), (Path::new("file"), vec![Zip]));
+        let (name, formats) = separate_known_extensions_from_name(Path::new(".."));
+        assert_eq!((name, formats), (Path::new(".."), vec![]));
+    }
+
+    #[test]
+    fn test_tar_aliases() {
+        let aliases = [
+            ("tgz", "tar.gz"),
+            ("tbz", "tar.bz"),
+            ("tbz2", "tar.bz2"),
+            ("txz", "tar.xz"),
+            ("tlz", "tar.lz"),
+            ("tsnap", "tar.sz"),
+            ("tzst", "tar.zst"),
+        ];
+        for (alias, long_form) in aliases {
+            let path = format!("backup.{}", alias);
+            let (name, formats) = separate_known_extensions_from_name(Path::new(&path));
+            assert_eq!(name, Path::new("backup"));
+            assert_eq!(formats, extensions_from_path(Path::new(&format!("backup.{}", long_form))));
+
+            // Shown in the long form
+            let shown: String = formats.iter().map(|format| format.to_string()).collect();
+            assert!(shown.starts_with(".tar."));
+            assert_eq!(extensions_from_path(Path::new(&format!("backup{}", shown))), formats);
+        }
+        assert_eq!(extensions_from_path(Path::new("backup.TXZ")), vec![Tar, Lzma]);
+        assert_eq!(extensions_from_path(Path::new("backup.tgz.age")), vec![]);
+    }
+
+    #[test]
+    fn test_reordered_formats() {
+        let fine: &[&[CompressionFormat]] =
+            &[&[Tar], &[Tar, Gzip], &[Zip, Lzma, Gzip], &[Gzip], &[Gzip, Bzip], &[Cpio, Lzma]];
+        for formats in fine {
+            assert_eq!(reordered_formats(formats), None, "{:?}", formats);
+        }
+
+        let reordered: &[(&[CompressionFormat], &[CompressionFormat])] = &[
+            (&[Gzip, Tar], &[Tar, Gzip]),
+            (&[Lzma, Zip], &[Zip, Lzma]),
+            (&[Gzip, Tar, Bzip], &[Tar, Gzip, Bzip]),
+            (&[Tar, Zip], &[Tar]),
+            (&[Zip, Tar], &[Zip]),
+            (&[Tar, Gzip, Zip], &[Tar, Gzip]),
+            (&[Bzip, Cpio, Tar], &[Cpio, Bzip]),
+        ];
+        for (formats, expected) in reordered {
+            assert_eq!(reordered_formats(formats).as_deref(), Some(*expected), "{:?}", formats);
+        }
+    }
+
+    #[test]
+    fn test_format_extensions() {
+        for capabilities in FORMATS {
+            assert_eq!(capabilities.format.capabilities().name, capabilities.name);
+            for extension in capabilities.extensions {
+                let formats = extensions_from_path(Path::new(&format!("file.{}", extension)));
+                assert_eq!(formats.first(), Some(&capabilities.format), "{}", extension);
+            }
+        }
+    }
+
+    #[test]
+    fn test_suggest_extension() {
+        let suggest = |path| suggest_extension(Path::new(path));
+        assert_eq!(suggest("out.zpi"), Some(PathBuf::from("out.zip")));
+        assert_eq!(suggest("dir/out.tra"), Some(PathBuf::from("dir/out.tar")));
+        assert_eq!(suggest("out.tar.gx"), Some(PathBuf::from("out.tar.gz")));
+        assert_eq!(suggest("out.lzam"), Some(PathBuf::from("out.lzma")));
+        assert_eq!(suggest("out.tgx"), Some(PathBuf::from("out.tgz")));
+        assert_eq!(suggest("out.gzz"), Some(PathBuf::from("out.gz")));
+        assert_eq!(suggest("out.ZPI"), Some(PathBuf::from("out.zip")));
+        // Too far from any of them
+        assert_eq!(suggest("out.docx"), None);
+        assert_eq!(suggest("out"), None);
+    }
+}
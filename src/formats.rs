@@ -0,0 +1,61 @@
+//! `ouch formats`: the supported formats and what can be done with each of them, as a table or,
+//! with `--json`, as one JSON object per line.
+
+use std::io::{self, Write};
+
+use crate::{
+    extension::{FormatCapabilities, FORMATS},
+    oof,
+    utils::{self, colors},
+};
+
+pub fn show_formats(flags: &oof::Flags) -> crate::Result<()> {
+    let mut output = vec![];
+    if flags.is_present("json") {
+        for capabilities in FORMATS {
+            // Safe unwrap: the table only holds strings and booleans
+            writeln!(output, "{}", serde_json::to_string(capabilities).unwrap())?;
+        }
+    } else {
+        print_table(&mut output, FORMATS)?;
+    }
+    utils::write_stdout(output)
+}
+
+fn print_table(out: &mut impl Write, formats: &[FormatCapabilities]) -> io::Result<()> {
+    let extensions: Vec<String> = formats
+        .iter()
+        .map(|capabilities| {
+            let extensions: Vec<String> =
+                capabilities.extensions.iter().map(|extension| format!(".{}", extension)).collect();
+            extensions.join(" ")
+        })
+        .collect();
+    // Wide enough for the headers too
+    let name_width = formats.iter().map(|capabilities| capabilities.name.len()).fold(6, usize::max);
+    let extensions_width = extensions.iter().map(String::len).fold(10, usize::max);
+
+    writeln!(
+        out,
+        "{}{:name_width$}  {:extensions_width$}  COMPRESS  DECOMPRESS  LIST  PASSWORD  METADATA{}",
+        colors::cyan(),
+        "FORMAT",
+        "EXTENSIONS",
+        colors::reset(),
+    )?;
+    for (capabilities, extensions) in formats.iter().zip(&extensions) {
+        let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+        writeln!(
+            out,
+            "{:name_width$}  {:extensions_width$}  {:8}  {:10}  {:4}  {:8}  {}",
+            capabilities.name,
+            extensions,
+            yes_no(capabilities.compress),
+            yes_no(capabilities.decompress),
+            yes_no(capabilities.list),
+            yes_no(capabilities.password),
+            yes_no(capabilities.metadata),
+        )?;
+    }
+    Ok(())
+}
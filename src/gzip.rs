@@ -0,0 +1,85 @@
+//! The original file name and modification time stored in gzip headers, like `gzip -N` does.
+
+use std::{
+    convert::TryFrom,
+    ffi::OsStr,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use flate2::{write::GzEncoder, GzBuilder, GzHeader};
+
+use crate::{oof, utils};
+
+/// Encoder storing the name and modification time of the file at `input_path` in the header, the
+/// time is set with `--mtime` or clamped with `--reproducible`, see `utils::archive_mtime`
+pub fn encoder<W: Write>(
+    input_path: &Path,
+    writer: W,
+    flags: &oof::Flags,
+) -> crate::Result<GzEncoder<W>> {
+    let mut builder = GzBuilder::new();
+    if let Some(name) = input_path.file_name() {
+        builder = builder.filename(name_bytes(name));
+    }
+
+    let metadata = fs::metadata(input_path)?;
+    let mtime = match utils::archive_mtime(flags)? {
+        Some(mtime) => mtime.of(&metadata),
+        None => utils::clamped_mtime(&metadata, u64::MAX),
+    };
+    // The header has 32 bits for the time, where 0 means that there is none
+    builder = builder.mtime(u32::try_from(mtime).unwrap_or(0));
+
+    Ok(builder.write(writer, Default::default()))
+}
+
+#[cfg(unix)]
+fn name_bytes(name: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    name.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn name_bytes(name: &OsStr) -> Vec<u8> {
+    name.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Name and modification time read from a gzip header
+#[derive(Debug, Default)]
+pub struct StoredMetadata {
+    pub name: Option<PathBuf>,
+    pub mtime: Option<SystemTime>,
+}
+
+impl StoredMetadata {
+    pub fn from_header(header: &GzHeader) -> Self {
+        Self { name: header.filename().and_then(sanitize_name), mtime: header.mtime_as_datetime() }
+    }
+}
+
+/// Only the last component of the stored name is kept, so that it can't point to other folders
+fn sanitize_name(raw_name: &[u8]) -> Option<PathBuf> {
+    let name = String::from_utf8_lossy(raw_name);
+    match name.rsplit(['/', '\\']).next()? {
+        "" | "." | ".." => None,
+        name => Some(PathBuf::from(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_name_sanitization() {
+        assert_eq!(sanitize_name(b"notes.txt"), Some(PathBuf::from("notes.txt")));
+        assert_eq!(sanitize_name(b"../../etc/passwd"), Some(PathBuf::from("passwd")));
+        assert_eq!(sanitize_name(b"C:\\Users\\notes.txt"), Some(PathBuf::from("notes.txt")));
+        assert_eq!(sanitize_name(b"/absolute/"), None);
+        assert_eq!(sanitize_name(b".."), None);
+        assert_eq!(sanitize_name(b""), None);
+    }
+}
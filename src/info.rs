@@ -0,0 +1,160 @@
+//! `ouch info`: what archives tell about themselves as a whole, like the variant of their format,
+//! how they are compressed and their comment, where `ouch list` shows their entries.
+
+use std::{
+    fs,
+    io::{self, prelude::*, BufReader},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    archive::{self, zip::SplitZipReader, ArchiveInfo},
+    commands::chain_reader_decoder,
+    error::FinalError,
+    extension::{
+        self,
+        CompressionFormat::{self, *},
+    },
+    oof,
+    utils::{
+        self, colors, format_size,
+        io::{spool, temp_dir, EntryLimit},
+        to_utf,
+    },
+    zstandard,
+};
+
+/// What `ouch info` found in one archive, printed as text or, with `--json`, as one JSON object
+/// per line
+#[derive(Debug, Serialize)]
+struct Info {
+    path: PathBuf,
+    format: String,
+    /// The formats the archive is compressed with, innermost first, like ["gz"] for .tar.gz
+    compression: Vec<String>,
+    archive_size: u64,
+    /// Size of the archive over the total size of its files
+    ratio: Option<f64>,
+    #[serde(flatten)]
+    archive: ArchiveInfo,
+}
+
+pub fn show_info(archives: &[PathBuf], flags: &oof::Flags) -> crate::Result<()> {
+    for path in archives {
+        let formats = extension::extensions_from_path(path);
+        if !formats.first().is_some_and(CompressionFormat::is_archive) {
+            return Err(FinalError::with_title(format!(
+                "Cannot show the info of '{}'.",
+                to_utf(path)
+            ))
+            .detail("Only archives, like .tar.gz or .zip, are described by ouch info.")
+            .hint("Use 'ouch list' to show what the header of a compressed file stores")
+            .into());
+        }
+
+        let info = archive_info(path, &formats, flags)?;
+        let mut output = vec![];
+        if flags.is_present("json") {
+            // Safe unwrap: the info only holds strings, numbers and paths
+            writeln!(output, "{}", serde_json::to_string(&info).unwrap())?;
+        } else {
+            print_info(&mut output, &info, flags.is_present("bytes"))?;
+        }
+        utils::write_stdout(output)?;
+    }
+
+    Ok(())
+}
+
+fn archive_info(
+    path: &Path,
+    formats: &[CompressionFormat],
+    flags: &oof::Flags,
+) -> crate::Result<Info> {
+    let file = fs::File::open(path)?;
+    let archive_size = file.metadata()?.len();
+
+    let limit = &mut EntryLimit::from_flags(flags)?;
+    // .zip and .iso need io::Seek, compressed ones are decompressed into a temporary file first
+    let archive = match *formats {
+        [Zip] => {
+            let volumes = SplitZipReader::open(path)?;
+            archive::zip::archive_info(zip::ZipArchive::new(volumes)?, limit)?
+        },
+        [Iso] => archive::iso::archive_info(archive::iso::IsoImage::new(file)?, limit)?,
+        _ => {
+            let mut reader: Box<dyn Read> = Box::new(BufReader::new(file));
+            let zstd_options = zstandard::Options::from_flags(flags)?;
+            for format in formats.iter().skip(1).rev() {
+                reader = chain_reader_decoder(format, reader, &zstd_options)?;
+            }
+
+            match formats[0] {
+                Tar => archive::tar::archive_info(reader, limit)?,
+                Cpio => archive::cpio::archive_info(reader, limit)?,
+                Zip => {
+                    let file = spool(&mut reader, &temp_dir(flags, None))?;
+                    archive::zip::archive_info(zip::ZipArchive::new(file)?, limit)?
+                },
+                Iso => {
+                    let file = spool(&mut reader, &temp_dir(flags, None))?;
+                    archive::iso::archive_info(archive::iso::IsoImage::new(file)?, limit)?
+                },
+                Gzip | Bzip | Lzma | Snappy | Zstd | Lz4 | Lz4Block => {
+                    unreachable!("checked to be an archive above")
+                },
+            }
+        },
+    };
+
+    let ratio = Some(archive.uncompressed_size)
+        .filter(|size| *size > 0)
+        .map(|size| archive_size as f64 / size as f64);
+    Ok(Info {
+        path: path.to_path_buf(),
+        format: formats.iter().map(|format| format.to_string()).collect(),
+        compression: formats[1..]
+            .iter()
+            .map(|format| format.to_string().trim_start_matches('.').to_string())
+            .collect(),
+        archive_size,
+        ratio,
+        archive,
+    })
+}
+
+/// With `raw_bytes` (`--bytes`), sizes are shown as plain numbers of bytes, for scripts
+fn print_info(out: &mut impl Write, info: &Info, raw_bytes: bool) -> io::Result<()> {
+    let show_size = |bytes: u64| if raw_bytes { bytes.to_string() } else { format_size(bytes) };
+    let archive = &info.archive;
+
+    writeln!(out, "{}{}:{}", colors::cyan(), to_utf(&info.path), colors::reset())?;
+    match &archive.version {
+        Some(version) => writeln!(out, "  format:            {} ({})", info.format, version)?,
+        None => writeln!(out, "  format:            {}", info.format)?,
+    }
+    if !info.compression.is_empty() {
+        writeln!(out, "  compressed with:   {}", info.compression.join(", then "))?;
+    }
+    if !archive.methods.is_empty() {
+        writeln!(out, "  entries stored as: {}", archive.methods.join(", "))?;
+    }
+    writeln!(out, "  encrypted:         {}", if archive.encrypted { "yes" } else { "no" })?;
+    if let Some(created_by) = &archive.created_by {
+        writeln!(out, "  created by:        {}", created_by)?;
+    }
+    writeln!(out, "  entries:           {}", archive.entries)?;
+    writeln!(out, "  archive size:      {}", show_size(info.archive_size))?;
+    writeln!(out, "  uncompressed size: {}", show_size(archive.uncompressed_size))?;
+    if let Some(ratio) = info.ratio {
+        writeln!(out, "  ratio:             {:.2}%", ratio * 100.0)?;
+    }
+    if let Some(comment) = &archive.comment {
+        // Lines after the first one are aligned with it
+        let comment = comment.lines().collect::<Vec<_>>().join("\n                     ");
+        writeln!(out, "  comment:           {}", comment)?;
+    }
+    Ok(())
+}
@@ -1,22 +1,71 @@
 // Public modules
 pub mod cli;
 pub mod commands;
+pub mod extension;
 pub mod oof;
+#[cfg(target_os = "linux")]
+pub mod sandbox;
 
 // Private modules
+mod api;
 pub mod archive;
+#[cfg(feature = "async")]
+mod async_api;
+mod atomic;
+mod bench;
+pub mod cancel;
+pub mod checksum;
+mod completions;
+mod config;
+mod contents_hash;
+mod convert;
 mod dialogs;
+mod encryption;
 mod error;
-mod extension;
+mod events;
+mod formats;
+mod gzip;
+mod info;
+mod list;
+mod lz4;
+mod members;
+mod merge;
+mod progress;
+mod seekable;
+mod stats;
+mod try_formats;
+mod unpack;
 mod utils;
+mod zstandard;
 
-pub use error::{Error, Result};
+pub use api::{
+    compress, decompress, list, CompressOptions, DecompressOptions, ListOptions, Overwrite, Report,
+};
+pub use archive::FileInArchive;
+#[cfg(feature = "async")]
+pub use async_api::{compress_async, decompress_async};
+pub use error::{Error, FinalError, Result};
+pub use events::{ChannelEvents, Event, EventSink, NoEvents, Phase};
+pub use progress::{ProgressReporter, ProgressUnit};
+pub use utils::logger;
 
+/// Exit code of failures that don't have a code of their own, see `Error::exit_code`
 pub const EXIT_FAILURE: i32 = 127;
+/// The arguments could not be understood
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NOT_FOUND: i32 = 3;
+pub const EXIT_PERMISSION_DENIED: i32 = 4;
+/// The output already exists, and overwriting it was declined
+pub const EXIT_ALREADY_EXISTS: i32 = 5;
+pub const EXIT_CORRUPT_ARCHIVE: i32 = 6;
+/// The archive is encrypted, and no password or a wrong one was given
+pub const EXIT_WRONG_PASSWORD: i32 = 7;
+/// Some entries of the archive failed to be extracted, with `--continue-on-error`
+pub const EXIT_PARTIAL_FAILURE: i32 = 8;
 
 const VERSION: &str = "0.1.5";
 
-fn help_command() {
+fn help_command() -> crate::Result<()> {
     use utils::colors::*;
     /*
     ouch - Obvious Unified Compressed files Helper
@@ -27,20 +76,209 @@ fn help_command() {
         ouch compress <files...> OUTPUT.EXT    Compresses files into OUTPUT.EXT,
                                                where EXT must be a supported format.
 
+        ouch list <archives...>                Lists the files inside of archives, or what
+                                               the headers of compressed files store.
+
+        ouch info <archives...>                Shows the format, compression, comment and
+                                               sizes of archives.
+
+        ouch contents-hash <archives...>       Prints a hash of the files inside of
+                                               archives, the same for any format.
+
+        ouch merge <archives...> -o OUTPUT     Merges archives of the same format into
+                                               OUTPUT, which has that format too.
+
+        ouch convert <file> OUTPUT.EXT         Compresses a file again with the formats
+                                               of EXT, keeping the archive inside as is.
+
+        ouch encrypt <file> [-o OUTPUT]        Encrypts any file with a passphrase,
+                                               into <file>.age by default.
+
+        ouch decrypt <file.age> [-o OUTPUT]    Decrypts a file made by ouch encrypt
+                                               or by age with a passphrase.
+
+        ouch dict-train <dict> <files...>      Trains a Zstandard dictionary on many
+                                               small files of a kind, for --dict.
+
+        ouch formats [--json]                  Shows the supported formats and what
+                                               can be done with each of them.
+
+        ouch completions <shell>               Prints the completion script of bash, zsh,
+                                               fish, powershell or elvish.
+
+        ouch config path                       Shows where the ouch.toml config file
+                                               with default options is read from.
+
+        ouch config show                       Shows the settings in effect, from the
+                                               config file and the given flags.
+
     FLAGS:
         -h, --help    Display this help information.
         -y, --yes     Skip overwrite questions.
         -n, --no      Skip overwrite questions.
+        -q, --quiet   Only print errors.
+        -v, --verbose Also print every file added to or extracted from archives.
         --version     Display version information.
 
     SPECIFIC FLAGS:
         -o, --output FOLDER_PATH    When decompressing, to decompress files to
                                     another folder.
+        -r, --recursive             When decompressing, look for archives inside of
+                                    the given folders.
+        --each EXT                  When compressing, compress each input into its
+                                    own archive, named after it plus EXT.
+        --no-recursion              When compressing, only include the direct children
+                                    of folders (walkdir's max_depth(1)).
+        --depth N                   When compressing, go at most N levels deep into
+                                    folders (walkdir's max_depth(N)).
+        --dereference-hardlinks     When compressing to .tar, store hard links as
+                                    separate copies of the file.
+        --no-sparse                 Store the holes of sparse files as zeroes in .tar,
+                                    and write them out as zeroes when extracting.
+        --sparse                    When extracting .zip, .iso or compressed files,
+                                    leave blocks of zeroes as holes.
+        --sparse-threshold BYTES    With --sparse, the shortest run of zeroes left as
+                                    a hole, like 64k (default: 4096, one block).
+        --reproducible              When compressing, produce the same archive from the
+                                    same files: fixed timestamps and owners, sorted
+                                    entries.
+        --mtime TIMESTAMP           When compressing, the modification time stored
+                                    for every entry, as a date like
+                                    2024-01-01T00:00:00Z or as @seconds since the
+                                    Unix epoch. --reproducible uses it instead of
+                                    SOURCE_DATE_EPOCH.
+        --gitignore                 When compressing, leave out the files git ignores,
+                                    with .gitignore, .git/info/exclude and the global
+                                    excludes, off by default (--no-gitignore).
+        --exclude-vcs               When compressing, leave out .git, .svn, .hg and
+                                    other version control folders and files, and
+                                    honor .gitignore unless --no-gitignore is given.
+        --no-exclude-vcs            Keep them, when exclude_vcs is set in ouch.toml.
+        --follow-symlinks           When compressing, store the files and folders that
+                                    symlinks point to, under the path of the link.
+        --allow-weird-extension     When compressing to a name like out.gz.tar, write
+                                    it as .tar.gz instead of failing.
+        --try-formats               When compressing to .tar, compress it with .gz,
+                                    .bz2, .xz, .zst and .lz4 at once and keep the
+                                    smallest, its extension is added to the output.
+        --zstd-long[=WINDOW_LOG]    When compressing to .zst, find matches as far back
+                                    as 2^WINDOW_LOG bytes, 10 to 30 (default: 27,
+                                    128 MiB). Needed to decompress or list a .zst
+                                    with a larger window than 128 MiB.
+        --dict FILE                 When compressing to .zst, use the dictionary in
+                                    FILE, made by ouch dict-train. Needed to
+                                    decompress or list a .zst compressed with it.
+        --max-dict-size SIZE        With ouch dict-train, the largest the dictionary
+                                    can be (default: 110 KiB).
+        --checksum-file ALGORITHM   When compressing, also write the sha256, sha512 or
+                                    blake3 of the archive to OUTPUT.sha256,
+                                    OUTPUT.sha512 or OUTPUT.b3.
+        --compression-stats         When compressing, show the size, ratio and time of
+                                    each file, the least compressed first.
+        --low-memory                When compressing a .zip inside of another format,
+                                    like .zip.gz, build it in a temporary file instead
+                                    of in memory.
+        --create-tar-index          When compressing to .tar or .tar.gz, also write
+                                    where each entry starts to OUTPUT.idx.
+        --use-index                 When listing a .tar or .tar.gz, read its entries
+                                    from where its index says they are.
+        --special-files MODE        What to do with FIFOs, sockets and device nodes:
+                                    store them in .tar, skip them with a warning
+                                    (default) or fail.
+        --extract-nested            When decompressing, also extract archives found
+                                    inside of the archive.
+        --max-nested-depth N        How deep --extract-nested goes (default: 1).
+        --keep-nested               Keep the inner archives after extracting them.
+        --max-output-size SIZE      Stop extracting an archive once its contents exceed
+                                    SIZE, like 500M or 20GiB (default: 100 times
+                                    the archive size, at least 1GiB).
+        --no-size-limit             Disable the extraction size limit.
+        --max-ratio N               Stop extracting an archive once its contents are
+                                    N times its size (default: 0, no limit).
+        --max-files N               Stop extracting or listing an archive once it has
+                                    more than N entries (default: 0, no limit).
+        --charset ENCODING          Encoding of zip entry names not marked as UTF-8,
+                                    like shift_jis or gbk, or auto to try UTF-8
+                                    first (default: cp437). Also --zip-encoding.
+        --transform REGEX:NEW       Rename the entries matching REGEX when extracting
+                                    or listing, $1 being its first group. Repeatable.
+        --temp-dir PATH             Where a .zip or .iso inside of another format is
+                                    copied first, to extract or list it, or built
+                                    with --low-memory (default: the output folder,
+                                    or the system's temp folder).
+        --use-gzip-name             When decompressing .gz, name the output after the
+                                    file name stored in it.
+        --no-mtime                  When decompressing .gz, don't restore the stored
+                                    modification time.
+        --uid-map OLD:NEW,...       When extracting .tar, restore the owners of the
+        --gid-map OLD:NEW,...       files with these IDs replaced, other IDs are kept.
+                                    Takes root or CAP_CHOWN.
+        --acl                       When compressing to or extracting .tar, keep the
+                                    POSIX ACLs of the files (Linux only).
+        --preserve WHAT,...         When compressing to or extracting .tar, keep the
+                                    owners (ownership), extended attributes (xattrs,
+                                    Linux only) or access times (atime) of the files,
+                                    or all of them (all).
+        --format-tar FORMAT         Headers of the .tar archives written: pax, with
+                                    times to the nanosecond (default), gnu or ustar.
+        --numeric-owner             With --preserve ownership, restore owners by the
+                                    stored IDs, not by the stored user names.
+        --no-same-permissions       Extract files without the permissions the umask
+                                    clears, nor setuid, setgid and sticky bits.
+                                    --same-permissions, restoring them, is the default.
+        --no-smart-unpack           Extract archives right into the current folder,
+                                    or the one given with --output, even those
+                                    without a single folder at their root.
+        --flat                      Extract the files of archives without their
+                                    folders, skipping symlinks.
+        --atomic                    Extract archives into a temporary folder first,
+                                    moving them into place only if nothing failed.
+        --continue-on-error         Skip the entries of .tar and .zip archives that
+                                    fail to be extracted, failing once it's done.
+        --verify-checksum           Check archives against the checksum files next to
+                                    them before extracting, failing without one.
+        --remove-archive            Delete the archive once it was extracted
+                                    successfully, --keep-archive is the default.
+        --json                      With list, info or --compression-stats, print one
+                                    JSON object per input.
+        --print0                    When listing, print only the paths of the entries,
+                                    each one followed by a NUL byte, for xargs -0.
+        --bytes                     With list or info, show sizes as numbers of bytes
+                                    instead of KiB, MiB, ...
+        --date-format FORMAT        When listing, show modification times in a strftime
+                                    FORMAT instead of %Y-%m-%dT%H:%M:%S, or as how long
+                                    ago with "relative".
+        --conflict MODE             When merging, what to do with entries at the same
+                                    path: rename, skip or error (default). When
+                                    extracting to a case-insensitive filesystem,
+                                    with files whose paths only differ in case, or
+                                    with --flat, files with the same name: ask
+                                    (default), rename or skip.
+        --log-format FORMAT         Print messages as human (default) or json, one
+                                    object per line on stderr, without colors.
+        --log-file PATH             Also append the messages to PATH, with the time.
+        --no-summary                Don't summarize the warnings at the end.
+        --strict                    Fail if there were any warnings, right away for
+                                    skipped entries and unrestored permissions.
+        --question-timeout SECONDS  Answer no to questions nobody answered in time,
+                                    so that unattended scripts don't hang.
+        --no-config                 Ignore the ouch.toml config file.
+        --no-sandbox                Don't restrict the files ouch can access with
+                                    Landlock (Linux only), also OUCH_NO_SANDBOX=1.
+        --sandbox MODE              strict (default) only reads the inputs, normal
+                                    reads anything, off is --no-sandbox.
+
+    EXIT CODES:
+        0    Success              5    Output exists, not overwritten
+        2    Invalid arguments    6    Corrupt archive
+        3    File not found       7    Password needed or wrong
+        4    Permission denied    8    Some entries failed
+        127  Any other failure    130  Interrupted with Ctrl-C
 
     Visit https://github.com/vrmiguel/ouch for more usage examples.
     */
 
-    println!(
+    utils::write_stdout(format!(
         "\
 {cyan}ouch{reset} - Obvious Unified Compression files Helper
 
@@ -50,28 +288,223 @@ fn help_command() {
     {green}ouch compress {magenta}<files...> OUTPUT.EXT{reset}    Compresses files into {magenta}OUTPUT.EXT{reset},
                                            where {magenta}EXT{reset} must be a supported format.
 
+    {green}ouch list {magenta}<archives...>{reset}                Lists the files inside of archives, or what
+                                           the headers of compressed files store.
+
+    {green}ouch info {magenta}<archives...>{reset}                Shows the format, compression, comment and
+                                           sizes of archives.
+
+    {green}ouch contents-hash {magenta}<archives...>{reset}       Prints a hash of the files inside of
+                                           archives, the same for any format.
+
+    {green}ouch merge {magenta}<archives...>{reset} {yellow}-o{reset} {magenta}OUTPUT{reset}     Merges archives of the same format into
+                                           {magenta}OUTPUT{reset}, which has that format too.
+
+    {green}ouch convert {magenta}<file> OUTPUT.EXT{reset}         Compresses a file again with the formats
+                                           of {magenta}EXT{reset}, keeping the archive inside as is.
+
+    {green}ouch encrypt {magenta}<file>{reset} [{yellow}-o{reset} {magenta}OUTPUT{reset}]        Encrypts any file with a passphrase,
+                                           into {magenta}<file>.age{reset} by default.
+
+    {green}ouch decrypt {magenta}<file.age>{reset} [{yellow}-o{reset} {magenta}OUTPUT{reset}]    Decrypts a file made by {green}ouch encrypt{reset}
+                                           or by {green}age{reset} with a passphrase.
+
+    {green}ouch dict-train {magenta}<dict> <files...>{reset}      Trains a Zstandard dictionary on many
+                                           small files of a kind, for {yellow}--dict{reset}.
+
+    {green}ouch formats {reset}[{yellow}--json{reset}]                  Shows the supported formats and what
+                                           can be done with each of them.
+
+    {green}ouch completions {magenta}<shell>{reset}               Prints the completion script of {magenta}bash{reset}, {magenta}zsh{reset},
+                                           {magenta}fish{reset}, {magenta}powershell{reset} or {magenta}elvish{reset}.
+
+    {green}ouch config path{reset}                       Shows where the {magenta}ouch.toml{reset} config file
+                                           with default options is read from.
+
+    {green}ouch config show{reset}                       Shows the settings in effect, from the
+                                           config file and the given flags.
+
 {cyan}FLAGS:{reset}
     {yellow}-h{white}, {yellow}--help{reset}    Display this help information.
     {yellow}-y{white}, {yellow}--yes{reset}     Skip overwrite questions.
     {yellow}-n{white}, {yellow}--no{reset}      Skip overwrite questions.
+    {yellow}-q{white}, {yellow}--quiet{reset}   Only print errors.
+    {yellow}-v{white}, {yellow}--verbose{reset} Also print every file added to or extracted from archives.
     {yellow}--version{reset}     Display version information.
 
 {cyan}SPECIFIC FLAGS:{reset}
     {yellow}-o{reset}, {yellow}--output{reset} FOLDER_PATH    When decompressing, to decompress files to
                                 another folder.
+    {yellow}-r{reset}, {yellow}--recursive{reset}             When decompressing, look for archives inside of
+                                the given folders.
+    {yellow}--each{reset} EXT                  When compressing, compress each input into its
+                                own archive, named after it plus {magenta}EXT{reset}.
+    {yellow}--no-recursion{reset}              When compressing, only include the direct children
+                                of folders (walkdir's max_depth(1)).
+    {yellow}--depth{reset} N                   When compressing, go at most N levels deep into
+                                folders (walkdir's max_depth(N)).
+    {yellow}--dereference-hardlinks{reset}     When compressing to .tar, store hard links as
+                                separate copies of the file.
+    {yellow}--no-sparse{reset}                 Store the holes of sparse files as zeroes in .tar,
+                                and write them out as zeroes when extracting.
+    {yellow}--sparse{reset}                    When extracting .zip, .iso or compressed files,
+                                leave blocks of zeroes as holes.
+    {yellow}--sparse-threshold{reset} BYTES    With {yellow}--sparse{reset}, the shortest run of zeroes left as
+                                a hole, like 64k (default: 4096, one block).
+    {yellow}--reproducible{reset}              When compressing, produce the same archive from the
+                                same files: fixed timestamps and owners, sorted
+                                entries.
+    {yellow}--mtime{reset} TIMESTAMP           When compressing, the modification time stored
+                                for every entry, as a date like
+                                2024-01-01T00:00:00Z or as @seconds since the
+                                Unix epoch. {yellow}--reproducible{reset} uses it instead of
+                                {magenta}SOURCE_DATE_EPOCH{reset}.
+    {yellow}--gitignore{reset}                 When compressing, leave out the files git ignores,
+                                with {magenta}.gitignore{reset}, {magenta}.git/info/exclude{reset} and the global
+                                excludes, off by default ({yellow}--no-gitignore{reset}).
+    {yellow}--exclude-vcs{reset}               When compressing, leave out {magenta}.git{reset}, {magenta}.svn{reset}, {magenta}.hg{reset} and
+                                other version control folders and files, and
+                                honor {magenta}.gitignore{reset} unless {yellow}--no-gitignore{reset} is given.
+    {yellow}--no-exclude-vcs{reset}            Keep them, when {magenta}exclude_vcs{reset} is set in {magenta}ouch.toml{reset}.
+    {yellow}--follow-symlinks{reset}           When compressing, store the files and folders that
+                                symlinks point to, under the path of the link.
+    {yellow}--allow-weird-extension{reset}     When compressing to a name like {magenta}out.gz.tar{reset}, write
+                                it as {magenta}.tar.gz{reset} instead of failing.
+    {yellow}--try-formats{reset}               When compressing to {magenta}.tar{reset}, compress it with {magenta}.gz{reset},
+                                {magenta}.bz2{reset}, {magenta}.xz{reset}, {magenta}.zst{reset} and {magenta}.lz4{reset} at once and keep the
+                                smallest, its extension is added to the output.
+    {yellow}--zstd-long{reset}[=WINDOW_LOG]    When compressing to {magenta}.zst{reset}, find matches as far back
+                                as 2^WINDOW_LOG bytes, 10 to 30 (default: 27,
+                                128 MiB). Needed to decompress or list a {magenta}.zst{reset}
+                                with a larger window than 128 MiB.
+    {yellow}--dict{reset} FILE                 When compressing to {magenta}.zst{reset}, use the dictionary in
+                                {magenta}FILE{reset}, made by {green}ouch dict-train{reset}. Needed to
+                                decompress or list a {magenta}.zst{reset} compressed with it.
+    {yellow}--max-dict-size{reset} SIZE        With {green}ouch dict-train{reset}, the largest the dictionary
+                                can be (default: 110 KiB).
+    {yellow}--checksum-file{reset} ALGORITHM   When compressing, also write the {magenta}sha256{reset}, {magenta}sha512{reset} or
+                                {magenta}blake3{reset} of the archive to {magenta}OUTPUT.sha256{reset},
+                                {magenta}OUTPUT.sha512{reset} or {magenta}OUTPUT.b3{reset}.
+    {yellow}--compression-stats{reset}         When compressing, show the size, ratio and time of
+                                each file, the least compressed first.
+    {yellow}--low-memory{reset}                When compressing a .zip inside of another format,
+                                like {magenta}.zip.gz{reset}, build it in a temporary file instead
+                                of in memory.
+    {yellow}--create-tar-index{reset}          When compressing to {magenta}.tar{reset} or {magenta}.tar.gz{reset}, also write
+                                where each entry starts to {magenta}OUTPUT.idx{reset}.
+    {yellow}--use-index{reset}                 When listing a {magenta}.tar{reset} or {magenta}.tar.gz{reset}, read its entries
+                                from where its index says they are.
+    {yellow}--special-files{reset} MODE        What to do with FIFOs, sockets and device nodes:
+                                {magenta}store{reset} them in .tar, {magenta}skip{reset} them with a warning
+                                (default) or {magenta}fail{reset}.
+    {yellow}--extract-nested{reset}            When decompressing, also extract archives found
+                                inside of the archive.
+    {yellow}--max-nested-depth{reset} N        How deep {yellow}--extract-nested{reset} goes (default: 1).
+    {yellow}--keep-nested{reset}               Keep the inner archives after extracting them.
+    {yellow}--max-output-size{reset} SIZE      Stop extracting an archive once its contents exceed
+                                {magenta}SIZE{reset}, like 500M or 20GiB (default: 100 times
+                                the archive size, at least 1GiB).
+    {yellow}--no-size-limit{reset}             Disable the extraction size limit.
+    {yellow}--max-ratio{reset} N               Stop extracting an archive once its contents are
+                                {magenta}N{reset} times its size (default: 0, no limit).
+    {yellow}--max-files{reset} N               Stop extracting or listing an archive once it has
+                                more than {magenta}N{reset} entries (default: 0, no limit).
+    {yellow}--charset{reset} ENCODING          Encoding of zip entry names not marked as UTF-8,
+                                like shift_jis or gbk, or auto to try UTF-8
+                                first (default: cp437). Also {yellow}--zip-encoding{reset}.
+    {yellow}--transform{reset} REGEX:NEW       Rename the entries matching {magenta}REGEX{reset} when extracting
+                                or listing, {magenta}$1{reset} being its first group. Repeatable.
+    {yellow}--temp-dir{reset} PATH             Where a .zip or .iso inside of another format is
+                                copied first, to extract or list it, or built
+                                with {yellow}--low-memory{reset} (default: the output folder,
+                                or the system's temp folder).
+    {yellow}--use-gzip-name{reset}             When decompressing .gz, name the output after the
+                                file name stored in it.
+    {yellow}--no-mtime{reset}                  When decompressing .gz, don't restore the stored
+                                modification time.
+    {yellow}--uid-map{reset} OLD:NEW,...       When extracting .tar, restore the owners of the
+    {yellow}--gid-map{reset} OLD:NEW,...       files with these IDs replaced, other IDs are kept.
+                                Takes root or {magenta}CAP_CHOWN{reset}.
+    {yellow}--acl{reset}                       When compressing to or extracting .tar, keep the
+                                POSIX ACLs of the files (Linux only).
+    {yellow}--preserve{reset} WHAT,...         When compressing to or extracting .tar, keep the
+                                owners ({magenta}ownership{reset}), extended attributes ({magenta}xattrs{reset},
+                                Linux only) or access times ({magenta}atime{reset}) of the files,
+                                or all of them ({magenta}all{reset}).
+    {yellow}--format-tar{reset} FORMAT         Headers of the .tar archives written: {magenta}pax{reset}, with
+                                times to the nanosecond (default), {magenta}gnu{reset} or {magenta}ustar{reset}.
+    {yellow}--numeric-owner{reset}             With {yellow}--preserve ownership{reset}, restore owners by the
+                                stored IDs, not by the stored user names.
+    {yellow}--no-same-permissions{reset}       Extract files without the permissions the umask
+                                clears, nor setuid, setgid and sticky bits.
+                                {yellow}--same-permissions{reset}, restoring them, is the default.
+    {yellow}--no-smart-unpack{reset}           Extract archives right into the current folder,
+                                or the one given with {yellow}--output{reset}, even those
+                                without a single folder at their root.
+    {yellow}--flat{reset}                      Extract the files of archives without their
+                                folders, skipping symlinks.
+    {yellow}--atomic{reset}                    Extract archives into a temporary folder first,
+                                moving them into place only if nothing failed.
+    {yellow}--continue-on-error{reset}         Skip the entries of .tar and .zip archives that
+                                fail to be extracted, failing once it's done.
+    {yellow}--verify-checksum{reset}           Check archives against the checksum files next to
+                                them before extracting, failing without one.
+    {yellow}--remove-archive{reset}            Delete the archive once it was extracted
+                                successfully, {yellow}--keep-archive{reset} is the default.
+    {yellow}--json{reset}                      With list, info or {yellow}--compression-stats{reset}, print one
+                                JSON object per input.
+    {yellow}--print0{reset}                    When listing, print only the paths of the entries,
+                                each one followed by a NUL byte, for {green}xargs -0{reset}.
+    {yellow}--bytes{reset}                     With list or info, show sizes as numbers of bytes
+                                instead of KiB, MiB, ...
+    {yellow}--date-format{reset} FORMAT        When listing, show modification times in a strftime
+                                FORMAT instead of {magenta}%Y-%m-%dT%H:%M:%S{reset}, or as how long
+                                ago with {magenta}relative{reset}.
+    {yellow}--conflict{reset} MODE             When merging, what to do with entries at the same
+                                path: {magenta}rename{reset}, {magenta}skip{reset} or {magenta}error{reset} (default). When
+                                extracting to a case-insensitive filesystem,
+                                with files whose paths only differ in case, or
+                                with {yellow}--flat{reset}, files with the same name: {magenta}ask{reset}
+                                (default), {magenta}rename{reset} or {magenta}skip{reset}.
+    {yellow}--log-format{reset} FORMAT         Print messages as {magenta}human{reset} (default) or {magenta}json{reset}, one
+                                object per line on stderr, without colors.
+    {yellow}--log-file{reset} PATH             Also append the messages to PATH, with the time.
+    {yellow}--no-summary{reset}                Don't summarize the warnings at the end.
+    {yellow}--strict{reset}                    Fail if there were any warnings, right away for
+                                skipped entries and unrestored permissions.
+    {yellow}--question-timeout{reset} SECONDS  Answer no to questions nobody answered in time,
+                                so that unattended scripts don't hang.
+    {yellow}--no-config{reset}                 Ignore the {magenta}ouch.toml{reset} config file.
+    {yellow}--no-sandbox{reset}                Don't restrict the files ouch can access with
+                                Landlock (Linux only), also {magenta}OUCH_NO_SANDBOX=1{reset}.
+    {yellow}--sandbox{reset} MODE              {magenta}strict{reset} (default) only reads the inputs, {magenta}normal{reset}
+                                reads anything, {magenta}off{reset} is {yellow}--no-sandbox{reset}.
+
+{cyan}EXIT CODES:{reset}
+    {green}0{reset}    Success              {green}5{reset}    Output exists, not overwritten
+    {green}2{reset}    Invalid arguments    {green}6{reset}    Corrupt archive
+    {green}3{reset}    File not found       {green}7{reset}    Password needed or wrong
+    {green}4{reset}    Permission denied    {green}8{reset}    Some entries failed
+    {green}127{reset}  Any other failure    {green}130{reset}  Interrupted with Ctrl-C
 
-Visit https://github.com/vrmiguel/ouch for more usage examples.",
+Visit https://github.com/vrmiguel/ouch for more usage examples.
+",
         magenta = magenta(),
         white = white(),
         green = green(),
         yellow = yellow(),
         reset = reset(),
         cyan = cyan()
-    );
+    ))
 }
 
 #[inline]
-fn version_command() {
+fn version_command() -> crate::Result<()> {
     use utils::colors::*;
-    println!("{green}ouch{reset} {}", crate::VERSION, green = green(), reset = reset());
+    utils::write_stdout(format!(
+        "{green}ouch{reset} {}\n",
+        crate::VERSION,
+        green = green(),
+        reset = reset()
+    ))
 }
@@ -0,0 +1,573 @@
+//! `ouch list`: the entries of archives, or what the headers of single file formats tell about
+//! their contents.
+
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, prelude::*, BufReader, SeekFrom},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+use serde::Serialize;
+
+use crate::{
+    archive::{self, transform::Transforms, zip::SplitZipReader, FileInArchive},
+    commands::chain_reader_decoder,
+    error::FinalError,
+    extension::{
+        self,
+        CompressionFormat::{self, *},
+    },
+    gzip, lz4, oof,
+    seekable::SeekableDecoder,
+    utils::{
+        self, colors, format_size,
+        io::{spool, temp_dir, EntryLimit},
+        to_utf,
+    },
+    zstandard,
+};
+
+/// What `ouch list` found in one file, printed as text or, with `--json`, as one JSON object per
+/// line
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Listing {
+    Archive {
+        path: PathBuf,
+        format: String,
+        entries: Vec<FileInArchive>,
+    },
+    /// Single file formats, like .gz, only compress a stream
+    Stream {
+        path: PathBuf,
+        format: String,
+        #[serde(flatten)]
+        info: StreamInfo,
+    },
+}
+
+#[derive(Debug, Default, Serialize)]
+struct StreamInfo {
+    compressed_size: u64,
+    /// Only known when the format records it, like .gz and .xz do
+    uncompressed_size: Option<u64>,
+    /// Compressed size over uncompressed size
+    ratio: Option<f64>,
+    /// Original name stored in .gz headers
+    name: Option<PathBuf>,
+    /// Modification time stored in .gz headers, in seconds since the Unix epoch
+    mtime: Option<i64>,
+}
+
+/// How modification times are shown, set by `--date-format`. JSON always has them in seconds since
+/// the Unix epoch.
+enum DateFormat {
+    /// A strftime format, in the local time zone
+    Strftime(String),
+    /// `--date-format relative`: how long before `now`, like "2 days ago"
+    Relative { now: DateTime<Local> },
+}
+
+impl DateFormat {
+    /// ISO 8601, without the time zone
+    const DEFAULT: &'static str = "%Y-%m-%dT%H:%M:%S";
+
+    fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let format = match flags.arg("date-format") {
+            Some(format) => format.to_str().ok_or(crate::Error::InvalidUnicode)?,
+            None => Self::DEFAULT,
+        };
+        if format == "relative" {
+            return Ok(Self::Relative { now: Local::now() });
+        }
+        if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            return Err(FinalError::with_title(format!("Invalid --date-format '{}'", format))
+                .detail("It is not a strftime format")
+                .hint("Use a format like '%Y-%m-%d %H:%M', or 'relative'")
+                .into());
+        }
+        Ok(Self::Strftime(format.to_string()))
+    }
+
+    /// `modified`, in seconds since the Unix epoch, or `None` when it is out of range
+    fn format(&self, modified: i64) -> Option<String> {
+        let time = DateTime::from_timestamp(modified, 0)?.with_timezone(&Local);
+        match self {
+            Self::Strftime(format) => Some(time.format(format).to_string()),
+            Self::Relative { now } => Some(relative_time(now.signed_duration_since(time))),
+        }
+    }
+}
+
+/// `elapsed` since a time, like "2 days ago", or "in 3 hours" for a time after now
+fn relative_time(elapsed: chrono::Duration) -> String {
+    let days = elapsed.num_days();
+    let units = [
+        (days / 365, "year"),
+        (days / 30, "month"),
+        (days, "day"),
+        (elapsed.num_hours(), "hour"),
+        (elapsed.num_minutes(), "minute"),
+    ];
+    let (amount, unit) = units
+        .iter()
+        .copied()
+        .find(|(amount, _)| *amount != 0)
+        .unwrap_or((elapsed.num_seconds(), "second"));
+
+    let plural = if amount.abs() == 1 { "" } else { "s" };
+    if amount < 0 {
+        format!("in {} {}{}", -amount, unit, plural)
+    } else {
+        format!("{} {}{} ago", amount, unit, plural)
+    }
+}
+
+pub fn list_files(files: &[PathBuf], flags: &oof::Flags) -> crate::Result<()> {
+    if flags.is_present("json") && flags.is_present("print0") {
+        return Err(crate::Error::ConflictingFlags("json", "print0"));
+    }
+    let dates = DateFormat::from_flags(flags)?;
+
+    for path in files {
+        let listing = list_file(path, &formats_of(path)?, flags)?;
+        let mut output = vec![];
+        if flags.is_present("json") {
+            // Safe unwrap: the listing only holds strings, numbers and paths
+            writeln!(output, "{}", serde_json::to_string(&listing).unwrap())?;
+        } else if flags.is_present("print0") {
+            print_paths0(&mut output, &listing)?;
+        } else {
+            print_listing(&mut output, &listing, &dates, flags.is_present("bytes"))?;
+        }
+        utils::write_stdout(output)?;
+    }
+
+    Ok(())
+}
+
+/// The entries of the archive at `path`, for programs using ouch as a library
+pub(crate) fn archive_entries(
+    path: &Path,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<FileInArchive>> {
+    entries(path, &formats_of(path)?, flags)
+}
+
+/// The entries of the archive at `path`, in `formats`, as they would be extracted
+pub(crate) fn entries(
+    path: &Path,
+    formats: &[CompressionFormat],
+    flags: &oof::Flags,
+) -> crate::Result<Vec<FileInArchive>> {
+    match list_file(path, formats, flags)? {
+        Listing::Archive { entries, .. } => Ok(entries),
+        Listing::Stream { .. } => {
+            Err(FinalError::with_title(format!("Cannot list the entries of '{}'.", to_utf(path)))
+                .detail("It is a single compressed file, not an archive.")
+                .into())
+        },
+    }
+}
+
+/// Call `visit` with the entries of the archive at `path`, in `formats`, as they would be
+/// extracted, until it returns `false`. .tar and .cpio archives are only read up to there, .zip
+/// and .iso ones are listed whole first, from their central directory.
+///
+/// `false`, without calling `visit`, for compressed .zip and .iso archives that would have to be
+/// decompressed whole into a temporary file to be listed.
+pub(crate) fn visit_entries(
+    path: &Path,
+    formats: &[CompressionFormat],
+    flags: &oof::Flags,
+    mut visit: impl FnMut(FileInArchive) -> bool,
+) -> crate::Result<bool> {
+    if matches!(formats[0], Zip | Iso) {
+        let seekable = matches!(formats, [_, Zstd])
+            && SeekableDecoder::new(fs::File::open(path)?)?.is_some();
+        if formats.len() > 1 && !seekable {
+            return Ok(false);
+        }
+        for entry in entries(path, formats, flags)? {
+            if !visit(entry) {
+                break;
+            }
+        }
+        return Ok(true);
+    }
+
+    let mut reader: Box<dyn Read> = Box::new(BufReader::new(fs::File::open(path)?));
+    let zstd_options = zstandard::Options::from_flags(flags)?;
+    for format in formats.iter().skip(1).rev() {
+        reader = chain_reader_decoder(format, reader, &zstd_options)?;
+    }
+    let limit = &mut EntryLimit::from_flags(flags)?;
+    let transforms = Transforms::from_flags(flags)?;
+    // Shown as they would be extracted with the same --transform
+    let visit = |mut entry: FileInArchive| -> crate::Result<bool> {
+        match transforms.apply(&entry.path, entry.is_dir)? {
+            Some(renamed) => {
+                entry.path = renamed;
+                Ok(visit(entry))
+            },
+            None => Ok(true),
+        }
+    };
+    match formats[0] {
+        Tar => archive::tar::visit_archive(reader, limit, visit)?,
+        _ => archive::cpio::visit_archive(reader, limit, visit)?,
+    }
+    Ok(true)
+}
+
+/// The formats of the file at `path`, from its extensions
+fn formats_of(path: &Path) -> crate::Result<Vec<CompressionFormat>> {
+    let formats = extension::extensions_from_path(path);
+    if formats.is_empty() {
+        return Err(FinalError::with_title(format!("Cannot list '{}'.", to_utf(path)))
+            .detail("Its extension is not one of the supported formats.")
+            .hint("Run 'ouch formats' for the full list of supported formats")
+            .into());
+    }
+    Ok(formats)
+}
+
+fn list_file(
+    path: &Path,
+    formats: &[CompressionFormat],
+    flags: &oof::Flags,
+) -> crate::Result<Listing> {
+    let format = formats.iter().map(|format| format.to_string()).collect();
+    let file = fs::File::open(path)?;
+
+    if !formats[0].is_archive() {
+        // The outermost format is the one whose header can be read
+        // Safe unwrap: `formats` is not empty
+        let info = stream_info(file, formats.last().unwrap())?;
+        return Ok(Listing::Stream { path: path.to_path_buf(), format, info });
+    }
+
+    let limit = &mut EntryLimit::from_flags(flags)?;
+    let transforms = Transforms::from_flags(flags)?;
+    let indexed = match *formats {
+        [Tar] | [Tar, Gzip] if flags.is_present("use-index") => {
+            archive::index::list_archive(path, formats.len() == 2, limit)?
+        },
+        _ => None,
+    };
+    let entries = match indexed {
+        Some(entries) => entries,
+        None => read_entries(path, file, formats, limit, flags)?,
+    };
+
+    // Shown as they would be extracted with the same --transform
+    let mut renamed_entries = vec![];
+    for mut entry in entries {
+        if let Some(renamed) = transforms.apply(&entry.path, entry.is_dir)? {
+            entry.path = renamed;
+            renamed_entries.push(entry);
+        }
+    }
+
+    Ok(Listing::Archive { path: path.to_path_buf(), format, entries: renamed_entries })
+}
+
+/// The entries of the archive at `path`, opened as `file`, read from start to end
+fn read_entries(
+    path: &Path,
+    file: fs::File,
+    formats: &[CompressionFormat],
+    limit: &mut EntryLimit,
+    flags: &oof::Flags,
+) -> crate::Result<Vec<FileInArchive>> {
+    // Archives in a seekable .zst are read in place, .tar ones by seeking from header to header
+    if let [Tar | Zip | Iso, Zstd] = *formats {
+        if let Some(decoder) = SeekableDecoder::new(&file)? {
+            return match formats[0] {
+                Tar => archive::tar::list_seekable_archive(decoder, limit),
+                Zip => archive::zip::list_archive(zip::ZipArchive::new(decoder)?, limit, flags),
+                _ => archive::iso::list_archive(archive::iso::IsoImage::new(decoder)?, limit),
+            };
+        }
+        (&file).rewind()?;
+    }
+
+    // .zip and .iso need io::Seek, compressed ones are decompressed into a temporary file first
+    let entries = match *formats {
+        [Zip] => {
+            let volumes = SplitZipReader::open(path)?;
+            archive::zip::list_archive(zip::ZipArchive::new(volumes)?, limit, flags)?
+        },
+        [Iso] => archive::iso::list_archive(archive::iso::IsoImage::new(file)?, limit)?,
+        _ => {
+            let mut reader: Box<dyn Read> = Box::new(BufReader::new(file));
+            let zstd_options = zstandard::Options::from_flags(flags)?;
+            for format in formats.iter().skip(1).rev() {
+                reader = chain_reader_decoder(format, reader, &zstd_options)?;
+            }
+
+            match formats[0] {
+                Tar => archive::tar::list_archive(reader, limit)?,
+                Cpio => archive::cpio::list_archive(reader, limit)?,
+                Zip | Iso => {
+                    let file = spool(&mut reader, &temp_dir(flags, None))?;
+                    if formats[0] == Zip {
+                        archive::zip::list_archive(zip::ZipArchive::new(file)?, limit, flags)?
+                    } else {
+                        archive::iso::list_archive(archive::iso::IsoImage::new(file)?, limit)?
+                    }
+                },
+                Gzip | Bzip | Lzma | Snappy | Zstd | Lz4 | Lz4Block => {
+                    unreachable!("checked to be an archive in list_file")
+                },
+            }
+        },
+    };
+    Ok(entries)
+}
+
+fn stream_info(mut file: fs::File, format: &CompressionFormat) -> crate::Result<StreamInfo> {
+    let mut info = StreamInfo { compressed_size: file.metadata()?.len(), ..StreamInfo::default() };
+
+    match format {
+        Gzip => {
+            let decoder = flate2::read::GzDecoder::new(BufReader::new(&file));
+            if let Some(header) = decoder.header() {
+                let stored = gzip::StoredMetadata::from_header(header);
+                info.name = stored.name;
+                info.mtime = stored
+                    .mtime
+                    .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                    .map(|mtime| mtime.as_secs() as i64);
+            }
+            info.uncompressed_size = gzip_uncompressed_size(&mut file)?;
+        },
+        Lzma => info.uncompressed_size = xz_uncompressed_size(&mut file)?,
+        Lz4 | Lz4Block => {
+            let mut start = vec![];
+            (&mut file).take(4).read_to_end(&mut start)?;
+            info.uncompressed_size = lz4::block_size(&start).map(u64::from);
+        },
+        // .bz doesn't record the size of its contents
+        _ => {},
+    }
+
+    info.ratio = info
+        .uncompressed_size
+        .filter(|size| *size > 0)
+        .map(|size| info.compressed_size as f64 / size as f64);
+    Ok(info)
+}
+
+/// The ISIZE field at the end of gzip streams, the uncompressed size modulo 2^32.
+///
+/// Files with several gzip members only record the size of the last one.
+fn gzip_uncompressed_size(file: &mut fs::File) -> io::Result<Option<u64>> {
+    if file.metadata()?.len() < 18 {
+        return Ok(None);
+    }
+
+    let mut isize = [0; 4];
+    file.seek(SeekFrom::End(-4))?;
+    file.read_exact(&mut isize)?;
+    Ok(Some(u32::from_le_bytes(isize) as u64))
+}
+
+/// Sum of the uncompressed sizes in the index of the last stream of an .xz file
+fn xz_uncompressed_size(file: &mut fs::File) -> io::Result<Option<u64>> {
+    const FOOTER_SIZE: u64 = 12;
+
+    let length = file.metadata()?.len();
+    if length < FOOTER_SIZE * 2 {
+        return Ok(None);
+    }
+
+    // Stream footer: CRC32, backward size, stream flags and the "YZ" magic bytes
+    let mut footer = [0; FOOTER_SIZE as usize];
+    file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+    file.read_exact(&mut footer)?;
+    if &footer[10..12] != b"YZ" {
+        return Ok(None);
+    }
+
+    // The backward size is the size of the index, stored in multiples of 4 bytes minus one
+    let index_size =
+        (u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]) as u64 + 1) * 4;
+    if index_size > length - FOOTER_SIZE {
+        return Ok(None);
+    }
+
+    let mut index = vec![0; index_size as usize];
+    file.seek(SeekFrom::Start(length - FOOTER_SIZE - index_size))?;
+    file.read_exact(&mut index)?;
+
+    // Index indicator, number of records, then the unpadded and uncompressed size of each block
+    if index[0] != 0 {
+        return Ok(None);
+    }
+    let mut position = 1;
+    let mut next = || read_xz_varint(&index, &mut position);
+    let total = next().and_then(|records| {
+        (0..records).try_fold(0u64, |total, _| {
+            let _unpadded_size = next()?;
+            total.checked_add(next()?)
+        })
+    });
+
+    Ok(total)
+}
+
+/// Variable length integers of .xz, 7 bits per byte with the highest bit set on all but the last
+fn read_xz_varint(bytes: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..63).step_by(7) {
+        let byte = *bytes.get(*position)?;
+        *position += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// With `raw_bytes` (`--bytes`), sizes are shown as plain numbers of bytes, for scripts
+fn print_listing(
+    out: &mut impl Write,
+    listing: &Listing,
+    dates: &DateFormat,
+    raw_bytes: bool,
+) -> io::Result<()> {
+    let show_size = |bytes: u64| if raw_bytes { bytes.to_string() } else { format_size(bytes) };
+
+    match listing {
+        Listing::Archive { path, entries, .. } => {
+            writeln!(out, "{}{}:{}", colors::cyan(), to_utf(path), colors::reset())?;
+            let sizes: Vec<String> = entries
+                .iter()
+                .map(|entry| if entry.is_dir { String::new() } else { show_size(entry.size) })
+                .collect();
+            let width = sizes.iter().map(String::len).max().unwrap_or(0);
+            let modified: Vec<String> = entries
+                .iter()
+                .map(|entry| entry.modified.and_then(|time| dates.format(time)).unwrap_or_default())
+                .collect();
+            // No column for formats that don't store modification times
+            let date_width = modified.iter().map(|date| date.chars().count()).max().unwrap_or(0);
+
+            for ((entry, size), date) in entries.iter().zip(&sizes).zip(&modified) {
+                let suffix = if entry.is_dir { "/" } else { "" };
+                let date = match date_width {
+                    0 => String::new(),
+                    _ => format!("{:<width$}  ", date, width = date_width),
+                };
+                writeln!(
+                    out,
+                    "{:>width$}  {}{}{}",
+                    size,
+                    date,
+                    to_utf(entry.path.to_string_lossy().trim_end_matches('/')),
+                    suffix,
+                    width = width
+                )?;
+            }
+
+            let folders = entries.iter().filter(|entry| entry.is_dir).count();
+            let files = entries.len() - folders;
+            let total = entries.iter().filter(|entry| !entry.is_dir).map(|entry| entry.size).sum();
+            writeln!(
+                out,
+                "{} file{} and {} folder{}, {} in total",
+                files,
+                if files == 1 { "" } else { "s" },
+                folders,
+                if folders == 1 { "" } else { "s" },
+                show_size(total)
+            )?;
+        },
+        Listing::Stream { path, format, info } => {
+            writeln!(
+                out,
+                "{}{}:{} {} stream, not an archive",
+                colors::cyan(),
+                to_utf(path),
+                colors::reset(),
+                format
+            )?;
+            writeln!(out, "  compressed size:   {}", show_size(info.compressed_size))?;
+            match info.uncompressed_size {
+                Some(size) => writeln!(out, "  uncompressed size: {}", show_size(size))?,
+                None => writeln!(out, "  uncompressed size: unknown, not recorded by {}", format)?,
+            }
+            if let Some(ratio) = info.ratio {
+                writeln!(out, "  ratio:             {:.2}%", ratio * 100.0)?;
+            }
+            if let Some(name) = &info.name {
+                writeln!(out, "  original name:     {}", to_utf(name))?;
+            }
+            if let Some(mtime) = info.mtime.and_then(|mtime| dates.format(mtime)) {
+                writeln!(out, "  modified:          {}", mtime)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// `--print0`: only the paths of the entries, each one followed by a NUL byte instead of a newline
+/// so that any name can go through `xargs -0`. Streams have no entries, nothing is printed for them.
+fn print_paths0(out: &mut impl Write, listing: &Listing) -> io::Result<()> {
+    let entries = match listing {
+        Listing::Archive { entries, .. } => entries,
+        Listing::Stream { .. } => return Ok(()),
+    };
+
+    for entry in entries {
+        let path = path_bytes(&entry.path);
+        let mut path: &[u8] = &path;
+        while let [rest @ .., b'/'] = path {
+            path = rest;
+        }
+        out.write_all(path)?;
+        if entry.is_dir {
+            out.write_all(b"/")?;
+        }
+        out.write_all(b"\0")?;
+    }
+    Ok(())
+}
+
+/// The bytes of `path` as they were in the archive, unlike `to_utf` nothing is escaped
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    Cow::Owned(path.to_string_lossy().into_owned().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_time() {
+        let relative = |seconds| relative_time(chrono::Duration::seconds(seconds));
+        assert_eq!(relative(0), "0 seconds ago");
+        assert_eq!(relative(90), "1 minute ago");
+        assert_eq!(relative(3 * 3600), "3 hours ago");
+        assert_eq!(relative(2 * 86400 + 3600), "2 days ago");
+        assert_eq!(relative(45 * 86400), "1 month ago");
+        assert_eq!(relative(800 * 86400), "2 years ago");
+        assert_eq!(relative(-3 * 3600), "in 3 hours");
+    }
+}
@@ -0,0 +1,113 @@
+//! The LZ4 block format, without the frame of the lz4 command around it, as stored by ClickHouse
+//! or older versions of Kafka. The size of the block decompressed comes first, as a little endian
+//! u32, like `lz4_flex::block::compress_prepend_size` writes it.
+//!
+//! A block is compressed and decompressed whole, in memory, so it holds at most 4 GiB. .lz4 and
+//! .lz4block files are told apart by the magic bytes of frames, whichever extension they have.
+
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, Cursor, Read, Write},
+};
+
+use crate::{extension::CompressionFormat::Lz4, members::MultiMemberDecoder};
+
+const FRAME_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+/// LZ4 doesn't expand data more than this many times
+const MAX_RATIO: u64 = 255;
+
+/// Writes the data written to it as a single block once dropped
+pub struct BlockEncoder<W: Write> {
+    writer: W,
+    data: Vec<u8>,
+}
+
+impl<W: Write> BlockEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, data: vec![] }
+    }
+}
+
+impl<W: Write> Write for BlockEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if u32::try_from(self.data.len() + buf.len()).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an .lz4block holds at most 4 GiB, use .lz4 instead",
+            ));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for BlockEncoder<W> {
+    /// Errors are ignored, like when the other encoders write the end of their stream on drop
+    fn drop(&mut self) {
+        let block = lz4_flex::block::compress_prepend_size(&self.data);
+        let _ = self.writer.write_all(&block).and_then(|()| self.writer.flush());
+    }
+}
+
+/// Decoder of the frames or of the block that `input` holds
+pub fn decoder<R: BufRead + 'static>(mut input: R) -> io::Result<Box<dyn Read>> {
+    if input.fill_buf()?.starts_with(FRAME_MAGIC) {
+        return Ok(Box::new(MultiMemberDecoder::new(input, Lz4)));
+    }
+
+    let mut block = vec![];
+    input.read_to_end(&mut block)?;
+    // The size is checked before it is allocated
+    let size = block_size(&block).unwrap_or(0);
+    if u64::from(size) > block.len() as u64 * MAX_RATIO {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the LZ4 block claims to hold {} bytes, more than it can", size),
+        ));
+    }
+    let data = lz4_flex::block::decompress_size_prepended(&block)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(Box::new(Cursor::new(data)))
+}
+
+/// The size of the decompressed data, that `block` starts with, `None` for frames, which don't
+/// always store it
+pub fn block_size(block: &[u8]) -> Option<u32> {
+    if block.starts_with(FRAME_MAGIC) {
+        return None;
+    }
+    let size = block.get(..4)?;
+    Some(u32::from_le_bytes(<[u8; 4]>::try_from(size).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(input: Vec<u8>) -> io::Result<Vec<u8>> {
+        let mut decoded = vec![];
+        decoder(Cursor::new(input))?.read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    #[test]
+    fn test_block_and_frames() {
+        let mut block = vec![];
+        BlockEncoder::new(&mut block).write_all(b"block block block").unwrap();
+        assert_eq!(block_size(&block), Some(17));
+        assert_eq!(decode(block).unwrap(), b"block block block");
+
+        let mut frame = lz4_flex::frame::FrameEncoder::new(vec![]);
+        frame.write_all(b"frame").unwrap();
+        assert_eq!(decode(frame.finish().unwrap()).unwrap(), b"frame");
+
+        // Too large for what follows it
+        let mut claims_too_much = 1_000_000u32.to_le_bytes().to_vec();
+        claims_too_much.extend([0x10, 0x61]);
+        assert!(decode(claims_too_much).is_err());
+    }
+}
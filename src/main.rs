@@ -1,16 +1,198 @@
 use ouch::{
-    cli::{parse_args, ParsedArgs},
+    cancel,
+    cli::{parse_args, Command, ParsedArgs},
     commands, Result,
 };
 
 fn main() {
-    if let Err(err) = run() {
-        println!("{}", err);
-        std::process::exit(ouch::EXIT_FAILURE);
+    cancel::install_handler();
+
+    match run() {
+        // The reader of the output got everything it wanted, like `ouch list big.tar | head`
+        Ok(()) | Err(ouch::Error::BrokenPipe) => {},
+        Err(err) => {
+            err.display();
+            std::process::exit(err.exit_code());
+        },
     }
 }
 
 fn run() -> crate::Result<()> {
     let ParsedArgs { command, flags } = parse_args()?;
-    commands::run(command, &flags)
+
+    // Before the sandbox, and before other threads create files
+    ouch::archive::permissions::read_umask();
+    #[cfg(target_os = "linux")]
+    enable_sandbox(&command, &flags)?;
+
+    let result = commands::run(command, &flags);
+    // Even after failures, the warnings that came before them are summarized
+    let finished = ouch::logger::finish(&flags);
+    result.and(finished)
+}
+
+/// Restrict filesystem access to what `command` needs: reading its inputs, and writing to where
+/// the outputs go.
+#[cfg(target_os = "linux")]
+fn enable_sandbox(command: &Command, flags: &ouch::oof::Flags) -> crate::Result<()> {
+    use std::path::{Path, PathBuf};
+
+    use ouch::extension::{self, CompressionFormat};
+
+    let mode = ouch::sandbox::SandboxMode::from_flags(flags)?;
+
+    // Directories that will be created don't exist yet, so access is granted on the closest
+    // existing ancestor instead
+    fn existing_ancestor(path: &Path) -> PathBuf {
+        path.ancestors()
+            // The last ancestor of a relative path is empty, it stands for the current directory
+            .map(|path| if path.as_os_str().is_empty() { Path::new(".") } else { path })
+            .find(|path| path.exists())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf()
+    }
+
+    let (mut read_paths, mut write_paths): (Vec<PathBuf>, Vec<PathBuf>) = match command {
+        Command::Compress { files, output_path } => {
+            let output_folder = output_path.parent().unwrap_or_else(|| Path::new("."));
+            (files.clone(), vec![existing_ancestor(output_folder)])
+        },
+        Command::CompressEach { files, extension, output_folder } => {
+            let output_paths =
+                commands::each_output_paths(files, extension, output_folder.as_deref());
+            let write_paths = output_paths
+                .iter()
+                .map(|path| existing_ancestor(path.parent().unwrap_or_else(|| Path::new("."))))
+                .collect();
+            (files.clone(), write_paths)
+        },
+        Command::Decompress { files, output_folder } => {
+            let mut write_paths = vec![];
+
+            match output_folder {
+                Some(output_folder) => write_paths.push(existing_ancestor(output_folder)),
+                // Recursive decompression places outputs inside of the given folders
+                None if flags.is_present("recursive") => {
+                    write_paths.extend(files.iter().filter(|path| path.is_dir()).cloned())
+                },
+                None => write_paths.push(existing_ancestor(Path::new("."))),
+            }
+
+            // Single file formats are always decompressed next to the compressed file
+            for file in files.iter().filter(|path| path.is_file()) {
+                let formats = extension::extensions_from_path(file);
+                let is_bundle = formats.first().is_some_and(CompressionFormat::is_archive);
+                if !is_bundle {
+                    write_paths.extend(file.parent().map(existing_ancestor));
+                }
+            }
+
+            // Removing the archive afterwards needs write access to its folder
+            if flags.is_present("remove-archive") {
+                write_paths
+                    .extend(files.iter().filter_map(|path| path.parent()).map(existing_ancestor));
+            }
+
+            (files.clone(), write_paths)
+        },
+        // Where compressed .zip and .iso archives are copied to, to seek in them
+        Command::List { archives }
+        | Command::Info { archives }
+        | Command::ContentsHash { archives } => {
+            let temp_dir = flags.arg("temp-dir").map_or_else(std::env::temp_dir, PathBuf::from);
+            (archives.clone(), vec![existing_ancestor(&temp_dir)])
+        },
+        Command::Merge { archives, output_path } => {
+            let output_folder = output_path.parent().unwrap_or_else(|| Path::new("."));
+            (archives.clone(), vec![existing_ancestor(output_folder)])
+        },
+        Command::Convert { file, output_path } => {
+            let output_folder = output_path.parent().unwrap_or_else(|| Path::new("."));
+            (vec![file.clone()], vec![existing_ancestor(output_folder)])
+        },
+        Command::Encrypt { file, output_path } | Command::Decrypt { file, output_path } => {
+            let output_folder = output_path.parent().unwrap_or_else(|| Path::new("."));
+            let mut write_paths = vec![existing_ancestor(output_folder)];
+            // Where the passphrase is asked for
+            write_paths.push(PathBuf::from("/dev/tty"));
+            (vec![file.clone()], write_paths)
+        },
+        Command::DictTrain { files, output_path } => {
+            let output_folder = output_path.parent().unwrap_or_else(|| Path::new("."));
+            (files.clone(), vec![existing_ancestor(output_folder)])
+        },
+        Command::Bench { file, .. } => (vec![file.clone()], vec![]),
+        Command::ShowFormats
+        | Command::ShowCompletions { .. }
+        | Command::ShowConfigPath
+        | Command::ShowConfig
+        | Command::ShowHelp
+        | Command::ShowVersion => return Ok(()),
+    };
+
+    // Extraction uses the output folder when it isn't given
+    if let Some(temp_dir) = flags.arg("temp-dir") {
+        write_paths.push(existing_ancestor(Path::new(temp_dir)));
+    }
+
+    if let Some(dictionary) = flags.arg("dict") {
+        read_paths.push(PathBuf::from(dictionary));
+    }
+
+    // Files read next to the inputs
+    match command {
+        Command::Decompress { files, .. } if flags.is_present("verify-checksum") => {
+            read_paths.extend(files.iter().flat_map(|file| ouch::checksum::checksum_paths(file)))
+        },
+        Command::List { archives } if flags.is_present("use-index") => {
+            read_paths.extend(archives.iter().map(|path| ouch::archive::index::index_path(path)))
+        },
+        Command::Compress { files, .. } | Command::CompressEach { files, .. }
+            if flags.is_present("follow-symlinks") =>
+        {
+            read_paths.extend(symlink_targets(files))
+        },
+        _ => {},
+    }
+
+    // The other volumes of split .zip archives are read along with them
+    if let Command::Decompress { .. }
+    | Command::List { .. }
+    | Command::Info { .. }
+    | Command::ContentsHash { .. } = command
+    {
+        let volumes: Vec<PathBuf> =
+            read_paths.iter().flat_map(|path| ouch::archive::zip::split_volumes(path)).collect();
+        read_paths.extend(volumes);
+    }
+
+    if let Command::Compress { files, .. } | Command::CompressEach { files, .. } = command {
+        read_paths.extend(ouch::sandbox::gitignore_read_paths(files, flags));
+    }
+
+    // Owners are stored and restored by their names, unless asked otherwise
+    let preserve = ouch::archive::Preserve::from_flags(flags)?;
+    if preserve.ownership && !flags.is_present("numeric-owner") {
+        read_paths.extend(ouch::sandbox::OWNER_READ_PATHS.iter().map(PathBuf::from));
+    }
+
+    let read_paths: Vec<&Path> = read_paths.iter().map(PathBuf::as_path).collect();
+    let write_paths: Vec<&Path> = write_paths.iter().map(PathBuf::as_path).collect();
+
+    ouch::sandbox::enable(mode, &read_paths, &write_paths);
+    Ok(())
+}
+
+/// Where the symlinks inside of `files` lead to, outside of them, for `--follow-symlinks`
+#[cfg(target_os = "linux")]
+fn symlink_targets(files: &[std::path::PathBuf]) -> Vec<std::path::PathBuf> {
+    files
+        .iter()
+        .flat_map(|file| walkdir::WalkDir::new(file).follow_links(true))
+        // Broken links and loops are reported by the compression itself
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path_is_symlink())
+        .filter_map(|entry| entry.path().canonicalize().ok())
+        .filter(|target| !files.iter().any(|file| target.starts_with(file)))
+        .collect()
 }
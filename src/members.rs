@@ -0,0 +1,193 @@
+//! .gz and .bz files can hold several compressed members one after the other, like those made by
+//! `cat a.gz b.gz > c.gz` or by pbzip2, which gzip and bzip2 decompress as a whole, one member after
+//! the other. The frames of .lz4 files are decompressed the same way, like lz4 does.
+//!
+//! Bytes after the last member that don't start another one are ignored with a warning naming
+//! their offset, like gzip and bzip2 do, instead of failing or silently cutting the output short.
+
+use std::io::{self, BufRead, Read};
+
+use flate2::GzHeader;
+
+use crate::{
+    extension::CompressionFormat::{self, *},
+    utils::logger,
+};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const BZIP_MAGIC: &[u8] = b"BZh";
+const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+
+/// Decoder of every member of a .gz, .bz or .lz4 stream
+pub struct MultiMemberDecoder<R: Read> {
+    format: CompressionFormat,
+    /// `None` once the last member was decoded
+    member: Option<Member<R>>,
+}
+
+enum Member<R: Read> {
+    Gzip(flate2::bufread::GzDecoder<CountingReader<R>>),
+    Bzip(bzip2::bufread::BzDecoder<CountingReader<R>>),
+    Lz4(lz4_flex::frame::FrameDecoder<CountingReader<R>>),
+}
+
+impl<R: BufRead> MultiMemberDecoder<R> {
+    /// `format` is either `Gzip`, `Bzip` or `Lz4`
+    pub fn new(reader: R, format: CompressionFormat) -> Self {
+        let member = Member::new(&format, CountingReader { inner: reader, position: 0 });
+        Self { format, member: Some(member) }
+    }
+
+    /// The header of the first gzip member, until the next one is reached
+    pub fn header(&self) -> Option<&GzHeader> {
+        match &self.member {
+            Some(Member::Gzip(decoder)) => decoder.header(),
+            _ => None,
+        }
+    }
+
+    /// Called once a member was decoded, whether another one follows it
+    fn next_member(&mut self) -> io::Result<bool> {
+        // Safe unwrap: only called while decoding a member
+        let mut input = self.member.take().unwrap().into_inner();
+        let next = input.fill_buf()?;
+        if next.is_empty() {
+            return Ok(false);
+        }
+
+        // The magic bytes may be split between two reads, a prefix of them is taken for a member
+        let magic = match self.format {
+            Gzip => GZIP_MAGIC,
+            Bzip => BZIP_MAGIC,
+            _ => LZ4_MAGIC,
+        };
+        let len = next.len().min(magic.len());
+        if next[..len] != magic[..len] {
+            logger::warning(format!(
+                "Ignored the data at byte {} of the {} stream, after its last member, it isn't \
+                 compressed data.",
+                input.position, self.format
+            ))
+            .category("trailing data ignored")
+            .log();
+            return Ok(false);
+        }
+
+        self.member = Some(Member::new(&self.format, input));
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for MultiMemberDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = match &mut self.member {
+                Some(member) => member.read(buf)?,
+                None => return Ok(0),
+            };
+            if read > 0 || buf.is_empty() || !self.next_member()? {
+                return Ok(read);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Member<R> {
+    fn new(format: &CompressionFormat, input: CountingReader<R>) -> Self {
+        match format {
+            Gzip => Self::Gzip(flate2::bufread::GzDecoder::new(input)),
+            Bzip => Self::Bzip(bzip2::bufread::BzDecoder::new(input)),
+            Lz4 => Self::Lz4(lz4_flex::frame::FrameDecoder::new(input)),
+            _ => unreachable!("only .gz, .bz and .lz4 have members"),
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Gzip(decoder) => decoder.read(buf),
+            Self::Bzip(decoder) => decoder.read(buf),
+            Self::Lz4(decoder) => decoder.read(buf),
+        }
+    }
+
+    fn into_inner(self) -> CountingReader<R> {
+        match self {
+            Self::Gzip(decoder) => decoder.into_inner(),
+            Self::Bzip(decoder) => decoder.into_inner(),
+            Self::Lz4(decoder) => decoder.into_inner(),
+        }
+    }
+}
+
+/// Keeps track of how many bytes of the compressed input the decoders consumed
+struct CountingReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount);
+        self.position += amount as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn bzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = bzip2::write::BzEncoder::new(vec![], Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn lz4(data: &[u8]) -> Vec<u8> {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(vec![]);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode(input: &[u8], format: CompressionFormat) -> Vec<u8> {
+        let mut decoded = vec![];
+        MultiMemberDecoder::new(input, format).read_to_end(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_members() {
+        let gzipped = [gzip(b"first "), gzip(b""), gzip(b"second")].concat();
+        assert_eq!(decode(&gzipped, Gzip), b"first second");
+
+        let bzipped = [bzip(b"first "), bzip(b"second")].concat();
+        assert_eq!(decode(&bzipped, Bzip), b"first second");
+
+        let lz4_frames = [lz4(b"first "), lz4(b""), lz4(b"second")].concat();
+        assert_eq!(decode(&lz4_frames, Lz4), b"first second");
+
+        let trailing = [gzip(b"first "), gzip(b"second"), b"garbage".to_vec()].concat();
+        assert_eq!(decode(&trailing, Gzip), b"first second");
+        let trailing = [bzip(b"data"), vec![0; 16]].concat();
+        assert_eq!(decode(&trailing, Bzip), b"data");
+    }
+}
@@ -0,0 +1,287 @@
+//! `ouch merge`: the entries of several archives of the same format copied into a new one.
+//!
+//! .tar entries are read through the decoders and written again, .zip entries are copied as they
+//! are stored, without recompressing them.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io::{prelude::*, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    commands::{chain_reader_decoder, chain_writer_encoder},
+    error::FinalError,
+    extension::{
+        self,
+        CompressionFormat::{self, *},
+    },
+    oof,
+    utils::{self, logger, to_utf},
+    zstandard,
+};
+
+/// What to do when an entry has the same path as one that was already merged, chosen with
+/// `--conflict`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Store it under a free name, like "notes (1).txt"
+    Rename,
+    /// Keep the first one, with a warning
+    Skip,
+    /// Stop with an error, the default
+    Error,
+}
+
+impl ConflictPolicy {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        match flags.arg("conflict").map(|policy| policy.to_str()) {
+            None | Some(Some("error")) => Ok(Self::Error),
+            Some(Some("rename")) => Ok(Self::Rename),
+            Some(Some("skip")) => Ok(Self::Skip),
+            Some(_) => Err(crate::Error::InvalidInput),
+        }
+    }
+}
+
+/// Paths of the entries merged so far
+struct MergedNames {
+    policy: ConflictPolicy,
+    taken: HashSet<PathBuf>,
+}
+
+impl MergedNames {
+    fn new(policy: ConflictPolicy) -> Self {
+        Self { policy, taken: HashSet::new() }
+    }
+
+    /// The path `path` is stored at, `None` if it should be left out.
+    ///
+    /// Directories found in several archives are stored once, their contents are merged.
+    fn claim(&mut self, path: &Path, is_dir: bool) -> crate::Result<Option<PathBuf>> {
+        // "dir/" and "dir" are the same entry
+        let key: PathBuf = path.components().collect();
+        if self.taken.insert(key.clone()) {
+            return Ok(Some(path.to_path_buf()));
+        }
+        if is_dir {
+            return Ok(None);
+        }
+
+        match self.policy {
+            ConflictPolicy::Error => Err(crate::Error::MergeConflict(key)),
+            ConflictPolicy::Skip => {
+                logger::warning(format!(
+                    "Skipping '{}', it was already merged from another archive.",
+                    to_utf(&key)
+                ))
+                .entry(&key)
+                .category("entries already merged skipped")
+                .log();
+                Ok(None)
+            },
+            ConflictPolicy::Rename => {
+                let renamed = (1..)
+                    .map(|number| numbered_path(&key, number))
+                    .find(|candidate| !self.taken.contains(candidate))
+                    .unwrap();
+                self.taken.insert(renamed.clone());
+                Ok(Some(renamed))
+            },
+        }
+    }
+}
+
+/// "dir/notes.txt" with `number` 1 becomes "dir/notes (1).txt"
+pub(crate) fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let mut name = OsString::new();
+    // Safe unwraps: paths of merged and extracted files always have a file name
+    name.push(path.file_stem().unwrap());
+    name.push(format!(" ({})", number));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+pub fn merge_archives(
+    archives: &[PathBuf],
+    output_path: &Path,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    let formats = extension::extensions_from_path(output_path);
+    match formats.first() {
+        Some(Tar) => {},
+        Some(Zip) if formats.len() == 1 => {},
+        _ => {
+            return Err(FinalError::with_title(format!(
+                "Cannot merge into '{}'.",
+                to_utf(output_path)
+            ))
+            .detail("Only .tar archives (compressed or not) and .zip archives can be merged.")
+            .hint("Try an output like 'merged.tar.gz' or 'merged.zip'.")
+            .into());
+        },
+    }
+
+    for archive in archives {
+        let input_formats = extension::extensions_from_path(archive);
+        // Only .tar can be read through decoders, .zip entries are copied from the file
+        let same_format = match formats[0] {
+            Zip => input_formats == [Zip],
+            _ => input_formats.first() == Some(&Tar),
+        };
+        if !same_format {
+            let expected = formats[0].to_string();
+            return Err(crate::Error::MergeFormatMismatch { path: archive.clone(), expected });
+        }
+    }
+
+    // Creating the output would truncate one of the inputs
+    if output_path.canonicalize().is_ok_and(|output_path| archives.contains(&output_path)) {
+        return Err(crate::Error::InvalidInput);
+    }
+
+    if output_path.exists() && !utils::user_wants_to_overwrite(output_path, flags)? {
+        return Err(crate::Error::OverwriteDeclined(output_path.to_path_buf()));
+    }
+
+    let mut names = MergedNames::new(ConflictPolicy::from_flags(flags)?);
+    let output_file = fs::File::create(output_path)?;
+    let result = match formats[0] {
+        Tar => {
+            let zstd_options = zstandard::Options::from_flags(flags)?;
+            merge_tar(archives, &formats, output_file, &mut names, &zstd_options)
+        },
+        Zip => merge_zip(archives, output_file, &mut names),
+        _ => unreachable!(),
+    };
+
+    // Don't leave a partial archive behind
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    } else {
+        logger::info(format!(
+            "Successfully merged {} archives into '{}'.",
+            archives.len(),
+            to_utf(output_path)
+        ))
+        .path(output_path)
+        .log();
+    }
+
+    result
+}
+
+fn merge_tar(
+    archives: &[PathBuf],
+    formats: &[CompressionFormat],
+    output_file: fs::File,
+    names: &mut MergedNames,
+    zstd_options: &zstandard::Options,
+) -> crate::Result<()> {
+    let mut writer: Box<dyn Write> = Box::new(BufWriter::new(output_file));
+    for format in formats.iter().skip(1).rev() {
+        writer = chain_writer_encoder(format, writer, zstd_options)?;
+    }
+    let mut builder = tar::Builder::new(writer);
+
+    for archive in archives {
+        // Inputs may use other compression formats than the output, like .tar.xz into .tar.gz
+        let input_formats = extension::extensions_from_path(archive);
+        let mut reader: Box<dyn Read> = Box::new(BufReader::new(fs::File::open(archive)?));
+        for format in input_formats.iter().skip(1).rev() {
+            reader = chain_reader_decoder(format, reader, zstd_options)?;
+        }
+
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let is_dir = entry.header().entry_type().is_dir();
+            let path = match names.claim(&entry.path()?, is_dir)? {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let mut header = entry.header().clone();
+            // Sparse entries are read with their holes filled in, and are stored as such
+            if header.entry_type().is_gnu_sparse() {
+                header.set_entry_type(tar::EntryType::Regular);
+            }
+            header.set_size(entry.size());
+            match entry.link_name()? {
+                Some(target) => {
+                    let target = target.into_owned();
+                    builder.append_link(&mut header, path, target)?;
+                },
+                None => builder.append_data(&mut header, path, &mut entry)?,
+            }
+        }
+    }
+
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+fn merge_zip(
+    archives: &[PathBuf],
+    output_file: fs::File,
+    names: &mut MergedNames,
+) -> crate::Result<()> {
+    let mut writer = zip::ZipWriter::new(BufWriter::new(output_file));
+
+    for archive in archives {
+        let mut archive = zip::ZipArchive::new(BufReader::new(fs::File::open(archive)?))?;
+        for index in 0..archive.len() {
+            // Raw entries are copied as they are stored, still compressed
+            let file = archive.by_index_raw(index)?;
+            let name = PathBuf::from(file.name());
+            let renamed = match names.claim(&name, file.is_dir())? {
+                Some(renamed) => renamed,
+                None => continue,
+            };
+
+            if renamed == name {
+                writer.raw_copy_file(file)?;
+            } else {
+                // Zip entry names always use forward slashes
+                let renamed = to_utf(&renamed).replace('\\', "/");
+                writer.raw_copy_file_rename(file, renamed)?;
+            }
+        }
+    }
+
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merged_names() {
+        let mut names = MergedNames::new(ConflictPolicy::Rename);
+        assert_eq!(names.claim(Path::new("dir/"), true).unwrap(), Some("dir/".into()));
+        assert_eq!(names.claim(Path::new("dir"), true).unwrap(), None);
+        assert_eq!(names.claim(Path::new("dir/a.txt"), false).unwrap(), Some("dir/a.txt".into()));
+        assert_eq!(
+            names.claim(Path::new("dir/a.txt"), false).unwrap(),
+            Some("dir/a (1).txt".into())
+        );
+        assert_eq!(
+            names.claim(Path::new("dir/a.txt"), false).unwrap(),
+            Some("dir/a (2).txt".into())
+        );
+
+        let mut names = MergedNames::new(ConflictPolicy::Error);
+        names.claim(Path::new("a"), false).unwrap();
+        assert_eq!(
+            names.claim(Path::new("a"), false).unwrap_err(),
+            crate::Error::MergeConflict("a".into())
+        );
+    }
+}
@@ -13,7 +13,7 @@ pub struct ArgFlag;
 
 impl ArgFlag {
     pub fn long(name: &'static str) -> Flag {
-        Flag { long: name, short: None, takes_value: true }
+        Flag { long: name, short: None, takes_value: true, repeated: false, optional_value: false }
     }
 }
 
@@ -23,6 +23,10 @@ pub struct Flag {
     pub long: &'static str,
     pub short: Option<char>,
     pub takes_value: bool,
+    /// Can be given more than once, the values are kept in order
+    pub repeated: bool,
+    /// Takes a value only when given as `--flag=value`, and is a boolean flag otherwise
+    pub optional_value: bool,
 }
 
 impl std::fmt::Display for Flag {
@@ -36,7 +40,17 @@ impl std::fmt::Display for Flag {
 
 impl Flag {
     pub fn long(name: &'static str) -> Self {
-        Self { long: name, short: None, takes_value: false }
+        Self { long: name, short: None, takes_value: false, repeated: false, optional_value: false }
+    }
+
+    pub fn repeated(mut self) -> Self {
+        self.repeated = true;
+        self
+    }
+
+    pub fn optional_value(mut self) -> Self {
+        self.optional_value = true;
+        self
     }
 
     pub fn short(mut self, short_flag_char: char) -> Self {
@@ -49,6 +63,8 @@ impl Flag {
 pub struct Flags {
     pub boolean_flags: BTreeSet<&'static str>,
     pub argument_flags: BTreeMap<&'static str, OsString>,
+    /// The values of the flags that can be repeated, in the order they were given
+    pub repeated_flags: BTreeMap<&'static str, Vec<OsString>>,
 }
 
 impl Flags {
@@ -57,13 +73,20 @@ impl Flags {
     }
 
     pub fn is_present(&self, flag_name: &str) -> bool {
-        self.boolean_flags.contains(flag_name) || self.argument_flags.contains_key(flag_name)
+        self.boolean_flags.contains(flag_name)
+            || self.argument_flags.contains_key(flag_name)
+            || self.repeated_flags.contains_key(flag_name)
     }
 
     pub fn arg(&self, flag_name: &str) -> Option<&OsString> {
         self.argument_flags.get(flag_name)
     }
 
+    /// Every value of a flag that can be repeated
+    pub fn args(&self, flag_name: &str) -> &[OsString] {
+        self.repeated_flags.get(flag_name).map_or(&[], Vec::as_slice)
+    }
+
     pub fn take_arg(&mut self, flag_name: &str) -> Option<OsString> {
         self.argument_flags.remove(flag_name)
     }
@@ -58,8 +58,8 @@ where
 /// - Argument flag comes at last arg, so there's no way to provide an argument.
 ///     - Or if it doesn't comes at last, but the rest are just flags, no possible and valid arg.
 /// - Short flags with multiple letters in the same arg contain a argument flag that does not come
-/// as the last one in the list (example "-oahc", where 'o', 'a', or 'h' is a argument flag, but do
-/// not comes at last, so it is impossible for them to receive the required argument.
+///   as the last one in the list (example "-oahc", where 'o', 'a', or 'h' is a argument flag, but do
+///   not comes at last, so it is impossible for them to receive the required argument.
 /// - User passes same flag twice (short or long, boolean or arg).
 ///
 /// ...
@@ -142,7 +142,12 @@ pub fn filter_flags(
 
                 let flag_name: &'static str = flag_info.long;
 
-                if flag_info.takes_value {
+                if flag_info.takes_value && flag_info.repeated {
+                    let flag_argument = iter
+                        .next()
+                        .ok_or_else(|| OofError::MissingValueToFlag(flag_info.clone()))?;
+                    result_flags.repeated_flags.entry(flag_name).or_default().push(flag_argument);
+                } else if flag_info.takes_value {
                     // If it was already inserted
                     if result_flags.argument_flags.contains_key(flag_name) {
                         return Err(OofError::DuplicatedFlag(flag_info.clone()));
@@ -169,13 +174,35 @@ pub fn filter_flags(
         if let FlagType::Long = flag_type {
             let flag = trim_double_hyphen(flag);
 
+            // "--flag=value", for the flags that take a value only that way
+            let inline_value = flag.split_once('=').filter(|(name, _)| {
+                long_flags_info.get(name).is_some_and(|flag_info| flag_info.optional_value)
+            });
+            let flag = inline_value.map_or(flag, |(name, _)| name);
+
             let flag_info = *long_flags_info
                 .get(flag)
                 .ok_or_else(|| OofError::UnknownLongFlag(String::from(flag)))?;
 
             let flag_name = flag_info.long;
 
-            if flag_info.takes_value {
+            if flag_info.optional_value {
+                if result_flags.is_present(flag_name) {
+                    return Err(OofError::DuplicatedFlag(flag_info.clone()));
+                }
+                match inline_value {
+                    Some((_, value)) => {
+                        result_flags.argument_flags.insert(flag_name, OsString::from(value));
+                    },
+                    None => {
+                        result_flags.boolean_flags.insert(flag_name);
+                    },
+                }
+            } else if flag_info.takes_value && flag_info.repeated {
+                let flag_argument =
+                    iter.next().ok_or_else(|| OofError::MissingValueToFlag(flag_info.clone()))?;
+                result_flags.repeated_flags.entry(flag_name).or_default().push(flag_argument);
+            } else if flag_info.takes_value {
                 // If it was already inserted
                 if result_flags.argument_flags.contains_key(&flag_name) {
                     return Err(OofError::DuplicatedFlag(flag_info.clone()));
@@ -213,6 +240,30 @@ where
     texts.iter().any(|text| args.iter().any(|arg| arg.as_ref() == text.as_ref()))
 }
 
+/// Create a flag with long flag (?).
+#[macro_export]
+macro_rules! flag {
+    ($short:expr, $long:expr) => {{
+        oof::Flag::long($long).short($short)
+    }};
+
+    ($long:expr) => {{
+        oof::Flag::long($long)
+    }};
+}
+
+/// Create a flag with long flag (?), receives argument (?).
+#[macro_export]
+macro_rules! arg_flag {
+    ($short:expr, $long:expr) => {{
+        oof::ArgFlag::long($long).short($short)
+    }};
+
+    ($long:expr) => {{
+        oof::ArgFlag::long($long)
+    }};
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -260,6 +311,35 @@ mod tests {
         assert!(matches!(result, OofError::DuplicatedFlag(flag) if flag == duplicated_flag));
     }
 
+    #[test]
+    fn test_optional_value_flags() {
+        let flags_info = [Flag::long("long").optional_value(), Flag::long("verbose")];
+        let (args, flags) = filter_flags(gen_args("ouch a --long=30 b"), &flags_info).unwrap();
+        assert_eq!(args, gen_args("ouch a b"));
+        assert_eq!(flags.arg("long"), Some(&OsString::from("30")));
+
+        // Without a value, the next argument isn't taken
+        let (args, flags) = filter_flags(gen_args("ouch --long 30"), &flags_info).unwrap();
+        assert_eq!(args, gen_args("ouch 30"));
+        assert!(flags.is_present("long") && flags.arg("long").is_none());
+
+        let result = filter_flags(gen_args("ouch --long --long=30"), &flags_info).unwrap_err();
+        assert!(matches!(result, OofError::DuplicatedFlag(flag) if flag.long == "long"));
+        let result = filter_flags(gen_args("ouch --verbose=1"), &flags_info).unwrap_err();
+        assert!(matches!(result, OofError::UnknownLongFlag(flag) if flag == "verbose=1"));
+    }
+
+    #[test]
+    fn test_repeated_flags() {
+        let flags_info = [ArgFlag::long("rename").repeated(), Flag::long("verbose")];
+        let args = gen_args("ouch --rename a:b x.tar --rename c:d");
+        let (args, flags) = filter_flags(args, &flags_info).unwrap();
+
+        assert_eq!(args, gen_args("ouch x.tar"));
+        assert_eq!(flags.args("rename"), gen_args("a:b c:d"));
+        assert!(flags.is_present("rename") && flags.args("verbose").is_empty());
+    }
+
     #[test]
     fn test_misplaced_flag() {
         let misplaced_flag = ArgFlag::long("output_file").short('o');
@@ -383,27 +463,3 @@ mod tests {
         assert!(!matches_any_arg(&args, &["--help", "-h"]));
     }
 }
-
-/// Create a flag with long flag (?).
-#[macro_export]
-macro_rules! flag {
-    ($short:expr, $long:expr) => {{
-        oof::Flag::long($long).short($short)
-    }};
-
-    ($long:expr) => {{
-        oof::Flag::long($long)
-    }};
-}
-
-/// Create a flag with long flag (?), receives argument (?).
-#[macro_export]
-macro_rules! arg_flag {
-    ($short:expr, $long:expr) => {{
-        oof::ArgFlag::long($long).short($short)
-    }};
-
-    ($long:expr) => {{
-        oof::ArgFlag::long($long)
-    }};
-}
@@ -0,0 +1,141 @@
+//! Progress shown while extracting, drawn to stderr only when it is a terminal, without `--quiet`
+//! and when the messages aren't JSON.
+
+use std::{
+    io::{self, Read},
+    rc::Rc,
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::utils::logger;
+
+/// What the progress is counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressUnit {
+    /// Entries of archives that list their contents upfront, like the central directory of .zip
+    Files,
+    /// Bytes read from the input file, for streams like .tar.gz that can't be counted beforehand
+    Bytes,
+}
+
+pub trait ProgressReporter {
+    /// How much there is to do, counted in `unit`
+    fn set_total(&self, total: u64, unit: ProgressUnit);
+    fn advance(&self, amount: u64);
+    fn finish(&self);
+}
+
+impl<P: ProgressReporter + ?Sized> ProgressReporter for Rc<P> {
+    fn set_total(&self, total: u64, unit: ProgressUnit) {
+        (**self).set_total(total, unit);
+    }
+
+    fn advance(&self, amount: u64) {
+        (**self).advance(amount);
+    }
+
+    fn finish(&self) {
+        (**self).finish();
+    }
+}
+
+/// Progress that isn't shown, for programs using ouch as a library that didn't ask for it
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn set_total(&self, _total: u64, _unit: ProgressUnit) {}
+
+    fn advance(&self, _amount: u64) {}
+
+    fn finish(&self) {}
+}
+
+/// Progress bar of a single input file
+#[derive(Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        // Hidden until the total is known
+        Self { bar: ProgressBar::hidden() }
+    }
+}
+
+impl ProgressReporter for Progress {
+    fn set_total(&self, total: u64, unit: ProgressUnit) {
+        let template = match unit {
+            ProgressUnit::Files => "[{elapsed_precise}] [{wide_bar}] {pos}/{len} files",
+            ProgressUnit::Bytes => "[{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes}",
+        };
+        // Safe unwrap: the templates are known to be valid
+        let style = ProgressStyle::default_bar().template(template).unwrap();
+        self.bar.set_style(style.progress_chars("#>-"));
+        self.bar.set_length(total);
+        self.bar.set_position(0);
+        // The bar would be drawn in between the JSON lines
+        if !logger::is_json() && !logger::is_quiet() {
+            self.bar.set_draw_target(indicatif::ProgressDrawTarget::stderr());
+        }
+    }
+
+    fn advance(&self, amount: u64) {
+        self.bar.inc(amount);
+    }
+
+    fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Reader that advances `progress` by every byte read through it
+pub struct ProgressReader<R, P> {
+    inner: R,
+    progress: P,
+}
+
+impl<R: Read, P: ProgressReporter> ProgressReader<R, P> {
+    pub fn new(inner: R, progress: P) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read, P: ProgressReporter> Read for ProgressReader<R, P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.progress.advance(bytes as u64);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter {
+        position: Cell<u64>,
+    }
+
+    impl ProgressReporter for &Counter {
+        fn set_total(&self, _total: u64, _unit: ProgressUnit) {}
+
+        fn advance(&self, amount: u64) {
+            self.position.set(self.position.get() + amount);
+        }
+
+        fn finish(&self) {}
+    }
+
+    #[test]
+    fn test_progress_reader_counts_bytes() {
+        let counter = Counter::default();
+        let mut reader = ProgressReader::new(&[0u8; 1000][..], &counter);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(counter.position.get(), 1000);
+    }
+}
@@ -0,0 +1,284 @@
+//! Filesystem sandboxing through Landlock (Linux only).
+//!
+//! Once `restrict_paths` is called, the process can only read from the given read paths and
+//! only write to the given write paths, protecting the rest of the system from bugs triggered
+//! by malicious archives.
+//!
+//! `--sandbox strict`, the default, only lets ouch read its inputs, the files that options read
+//! along with them, and the system files it needs to keep running. `--sandbox normal` lets it
+//! read anything, for when something else it reads is missing, and `--sandbox off` turns the
+//! sandbox off.
+//!
+//! The newest Landlock ABI known is asked for, and kernels enforce as much of it as they support.
+//! Those that don't support the second one, which lets files be moved across folders, run ouch
+//! without the sandbox.
+//!
+//! `--no-sandbox`, or `OUCH_NO_SANDBOX=1`, is the same as `--sandbox off`, to tell whether it is
+//! to blame for a failure.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use landlock::{
+    path_beneath_rules, Access, AccessFs, BitFlags, CompatLevel, Compatible, LandlockStatus,
+    RestrictionStatus, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetError, RulesetStatus, ABI,
+};
+
+use crate::{oof, utils::logger};
+
+/// Disables the sandbox when set to 1, like `--no-sandbox`
+pub const NO_SANDBOX_VAR: &str = "OUCH_NO_SANDBOX";
+
+/// The newest Landlock ABI known to the landlock crate
+const LATEST_ABI: ABI = ABI::V6;
+
+/// What `--sandbox strict` lets ouch read besides its inputs: the libraries loaded after it
+/// started, like those of name lookups, and what the standard library reads, like the number of
+/// CPUs. Those that don't exist are skipped.
+const SYSTEM_READ_PATHS: &[&str] = &[
+    "/usr",
+    "/lib",
+    "/lib64",
+    "/etc/ld.so.cache",
+    "/etc/localtime",
+    "/proc/self",
+    "/sys/fs/cgroup",
+    "/sys/devices/system/cpu",
+    "/dev/urandom",
+];
+
+/// What `--preserve ownership` reads besides, to look up the names of users and groups
+pub const OWNER_READ_PATHS: &[&str] = &["/etc/passwd", "/etc/group", "/etc/nsswitch.conf"];
+
+/// What `--gitignore` reads besides the inputs, nothing unless it is given: the ignore files of
+/// the folders above them, and the global excludes file along with the git configuration that
+/// names it
+pub fn gitignore_read_paths(files: &[PathBuf], flags: &oof::Flags) -> Vec<PathBuf> {
+    if !crate::utils::reads_gitignores(flags) {
+        return vec![];
+    }
+
+    let mut paths: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| file.canonicalize().ok())
+        .flat_map(|file| file.ancestors().skip(1).map(Path::to_path_buf).collect::<Vec<_>>())
+        .flat_map(|folder| [folder.join(".gitignore"), folder.join(".git/info/exclude")])
+        .collect();
+
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let xdg_config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+    let config_vars = ["GIT_CONFIG_GLOBAL", "GIT_CONFIG_SYSTEM"];
+    paths.extend(config_vars.iter().filter_map(env::var_os).map(PathBuf::from));
+    paths.push(PathBuf::from("/etc/gitconfig"));
+    paths.extend(home.map(|home| home.join(".gitconfig")));
+    paths.extend(xdg_config.map(|config| config.join("git/config")));
+    paths.extend(ignore::gitignore::gitconfig_excludes_path());
+    paths
+}
+
+/// How much the sandbox restricts, chosen with `--sandbox strict|normal|off`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxMode {
+    /// Reading only the inputs and `SYSTEM_READ_PATHS`, the default
+    Strict,
+    /// Reading anything, writing is still restricted
+    Normal,
+    Off,
+}
+
+impl SandboxMode {
+    /// The mode of `--sandbox`, which `--no-sandbox` and `OUCH_NO_SANDBOX=1` turn off
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let mode = match flags.arg("sandbox").map(|mode| mode.to_str()) {
+            Some(_) if flags.is_present("no-sandbox") => {
+                return Err(crate::Error::ConflictingFlags("sandbox", "no-sandbox"))
+            },
+            Some(Some("strict")) => Self::Strict,
+            Some(Some("normal")) => Self::Normal,
+            Some(Some("off")) => Self::Off,
+            Some(_) => return Err(crate::Error::InvalidInput),
+            None if flags.is_present("no-sandbox") || no_sandbox_var()? => Self::Off,
+            None => Self::Strict,
+        };
+        Ok(mode)
+    }
+}
+
+/// How much of the sandbox the kernel enforces, with the Landlock ABI it supports
+#[derive(Debug, PartialEq, Eq)]
+pub enum SandboxStatus {
+    Enforced(ABI),
+    /// The kernel only supports an older ABI, whose rights are enforced
+    PartiallyEnforced(ABI),
+    /// Running without sandbox, for this reason
+    NotEnforced(String),
+}
+
+impl From<Result<RestrictionStatus, RulesetError>> for SandboxStatus {
+    fn from(result: Result<RestrictionStatus, RulesetError>) -> Self {
+        let status = match result {
+            Ok(status) => status,
+            Err(err) => return Self::NotEnforced(err.to_string()),
+        };
+        match (status.ruleset, status.landlock) {
+            (RulesetStatus::FullyEnforced, LandlockStatus::Available { effective_abi, .. }) => {
+                Self::Enforced(effective_abi)
+            },
+            (RulesetStatus::PartiallyEnforced, LandlockStatus::Available { effective_abi, .. }) => {
+                Self::PartiallyEnforced(effective_abi)
+            },
+            (_, LandlockStatus::Available { effective_abi, .. }) => Self::NotEnforced(format!(
+                "the kernel only supports Landlock ABI {:?}, which forbids moving files across \
+                 folders",
+                effective_abi
+            )),
+            (_, LandlockStatus::NotEnabled) => {
+                Self::NotEnforced("Landlock is not enabled in the kernel".into())
+            },
+            (_, LandlockStatus::NotImplemented) => {
+                Self::NotEnforced("Landlock is not supported by the kernel".into())
+            },
+        }
+    }
+}
+
+/// Whether `OUCH_NO_SANDBOX` turns the sandbox off
+fn no_sandbox_var() -> crate::Result<bool> {
+    match env::var_os(NO_SANDBOX_VAR) {
+        None => Ok(false),
+        Some(value) => match value.to_str() {
+            Some("1") => Ok(true),
+            Some("0" | "") => Ok(false),
+            _ => Err(crate::Error::InvalidEnvVar {
+                name: NO_SANDBOX_VAR,
+                reason: format!("'{}' is neither 1 nor 0", value.to_string_lossy()),
+            }),
+        },
+    }
+}
+
+/// `restrict_paths` as `mode` asks for, telling how much of it is enforced. Running without the
+/// sandbox is only warned about, the rest is shown with `-v`.
+pub fn enable(mode: SandboxMode, read_paths: &[&Path], write_paths: &[&Path]) -> SandboxStatus {
+    let read_paths: Vec<&Path> = match mode {
+        SandboxMode::Strict => {
+            read_paths.iter().copied().chain(SYSTEM_READ_PATHS.iter().map(Path::new)).collect()
+        },
+        SandboxMode::Normal => vec![Path::new("/")],
+        SandboxMode::Off => {
+            logger::debug("Filesystem sandbox disabled.").log();
+            return SandboxStatus::NotEnforced("turned off".into());
+        },
+    };
+
+    let status = SandboxStatus::from(restrict_paths(&read_paths, write_paths));
+    match &status {
+        SandboxStatus::Enforced(abi) => logger::debug(format!(
+            "Filesystem sandbox enabled ({:?}), with Landlock ABI {:?}.",
+            mode, abi
+        ))
+        .log(),
+        // Older kernels are expected, the sandbox still restricts what they support
+        SandboxStatus::PartiallyEnforced(abi) => logger::debug(format!(
+            "Filesystem sandbox enabled ({:?}), with Landlock ABI {:?}, newer kernels restrict \
+             more.",
+            mode, abi
+        ))
+        .log(),
+        SandboxStatus::NotEnforced(reason) => {
+            logger::warning(format!("running without filesystem sandbox: {}", reason))
+                .category("sandbox not enabled")
+                .log()
+        },
+    }
+    status
+}
+
+/// Restrict the current process so that it can only read from `read_paths` (and their contents)
+/// and only read or write inside of `write_paths`.
+///
+/// Paths that don't exist are skipped. On kernels without Landlock support this is a no-op, the
+/// returned status tells if the rules are enforced.
+pub fn restrict_paths(
+    read_paths: &[&Path],
+    write_paths: &[&Path],
+) -> Result<RestrictionStatus, RulesetError> {
+    restrict_paths_with_abi(LATEST_ABI, read_paths, write_paths)
+}
+
+/// The rights given beneath the read paths and beneath the write paths, out of those of `abi`.
+/// Those the kernel doesn't support are left out when restricting.
+fn access_rights(abi: ABI) -> (BitFlags<AccessFs>, BitFlags<AccessFs>) {
+    // Writing includes moving files across folders (Refer) and truncating them (Truncate), from
+    // the ABIs that restrict them
+    (AccessFs::from_read(abi), AccessFs::from_all(abi))
+}
+
+fn restrict_paths_with_abi(
+    abi: ABI,
+    read_paths: &[&Path],
+    write_paths: &[&Path],
+) -> Result<RestrictionStatus, RulesetError> {
+    let (access_read, access_all) = access_rights(abi);
+
+    Ruleset::default()
+        // Without Refer, Landlock forbids moving files across folders, which --atomic and hard
+        // links need, so the sandbox is left out instead
+        .set_compatibility(CompatLevel::SoftRequirement)
+        .handle_access(AccessFs::Refer)?
+        .set_compatibility(CompatLevel::BestEffort)
+        .handle_access(access_all)?
+        .create()?
+        .add_rules(path_beneath_rules(read_paths, access_read))?
+        .add_rules(path_beneath_rules(write_paths, access_all))?
+        .restrict_self()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_rights() {
+        let abis = [ABI::V1, ABI::V2, ABI::V3, ABI::V4, ABI::V5, ABI::V6];
+        for abi in abis {
+            let (read, write) = access_rights(abi);
+            assert!(write.contains(read), "{:?}", abi);
+            // Truncating empties files as surely as writing to them
+            let writing = AccessFs::WriteFile | AccessFs::RemoveFile | AccessFs::Truncate;
+            assert!(!read.intersects(writing), "{:?}", abi);
+            assert_eq!(write.contains(AccessFs::Refer), abi >= ABI::V2, "{:?}", abi);
+            assert_eq!(write.contains(AccessFs::Truncate), abi >= ABI::V3, "{:?}", abi);
+        }
+        assert_eq!(access_rights(LATEST_ABI).1, AccessFs::from_all(LATEST_ABI));
+    }
+
+    #[test]
+    fn test_mode_from_flags() {
+        let mode = |args: &[(&'static str, &str)], boolean: &[&'static str]| {
+            let mut flags = oof::Flags::new();
+            for (name, value) in args {
+                flags.argument_flags.insert(name, value.into());
+            }
+            flags.boolean_flags.extend(boolean);
+            SandboxMode::from_flags(&flags)
+        };
+        assert_eq!(mode(&[("sandbox", "normal")], &[]).unwrap(), SandboxMode::Normal);
+        assert_eq!(mode(&[("sandbox", "off")], &[]).unwrap(), SandboxMode::Off);
+        assert_eq!(mode(&[], &["no-sandbox"]).unwrap(), SandboxMode::Off);
+        assert!(mode(&[("sandbox", "lax")], &[]).is_err());
+        assert!(mode(&[("sandbox", "strict")], &["no-sandbox"]).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_abi() {
+        // Fails before anything is restricted, so the tests that come after are not sandboxed
+        let status = SandboxStatus::from(restrict_paths_with_abi(ABI::Unsupported, &[], &[]));
+        assert!(matches!(status, SandboxStatus::NotEnforced(reason) if !reason.is_empty()));
+    }
+}
@@ -0,0 +1,233 @@
+//! The seekable format of Zstandard: the data is split into frames compressed on their own, and a
+//! seek table at the end of the .zst, in a skippable frame that other decoders ignore, gives the
+//! sizes of every frame. Any part of the data is reached by decompressing the frame it is in.
+//!
+//! It keeps the .zst extension, and is recognized by the seek table. .zip and .iso archives in it
+//! are read in place, instead of being decompressed into a temporary file first, and .tar archives
+//! are listed by seeking from header to header, over the contents of their entries.
+//!
+//! See https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use crate::utils::logger;
+
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+/// Number of frames, descriptor and magic number
+const FOOTER_SIZE: u64 = 9;
+/// Frames are decompressed whole, larger ones are read sequentially instead
+const MAX_FRAME_SIZE: u32 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    compressed_offset: u64,
+    compressed_size: u32,
+    decompressed_offset: u64,
+    decompressed_size: u32,
+}
+
+/// Reads and seeks through the decompressed data of a seekable .zst
+pub struct SeekableDecoder<R> {
+    inner: R,
+    frames: Vec<Frame>,
+    /// In the decompressed data
+    position: u64,
+    size: u64,
+    /// The index of the last frame decompressed, and its data
+    current: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> SeekableDecoder<R> {
+    /// `None` when `inner` has no seek table, or one that doesn't match it, and is to be read
+    /// sequentially
+    pub fn new(mut inner: R) -> io::Result<Option<Self>> {
+        let frames = match read_seek_table(&mut inner)? {
+            Some(frames) => frames,
+            None => return Ok(None),
+        };
+        let size = frames
+            .last()
+            .map_or(0, |frame| frame.decompressed_offset + u64::from(frame.decompressed_size));
+        logger::debug(format!("Seekable .zst of {} frames.", frames.len())).log();
+        Ok(Some(Self { inner, frames, position: 0, size, current: None }))
+    }
+
+    /// The data of the frame at `index`, decompressed
+    fn frame(&mut self, index: usize) -> io::Result<&[u8]> {
+        if self.current.as_ref().map(|(current, _)| *current) != Some(index) {
+            let frame = self.frames[index];
+            self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+            let compressed = (&mut self.inner).take(u64::from(frame.compressed_size));
+            let decoder = zstd::stream::read::Decoder::new(compressed)?.single_frame();
+            let mut data = Vec::with_capacity(frame.decompressed_size as usize);
+            decoder.take(u64::from(frame.decompressed_size) + 1).read_to_end(&mut data)?;
+            if data.len() != frame.decompressed_size as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "frame {} of the seekable .zst doesn't have the size in its table",
+                        index
+                    ),
+                ));
+            }
+            self.current = Some((index, data));
+        }
+        // Safe unwrap: set above
+        Ok(&self.current.as_ref().unwrap().1)
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        // The last frame that starts at or before the position, empty frames are skipped over
+        let index = self.frames.partition_point(|frame| frame.decompressed_offset <= self.position);
+        let Frame { decompressed_offset, .. } = self.frames[index - 1];
+        let start = (self.position - decompressed_offset) as usize;
+        let data = self.frame(index - 1)?;
+        let read = (data.len() - start).min(buf.len());
+        buf[..read].copy_from_slice(&data[start..start + read]);
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableDecoder<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the .zst")
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// The frames listed in the seek table at the end of `input`
+fn read_seek_table<R: Read + Seek>(input: &mut R) -> io::Result<Option<Vec<Frame>>> {
+    let input_size = input.seek(SeekFrom::End(0))?;
+    if input_size < FOOTER_SIZE + 8 {
+        return Ok(None);
+    }
+    input.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+    let mut footer = [0; FOOTER_SIZE as usize];
+    input.read_exact(&mut footer)?;
+    let frame_count = u32_at(&footer, 0);
+    let descriptor = footer[4];
+    // The reserved bits must be unset
+    if u32_at(&footer, 5) != SEEKABLE_MAGIC || descriptor & 0b0111_1100 != 0 {
+        return Ok(None);
+    }
+
+    let entry_size = if descriptor & 0b1000_0000 != 0 { 12 } else { 8 };
+    let table_size = u64::from(frame_count) * entry_size + FOOTER_SIZE;
+    if table_size + 8 > input_size {
+        return Ok(None);
+    }
+    input.seek(SeekFrom::End(-((table_size + 8) as i64)))?;
+    let mut table = vec![0; table_size as usize + 8];
+    input.read_exact(&mut table)?;
+    if u32_at(&table, 0) != SKIPPABLE_FRAME_MAGIC || u64::from(u32_at(&table, 4)) != table_size {
+        return Ok(None);
+    }
+
+    let mut frames = vec![];
+    let (mut compressed_offset, mut decompressed_offset) = (0, 0);
+    for entry in table[8..].chunks_exact(entry_size as usize).take(frame_count as usize) {
+        let frame = Frame {
+            compressed_offset,
+            compressed_size: u32_at(entry, 0),
+            decompressed_offset,
+            decompressed_size: u32_at(entry, 4),
+        };
+        if frame.decompressed_size > MAX_FRAME_SIZE {
+            return Ok(None);
+        }
+        compressed_offset += u64::from(frame.compressed_size);
+        decompressed_offset += u64::from(frame.decompressed_size);
+        frames.push(frame);
+    }
+
+    // The frames fill the file up to the seek table
+    if compressed_offset + table_size + 8 != input_size {
+        logger::debug("Ignoring the seek table of the .zst, it doesn't match its frames.").log();
+        return Ok(None);
+    }
+    Ok(Some(frames))
+}
+
+fn u32_at(bytes: &[u8], start: usize) -> u32 {
+    // Safe unwrap: the slice is 4 bytes long
+    u32::from_le_bytes(<[u8; 4]>::try_from(&bytes[start..start + 4]).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// `data` in the seekable format, in frames of `frame_size` bytes
+    fn seekable(data: &[u8], frame_size: usize) -> Vec<u8> {
+        let (mut compressed, mut table) = (vec![], vec![]);
+        for chunk in data.chunks(frame_size) {
+            let frame = zstd::bulk::compress(chunk, 3).unwrap();
+            compressed.extend(&frame);
+            table.extend((frame.len() as u32).to_le_bytes());
+            table.extend((chunk.len() as u32).to_le_bytes());
+        }
+        let frame_count = table.len() as u32 / 8;
+        table.extend(frame_count.to_le_bytes());
+        table.push(0);
+        table.extend(SEEKABLE_MAGIC.to_le_bytes());
+
+        compressed.extend(SKIPPABLE_FRAME_MAGIC.to_le_bytes());
+        compressed.extend((table.len() as u32).to_le_bytes());
+        compressed.extend(table);
+        compressed
+    }
+
+    #[test]
+    fn test_seekable_decoder() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|number| number.to_le_bytes()).collect();
+        let input = seekable(&data, 1000);
+        // Other decoders read it like any .zst
+        assert_eq!(zstd::stream::decode_all(input.as_slice()).unwrap(), data);
+
+        let mut decoder = SeekableDecoder::new(Cursor::new(input)).unwrap().unwrap();
+        assert_eq!(decoder.frames.len(), 40);
+        let mut all = vec![];
+        decoder.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+
+        // Across two frames
+        let mut read = [0; 8];
+        decoder.seek(SeekFrom::Start(2996)).unwrap();
+        decoder.read_exact(&mut read).unwrap();
+        assert_eq!(read, data[2996..3004]);
+        decoder.seek(SeekFrom::End(-4)).unwrap();
+        decoder.read_exact(&mut read[..4]).unwrap();
+        assert_eq!(read[..4], 9999u32.to_le_bytes());
+        assert!(decoder.seek(SeekFrom::Current(-100_000)).is_err());
+    }
+
+    #[test]
+    fn test_not_seekable() {
+        let plain = zstd::bulk::compress(b"not seekable", 3).unwrap();
+        assert!(SeekableDecoder::new(Cursor::new(plain)).unwrap().is_none());
+
+        // A table that doesn't add up to the size of the frames
+        let mut input = seekable(b"data", 2);
+        input.insert(0, 0);
+        assert!(SeekableDecoder::new(Cursor::new(input)).unwrap().is_none());
+    }
+}
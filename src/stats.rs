@@ -0,0 +1,170 @@
+//! `--compression-stats`: once compressed, a table of how much each file was compressed and how
+//! long it took, the least compressed files first.
+//!
+//! The compressed size of a file is what was written to the output while it was added, counted
+//! under the encoders, which are flushed after each file so that what they buffered is counted
+//! towards it, which can make the archive slightly larger. Entries of .zip are compressed on their
+//! own, their sizes are read back from the archive once it was written.
+
+use std::{
+    cell::Cell,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use crate::{
+    oof,
+    utils::{self, colors, format_size, io::CountingWriter, logger, to_utf},
+};
+
+#[derive(Debug, Serialize)]
+struct FileStats {
+    path: PathBuf,
+    original_size: u64,
+    compressed_size: u64,
+    /// Compressed size over the original size, `None` for empty files
+    ratio: Option<f64>,
+    seconds: f64,
+}
+
+/// The file being added, since when and how much was written before it
+#[derive(Debug)]
+struct Started {
+    path: PathBuf,
+    original_size: u64,
+    written: u64,
+    instant: Instant,
+}
+
+/// The statistics of the files added to an archive, only recorded with `--compression-stats`
+#[derive(Debug, Default)]
+pub struct CompressionStats {
+    enabled: bool,
+    /// Bytes written to the output so far
+    written: Rc<Cell<u64>>,
+    started: Option<Started>,
+    files: Vec<FileStats>,
+}
+
+impl CompressionStats {
+    pub fn from_flags(flags: &oof::Flags) -> Self {
+        Self { enabled: flags.is_present("compression-stats"), ..Self::default() }
+    }
+
+    /// `writer`, the output file under the encoders, counting what is written to it
+    pub fn counting<W: Write>(&self, writer: W) -> CountingWriter<W> {
+        CountingWriter::new(writer, Rc::clone(&self.written))
+    }
+
+    /// Called before the file at `path`, `original_size` bytes long, is added
+    pub fn start(&mut self, path: &Path, original_size: u64) {
+        if self.enabled {
+            self.started = Some(Started {
+                path: path.to_path_buf(),
+                original_size,
+                written: self.written.get(),
+                instant: Instant::now(),
+            });
+        }
+    }
+
+    /// Called once the file started last was added to `writer`, which is flushed so that what the
+    /// encoders buffered is counted towards that file
+    pub fn finish(&mut self, writer: &mut dyn Write) -> io::Result<()> {
+        let started = match self.started.take() {
+            Some(started) => started,
+            None => return Ok(()),
+        };
+        writer.flush()?;
+
+        let compressed_size = self.written.get() - started.written;
+        self.files.push(FileStats {
+            ratio: ratio(started.original_size, compressed_size),
+            path: started.path,
+            original_size: started.original_size,
+            compressed_size,
+            seconds: started.instant.elapsed().as_secs_f64(),
+        });
+        Ok(())
+    }
+
+    /// Replace the sizes counted while writing the .zip `archive` by those it stores, the data of
+    /// an entry is only written out in full once the next one starts
+    pub fn read_zip_sizes(&mut self, archive: impl Read + Seek) -> crate::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let mut archive = zip::ZipArchive::new(archive)?;
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index)?;
+            let file = self.files.iter_mut().find(|file| file.path == Path::new(entry.name()));
+            if let Some(file) = file {
+                file.compressed_size = entry.compressed_size();
+                file.ratio = ratio(file.original_size, file.compressed_size);
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the table of the files added to `output_path`, or with `--json` or
+    /// `--log-format json`, one JSON object with them in its "stats" array
+    pub fn print(mut self, output_path: &Path, flags: &oof::Flags) -> crate::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        // Empty files, without a ratio, come last
+        self.files.sort_by(|a, b| b.ratio.unwrap_or(-1.0).total_cmp(&a.ratio.unwrap_or(-1.0)));
+
+        let mut output = vec![];
+        if flags.is_present("json") || logger::is_json() {
+            let json = serde_json::json!({ "path": output_path, "stats": self.files });
+            writeln!(output, "{}", json)?;
+        } else {
+            print_table(&mut output, output_path, &self.files)?;
+        }
+        utils::write_stdout(output)
+    }
+}
+
+fn ratio(original_size: u64, compressed_size: u64) -> Option<f64> {
+    Some(original_size).filter(|size| *size > 0).map(|size| compressed_size as f64 / size as f64)
+}
+
+fn print_table(out: &mut impl Write, output_path: &Path, files: &[FileStats]) -> io::Result<()> {
+    writeln!(out, "{}{}:{}", colors::cyan(), to_utf(output_path), colors::reset())?;
+    let paths: Vec<String> = files.iter().map(|file| to_utf(&file.path)).collect();
+    // At least as wide as the header
+    let width = paths.iter().map(|path| path.chars().count()).fold("path".len(), usize::max);
+
+    writeln!(
+        out,
+        "{:<width$}  {:>10}  {:>10}  {:>7}  {:>8}",
+        "path",
+        "original",
+        "compressed",
+        "ratio",
+        "time",
+        width = width
+    )?;
+    for (file, path) in files.iter().zip(&paths) {
+        let ratio = match file.ratio {
+            Some(ratio) => format!("{:.1}%", ratio * 100.0),
+            None => "-".to_string(),
+        };
+        writeln!(
+            out,
+            "{:<width$}  {:>10}  {:>10}  {:>7}  {:>7.3}s",
+            path,
+            format_size(file.original_size),
+            format_size(file.compressed_size),
+            ratio,
+            file.seconds,
+            width = width
+        )?;
+    }
+    Ok(())
+}
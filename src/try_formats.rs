@@ -0,0 +1,162 @@
+//! `--try-formats`: the .tar is compressed with every compression format at the same time, and
+//! only the smallest result is kept, like the automatic method selection of other archivers.
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, prelude::*, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+};
+
+use crate::{
+    cancel::Cancellable,
+    checksum,
+    commands::{chain_writer_encoder, compress_files},
+    events::EventSink,
+    extension::CompressionFormat::{self, *},
+    oof,
+    utils::{self, logger, to_utf, Bytes},
+    zstandard,
+};
+
+/// Formats the .tar is compressed with, and the extension given to their output
+const CANDIDATES: [(CompressionFormat, &str); 5] =
+    [(Gzip, ".gz"), (Bzip, ".bz2"), (Lzma, ".xz"), (Zstd, ".zst"), (Lz4, ".lz4")];
+
+/// Compress `files` into `output_path` (a .tar) followed by the extension of the format that
+/// compressed it the best, like "backup.tar.xz"
+pub fn compress(
+    files: Vec<PathBuf>,
+    output_path: &Path,
+    events: &dyn EventSink,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    // The .tar is built once, and every format compresses that same file
+    let tar_path = temporary_path(output_path, "");
+    let result = fs::File::create(&tar_path)
+        .map_err(crate::Error::from)
+        .and_then(|tar_file| {
+            compress_files(files, vec![Tar], &tar_path, tar_file, events, flags)
+        })
+        .and_then(|_| try_candidates(&tar_path, output_path, flags));
+
+    let _ = fs::remove_file(&tar_path);
+    result
+}
+
+fn try_candidates(tar_path: &Path, output_path: &Path, flags: &oof::Flags) -> crate::Result<()> {
+    let tar_size = fs::metadata(tar_path)?.len();
+    let results = Mutex::new(vec![]);
+    let zstd_options = zstandard::Options::from_flags(flags)?;
+
+    rayon::scope(|scope| {
+        for (format, extension) in &CANDIDATES {
+            let (results, zstd_options) = (&results, &zstd_options);
+            scope.spawn(move |_| {
+                let candidate_path = temporary_path(output_path, extension);
+                let result = compress_candidate(tar_path, &candidate_path, format, zstd_options);
+                results.lock().unwrap().push((*extension, candidate_path, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    // Same order as CANDIDATES, whichever finished first
+    results.sort_by_key(|(extension, ..)| {
+        CANDIDATES.iter().position(|(_, candidate)| candidate == extension)
+    });
+
+    let candidate_paths: Vec<PathBuf> = results.iter().map(|(_, path, _)| path.clone()).collect();
+
+    let mut smallest: Option<(&str, PathBuf, u64)> = None;
+    let mut first_error = None;
+    for (extension, candidate_path, result) in results {
+        match result {
+            Ok(size) => {
+                logger::info(format!(
+                    "{}: {} ({:.2}% of the .tar)",
+                    extension,
+                    Bytes::new(size),
+                    size as f64 / tar_size.max(1) as f64 * 100.0
+                ))
+                .log();
+                if smallest.as_ref().is_none_or(|(_, _, smallest_size)| size < *smallest_size) {
+                    smallest = Some((extension, candidate_path, size));
+                }
+            },
+            Err(err) => {
+                logger::error(format!("{}: {}", extension, err)).log();
+                first_error.get_or_insert(err);
+            },
+        }
+    }
+
+    let result = match (smallest, first_error) {
+        (Some((extension, candidate_path, _)), None) => {
+            keep_candidate(extension, &candidate_path, output_path, flags)
+        },
+        (_, Some(err)) => Err(err),
+        (None, None) => unreachable!(),
+    };
+
+    // The losers, and the winner if it wasn't kept
+    for candidate_path in candidate_paths {
+        let _ = fs::remove_file(candidate_path);
+    }
+    result
+}
+
+/// Compress the .tar at `tar_path` into `candidate_path`, returning the size of the result
+fn compress_candidate(
+    tar_path: &Path,
+    candidate_path: &Path,
+    format: &CompressionFormat,
+    zstd_options: &zstandard::Options,
+) -> crate::Result<u64> {
+    let mut reader = Cancellable::new(BufReader::new(fs::File::open(tar_path)?));
+    let writer = BufWriter::new(Cancellable::new(fs::File::create(candidate_path)?));
+    let mut encoder = chain_writer_encoder(format, Box::new(writer), zstd_options)?;
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.flush()?;
+    // Dropping the encoder writes the end of the stream
+    drop(encoder);
+
+    Ok(fs::metadata(candidate_path)?.len())
+}
+
+fn keep_candidate(
+    extension: &str,
+    candidate_path: &Path,
+    output_path: &Path,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    let mut final_path = OsString::from(output_path);
+    final_path.push(extension);
+    let final_path = PathBuf::from(final_path);
+
+    if final_path.exists() && !utils::user_wants_to_overwrite(&final_path, flags)? {
+        return Err(crate::Error::OverwriteDeclined(final_path));
+    }
+    fs::rename(candidate_path, &final_path)?;
+    if let Some(algorithm) = checksum::Algorithm::from_flags(flags)? {
+        checksum::write_checksum_file(&final_path, algorithm)?;
+    }
+
+    logger::info(format!(
+        "Successfully compressed '{}', {} was the smallest.",
+        to_utf(&final_path),
+        extension
+    ))
+    .path(&final_path)
+    .log();
+    Ok(())
+}
+
+/// "backup.tar" becomes "backup.tar.gz.tmp-1f2e", next to it so that it can be renamed into place
+fn temporary_path(output_path: &Path, extension: &str) -> PathBuf {
+    let mut path = OsString::from(output_path);
+    path.push(format!("{}.tmp-{:x}", extension, process::id()));
+    PathBuf::from(path)
+}
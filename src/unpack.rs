@@ -0,0 +1,159 @@
+//! Smart unpack: archives with a single folder at their root are extracted as that folder, the
+//! others into a new folder named after the archive, instead of scattering their files among the
+//! ones already in the current folder. The same goes inside of the folder given with `--output`.
+//!
+//! `--no-smart-unpack` extracts every archive right into the current folder, or the one given
+//! with `--output`, and so does `--flat`, that leaves out the folders of the entries too.
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    archive::FileInArchive,
+    extension::{self, CompressionFormat},
+    list, oof,
+    utils::{logger, to_utf},
+};
+
+/// Entries read to find the root of an archive. Archives still undecided after them are
+/// extracted into a folder named after them, which is never wrong, only sometimes one level too
+/// deep.
+const PEEK_LIMIT: usize = 1000;
+
+/// The folder named after the archive at `archive` that it is extracted into, or `None` when it
+/// is extracted right into `output_folder`, or the current folder.
+///
+/// `stem` is the name of the archive without its extensions. The entries are peeked at first to
+/// find their root, reading .tar and .cpio archives only until a second root shows up, and at
+/// most `PEEK_LIMIT` entries. Compressed .zip and .iso archives aren't, they would have to be
+/// decompressed twice, and always get their folder.
+pub fn archive_folder(
+    archive: &Path,
+    formats: &[CompressionFormat],
+    output_folder: Option<&Path>,
+    stem: &Path,
+    flags: &oof::Flags,
+) -> crate::Result<Option<PathBuf>> {
+    if flags.is_present("no-smart-unpack") || flags.is_present("flat") {
+        return Ok(None);
+    }
+    let formats = extension::reordered_formats(formats).unwrap_or_else(|| formats.to_vec());
+    let name = match (formats[0].is_archive(), stem.file_name()) {
+        (true, Some(name)) => name,
+        _ => return Ok(None),
+    };
+
+    let mut root = RootFolder::default();
+    let mut peeked = 0;
+    let listed = list::visit_entries(archive, &formats, flags, |entry| {
+        peeked += 1;
+        root.add(&entry) && peeked < PEEK_LIMIT
+    })?;
+    let reason = if !listed {
+        "to be decompressed whole to find its root"
+    } else if root.folder().is_none() {
+        "no single folder at its root"
+    } else if peeked >= PEEK_LIMIT {
+        "too many entries to find its root"
+    } else {
+        return Ok(None);
+    };
+
+    let folder = match output_folder {
+        Some(output_folder) => output_folder.join(name),
+        None => PathBuf::from(name),
+    };
+    logger::debug(format!(
+        "'{}' has {}, extracting it into '{}'.",
+        to_utf(archive),
+        reason,
+        to_utf(&folder)
+    ))
+    .path(archive)
+    .log();
+    Ok(Some(folder))
+}
+
+/// The root of the entries of an archive, added one at a time
+#[derive(Default)]
+struct RootFolder {
+    root: Option<OsString>,
+    is_folder: bool,
+    /// Entries outside of `root` were seen, there is no single root folder
+    mixed: bool,
+}
+
+impl RootFolder {
+    /// Add `entry`, `false` once there can't be a single root folder anymore
+    fn add(&mut self, entry: &FileInArchive) -> bool {
+        let mut components = entry.path.components().filter(|part| *part != Component::CurDir);
+        let first = match components.next() {
+            Some(Component::Normal(first)) => first,
+            // "./", the output folder itself
+            None => return true,
+            // Absolute paths and "..", that are refused when extracting anyway
+            Some(_) => {
+                self.mixed = true;
+                return false;
+            },
+        };
+        match &self.root {
+            Some(root) if root != first => {
+                self.mixed = true;
+                return false;
+            },
+            Some(_) => {},
+            None => self.root = Some(first.to_os_string()),
+        }
+        self.is_folder |= entry.is_dir || components.next().is_some();
+        true
+    }
+
+    /// The folder all of the entries added are in, if there is one
+    fn folder(&self) -> Option<&OsStr> {
+        self.root.as_deref().filter(|_| self.is_folder && !self.mixed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, is_dir: bool) -> FileInArchive {
+        FileInArchive { path: PathBuf::from(path), is_dir, size: 0, modified: None }
+    }
+
+    fn root_of(paths: &[(&str, bool)]) -> Option<String> {
+        let mut root = RootFolder::default();
+        for &(path, is_dir) in paths {
+            if !root.add(&entry(path, is_dir)) {
+                break;
+            }
+        }
+        root.folder().map(|root| root.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn test_root_folder() {
+        assert_eq!(
+            root_of(&[("project", true), ("project/src/main.rs", false)]).as_deref(),
+            Some("project")
+        );
+        assert_eq!(
+            root_of(&[("./project/a", false), ("project/b", false)]).as_deref(),
+            Some("project")
+        );
+        assert_eq!(root_of(&[("project", true)]).as_deref(), Some("project"));
+        assert_eq!(root_of(&[("project/a", false), ("README", false)]), None);
+        assert_eq!(root_of(&[("firmware.bin", false)]), None);
+        assert_eq!(root_of(&[("/etc/passwd", false)]), None);
+        assert_eq!(root_of(&[]), None);
+
+        // Nothing after the second root needs to be read
+        let mut root = RootFolder::default();
+        assert!(root.add(&entry("project/a", false)));
+        assert!(!root.add(&entry("README", false)));
+    }
+}
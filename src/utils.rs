@@ -1,31 +1,52 @@
 use std::{
-    cmp, env,
+    cmp,
+    convert::TryFrom,
+    env,
     ffi::OsStr,
     fs,
-    path::{Path, PathBuf},
+    io::Write as _,
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
 };
 
-use crate::{dialogs::Confirmation, oof};
+use ignore::{DirEntry, WalkBuilder};
+
+use crate::{dialogs::Confirmation, error::FinalError, oof};
+
+pub mod io;
+pub mod logger;
+pub mod sparse;
 
 pub fn create_dir_if_non_existent(path: &Path) -> crate::Result<()> {
     if !path.exists() {
-        println!(
-            "{}[INFO]{} attempting to create folder {:?}.",
-            colors::yellow(),
-            colors::reset(),
-            &path
-        );
+        logger::info(format!("attempting to create folder {:?}.", &path)).path(path).log();
         fs::create_dir_all(path)?;
-        println!(
-            "{}[INFO]{} directory {:#?} created.",
-            colors::yellow(),
-            colors::reset(),
-            fs::canonicalize(&path)?
-        );
+        let path = fs::canonicalize(path)?;
+        logger::info(format!("directory {:#?} created.", path)).path(&path).log();
     }
     Ok(())
 }
 
+/// Fails with `Error::PathTraversal` unless `entry`, the path an entry of an archive is about to
+/// be extracted to, is inside of `output_root` once the symbolic links leading to it are resolved.
+///
+/// The folders leading to it may not exist yet, only the ones that do are resolved, the others
+/// can't be links. The entry itself isn't, an existing link there is replaced, not followed.
+pub fn validate_entry_path(entry: &Path, output_root: &Path) -> crate::Result<()> {
+    let traversal = || crate::Error::PathTraversal { entry: to_utf(entry) };
+    let parent = entry.parent().ok_or_else(traversal)?;
+    // Relative paths start in the current folder when none of their folders exist
+    let existing = parent.ancestors().find(|path| path.exists()).unwrap_or_else(|| Path::new("."));
+    let missing = parent.strip_prefix(existing).unwrap_or(parent);
+    let is_enclosed = missing.components().all(|part| matches!(part, Component::Normal(_)));
+
+    if is_enclosed && existing.canonicalize()?.starts_with(output_root.canonicalize()?) {
+        Ok(())
+    } else {
+        Err(traversal())
+    }
+}
+
 pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
     let previous_location = env::current_dir()?;
 
@@ -42,6 +63,205 @@ pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
     Ok(previous_location)
 }
 
+/// Walks `path` up to `max_depth` levels deep.
+///
+/// With `--reproducible` the entries of each folder are sorted by name, so that archives don't
+/// depend on the order in which the filesystem lists them. `--follow-symlinks` walks into the
+/// targets of symlinks, storing their contents under the path of the link, and `--gitignore`
+/// leaves out what git ignores: the `.gitignore` files of the walked folders and of their parents,
+/// `.git/info/exclude` and the global excludes file. `--exclude-vcs` leaves out version control
+/// folders and files, and also reads `.gitignore` unless `--no-gitignore` is given.
+pub fn walk_dir(path: &Path, max_depth: usize, flags: &oof::Flags) -> Walk {
+    let read_gitignores = reads_gitignores(flags);
+    let mut walker = WalkBuilder::new(path);
+    walker
+        .max_depth(Some(max_depth))
+        .follow_links(flags.is_present("follow-symlinks"))
+        // Hidden files and `.ignore` files are none of git's business
+        .standard_filters(false)
+        .parents(read_gitignores)
+        .git_ignore(read_gitignores)
+        .git_exclude(read_gitignores)
+        .git_global(read_gitignores)
+        .require_git(false);
+    if flags.is_present("reproducible") {
+        walker.sort_by_file_name(|a, b| a.cmp(b));
+    }
+    // Inputs are kept even when they are version control folders themselves, the walker never
+    // filters them out
+    if flags.is_present("exclude-vcs") {
+        walker.filter_entry(|entry| {
+            !entry.file_name().to_str().is_some_and(|name| VCS_NAMES.contains(&name))
+        });
+    }
+    Walk { inner: walker.build() }
+}
+
+/// Whether `walk_dir` leaves out what git ignores, with `--gitignore` or `--exclude-vcs`
+pub(crate) fn reads_gitignores(flags: &oof::Flags) -> bool {
+    flags.is_present("gitignore")
+        || (flags.is_present("exclude-vcs") && !flags.is_present("no-gitignore"))
+}
+
+/// Folders and files of version control systems, left out with `--exclude-vcs` like GNU tar does
+const VCS_NAMES: &[&str] = &[
+    ".git",
+    ".gitignore",
+    ".gitattributes",
+    ".gitmodules",
+    ".svn",
+    ".hg",
+    ".hgignore",
+    ".hgtags",
+    ".bzr",
+    ".bzrignore",
+    "CVS",
+    ".cvsignore",
+    "_darcs",
+];
+
+/// Iterator returned by `walk_dir`
+pub struct Walk {
+    inner: ignore::Walk,
+}
+
+impl Iterator for Walk {
+    type Item = crate::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if crate::cancel::is_cancelled() {
+                return Some(Err(crate::Error::Cancelled));
+            }
+
+            let entry = match self.inner.next()? {
+                Ok(entry) => entry,
+                // A link to one of its own parent folders, the rest of the folder is still walked
+                Err(err) if loop_child(&err).is_some() => {
+                    let path = loop_child(&err).map(to_utf).unwrap_or_default();
+                    let warning = logger::warning(format!(
+                        "Skipping '{}', it links to one of its parent folders.",
+                        path
+                    ))
+                    .path(&path)
+                    .category("links to parent folders skipped");
+                    if let Err(err) = warning.warn() {
+                        return Some(Err(err));
+                    }
+                    continue;
+                },
+                Err(err) => return Some(Err(err.into())),
+            };
+
+            // The patterns git would also reject, the others of the file still apply
+            if let Some(err) = entry.error() {
+                logger::warning(format!("Ignoring a pattern of a .gitignore: {}", err))
+                    .path(entry.path())
+                    .category("unreadable .gitignore patterns")
+                    .log();
+            }
+            return Some(Ok(entry));
+        }
+    }
+}
+
+/// The link of `err` that leads to one of its parent folders, if it is about one
+fn loop_child(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => loop_child(err),
+        _ => None,
+    }
+}
+
+/// The inputs in the order they are archived, sorted with `--reproducible`
+pub fn archive_order(input_filenames: &[PathBuf], flags: &oof::Flags) -> Vec<PathBuf> {
+    let mut input_filenames = input_filenames.to_vec();
+    if flags.is_present("reproducible") {
+        input_filenames.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    }
+    input_filenames
+}
+
+/// Set by build systems to the time the output of reproducible builds gets, see
+/// https://reproducible-builds.org/specs/source-date-epoch/
+const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// The modification times stored in archives instead of those of the files, in seconds since the
+/// Unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveMtime {
+    /// The time of every entry, like `--mtime` of GNU tar
+    Fixed(u64),
+    /// The latest time stored, later ones are clamped to it
+    Latest(u64),
+}
+
+impl ArchiveMtime {
+    /// The time stored for the file of `metadata`
+    pub fn of(self, metadata: &fs::Metadata) -> u64 {
+        match self {
+            Self::Fixed(mtime) => mtime,
+            Self::Latest(latest) => clamped_mtime(metadata, latest),
+        }
+    }
+}
+
+/// The modification times stored in archives, `None` for those of the files. Every entry gets the
+/// one of `--mtime`. Otherwise, with `--reproducible`, they are clamped like other reproducible
+/// builds do to `SOURCE_DATE_EPOCH`, or to the epoch itself, which stores no times at all
+pub fn archive_mtime(flags: &oof::Flags) -> crate::Result<Option<ArchiveMtime>> {
+    if let Some(mtime) = flags.arg("mtime") {
+        return match mtime.to_str().and_then(parse_timestamp) {
+            Some(mtime) => Ok(Some(ArchiveMtime::Fixed(mtime))),
+            None => Err(FinalError::with_title(format!("Invalid --mtime '{}'", to_utf(mtime)))
+                .detail("It must be an RFC 3339 date, like 2024-01-01T00:00:00Z")
+                .detail("Or a number of seconds since the Unix epoch, like @1704067200")
+                .hint(
+                    "Use `--mtime \"@$(git log -1 --format=%ct)\"` for the time of the last commit",
+                )
+                .into()),
+        };
+    }
+
+    if !flags.is_present("reproducible") {
+        return Ok(None);
+    }
+
+    match env::var_os(SOURCE_DATE_EPOCH).filter(|value| !value.is_empty()) {
+        Some(value) => match value.to_str().and_then(|value| value.parse().ok()) {
+            Some(mtime) => Ok(Some(ArchiveMtime::Latest(mtime))),
+            None => Err(crate::Error::InvalidEnvVar {
+                name: SOURCE_DATE_EPOCH,
+                reason: format!(
+                    "'{}' is not a number of seconds since the Unix epoch",
+                    to_utf(value)
+                ),
+            }),
+        },
+        None => Ok(Some(ArchiveMtime::Latest(0))),
+    }
+}
+
+/// Seconds since the Unix epoch of an RFC 3339 date like "2024-01-01T00:00:00Z", or of seconds
+/// written as "@1704067200" or "1704067200". Dates before the epoch aren't stored by archives.
+fn parse_timestamp(text: &str) -> Option<u64> {
+    let seconds = text.strip_prefix('@').unwrap_or(text);
+    if let Ok(seconds) = seconds.parse() {
+        return Some(seconds);
+    }
+    let date = chrono::DateTime::parse_from_rfc3339(text).ok()?;
+    u64::try_from(date.timestamp()).ok()
+}
+
+/// The modification time of `metadata` in seconds since the Unix epoch, at most `latest`
+pub fn clamped_mtime(metadata: &fs::Metadata, latest: u64) -> u64 {
+    let mtime = metadata.modified().ok().and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok());
+    mtime.map_or(0, |mtime| mtime.as_secs()).min(latest)
+}
+
 pub fn user_wants_to_overwrite(path: &Path, flags: &oof::Flags) -> crate::Result<bool> {
     match (flags.is_present("yes"), flags.is_present("no")) {
         (true, true) => {
@@ -59,7 +279,32 @@ pub fn user_wants_to_overwrite(path: &Path, flags: &oof::Flags) -> crate::Result
     const OVERWRITE_CONFIRMATION_QUESTION: Confirmation =
         Confirmation::new("Do you want to overwrite 'FILE'?", Some("FILE"));
 
-    OVERWRITE_CONFIRMATION_QUESTION.ask(Some(&file_path_str))
+    OVERWRITE_CONFIRMATION_QUESTION.ask(Some(&file_path_str), question_timeout(flags)?)
+}
+
+/// How long questions wait for an answer, set by `--question-timeout SECONDS`
+fn question_timeout(flags: &oof::Flags) -> crate::Result<Option<Duration>> {
+    match flags.arg("question-timeout") {
+        Some(seconds) => {
+            let seconds: u64 = seconds
+                .to_str()
+                .and_then(|seconds| seconds.parse().ok())
+                .ok_or(crate::Error::InvalidInput)?;
+            Ok(Some(Duration::from_secs(seconds)))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Writes `output` to stdout. print! panics once stdout was closed, like by `| head`, this fails
+/// with `Error::BrokenPipe` instead, which ends the run silently and successfully
+pub fn write_stdout(output: impl AsRef<[u8]>) -> crate::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    match stdout.write_all(output.as_ref()).and_then(|()| stdout.flush()) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Err(crate::Error::BrokenPipe),
+        Err(err) => Err(err.into()),
+    }
 }
 
 pub fn to_utf(os_str: impl AsRef<OsStr>) -> String {
@@ -67,40 +312,116 @@ pub fn to_utf(os_str: impl AsRef<OsStr>) -> String {
     text.trim_matches('"').to_string()
 }
 
+/// Parses sizes like "500", "20k", "1.5G" or "4GiB", suffixes without an "i" are powers of 1000
+/// (like `Bytes` prints them) and suffixes with an "i" are powers of 1024
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let number_end = text.find(|ch: char| !ch.is_ascii_digit() && ch != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(number_end);
+    let number: f64 = number.parse().ok()?;
+
+    let unit = unit.trim().to_ascii_lowercase();
+    let unit = unit.strip_suffix('b').unwrap_or(&unit);
+    let (prefix, base) = match unit.strip_suffix('i') {
+        Some(prefix) if !prefix.is_empty() => (prefix, 1024_f64),
+        _ => (unit, 1000_f64),
+    };
+    let exponent = match prefix {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        "p" => 5,
+        _ => return None,
+    };
+
+    Some((number * base.powi(exponent)) as u64)
+}
+
 pub struct Bytes {
     bytes: f64,
 }
 
+/// `bytes` with IEC binary prefixes and one decimal, like "1.4 MiB", for sizes shown by `ouch list`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Rounded up to the next unit once it would show as "1024.0"
+    while size >= 1023.95 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
 /// Module with a list of bright colors.
 #[allow(dead_code)]
 #[cfg(target_family = "unix")]
 pub mod colors {
-    pub const fn reset() -> &'static str {
-        "\u{1b}[39m"
+    use std::{
+        env,
+        io::{self, IsTerminal},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    static DISABLED: AtomicBool = AtomicBool::new(false);
+
+    /// Make every color empty, for output read by other programs
+    pub fn disable() {
+        DISABLED.store(true, Ordering::SeqCst);
     }
-    pub const fn black() -> &'static str {
-        "\u{1b}[38;5;8m"
+
+    /// Colors are only for terminals, unless `CLICOLOR_FORCE` is set to something other than 0.
+    /// `NO_COLOR` turns them off everywhere, see <https://no-color.org>.
+    pub fn set_from_env() {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let force =
+            env::var_os("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0");
+        if no_color || (!force && !io::stdout().is_terminal()) {
+            disable();
+        }
+    }
+
+    fn color(code: &'static str) -> &'static str {
+        if DISABLED.load(Ordering::SeqCst) {
+            ""
+        } else {
+            code
+        }
+    }
+
+    pub fn reset() -> &'static str {
+        color("\u{1b}[39m")
     }
-    pub const fn blue() -> &'static str {
-        "\u{1b}[38;5;12m"
+    pub fn black() -> &'static str {
+        color("\u{1b}[38;5;8m")
     }
-    pub const fn cyan() -> &'static str {
-        "\u{1b}[38;5;14m"
+    pub fn blue() -> &'static str {
+        color("\u{1b}[38;5;12m")
     }
-    pub const fn green() -> &'static str {
-        "\u{1b}[38;5;10m"
+    pub fn cyan() -> &'static str {
+        color("\u{1b}[38;5;14m")
     }
-    pub const fn magenta() -> &'static str {
-        "\u{1b}[38;5;13m"
+    pub fn green() -> &'static str {
+        color("\u{1b}[38;5;10m")
     }
-    pub const fn red() -> &'static str {
-        "\u{1b}[38;5;9m"
+    pub fn magenta() -> &'static str {
+        color("\u{1b}[38;5;13m")
     }
-    pub const fn white() -> &'static str {
-        "\u{1b}[38;5;15m"
+    pub fn red() -> &'static str {
+        color("\u{1b}[38;5;9m")
     }
-    pub const fn yellow() -> &'static str {
-        "\u{1b}[38;5;11m"
+    pub fn white() -> &'static str {
+        color("\u{1b}[38;5;15m")
+    }
+    pub fn yellow() -> &'static str {
+        color("\u{1b}[38;5;11m")
     }
 }
 // Windows does not support ANSI escape codes
@@ -110,6 +431,8 @@ pub mod colors {
     pub fn empty() -> &'static str {
         ""
     }
+    pub fn disable() {}
+    pub fn set_from_env() {}
     pub const reset: fn() -> &'static str = empty;
     pub const black: fn() -> &'static str = empty;
     pub const blue: fn() -> &'static str = empty;
@@ -148,6 +471,18 @@ impl std::fmt::Display for Bytes {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(1024 * 1024 - 1), "1.0 MiB");
+        assert_eq!(format_size(1_468_006), "1.4 MiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+        assert_eq!(format_size(u64::MAX), "16.0 EiB");
+    }
+
     #[test]
     fn test_pretty_bytes_formatting() {
         fn format_bytes(bytes: u64) -> String {
@@ -183,4 +518,28 @@ mod tests {
         assert_eq!("95.20 GB", format_bytes(95200000000));
         assert_eq!("302.00 GB", format_bytes(302000000000));
     }
+
+    #[test]
+    fn test_timestamp_parsing() {
+        assert_eq!(parse_timestamp("@1704067200"), Some(1_704_067_200));
+        assert_eq!(parse_timestamp("1704067200"), Some(1_704_067_200));
+        assert_eq!(parse_timestamp("2024-01-01T00:00:00Z"), Some(1_704_067_200));
+        assert_eq!(parse_timestamp("2024-01-01T02:00:00+02:00"), Some(1_704_067_200));
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_timestamp("@-1"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn test_size_parsing() {
+        assert_eq!(parse_size("500"), Some(500));
+        assert_eq!(parse_size("20k"), Some(20_000));
+        assert_eq!(parse_size("20KB"), Some(20_000));
+        assert_eq!(parse_size("1.5G"), Some(1_500_000_000));
+        assert_eq!(parse_size("4GiB"), Some(4 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("2 Mi"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_size("ten"), None);
+        assert_eq!(parse_size("10X"), None);
+        assert_eq!(parse_size(""), None);
+    }
 }
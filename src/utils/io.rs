@@ -0,0 +1,423 @@
+//! Readers and writers wrapped around the inputs and outputs of ouch.
+//!
+//! `OutputLimit` guards against decompression bombs, tiny archives that expand to enormous
+//! outputs. The others count, hash or track the position of what goes through them, report
+//! decoding errors as corrupt archives, and spool inputs that need to seek to temporary files.
+
+use std::{
+    cell::Cell,
+    env, fs,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use sha2::digest::DynDigest;
+
+use super::{logger, to_utf, Bytes};
+use crate::{error::CorruptData, extension::CompressionFormat, oof};
+
+/// Default limit, relative to the size of the compressed input
+const DEFAULT_RATIO_LIMIT: u64 = 100;
+/// The default limit is never lower than this, small archives of text compress very well
+const DEFAULT_MINIMUM_LIMIT: u64 = 1024 * 1024 * 1024;
+
+/// Keeps track of how many bytes were extracted from an archive, failing once it exceeds the limit
+#[derive(Debug)]
+pub struct OutputLimit {
+    limit: Option<u64>,
+    ratio_limit: Option<RatioLimit>,
+    written: u64,
+    exceeded_at: Option<PathBuf>,
+    entries: EntryLimit,
+}
+
+/// `--max-ratio`, the output can't be more than `max_ratio` times the size of the compressed input
+#[derive(Debug)]
+struct RatioLimit {
+    max_ratio: u64,
+    input_size: u64,
+}
+
+impl OutputLimit {
+    pub fn new(limit: u64) -> Self {
+        Self { limit: Some(limit), ..Self::unlimited() }
+    }
+
+    pub fn unlimited() -> Self {
+        Self {
+            limit: None,
+            ratio_limit: None,
+            written: 0,
+            exceeded_at: None,
+            entries: EntryLimit::unlimited(),
+        }
+    }
+
+    /// Also fail once the output is more than `max_ratio` times `input_size`
+    pub fn with_max_ratio(mut self, max_ratio: u64, input_size: u64) -> Self {
+        self.ratio_limit = Some(RatioLimit { max_ratio, input_size });
+        self
+    }
+
+    /// Also fail once more entries than `entries` allows were extracted
+    pub fn with_entry_limit(mut self, entries: EntryLimit) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Account for one more entry of the archive, extracted or not
+    pub fn add_entry(&mut self) -> crate::Result<()> {
+        self.entries.add()
+    }
+
+    /// The limit used when `--max-output-size` isn't given
+    pub fn default_for_input_size(input_size: u64) -> Self {
+        Self::new(input_size.saturating_mul(DEFAULT_RATIO_LIMIT).max(DEFAULT_MINIMUM_LIMIT))
+    }
+
+    /// Whether there is no size limit, the ratio limit doesn't count
+    pub fn is_unlimited(&self) -> bool {
+        self.limit.is_none()
+    }
+
+    /// Account for `bytes` more being written to `entry`
+    pub fn add(&mut self, bytes: u64, entry: &Path) -> crate::Result<()> {
+        self.written = self.written.saturating_add(bytes);
+        match self.exceeded(entry) {
+            Some(err) => {
+                self.exceeded_at = Some(entry.to_path_buf());
+                Err(err)
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// The error for the limit that the output went over, if any
+    fn exceeded(&self, entry: &Path) -> Option<crate::Error> {
+        let entry = entry.to_path_buf();
+        if let Some(limit) = self.limit.filter(|&limit| self.written > limit) {
+            return Some(crate::Error::OutputSizeExceeded { limit, entry });
+        }
+        // The output only grows, so once the ratio is over the limit it stays over it
+        let ratio_limit = self.ratio_limit.as_ref()?;
+        if self.written > ratio_limit.input_size.saturating_mul(ratio_limit.max_ratio) {
+            return Some(crate::Error::RatioExceeded { max_ratio: ratio_limit.max_ratio, entry });
+        }
+        None
+    }
+
+    /// Wrap `writer`, so that everything written to it counts towards the limit
+    pub fn writer<'a, W: Write>(&'a mut self, writer: W, entry: &'a Path) -> LimitedWriter<'a, W> {
+        LimitedWriter { inner: writer, limit: self, entry }
+    }
+}
+
+/// `--max-files`, the number of entries an archive can have, whether they are extracted or listed
+#[derive(Debug)]
+pub struct EntryLimit {
+    max_files: Option<u64>,
+    count: u64,
+}
+
+impl EntryLimit {
+    pub fn unlimited() -> Self {
+        Self { max_files: None, count: 0 }
+    }
+
+    /// The limit of `--max-files N`, where 0, the default, is no limit
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let max_files = match flags.arg("max-files") {
+            Some(max_files) => max_files
+                .to_str()
+                .and_then(|max_files| max_files.parse().ok())
+                .ok_or(crate::Error::InvalidInput)?,
+            None => 0,
+        };
+        Ok(Self { max_files: Some(max_files).filter(|&max_files| max_files != 0), count: 0 })
+    }
+
+    /// Account for one more entry, failing once there are more than the limit
+    pub fn add(&mut self) -> crate::Result<()> {
+        self.count += 1;
+        match self.max_files {
+            Some(max_files) if self.count > max_files => {
+                Err(crate::Error::EntryLimitExceeded { max_files })
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writer that fails once the `OutputLimit` it is bound to is exceeded
+pub struct LimitedWriter<'a, W> {
+    inner: W,
+    limit: &'a mut OutputLimit,
+    entry: &'a Path,
+}
+
+impl<W: Write> Write for LimitedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.limit.add(buf.len() as u64, self.entry).is_err() {
+            return Err(io::Error::other("output size limit exceeded"));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Copies `reader` into the file at `output_path`, stopping once `limit` is exceeded.
+///
+/// The incomplete file is removed when the limit is hit.
+pub fn copy_limited(
+    reader: &mut impl Read,
+    writer: impl Write,
+    output_path: &Path,
+    limit: &mut OutputLimit,
+) -> crate::Result<u64> {
+    let result = io::copy(reader, &mut limit.writer(writer, output_path));
+
+    match (result, limit.exceeded_at.take()) {
+        (Ok(bytes), _) => Ok(bytes),
+        (Err(_), Some(entry)) => {
+            let _ = fs::remove_file(output_path);
+            // Safe unwrap: the output is still over the limit that stopped it
+            Err(limit.exceeded(&entry).unwrap())
+        },
+        (Err(err), None) => Err(err.into()),
+    }
+}
+
+/// Writer that hashes everything written through it, for `--checksum-file`
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Box<dyn DynDigest>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W, hasher: Box<dyn DynDigest>) -> Self {
+        Self { inner, hasher }
+    }
+
+    /// The digest of what was written, in lowercase hex
+    pub fn hex_digest(self) -> String {
+        self.hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer counting the bytes written through it, into a counter that can be read while the
+/// encoders and archive builders it is wrapped in own it
+pub struct CountingWriter<W> {
+    inner: W,
+    written: Rc<Cell<u64>>,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W, written: Rc<Cell<u64>>) -> Self {
+        Self { inner, written }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written.set(self.written.get() + written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// For .zip, headers that are written again after seeking back are counted twice
+impl<W: Seek> Seek for CountingWriter<W> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(position)
+    }
+}
+
+/// Input of a decoder, keeping track of how many bytes of it the decoder consumed for the
+/// `DecodingReader` over its output, which can't reach it through the decoder
+pub struct PositionReader<R> {
+    inner: R,
+    position: Rc<Cell<u64>>,
+}
+
+impl<R: BufRead> PositionReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, position: Rc::default() }
+    }
+
+    /// Shared with the reader, updated as it is consumed
+    pub fn position(&self) -> Rc<Cell<u64>> {
+        Rc::clone(&self.position)
+    }
+}
+
+impl<R: BufRead> Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position.set(self.position.get() + read as u64);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for PositionReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount);
+        self.position.set(self.position.get() + amount as u64);
+    }
+}
+
+/// Reader over the output of a decoder, tagging its decoding errors with `format` so that they are
+/// reported as `Error::CorruptArchive` instead of a generic IO error, along with how far the
+/// decoder got into its input, `input_position` from the `PositionReader` it reads
+pub struct DecodingReader<R> {
+    inner: R,
+    format: CompressionFormat,
+    decoded: u64,
+    input_position: Rc<Cell<u64>>,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(inner: R, format: CompressionFormat, input_position: Rc<Cell<u64>>) -> Self {
+        Self { inner, format, decoded: 0, input_position }
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let err = match self.inner.read(buf) {
+            Ok(bytes) => {
+                self.decoded += bytes as u64;
+                return Ok(bytes);
+            },
+            Err(err) => err,
+        };
+
+        // Errors that were already tagged come from an inner decoder
+        let is_tagged = err.get_ref().is_some_and(|err| err.is::<CorruptData>());
+        // The codecs report bad data with these kinds, xz2 uses `Other`, while errors from the
+        // operating system get more specific kinds
+        let is_decoding_error = matches!(
+            err.kind(),
+            io::ErrorKind::InvalidData
+                | io::ErrorKind::InvalidInput
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::Other
+        );
+        if is_tagged || !is_decoding_error {
+            return Err(err);
+        }
+
+        let corrupt = CorruptData {
+            format: self.format.clone(),
+            reason: err.to_string(),
+            offset: Some(self.decoded),
+            compressed_offset: Some(self.input_position.get()),
+        };
+        Err(io::Error::new(io::ErrorKind::InvalidData, corrupt))
+    }
+}
+
+/// Warn before extracting if the archive declares more contents than the free space available
+/// at `output_folder`
+pub fn warn_if_low_on_space(declared_size: u64, output_folder: &Path) {
+    let available = match available_space(output_folder) {
+        Some(available) if available < declared_size => available,
+        _ => return,
+    };
+
+    logger::warning(format!(
+        "The archive declares {} of contents, but only {} are available at '{}'.",
+        Bytes::new(declared_size),
+        Bytes::new(available),
+        to_utf(output_folder)
+    ))
+    .path(output_folder)
+    .category("low on disk space")
+    .log();
+}
+
+/// Where archives that need to seek, like a .zip inside of a .gz, are spooled while extracting:
+/// `--temp-dir`, or else `default`, or else the temporary folder of the system
+pub fn temp_dir(flags: &oof::Flags, default: Option<&Path>) -> PathBuf {
+    match (flags.arg("temp-dir"), default) {
+        (Some(dir), _) => PathBuf::from(dir),
+        (None, Some(default)) => default.to_path_buf(),
+        (None, None) => env::temp_dir(),
+    }
+}
+
+/// Copy `reader` into a file in `dir`, removed once closed, and rewind it
+pub fn spool(reader: &mut impl Read, dir: &Path) -> crate::Result<fs::File> {
+    let mut file = temp_file(dir)?;
+    io::copy(reader, &mut file).map_err(|err| in_temp_dir(err, dir))?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// A new file in `dir`, removed once closed
+pub fn temp_file(dir: &Path) -> crate::Result<fs::File> {
+    fs::create_dir_all(dir)?;
+    tempfile::tempfile_in(dir).map_err(|err| in_temp_dir(err, dir))
+}
+
+/// Telling that `dir` is full from other failures, `--temp-dir` can point elsewhere
+fn in_temp_dir(err: io::Error, dir: &Path) -> crate::Error {
+    match crate::Error::from(err) {
+        crate::Error::NoSpaceLeft { temp_dir: None } => {
+            crate::Error::NoSpaceLeft { temp_dir: Some(dir.to_path_buf()) }
+        },
+        err => err,
+    }
+}
+
+/// Free space available to unprivileged users in the filesystem that contains `path`
+#[cfg(target_family = "unix")]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+
+    // Safety: `path` is a valid C string and `stats` is only read if the call succeeded
+    let stats = unsafe {
+        if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stats.assume_init()
+    };
+
+    #[allow(clippy::unnecessary_cast)]
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,607 @@
+//! Messages for the user. By default they are "[INFO] ..." lines, and with `--log-format json`
+//! every message is one JSON object per line on stderr, for other programs to read.
+//!
+//! Which messages are shown is filtered by `OUCH_LOG`, with the syntax of `env_logger`: a list
+//! like "debug,ouch::archive::zip=trace" of a default level and levels for modules (and the
+//! modules inside of them). Messages belong to the module they were logged from. `--quiet` and
+//! `--verbose` replace its default level.
+//!
+//! With `--log-file`, the messages that are shown are also appended to a file, with the time.
+//!
+//! Warnings are also collected, to be summarized once the operation is done.
+//!
+//! Programs using ouch as a library get the messages through a `LogSink` instead, nothing is
+//! printed then.
+
+use std::{
+    cell::RefCell,
+    env, fs,
+    io::{self, Write},
+    panic::Location,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+use crate::{
+    oof,
+    utils::{self, colors, to_utf},
+};
+
+/// Environment variable with the filter, like "debug,ouch::archive::zip=trace"
+const FILTER_VAR: &str = "OUCH_LOG";
+
+static JSON: AtomicBool = AtomicBool::new(false);
+/// With `--strict`, warnings about entries that can't be handled fail the operation right away
+static STRICT: AtomicBool = AtomicBool::new(false);
+static FILTER: OnceLock<Filter> = OnceLock::new();
+/// Default level chosen with `--quiet` or `--verbose`
+static VERBOSITY: OnceLock<Level> = OnceLock::new();
+/// Written line by line, without buffering, so nothing is lost when ouch exits early
+static LOG_FILE: OnceLock<Mutex<fs::File>> = OnceLock::new();
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(vec![]);
+
+thread_local! {
+    /// Set by the library API for the duration of each call, see `with_sink`
+    static SINK: RefCell<Option<Rc<dyn LogSink>>> = RefCell::new(None);
+}
+
+/// Receives the messages of ouch, for programs using it as a library
+pub trait LogSink {
+    /// Called with every message, whatever its level
+    fn log(&self, message: &Message);
+}
+
+/// Paths listed for each category of the summary, the rest are only counted
+const SUMMARY_PATHS: usize = 5;
+
+/// A warning logged during the operation, for the summary
+#[derive(Debug)]
+struct Warning {
+    category: &'static str,
+    path: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct SummaryGroup {
+    category: &'static str,
+    count: usize,
+    paths: Vec<String>,
+}
+
+/// Run `f` with the messages logged on this thread going to `sink` instead of being printed
+pub fn with_sink<T>(sink: Rc<dyn LogSink>, f: impl FnOnce() -> T) -> T {
+    let previous = SINK.with(|current| current.replace(Some(sink)));
+    let result = f();
+    SINK.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Whether the messages go to a `LogSink`, instead of being printed
+pub fn has_sink() -> bool {
+    SINK.with(|sink| sink.borrow().is_some())
+}
+
+/// Use the format given with `--log-format`, "human" (the default) or "json"
+pub fn set_format(flags: &oof::Flags) -> crate::Result<()> {
+    match flags.arg("log-format").map(|format| format.to_str()) {
+        None | Some(Some("human")) => {},
+        Some(Some("json")) => {
+            JSON.store(true, Ordering::SeqCst);
+            // Escape codes would end up inside of the JSON strings
+            colors::disable();
+        },
+        Some(_) => return Err(crate::Error::InvalidInput),
+    }
+    Ok(())
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+/// Use the level of `--quiet` (only errors) or `--verbose` (every archived file too)
+pub fn set_verbosity(flags: &oof::Flags) -> crate::Result<()> {
+    let level = match (flags.is_present("quiet"), flags.is_present("verbose")) {
+        (true, true) => return Err(crate::Error::ConflictingFlags("quiet", "verbose")),
+        (true, false) => Level::Error,
+        (false, true) => Level::Debug,
+        (false, false) => return Ok(()),
+    };
+    let _ = VERBOSITY.set(level);
+    Ok(())
+}
+
+pub fn is_quiet() -> bool {
+    VERBOSITY.get() == Some(&Level::Error)
+}
+
+pub fn set_strict(flags: &oof::Flags) {
+    STRICT.store(flags.is_present("strict"), Ordering::SeqCst);
+}
+
+/// Open the file given with `--log-file` to append messages to it, creating its parent folders
+pub fn set_log_file(flags: &oof::Flags) -> crate::Result<()> {
+    let path = match flags.arg("log-file") {
+        Some(path) => PathBuf::from(path),
+        None => return Ok(()),
+    };
+
+    let open = || -> io::Result<fs::File> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::OpenOptions::new().create(true).append(true).open(&path)
+    };
+    let file = open().map_err(|err| crate::Error::LogFileError {
+        path: path.clone(),
+        reason: err.to_string(),
+    })?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Append `text` to the log file, if there is one, every line after the time and without colors
+pub fn write_to_log_file(text: &str) {
+    let file = match LOG_FILE.get() {
+        Some(file) => file,
+        None => return,
+    };
+
+    let time = humantime::format_rfc3339_seconds(SystemTime::now());
+    let lines: String =
+        strip_colors(text).lines().map(|line| format!("{} {}\n", time, line)).collect();
+    // There is nowhere left to report failures to write the log to
+    let _ = file.lock().unwrap().write_all(lines.as_bytes());
+}
+
+/// `text` without the escape codes of `colors`
+fn strip_colors(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('\u{1b}') {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // The codes end with 'm', like "\u{1b}[38;5;11m"
+        rest = match rest.find('m') {
+            Some(end) => &rest[end + 1..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// From the most to the least important
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn tag(self) -> &'static str {
+        match self {
+            Level::Error => "[ERROR]",
+            Level::Warning => "[WARNING]",
+            Level::Info => "[INFO]",
+            Level::Debug => "[DEBUG]",
+            Level::Trace => "[TRACE]",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Level::Error => colors::red(),
+            Level::Warning | Level::Info => colors::yellow(),
+            Level::Debug => colors::cyan(),
+            Level::Trace => colors::black(),
+        }
+    }
+}
+
+/// The most verbose level shown by default, and for modules. `None` shows nothing.
+#[derive(Debug, PartialEq, Eq)]
+struct Filter {
+    default: Option<Level>,
+    modules: Vec<(String, Option<Level>)>,
+}
+
+impl Filter {
+    /// Parse `spec`, directives that can't be parsed are returned to be reported
+    fn parse(spec: &str) -> (Self, Vec<&str>) {
+        let mut filter = Self { default: Some(Level::Info), modules: vec![] };
+        let mut invalid = vec![];
+
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => match parse_level(level) {
+                    Some(level) if !module.is_empty() => {
+                        filter.modules.push((module.to_string(), level))
+                    },
+                    _ => invalid.push(directive),
+                },
+                None => match parse_level(directive) {
+                    Some(level) => filter.default = level,
+                    // A module alone shows everything it logs
+                    None => filter.modules.push((directive.to_string(), Some(Level::Trace))),
+                },
+            }
+        }
+        (filter, invalid)
+    }
+
+    fn enabled(&self, target: &str, level: Level) -> bool {
+        self.max_level(target, VERBOSITY.get().copied()).is_some_and(|max_level| level <= max_level)
+    }
+
+    /// The level of `--quiet` or `--verbose`, given for this run, wins over the directives, and
+    /// otherwise the most specific module does
+    fn max_level(&self, target: &str, verbosity: Option<Level>) -> Option<Level> {
+        if verbosity.is_some() {
+            return verbosity;
+        }
+        self.modules
+            .iter()
+            .filter(|(module, _)| is_within(target, module))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+/// "off" is `Some(None)`, anything that isn't a level is `None`
+fn parse_level(text: &str) -> Option<Option<Level>> {
+    let level = match text.trim().to_ascii_lowercase().as_str() {
+        "off" => None,
+        "error" => Some(Level::Error),
+        "warn" | "warning" => Some(Level::Warning),
+        "info" => Some(Level::Info),
+        "debug" => Some(Level::Debug),
+        "trace" => Some(Level::Trace),
+        _ => return None,
+    };
+    Some(level)
+}
+
+/// Whether `target` is `module`, or a module inside of it
+fn is_within(target: &str, module: &str) -> bool {
+    target.strip_prefix(module).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+fn filter() -> &'static Filter {
+    FILTER.get_or_init(|| {
+        let spec = env::var(FILTER_VAR).unwrap_or_default();
+        let (filter, invalid) = Filter::parse(&spec);
+        for directive in invalid {
+            // Printed directly, the filter being built can't filter its own warnings
+            warning(format!(
+                "Ignoring '{}' in {}, it is not a valid directive.",
+                directive, FILTER_VAR
+            ))
+            .print();
+        }
+        filter
+    })
+}
+
+/// To be called once the operation is done: summarize the warnings logged during it, unless
+/// `--no-summary` was given, and fail if there were any and `--strict` was given
+pub fn finish(flags: &oof::Flags) -> crate::Result<()> {
+    let warnings = WARNINGS.lock().unwrap();
+    if warnings.is_empty() {
+        return Ok(());
+    }
+
+    if !flags.is_present("no-summary") && filter().enabled("ouch", Level::Warning) {
+        print_summary(&warnings);
+    }
+    if flags.is_present("strict") {
+        return Err(crate::Error::StrictWarnings(warnings.len()));
+    }
+    Ok(())
+}
+
+/// Warnings grouped by category, in the order the categories first showed up
+fn group_warnings(warnings: &[Warning]) -> Vec<SummaryGroup> {
+    let mut groups: Vec<SummaryGroup> = vec![];
+    for warning in warnings {
+        let index = match groups.iter().position(|group| group.category == warning.category) {
+            Some(index) => index,
+            None => {
+                groups.push(SummaryGroup { category: warning.category, count: 0, paths: vec![] });
+                groups.len() - 1
+            },
+        };
+        let group = &mut groups[index];
+        group.count += 1;
+        if let Some(path) = &warning.path {
+            group.paths.push(path.clone());
+        }
+    }
+    groups
+}
+
+fn print_summary(warnings: &[Warning]) {
+    let groups = group_warnings(warnings);
+    let plural = if warnings.len() == 1 { "" } else { "s" };
+    let counts: Vec<String> =
+        groups.iter().map(|group| format!("{} ({})", group.category, group.count)).collect();
+    let title = format!("{} warning{}: {}", warnings.len(), plural, counts.join(", "));
+
+    let mut text = title.clone();
+    for group in groups.iter().filter(|group| !group.paths.is_empty()) {
+        let listed: Vec<String> =
+            group.paths.iter().take(SUMMARY_PATHS).map(|path| format!("'{}'", path)).collect();
+        text.push_str(&format!("\n - {}: {}", group.category, listed.join(", ")));
+        if group.paths.len() > SUMMARY_PATHS {
+            text.push_str(&format!(", and {} more", group.paths.len() - SUMMARY_PATHS));
+        }
+    }
+
+    if is_json() {
+        write_to_log_file(&format!("{} {}", Level::Warning.tag(), text));
+        let summary = serde_json::json!({
+            "level": "warning",
+            "message": title,
+            "summary": { "total": warnings.len(), "groups": groups },
+        });
+        eprintln!("{}", summary);
+    } else {
+        Message::new(Level::Warning, text).print();
+    }
+}
+
+/// "src/archive/zip.rs" is logged from "ouch::archive::zip"
+fn module_of(file: &str) -> String {
+    let file = file.replace('\\', "/");
+    let file = file.rfind("src/").map_or(file.as_str(), |start| &file[start + "src/".len()..]);
+    let file = file.strip_suffix(".rs").unwrap_or(file);
+    let file = file.strip_suffix("/mod").unwrap_or(file);
+
+    match file {
+        "lib" | "main" => "ouch".to_string(),
+        module => format!("ouch::{}", module.replace('/', "::")),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Message {
+    level: Level,
+    message: String,
+    /// The file on disk the message is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    /// The archive entry the message is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    /// Source file it was logged from, for filtering
+    #[serde(skip)]
+    file: &'static str,
+    /// What warnings are grouped under in the summary
+    #[serde(skip)]
+    category: Option<&'static str>,
+}
+
+#[track_caller]
+pub fn error(message: impl ToString) -> Message {
+    Message::new(Level::Error, message)
+}
+
+#[track_caller]
+pub fn warning(message: impl ToString) -> Message {
+    Message::new(Level::Warning, message)
+}
+
+#[track_caller]
+pub fn info(message: impl ToString) -> Message {
+    Message::new(Level::Info, message)
+}
+
+#[track_caller]
+pub fn debug(message: impl ToString) -> Message {
+    Message::new(Level::Debug, message)
+}
+
+#[track_caller]
+pub fn trace(message: impl ToString) -> Message {
+    Message::new(Level::Trace, message)
+}
+
+impl Message {
+    #[track_caller]
+    fn new(level: Level, message: impl ToString) -> Self {
+        let file = Location::caller().file();
+        Self { level, message: message.to_string(), path: None, entry: None, file, category: None }
+    }
+
+    pub fn path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(to_utf(path.as_ref()));
+        self
+    }
+
+    pub fn entry(mut self, entry: impl AsRef<Path>) -> Self {
+        self.entry = Some(to_utf(entry.as_ref()));
+        self
+    }
+
+    /// What the warning is grouped under in the summary, like "special files skipped"
+    pub fn category(mut self, category: &'static str) -> Self {
+        self.category = Some(category);
+        self
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The file on disk the message is about
+    pub fn file_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// The archive entry the message is about
+    pub fn entry_path(&self) -> Option<&str> {
+        self.entry.as_deref()
+    }
+
+    /// Print the message, unless `OUCH_LOG` filters it out
+    pub fn log(&self) {
+        if let Some(sink) = SINK.with(|sink| sink.borrow().clone()) {
+            sink.log(self);
+            return;
+        }
+        if self.level == Level::Warning {
+            let path = self.path.clone().or_else(|| self.entry.clone());
+            let category = self.category.unwrap_or("other warnings");
+            WARNINGS.lock().unwrap().push(Warning { category, path });
+        }
+        if filter().enabled(&module_of(self.file), self.level) {
+            self.print();
+        }
+    }
+
+    /// Print the warning like `log`, or fail with it when `--strict` was given, for the things
+    /// that are skipped or done differently than the archive asked for
+    pub fn warn(&self) -> crate::Result<()> {
+        if STRICT.load(Ordering::SeqCst) {
+            return Err(crate::Error::StrictWarning(self.message.clone()));
+        }
+        self.log();
+        Ok(())
+    }
+
+    fn print(&self) {
+        write_to_log_file(&format!("{} {}", self.level.tag(), self.message));
+
+        if is_json() {
+            // Safe unwrap: strings always serialize
+            eprintln!("{}", serde_json::to_string(self).unwrap());
+            return;
+        }
+
+        let line = format!(
+            "{}{}{} {}",
+            self.level.color(),
+            self.level.tag(),
+            colors::reset(),
+            self.message
+        );
+        // Only informational messages go to stdout, like the output of ouch. Once it was closed,
+        // like by `| head`, they are dropped and the run goes on
+        if self.level == Level::Info {
+            let _ = utils::write_stdout(line + "\n");
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_messages() {
+        let message = warning("Skipped a file").path("a.tar").entry("dir/file");
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"level":"warning","message":"Skipped a file","path":"a.tar","entry":"dir/file"}"#
+        );
+        let message = info("Done");
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"level":"info","message":"Done"}"#
+        );
+    }
+
+    #[test]
+    fn test_group_warnings() {
+        let warning =
+            |category, path: Option<&str>| Warning { category, path: path.map(String::from) };
+        let warnings = [
+            warning("special files skipped", Some("fifo")),
+            warning("other warnings", None),
+            warning("special files skipped", Some("socket")),
+        ];
+        assert_eq!(
+            group_warnings(&warnings),
+            [
+                SummaryGroup {
+                    category: "special files skipped",
+                    count: 2,
+                    paths: vec!["fifo".into(), "socket".into()],
+                },
+                SummaryGroup { category: "other warnings", count: 1, paths: vec![] },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_colors() {
+        let text = format!(
+            "{}[INFO]{} done, {}no{} colors",
+            colors::yellow(),
+            colors::reset(),
+            colors::red(),
+            colors::reset()
+        );
+        assert_eq!(strip_colors(&text), "[INFO] done, no colors");
+        assert_eq!(strip_colors("plain"), "plain");
+    }
+
+    #[test]
+    fn test_filter() {
+        let (filter, invalid) = Filter::parse("");
+        assert!(invalid.is_empty());
+        assert!(filter.enabled("ouch::commands", Level::Info));
+        assert!(!filter.enabled("ouch::commands", Level::Debug));
+
+        let (filter, invalid) = Filter::parse(
+            "debug, ouch::archive=warn,ouch::archive::zip=trace,ouch::bench,=info,x=y",
+        );
+        assert_eq!(invalid, ["=info", "x=y"]);
+        assert!(filter.enabled("ouch::commands", Level::Debug));
+        assert!(!filter.enabled("ouch::commands", Level::Trace));
+        assert!(!filter.enabled("ouch::archive::tar", Level::Info));
+        assert!(filter.enabled("ouch::archive::tar", Level::Warning));
+        assert!(filter.enabled("ouch::archive::zip", Level::Trace));
+        assert!(filter.enabled("ouch::bench", Level::Trace));
+        // Not inside of ouch::bench
+        assert!(!filter.enabled("ouch::benchmarks", Level::Trace));
+
+        let (filter, _) = Filter::parse("off,ouch::commands=error");
+        assert!(!filter.enabled("ouch::list", Level::Error));
+        assert!(filter.enabled("ouch::commands", Level::Error));
+        assert!(!filter.enabled("ouch::commands", Level::Warning));
+
+        // --quiet and --verbose win over the modules too
+        let (filter, _) = Filter::parse("info,ouch::archive::zip=trace,ouch::list=off");
+        assert_eq!(filter.max_level("ouch::archive::zip", Some(Level::Error)), Some(Level::Error));
+        assert_eq!(filter.max_level("ouch::list", Some(Level::Debug)), Some(Level::Debug));
+        assert_eq!(filter.max_level("ouch::list", None), None);
+    }
+
+    #[test]
+    fn test_module_of() {
+        assert_eq!(module_of("src/archive/zip.rs"), "ouch::archive::zip");
+        assert_eq!(module_of("src/utils/logger.rs"), "ouch::utils::logger");
+        assert_eq!(module_of("src/lib.rs"), "ouch");
+        assert_eq!(module_of("crates/ouch/src/commands.rs"), "ouch::commands");
+        assert_eq!(module_of("src\\archive\\tar.rs"), "ouch::archive::tar");
+        assert_eq!(module_of(info("here").file), "ouch::utils::logger");
+    }
+}
@@ -0,0 +1,225 @@
+//! `--sparse`: blocks of zeroes in the extracted files are left as holes instead of written out,
+//! like in the disk images and databases that are usually archived.
+//!
+//! Short runs of zeroes are still written out, as many small holes fragment the file, like `dd
+//! conv=sparse` does. `--sparse-threshold` sets how long a run has to be.
+
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::logger;
+use crate::{oof, utils};
+
+/// Only whole blocks of zeroes become holes, filesystems allocate space block by block
+const BLOCK_SIZE: usize = 4096;
+
+/// Whether the fallback was already warned about, once is enough for the whole operation
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// With `--sparse`, the shortest run of zeroes left as a hole in the extracted files, one block
+/// unless set with `--sparse-threshold BYTES`. `None` when holes aren't left at all.
+pub fn from_flags(flags: &oof::Flags) -> crate::Result<Option<u64>> {
+    match (flags.is_present("sparse"), flags.is_present("no-sparse")) {
+        (true, true) => Err(crate::Error::ConflictingFlags("sparse", "no-sparse")),
+        (true, false) => match flags.arg("sparse-threshold") {
+            Some(threshold) => {
+                let threshold = threshold
+                    .to_str()
+                    .and_then(utils::parse_size)
+                    .ok_or(crate::Error::InvalidInput)?;
+                Ok(Some(threshold))
+            },
+            None => Ok(Some(BLOCK_SIZE as u64)),
+        },
+        (false, _) => Ok(None),
+    }
+}
+
+/// Writer to a file that punches holes where whole blocks of zeroes would be written.
+///
+/// The zeroes are held back until the data after them, or the end of the file, shows whether
+/// they are at least `threshold` bytes long, they are written out otherwise. `finish` must be
+/// called once everything was written, the file may be missing its trailing zeroes otherwise.
+pub struct SparseWriter {
+    file: fs::File,
+    /// The shortest hole punched, when `None` everything is written as is
+    threshold: Option<u64>,
+    position: u64,
+    /// Where the zeroes not written yet start, they end at `position`
+    hole_start: Option<u64>,
+}
+
+impl SparseWriter {
+    pub fn new(file: fs::File, threshold: Option<u64>) -> Self {
+        Self { file, threshold, position: 0, hole_start: None }
+    }
+
+    /// Punch the hole at the end of the file, if there is one
+    pub fn finish(mut self) -> io::Result<()> {
+        self.end_hole()
+    }
+
+    /// Punch the pending hole, or write it out as zeroes when the filesystem can't
+    fn end_hole(&mut self) -> io::Result<()> {
+        let start = match self.hole_start.take() {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+
+        let len = self.position - start;
+        if self.threshold.is_some_and(|threshold| len < threshold) {
+            return self.write_zeroes(start);
+        }
+
+        // The file grows over the hole before the hole is punched into it
+        self.file.set_len(self.file.metadata()?.len().max(self.position))?;
+        match punch_hole(&self.file, start, len) {
+            Ok(()) => {},
+            Err(err) if is_unsupported(&err) => {
+                if !WARNED.swap(true, Ordering::SeqCst) {
+                    logger::warning(format!(
+                        "Could not punch holes in the extracted files ({}), writing out their \
+                         zeroes instead.",
+                        err
+                    ))
+                    .category("holes written as zeroes")
+                    .log();
+                }
+                self.threshold = None;
+                return self.write_zeroes(start);
+            },
+            Err(err) => return Err(err),
+        }
+        self.file.seek(SeekFrom::Start(self.position))?;
+        Ok(())
+    }
+
+    /// Write out the zeroes from `start` up to the current position
+    fn write_zeroes(&mut self, start: u64) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(start))?;
+        io::copy(&mut io::repeat(0).take(self.position - start), &mut self.file)?;
+        Ok(())
+    }
+}
+
+impl Write for SparseWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.threshold.is_none() {
+            let written = self.file.write(buf)?;
+            self.position += written as u64;
+            return Ok(written);
+        }
+
+        // Up to the next block boundary, nothing before it can be a whole block
+        let misalignment = (self.position % BLOCK_SIZE as u64) as usize;
+        let data_len = if misalignment != 0 {
+            buf.len().min(BLOCK_SIZE - misalignment)
+        } else if buf.len() >= BLOCK_SIZE && is_zeroes(&buf[..BLOCK_SIZE]) {
+            self.hole_start.get_or_insert(self.position);
+            self.position += BLOCK_SIZE as u64;
+            return Ok(BLOCK_SIZE);
+        } else {
+            // Up to the next block of zeroes, written all at once
+            buf.chunks(BLOCK_SIZE)
+                .take_while(|block| block.len() < BLOCK_SIZE || !is_zeroes(block))
+                .map(<[u8]>::len)
+                .sum()
+        };
+
+        self.end_hole()?;
+        let written = self.file.write(&buf[..data_len])?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn is_zeroes(block: &[u8]) -> bool {
+    block.iter().all(|&byte| byte == 0)
+}
+
+fn is_unsupported(err: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    if matches!(err.raw_os_error(), Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS)) {
+        return true;
+    }
+    err.kind() == io::ErrorKind::Unsupported
+}
+
+/// Deallocate `len` bytes of `file` from `offset`, keeping its size
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &fs::File, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    // Safety: the file descriptor stays open while `file` is borrowed
+    let result = unsafe {
+        libc::fallocate(file.as_raw_fd(), mode, offset as libc::off_t, len as libc::off_t)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &fs::File, _offset: u64, _len: u64) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "hole punching is only supported on Linux"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sparse");
+
+        let mut contents = vec![0; BLOCK_SIZE * 8];
+        contents[10] = 1;
+        contents[BLOCK_SIZE * 4 + 100] = 2;
+
+        let mut writer =
+            SparseWriter::new(fs::File::create(&path).unwrap(), Some(BLOCK_SIZE as u64));
+        // Written in pieces that don't line up with the blocks
+        for piece in contents.chunks(BLOCK_SIZE * 3 / 2) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), contents);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sparse_threshold() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sparse");
+
+        // Data, 2 blocks of zeroes, data, 6 blocks of zeroes and data again
+        let mut contents = vec![0; BLOCK_SIZE * 11];
+        for block in [0, 3, 10] {
+            contents[BLOCK_SIZE * block] = 1;
+        }
+
+        let threshold = BLOCK_SIZE as u64 * 4;
+        let mut writer = SparseWriter::new(fs::File::create(&path).unwrap(), Some(threshold));
+        writer.write_all(&contents).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), contents);
+        // Only the longer run is a hole, blocks are counted in units of 512 bytes
+        let allocated = fs::metadata(&path).unwrap().blocks() * 512;
+        assert!(allocated >= BLOCK_SIZE as u64 * 5, "{} bytes allocated", allocated);
+        assert!(allocated < BLOCK_SIZE as u64 * 11, "{} bytes allocated", allocated);
+    }
+}
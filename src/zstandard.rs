@@ -0,0 +1,260 @@
+//! Long distance matching of Zstandard, with `--zstd-long`, like `zstd --long` does, and
+//! dictionaries, with `--dict` and `ouch dict-train`.
+//!
+//! Matches are looked for as far back as the window, 128 MiB by default and up to 1 GiB, which
+//! pays off on large files that repeat themselves far apart, at the cost of as much memory to
+//! decompress them.
+//!
+//! A dictionary holds what many small files of a kind have in common, like the keys of JSON
+//! documents, so that each one compresses well on its own. The same dictionary is needed to
+//! decompress them, the frames name it by its ID.
+
+use std::{
+    convert::TryInto,
+    fs,
+    io::{BufRead, Read, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use walkdir::WalkDir;
+use zstd::zstd_safe;
+
+use crate::{
+    error::FinalError,
+    extension::CompressionFormat,
+    oof,
+    utils::{self, logger, to_utf},
+};
+
+/// The window of `--zstd-long` without a value, and the largest one decompressed without it
+pub const DEFAULT_WINDOW_LOG: u32 = 27;
+const WINDOW_LOGS: RangeInclusive<u32> = 10..=30;
+const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// The default of `zstd --train`, 110 KiB
+const DEFAULT_MAX_DICT_SIZE: u64 = 112_640;
+
+/// Options of the encoder and decoder, from the flags
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// log2 of the window size, given with `--zstd-long`
+    window_log: Option<u32>,
+    /// The contents of the `--dict` file
+    dictionary: Option<Arc<[u8]>>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &oof::Flags) -> crate::Result<Self> {
+        let window_log = match flags.arg("zstd-long") {
+            Some(value) => {
+                let window_log = value.to_str().and_then(|value| value.parse().ok());
+                match window_log.filter(|window_log| WINDOW_LOGS.contains(window_log)) {
+                    Some(window_log) => Some(window_log),
+                    None => {
+                        return Err(FinalError::with_title(format!(
+                            "Invalid window log '{}' for --zstd-long.",
+                            value.to_string_lossy()
+                        ))
+                        .detail("It must be between 10 and 30, for windows of 1 KiB to 1 GiB.")
+                        .hint("Use --zstd-long without a value for the default of 27, 128 MiB.")
+                        .into())
+                    },
+                }
+            },
+            None if flags.is_present("zstd-long") => Some(DEFAULT_WINDOW_LOG),
+            None => None,
+        };
+        let dictionary = match flags.arg("dict") {
+            Some(path) => Some(read_dictionary(Path::new(path))?),
+            None => None,
+        };
+        Ok(Self { window_log, dictionary })
+    }
+
+    /// The encoder, at the default level of the zstd command, with the checksum it writes too
+    pub fn encoder(&self, writer: Box<dyn Write>) -> crate::Result<Box<dyn Write>> {
+        let mut encoder = match &self.dictionary {
+            Some(dictionary) => {
+                zstd::stream::write::Encoder::with_dictionary(writer, 3, dictionary)?
+            },
+            None => zstd::stream::write::Encoder::new(writer, 3)?,
+        };
+        encoder.include_checksum(true)?;
+        if let Some(window_log) = self.window_log {
+            encoder.long_distance_matching(true)?;
+            encoder.window_log(window_log)?;
+        }
+        Ok(Box::new(encoder.auto_finish()))
+    }
+
+    /// The decoder, concatenated frames are decoded one after the other, like `zstd -d` does.
+    ///
+    /// Frames with a window larger than 128 MiB need `--zstd-long` with at least as large a window,
+    /// and frames compressed with a dictionary need it in `--dict`.
+    pub fn decoder<R: BufRead + 'static>(&self, mut input: R) -> crate::Result<Box<dyn Read>> {
+        let window_log_max =
+            self.window_log.map_or(DEFAULT_WINDOW_LOG, |log| log.max(DEFAULT_WINDOW_LOG));
+        // Checked on the first frame, to tell how large a window and which dictionary it needs
+        let header = input.fill_buf()?;
+        if let Some(window_size) = window_size(header) {
+            if window_size > 1 << window_log_max {
+                return Err(window_too_large(window_size));
+            }
+        }
+        if let Some(dictionary_id) = zstd_safe::get_dict_id_from_frame(header) {
+            let given_id = self.dictionary.as_deref().and_then(zstd_safe::get_dict_id_from_dict);
+            if given_id != Some(dictionary_id) {
+                return Err(missing_dictionary(dictionary_id.get(), given_id.is_some()));
+            }
+        }
+        let mut decoder = match &self.dictionary {
+            Some(dictionary) => zstd::stream::read::Decoder::with_dictionary(input, dictionary)?,
+            None => zstd::stream::read::Decoder::with_buffer(input)?,
+        };
+        decoder.window_log_max(window_log_max)?;
+        Ok(Box::new(decoder))
+    }
+}
+
+fn window_too_large(window_size: u64) -> crate::Error {
+    // Rounded up to a power of two
+    let window_log = u64::BITS - (window_size - 1).leading_zeros();
+    let mut error = FinalError::with_title(format!(
+        "Decompressing this .zst takes a window of {}.",
+        utils::format_size(window_size)
+    ));
+    error.detail("It was compressed with long distance matching, like `zstd --long` does.");
+    let error = if WINDOW_LOGS.contains(&window_log) {
+        error.hint(format!("Use --zstd-long={} to allow that much memory to be used.", window_log))
+    } else {
+        error.detail("The largest window supported is 1 GiB.")
+    };
+    error.into()
+}
+
+/// Warn that `--zstd-long` does nothing when the output, in `formats`, isn't compressed with .zst
+pub fn warn_unused_flags(formats: &[CompressionFormat], flags: &oof::Flags) {
+    if flags.is_present("zstd-long") && !formats.contains(&CompressionFormat::Zstd) {
+        logger::warning("Ignoring --zstd-long, the output is not compressed with .zst.").log();
+    }
+}
+
+fn missing_dictionary(dictionary_id: u32, other_given: bool) -> crate::Error {
+    let mut error = FinalError::with_title(format!(
+        "Decompressing this .zst takes the dictionary {}.",
+        dictionary_id
+    ));
+    if other_given {
+        error.detail("The dictionary given with --dict is another one.");
+    } else {
+        error
+            .detail("It was compressed with that dictionary, it can't be decompressed without it.");
+    }
+    error.hint("Use --dict FILE with the dictionary it was compressed with.").into()
+}
+
+/// The contents of the dictionary at `path`
+fn read_dictionary(path: &Path) -> crate::Result<Arc<[u8]>> {
+    match fs::read(path) {
+        Ok(dictionary) => Ok(dictionary.into()),
+        Err(err) => {
+            Err(FinalError::with_title(format!("Cannot read the dictionary '{}'.", to_utf(path)))
+                .detail(err.to_string())
+                .into())
+        },
+    }
+}
+
+/// `ouch dict-train`: trains a dictionary on `files`, and on the files in the folders among them,
+/// of at most `--max-dict-size` bytes
+pub fn train_dictionary(
+    files: &[PathBuf],
+    output_path: &Path,
+    flags: &oof::Flags,
+) -> crate::Result<()> {
+    if output_path.exists() && !utils::user_wants_to_overwrite(output_path, flags)? {
+        return Err(crate::Error::OverwriteDeclined(output_path.to_path_buf()));
+    }
+
+    let max_size = match flags.arg("max-dict-size") {
+        Some(size) => {
+            size.to_str().and_then(utils::parse_size).ok_or(crate::Error::InvalidInput)?
+        },
+        None => DEFAULT_MAX_DICT_SIZE,
+    };
+
+    let mut samples = vec![];
+    for file in files {
+        for entry in WalkDir::new(file).sort_by_file_name() {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                samples.push(entry.into_path());
+            }
+        }
+    }
+
+    let dictionary = zstd::dict::from_files(&samples, max_size as usize).map_err(|err| {
+        crate::Error::from(
+            FinalError::with_title("Could not train the dictionary.")
+                .detail(err.to_string())
+                .hint("Train it on more files, or lower --max-dict-size."),
+        )
+    })?;
+    fs::write(output_path, &dictionary)?;
+
+    logger::info(format!(
+        "Successfully trained '{}' on {} files, {}.",
+        to_utf(output_path),
+        samples.len(),
+        utils::format_size(dictionary.len() as u64)
+    ))
+    .path(output_path)
+    .log();
+    Ok(())
+}
+
+/// The window size given in the header of the frame that `data` starts with, if it holds one
+fn window_size(data: &[u8]) -> Option<u64> {
+    let descriptor = *data.strip_prefix(&MAGIC)?.first()?;
+    let single_segment = descriptor & 0b0010_0000 != 0;
+    if !single_segment {
+        let window_descriptor = *data.get(5)?;
+        let window_log = 10 + u32::from(window_descriptor >> 3);
+        let window_base = 1u64 << window_log;
+        return Some(window_base + (window_base / 8) * u64::from(window_descriptor & 0b111));
+    }
+
+    // The window of a frame in a single segment is its content, whose size comes after the
+    // dictionary ID
+    let dictionary_id_size = [0, 1, 2, 4][usize::from(descriptor & 0b11)];
+    let content_size = &data.get(5 + dictionary_id_size..)?;
+    let content_size = match descriptor >> 6 {
+        0 => u64::from(*content_size.first()?),
+        1 => u64::from(u16::from_le_bytes([*content_size.first()?, *content_size.get(1)?])) + 256,
+        2 => u64::from(u32::from_le_bytes(content_size.get(..4)?.try_into().ok()?)),
+        _ => u64::from_le_bytes(content_size.get(..8)?.try_into().ok()?),
+    };
+    Some(content_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_size() {
+        let compressed = |data: &[u8], window_log| {
+            let mut encoder = zstd::stream::write::Encoder::new(vec![], 3).unwrap();
+            encoder.long_distance_matching(true).unwrap();
+            encoder.window_log(window_log).unwrap();
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        assert_eq!(window_size(&compressed(b"data", 30)), Some(1 << 30));
+        assert_eq!(window_size(&compressed(b"data", 20)), Some(1 << 20));
+        // Compressed in one go, in a single segment
+        assert_eq!(window_size(&zstd::bulk::compress(&[0; 300], 3).unwrap()), Some(300));
+        assert_eq!(window_size(b"not zstd"), None);
+    }
+}
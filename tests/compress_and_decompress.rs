@@ -18,6 +18,12 @@ fn test_each_format() {
     test_compressing_and_decompressing_archive("tar.xz");
     test_compressing_and_decompressing_archive("tar.lz");
     test_compressing_and_decompressing_archive("tar.lzma");
+    test_compressing_and_decompressing_archive("tar.sz");
+    test_compressing_and_decompressing_archive("tsnap");
+    test_compressing_and_decompressing_archive("tar.zst");
+    test_compressing_and_decompressing_archive("tzst");
+    test_compressing_and_decompressing_archive("tar.lz4");
+    test_compressing_and_decompressing_archive("tar.lz4block");
     test_compressing_and_decompressing_archive("zip");
     test_compressing_and_decompressing_archive("zip.gz");
     test_compressing_and_decompressing_archive("zip.bz");
@@ -25,6 +31,10 @@ fn test_each_format() {
     test_compressing_and_decompressing_archive("zip.xz");
     test_compressing_and_decompressing_archive("zip.lz");
     test_compressing_and_decompressing_archive("zip.lzma");
+    test_compressing_and_decompressing_archive("zip.sz");
+    test_compressing_and_decompressing_archive("zip.zst");
+    test_compressing_and_decompressing_archive("zip.lz4");
+    test_compressing_and_decompressing_archive("zip.lz4block");
 
     // Why not
     test_compressing_and_decompressing_archive("tar.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.gz.lz.lz.lz.lz.lz.lz.lz.lz.lz.lz.bz.bz.bz.bz.bz.bz.bz");
@@ -52,9 +62,9 @@ fn test_compressing_and_decompressing_archive(format: &str) {
         (0..quantity_of_files).map(|_| generate_random_file_content(&mut rng)).collect();
 
     // Create them
-    let mut file_paths = create_files(&testing_dir_path, &contents_of_files);
+    let mut file_paths = create_files(testing_dir_path, &contents_of_files);
     // Compress them
-    let compressed_archive_path = compress_files(&testing_dir_path, &file_paths, &format);
+    let compressed_archive_path = compress_files(testing_dir_path, &file_paths, format);
     // Decompress them
     let mut extracted_paths = extract_files(&compressed_archive_path);
 
@@ -125,7 +135,8 @@ fn extract_files(archive_path: &Path) -> Vec<PathBuf> {
     };
     run(command, &oof::Flags::default()).expect("Failed to extract");
 
-    fs::read_dir(extraction_output_folder)
+    // The files aren't in a single folder, so they are extracted into one named after the archive
+    fs::read_dir(extraction_output_folder.join("archive"))
         .unwrap()
         .map(Result::unwrap)
         .map(|entry| entry.path())
@@ -157,3 +168,3500 @@ fn compare_file_contents(extracted: &[PathBuf], contents: &[FileContent], format
         );
     });
 }
+
+/// A temporary folder for a test, removed once dropped
+fn testing_dir() -> tempfile::TempDir {
+    tempfile::Builder::new()
+        .prefix("ouch-testing")
+        .tempdir_in(env::temp_dir())
+        .expect("Could not create testing_dir")
+}
+
+#[test]
+/// Empty directories survive a roundtrip through .zip.
+fn test_zip_keeps_empty_directories() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("source");
+    fs::create_dir_all(source.join("empty")).unwrap();
+    fs::create_dir_all(source.join("nested").join("also_empty")).unwrap();
+    fs::create_dir_all(source.join("with_file").join("empty_sibling")).unwrap();
+    fs::write(source.join("with_file").join("file.txt"), b"contents").unwrap();
+
+    let archive_path = testing_dir.join("source.zip");
+    let command =
+        Command::Compress { files: vec![source.clone()], output_path: archive_path.clone() };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+
+    let output = testing_dir.join("output");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+
+    assert_eq!(list_tree(&source), list_tree(&output.join("source")));
+}
+
+#[cfg(unix)]
+#[test]
+/// A file with three names is stored once in .tar, and extracted as three hard links.
+fn test_tar_keeps_hard_links() {
+    use std::os::unix::fs::MetadataExt;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("source");
+    fs::create_dir_all(source.join("sub")).unwrap();
+    fs::write(source.join("a"), b"linked").unwrap();
+    fs::hard_link(source.join("a"), source.join("b")).unwrap();
+    fs::hard_link(source.join("a"), source.join("sub").join("c")).unwrap();
+
+    let archive_path = testing_dir.join("source.tar");
+    let command = Command::Compress { files: vec![source], output_path: archive_path.clone() };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+
+    let output = testing_dir.join("output");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+
+    let names = ["a", "b", "sub/c"];
+    let inodes: Vec<u64> = names
+        .iter()
+        .map(|name| fs::metadata(output.join("source").join(name)).unwrap().ino())
+        .collect();
+    assert!(inodes.iter().all(|inode| *inode == inodes[0]));
+    assert_eq!(fs::metadata(output.join("source").join("a")).unwrap().nlink(), 3);
+    assert_eq!(fs::read(output.join("source").join("sub").join("c")).unwrap(), b"linked");
+}
+
+#[cfg(unix)]
+#[test]
+/// Hard links are only made to files extracted from the archive, never to other files.
+fn test_tar_hard_link_targets() {
+    use std::os::unix::fs::MetadataExt;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let mut builder = tar::Builder::new(vec![]);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(6);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, "./a", &b"linked"[..]).unwrap();
+    for (path, target) in [("b", "a"), ("c", "./b"), ("d", "existing"), ("e", "../outside")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Link);
+        header.set_size(0);
+        builder.append_link(&mut header, path, target).unwrap();
+    }
+    let archive_path = testing_dir.join("links.tar");
+    fs::write(&archive_path, builder.into_inner().unwrap()).unwrap();
+
+    let output = testing_dir.join("output");
+    fs::create_dir(&output).unwrap();
+    fs::write(output.join("existing"), b"not from the archive").unwrap();
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    // Right next to the existing file
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("no-smart-unpack");
+    run(command, &flags).expect("Failed to extract");
+
+    assert_eq!(fs::metadata(output.join("a")).unwrap().nlink(), 3);
+    assert_eq!(fs::read(output.join("c")).unwrap(), b"linked");
+    assert_eq!(fs::metadata(output.join("existing")).unwrap().nlink(), 1);
+    assert!(!output.join("d").exists());
+    assert!(!output.join("e").exists());
+}
+
+#[cfg(unix)]
+#[test]
+/// Entries written through a symbolic link that leads out of the output folder, extracted from
+/// the same archive before them, are refused.
+fn test_path_traversal() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let outside = testing_dir.join("outside");
+    fs::create_dir(&outside).unwrap();
+
+    let mut builder = tar::Builder::new(vec![]);
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "link", &outside).unwrap();
+    for path in ["link/evil.txt", "safe.txt"] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, &b"evil"[..]).unwrap();
+    }
+    let archive_path = testing_dir.join("slip.tar");
+    fs::write(&archive_path, builder.into_inner().unwrap()).unwrap();
+
+    let output = testing_dir.join("output");
+    let command = Command::Decompress {
+        files: vec![archive_path.clone()],
+        output_folder: Some(output.clone()),
+    };
+    let err = run(command, &oof::Flags::default()).unwrap_err();
+    let entry = output.join("slip/link/evil.txt").to_str().unwrap().to_string();
+    assert_eq!(err, ouch::Error::PathTraversal { entry });
+    assert_eq!(err.exit_code(), ouch::EXIT_CORRUPT_ARCHIVE);
+    assert!(!outside.join("evil.txt").exists());
+
+    // The other entries are still extracted with --continue-on-error
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("continue-on-error");
+    flags.boolean_flags.insert("yes");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    let err = run(command, &flags).unwrap_err();
+    assert!(matches!(err, ouch::Error::EntriesFailed { .. }));
+    assert_eq!(fs::read(output.join("slip/safe.txt")).unwrap(), b"evil");
+    assert!(!outside.join("evil.txt").exists());
+}
+
+#[test]
+/// Entries of .zip archives named to be written out of the output folder are refused like those
+/// of .tar archives, or skipped with --continue-on-error.
+fn test_zip_path_traversal() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default();
+    for name in ["../evil.txt", "safe.txt"] {
+        writer.start_file(name, options).unwrap();
+        writer.write_all(b"evil").unwrap();
+    }
+    fs::write(testing_dir.join("slip.zip"), writer.finish().unwrap().into_inner()).unwrap();
+
+    let decompress = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["slip.zip", "--output", "output", "--yes"])
+            .args(args)
+            .current_dir(&testing_dir)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap()
+    };
+    let output = decompress(&[]);
+    assert_eq!(output.status.code(), Some(ouch::EXIT_CORRUPT_ARCHIVE));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Refusing to write"), "{}", stderr);
+    assert!(!testing_dir.join("evil.txt").exists());
+
+    let output = decompress(&["--continue-on-error"]);
+    assert_eq!(output.status.code(), Some(ouch::EXIT_PARTIAL_FAILURE));
+    assert_eq!(fs::read(testing_dir.join("output/slip/safe.txt")).unwrap(), b"evil");
+    assert!(!testing_dir.join("evil.txt").exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[ignore = "compresses 1 GiB, run with `cargo test --release -- --ignored`"]
+/// Tar archives are written while the inputs are read, without holding them in memory.
+fn test_tar_compression_memory_stays_bounded() {
+    const GIB: u64 = 1024 * 1024 * 1024;
+    const MAX_RESIDENT_MEMORY_KIB: u64 = 128 * 1024;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // Sparse files, they take no space on disk but are read as zeroes
+    let source = testing_dir.join("source");
+    fs::create_dir(&source).unwrap();
+    for name in ["first.img", "second.img"] {
+        fs::File::create(source.join(name)).unwrap().set_len(GIB / 2).unwrap();
+    }
+
+    let archive_path = testing_dir.join("source.tar.gz");
+    let command = Command::Compress { files: vec![source], output_path: archive_path };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+
+    // Peak resident memory of this process, in KiB
+    let status = fs::read_to_string("/proc/self/status").unwrap();
+    let peak_memory: u64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap();
+    assert!(peak_memory < MAX_RESIDENT_MEMORY_KIB, "peak memory: {} KiB", peak_memory);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+/// The holes of sparse files are neither stored in .tar nor written out when extracting.
+fn test_tar_sparse_files() {
+    use std::{io::SeekFrom, os::unix::fs::MetadataExt};
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+    const MIB: u64 = 1024 * 1024;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // 1 GiB file with 1 MiB of data in the middle of it
+    let source = testing_dir.join("source");
+    fs::create_dir(&source).unwrap();
+    let mut disk_image = fs::File::create(source.join("disk.img")).unwrap();
+    disk_image.set_len(GIB).unwrap();
+    disk_image.seek(SeekFrom::Start(GIB / 2)).unwrap();
+    disk_image.write_all(&vec![0xaa; MIB as usize]).unwrap();
+    drop(disk_image);
+
+    let archive_path = testing_dir.join("source.tar");
+    let command = Command::Compress { files: vec![source], output_path: archive_path.clone() };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+    assert!(fs::metadata(&archive_path).unwrap().len() < 2 * MIB);
+
+    let output = testing_dir.join("output");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+
+    let extracted = fs::metadata(output.join("source").join("disk.img")).unwrap();
+    assert_eq!(extracted.len(), GIB);
+    // Blocks are counted in units of 512 bytes
+    assert!(extracted.blocks() * 512 < 4 * MIB, "{} blocks allocated", extracted.blocks());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+/// With `--sparse`, the zeroes of files extracted from .zip are left as holes.
+fn test_sparse_extraction() {
+    use std::os::unix::fs::MetadataExt;
+
+    const MIB: usize = 1024 * 1024;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // 16 MiB of zeroes, with a little data at the start and at the end
+    let mut contents = vec![0; 16 * MIB];
+    contents[..1000].fill(0xaa);
+    contents[16 * MIB - 10..].fill(0xbb);
+    let source = testing_dir.join("source");
+    fs::create_dir(&source).unwrap();
+    fs::write(source.join("disk.img"), &contents).unwrap();
+
+    let archive_path = testing_dir.join("source.zip");
+    let command = Command::Compress { files: vec![source], output_path: archive_path.clone() };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+
+    let output = testing_dir.join("output");
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("sparse");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    run(command, &flags).expect("Failed to extract");
+
+    let extracted = output.join("source").join("disk.img");
+    assert_eq!(fs::read(&extracted).unwrap(), contents);
+    // Blocks are counted in units of 512 bytes
+    let blocks = fs::metadata(&extracted).unwrap().blocks();
+    assert!(blocks * 512 < MIB as u64, "{} blocks allocated", blocks);
+}
+
+#[test]
+/// The same files, created in a different order and at different times, give identical archives.
+fn test_reproducible_archives() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let files: [(&str, &[u8]); 3] =
+        [("b.txt", b"second"), ("a.txt", b"first"), ("sub/c.txt", b"third")];
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("reproducible");
+
+    for format in ["tar.gz", "tar.bz2", "tar.xz", "zip", "cpio"] {
+        let archives: Vec<Vec<u8>> = [0, 1]
+            .iter()
+            .map(|&attempt| {
+                let source = testing_dir.join(format!("{}-{}", format, attempt)).join("source");
+                fs::create_dir_all(source.join("sub")).unwrap();
+                let ordered: Vec<_> = if attempt == 0 {
+                    files.iter().collect()
+                } else {
+                    files.iter().rev().collect()
+                };
+                for (index, (name, contents)) in ordered.into_iter().enumerate() {
+                    fs::write(source.join(name), contents).unwrap();
+                    let mtime = std::time::UNIX_EPOCH
+                        + std::time::Duration::from_secs(1_000_000 * (index as u64 + attempt + 1));
+                    fs::File::options()
+                        .write(true)
+                        .open(source.join(name))
+                        .unwrap()
+                        .set_modified(mtime)
+                        .unwrap();
+                }
+
+                let archive_path = source.with_extension(format);
+                let command =
+                    Command::Compress { files: vec![source], output_path: archive_path.clone() };
+                run(command, &flags).expect("Failed to compress");
+                fs::read(archive_path).unwrap()
+            })
+            .collect();
+
+        assert_eq!(archives[0], archives[1], "{} archives differ", format);
+    }
+}
+
+#[test]
+/// `--mtime` sets the modification time stored for every entry, given as an RFC 3339 date or as
+/// seconds since the Unix epoch, with or without `--reproducible`.
+fn test_reproducible_mtime() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let source = testing_dir.join("source");
+    fs::create_dir(&source).unwrap();
+    for (name, mtime) in [("old.txt", 1_000), ("new.txt", 2_000_000_000)] {
+        let path = source.join(name);
+        fs::write(&path, name).unwrap();
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+    }
+
+    let compress = |output: &str, mtime: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["compress", "source", output, "--reproducible", "--mtime", mtime])
+            .current_dir(&testing_dir)
+            .stdin(std::process::Stdio::null())
+            .status()
+            .unwrap()
+    };
+    for (output, mtime) in [
+        ("seconds.tar.gz", "1000000000"),
+        ("epoch.tar.gz", "@1000000000"),
+        ("date.tar.gz", "2001-09-09T01:46:40Z"),
+        ("offset.tar.gz", "2001-09-09T03:46:40+02:00"),
+    ] {
+        assert!(compress(output, mtime).success());
+
+        let mut times: Vec<_> = ouch::list(&testing_dir.join(output), Default::default())
+            .map(|entry| entry.unwrap())
+            .map(|entry| (entry.path, entry.modified))
+            .collect();
+        times.sort();
+        assert_eq!(times, [
+            (PathBuf::from("source/new.txt"), Some(1_000_000_000)),
+            (PathBuf::from("source/old.txt"), Some(1_000_000_000)),
+        ]);
+    }
+    let read = |name: &str| fs::read(testing_dir.join(name)).unwrap();
+    assert!(read("seconds.tar.gz") == read("date.tar.gz"));
+
+    assert_eq!(compress("source.tar", "yesterday").code(), Some(ouch::EXIT_FAILURE));
+
+    // Without --reproducible, the modes are kept and only the times change
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        for (name, mode) in [("old.txt", 0o600), ("new.txt", 0o644)] {
+            fs::set_permissions(source.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let headers = |args: &[&str]| {
+            let status = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+                .args(["compress", "source", "mtime.tar", "--yes", "--mtime", "@1000000000"])
+                .args(args)
+                .current_dir(&testing_dir)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            let archive = fs::File::open(testing_dir.join("mtime.tar")).unwrap();
+            let mut archive = tar::Archive::new(archive);
+            let mut headers: Vec<_> = archive
+                .entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().header().clone())
+                .map(|header| {
+                    let path = header.path().unwrap().into_owned();
+                    (path, header.mode().unwrap() & 0o777, header.mtime().unwrap())
+                })
+                .collect();
+            headers.sort();
+            headers
+        };
+        assert_eq!(headers(&[]), [
+            (PathBuf::from("source/new.txt"), 0o644, 1_000_000_000),
+            (PathBuf::from("source/old.txt"), 0o600, 1_000_000_000),
+        ]);
+        assert_eq!(headers(&["--reproducible"]), [
+            (PathBuf::from("source/new.txt"), 0o644, 1_000_000_000),
+            (PathBuf::from("source/old.txt"), 0o644, 1_000_000_000),
+        ]);
+    }
+}
+
+#[test]
+/// Without --output, archives with a single folder at their root are extracted as that folder and
+/// the others into a folder named after them, `--no-smart-unpack` and `--flat` extract them where
+/// they are.
+fn test_smart_unpack() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let firmware = testing_dir.join("project/firmware/v2");
+    fs::create_dir_all(&firmware).unwrap();
+    fs::write(firmware.join("boot.bin"), "boot").unwrap();
+    fs::write(testing_dir.join("a.txt"), "a").unwrap();
+    fs::write(testing_dir.join("b.txt"), "b").unwrap();
+
+    let ouch = |args: &[&str], folder: &str| {
+        let folder = testing_dir.join(folder);
+        fs::create_dir_all(&folder).unwrap();
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .current_dir(&folder)
+            .stdin(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "{:?}", args);
+        // Without the folder itself
+        let paths = list_tree(&folder).into_iter().skip(1);
+        paths.map(|path| path.to_string_lossy().into_owned()).collect::<Vec<_>>()
+    };
+    ouch(&["compress", "project", "release-v1.2.zip"], ".");
+    ouch(&["compress", "a.txt", "b.txt", "loose.tar.gz"], ".");
+
+    // A single folder keeps its own name
+    assert_eq!(ouch(&["../release-v1.2.zip"], "single"), [
+        "project",
+        "project/firmware",
+        "project/firmware/v2",
+        "project/firmware/v2/boot.bin",
+    ]);
+    let loose = ouch(&["../loose.tar.gz"], "loose");
+    assert_eq!(loose, ["loose", "loose/a.txt", "loose/b.txt"]);
+    let here = ouch(&["../loose.tar.gz", "--no-smart-unpack"], "here");
+    assert_eq!(here, ["a.txt", "b.txt"]);
+    let flat = ouch(&["../release-v1.2.zip", "--flat"], "flat");
+    assert_eq!(flat, ["boot.bin"]);
+
+    // The same goes inside of the folder given with --output
+    let output = ouch(&["../release-v1.2.zip", "../loose.tar.gz", "-o", "out"], "output");
+    assert_eq!(output, [
+        "out",
+        "out/loose",
+        "out/loose/a.txt",
+        "out/loose/b.txt",
+        "out/project",
+        "out/project/firmware",
+        "out/project/firmware/v2",
+        "out/project/firmware/v2/boot.bin",
+    ]);
+
+    // Archives with more entries than are peeked at get their folder, even with a single root
+    let mut builder = tar::Builder::new(vec![]);
+    for index in 0..1001 {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, format!("big/{}", index), &b""[..]).unwrap();
+    }
+    fs::write(testing_dir.join("many.tar"), builder.into_inner().unwrap()).unwrap();
+    let many = ouch(&["../many.tar"], "many");
+    assert_eq!(many[..3], ["many", "many/big", "many/big/0"]);
+}
+
+#[test]
+/// With `--flat`, files with the same name in different folders are handled with `--conflict`, and
+/// symlinks are skipped.
+fn test_flat_conflicts() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // Built by hand, ouch stores the files symlinks point to
+    let mut builder = tar::Builder::new(vec![]);
+    for (path, contents) in [
+        ("samples/drums/kick.wav", "drums"),
+        ("samples/bass/kick.wav", "bass"),
+        ("samples/bass/sub.wav", "sub"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents.as_bytes()).unwrap();
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "samples/latest.wav", "bass/sub.wav").unwrap();
+    let archive = testing_dir.join("samples.tar");
+    fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+
+    let extract = |policy: &str| {
+        let output = testing_dir.join(policy);
+        fs::create_dir(&output).unwrap();
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args([archive.as_os_str(), "--flat".as_ref(), "--conflict".as_ref(), policy.as_ref()])
+            .current_dir(&output)
+            .stdin(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "{}", policy);
+        output
+    };
+
+    let renamed = extract("rename");
+    let paths: Vec<_> = list_tree(&renamed).into_iter().skip(1).collect();
+    assert_eq!(paths, ["kick (1).wav", "kick.wav", "sub.wav"].map(PathBuf::from));
+    assert_eq!(fs::read_to_string(renamed.join("kick.wav")).unwrap(), "drums");
+    assert_eq!(fs::read_to_string(renamed.join("kick (1).wav")).unwrap(), "bass");
+
+    let skipped = extract("skip");
+    let paths: Vec<_> = list_tree(&skipped).into_iter().skip(1).collect();
+    assert_eq!(paths, ["kick.wav", "sub.wav"].map(PathBuf::from));
+}
+
+#[test]
+/// .tar.sz archives are Snappy streams in the framed format, that other tools read too, and can
+/// be listed like the other compressed archives.
+fn test_tar_snappy() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+    let input = testing_dir.join("data");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("rows.csv"), "id,value\n".repeat(1000)).unwrap();
+
+    let archive = testing_dir.join("data.tar.sz");
+    ouch::compress(std::slice::from_ref(&input), &archive, Default::default()).unwrap();
+    // The stream identifier chunk, raw Snappy blocks have no header
+    let compressed = fs::read(&archive).unwrap();
+    assert!(compressed.starts_with(b"\xff\x06\x00\x00sNaPpY"));
+    assert!(compressed.len() < 10_000);
+
+    let entries: Vec<_> = ouch::list(&archive, Default::default())
+        .map(|entry| entry.unwrap().path)
+        .collect();
+    assert_eq!(entries, [PathBuf::from("data/rows.csv")]);
+
+    let output = testing_dir.join("output");
+    ouch::decompress(&[archive], Some(&output), Default::default()).unwrap();
+    let contents = fs::read_to_string(output.join("data/rows.csv")).unwrap();
+    assert_eq!(contents, "id,value\n".repeat(1000));
+}
+
+#[test]
+/// .zst files are Zstandard frames, and like `zstd -d`, the frames of files joined with `cat` are
+/// all decompressed.
+fn test_zstd() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+    let mut joined = vec![];
+    for (name, contents) in [("first.txt", "first\n"), ("second.txt", "second\n")] {
+        let input = testing_dir.join(name);
+        fs::write(&input, contents).unwrap();
+        let compressed = testing_dir.join(format!("{}.zst", name));
+        ouch::compress(&[input], &compressed, Default::default()).unwrap();
+        let compressed = fs::read(&compressed).unwrap();
+        assert!(compressed.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+        joined.extend(compressed);
+    }
+
+    let archive = testing_dir.join("joined.txt.zst");
+    fs::write(&archive, joined).unwrap();
+    let output = testing_dir.join("output");
+    fs::create_dir(&output).unwrap();
+    ouch::decompress(&[archive], Some(&output), Default::default()).unwrap();
+    assert_eq!(fs::read_to_string(output.join("joined.txt")).unwrap(), "first\nsecond\n");
+}
+
+#[test]
+/// .lz4 files are in the LZ4 frame format of the lz4 command, concatenated frames are decompressed
+/// one after the other.
+fn test_lz4() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+    let mut joined = vec![];
+    for (name, contents) in [("first.txt", "first\n"), ("second.txt", "second\n")] {
+        let input = testing_dir.join(name);
+        fs::write(&input, contents).unwrap();
+        let compressed = testing_dir.join(format!("{}.lz4", name));
+        ouch::compress(&[input], &compressed, Default::default()).unwrap();
+        let compressed = fs::read(&compressed).unwrap();
+        assert!(compressed.starts_with(&[0x04, 0x22, 0x4d, 0x18]));
+        joined.extend(compressed);
+    }
+
+    let archive = testing_dir.join("joined.txt.lz4");
+    fs::write(&archive, joined).unwrap();
+    let output = testing_dir.join("output");
+    fs::create_dir(&output).unwrap();
+    ouch::decompress(&[archive], Some(&output), Default::default()).unwrap();
+    assert_eq!(fs::read_to_string(output.join("joined.txt")).unwrap(), "first\nsecond\n");
+}
+
+#[cfg(feature = "async")]
+#[test]
+/// The async API compresses and decompresses streams with the formats of a name, an archive in
+/// them is passed through as it is.
+fn test_async_streams() {
+    use tokio::io::{AsyncRead, AsyncReadExt};
+
+    /// Everything the other end of `stream` gives
+    async fn read_all(mut stream: impl AsyncRead + Unpin) -> Vec<u8> {
+        let mut contents = vec![];
+        stream.read_to_end(&mut contents).await.unwrap();
+        contents
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let (writer, reader) = tokio::io::duplex(1024);
+        let name = Path::new("notes.txt.gz");
+        let compression = tokio::spawn(ouch::compress_async(&b"hello, world"[..], writer, name));
+        let compressed = read_all(reader).await;
+        compression.await.unwrap().unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "hello, world");
+
+        let (writer, reader) = tokio::io::duplex(1024);
+        let compressed = std::io::Cursor::new(compressed);
+        let decompression = tokio::spawn(ouch::decompress_async(compressed, writer, name));
+        assert_eq!(read_all(reader).await, b"hello, world");
+        decompression.await.unwrap().unwrap();
+
+        // The .tar comes out as it is
+        let archive = std::io::Cursor::new(create_tar_gz(&[("a.txt", b"a")]));
+        let (writer, reader) = tokio::io::duplex(1024);
+        let name = Path::new("backup.tar.gz");
+        let decompression = tokio::spawn(ouch::decompress_async(archive, writer, name));
+        let tar = read_all(reader).await;
+        decompression.await.unwrap().unwrap();
+        let mut entries = tar::Archive::new(&tar[..]);
+        let paths: Vec<PathBuf> =
+            entries.entries().unwrap().map(|entry| entry.unwrap().path().unwrap().into()).collect();
+        assert_eq!(paths, [PathBuf::from("a.txt")]);
+
+        // Nothing to compress with
+        let refused = ouch::compress_async(&b""[..], tokio::io::sink(), Path::new("backup.tar"));
+        assert!(refused.await.is_err());
+    });
+}
+
+#[test]
+/// .lz4block files are a single LZ4 block after its size, LZ4 frames and blocks are told apart by
+/// their magic bytes whatever their extension.
+fn test_lz4_block() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+    let input = testing_dir.join("data.txt");
+    fs::write(&input, "data ".repeat(100)).unwrap();
+    let block = testing_dir.join("data.txt.lz4block");
+    ouch::compress(&[input], &block, Default::default()).unwrap();
+    let block = fs::read(&block).unwrap();
+    assert_eq!(block[..4], 500u32.to_le_bytes());
+    let decompressed = lz4_flex::block::decompress_size_prepended(&block).unwrap();
+    assert_eq!(decompressed, "data ".repeat(100).as_bytes());
+
+    let mut frame = lz4_flex::frame::FrameEncoder::new(vec![]);
+    frame.write_all(b"frame").unwrap();
+    let inputs = [("block.lz4", block), ("frame.lz4block", frame.finish().unwrap())];
+    let output = testing_dir.join("output");
+    fs::create_dir(&output).unwrap();
+    for (name, contents) in &inputs {
+        fs::write(testing_dir.join(name), contents).unwrap();
+        ouch::decompress(&[testing_dir.join(name)], Some(&output), Default::default()).unwrap();
+    }
+    assert_eq!(fs::read_to_string(output.join("block")).unwrap(), "data ".repeat(100));
+    assert_eq!(fs::read_to_string(output.join("frame")).unwrap(), "frame");
+}
+
+#[test]
+/// `--zstd-long` finds repetitions further apart than the usual window, and the .zst is only
+/// decompressed with a window at least as large.
+fn test_zstd_long() {
+    const MIB: usize = 1024 * 1024;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // The same 4 MiB twice, further apart than the 2 MiB window of the default level
+    let mut block = vec![0; 4 * MIB];
+    SmallRng::seed_from_u64(7).fill_bytes(&mut block);
+    let input = testing_dir.join("dump.bin");
+    fs::write(&input, [block.as_slice(), block.as_slice()].concat()).unwrap();
+
+    let compress = |output_path: &Path, flags: &oof::Flags| {
+        let files = vec![input.clone()];
+        run(Command::Compress { files, output_path: output_path.into() }, flags)
+            .expect("Failed to compress");
+        fs::metadata(output_path).unwrap().len()
+    };
+    let mut long_flags = oof::Flags::default();
+    long_flags.argument_flags.insert("zstd-long", "30".into());
+    let plain_size = compress(&testing_dir.join("plain.bin.zst"), &oof::Flags::default());
+    let long_size = compress(&testing_dir.join("long.bin.zst"), &long_flags);
+    assert!(long_size < plain_size * 6 / 10, "{} bytes, {} without it", long_size, plain_size);
+
+    let decompress = |flags: &oof::Flags| {
+        let files = vec![testing_dir.join("long.bin.zst")];
+        let output = testing_dir.join("output");
+        fs::create_dir_all(&output).unwrap();
+        run(Command::Decompress { files, output_folder: Some(output.clone()) }, flags)
+            .map(|_| fs::read(output.join("long.bin")).unwrap())
+    };
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("yes");
+    let err = decompress(&flags).unwrap_err();
+    assert!(err.to_string().contains("--zstd-long=30"), "{}", err);
+    long_flags.boolean_flags.insert("yes");
+    assert_eq!(decompress(&long_flags).unwrap(), [block.as_slice(), block.as_slice()].concat());
+
+    // Other formats don't have it
+    fs::write(testing_dir.join("notes.txt"), "notes").unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["compress", "notes.txt", "notes.txt.gz", "--zstd-long"])
+        .current_dir(&testing_dir)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Ignoring --zstd-long"), "{}", stderr);
+}
+
+#[test]
+fn test_zstd_dict() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // Small JSON logs that only differ by a few values
+    let samples = testing_dir.join("samples");
+    fs::create_dir(&samples).unwrap();
+    let log = |id: u32| {
+        format!(
+            r#"{{"id": {}, "level": "info", "service": "checkout", "message": "took {} ms"}}"#,
+            id,
+            id * 7 % 300
+        )
+    };
+    for id in 0..500 {
+        fs::write(samples.join(format!("{}.json", id)), log(id)).unwrap();
+    }
+
+    let dictionary = testing_dir.join("logs.dict");
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("max-dict-size", "4k".into());
+    run(Command::DictTrain { files: vec![samples], output_path: dictionary.clone() }, &flags)
+        .expect("Failed to train the dictionary");
+    let dictionary_id = zstd::zstd_safe::get_dict_id_from_dict(&fs::read(&dictionary).unwrap())
+        .expect("Not a zstd dictionary");
+
+    let input = testing_dir.join("new.json");
+    fs::write(&input, log(1234)).unwrap();
+    let mut dict_flags = oof::Flags::default();
+    dict_flags.argument_flags.insert("dict", dictionary.into_os_string());
+    dict_flags.boolean_flags.insert("yes");
+    let compress = |output_path: &Path, flags: &oof::Flags| {
+        let files = vec![input.clone()];
+        run(Command::Compress { files, output_path: output_path.into() }, flags)
+            .expect("Failed to compress");
+        fs::metadata(output_path).unwrap().len()
+    };
+    let plain_size = compress(&testing_dir.join("plain.json.zst"), &oof::Flags::default());
+    let dict_size = compress(&testing_dir.join("dict.json.zst"), &dict_flags);
+    assert!(dict_size < plain_size / 2, "{} bytes, {} without it", dict_size, plain_size);
+
+    let decompress = |flags: &oof::Flags| {
+        let files = vec![testing_dir.join("dict.json.zst")];
+        let output = testing_dir.join("output");
+        fs::create_dir_all(&output).unwrap();
+        run(Command::Decompress { files, output_folder: Some(output.clone()) }, flags)
+            .map(|_| fs::read_to_string(output.join("dict.json")).unwrap())
+    };
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("yes");
+    let err = decompress(&flags).unwrap_err();
+    assert!(err.to_string().contains(&format!("dictionary {}", dictionary_id)), "{}", err);
+    assert_eq!(decompress(&dict_flags).unwrap(), log(1234));
+}
+
+/// `data` in the seekable format of Zstandard, in frames of 1 KiB and followed by their seek table
+fn seekable_zstd(data: &[u8]) -> Vec<u8> {
+    let (mut compressed, mut table) = (vec![], vec![]);
+    for chunk in data.chunks(1024) {
+        let frame = zstd::bulk::compress(chunk, 3).unwrap();
+        compressed.extend(&frame);
+        table.extend((frame.len() as u32).to_le_bytes());
+        table.extend((chunk.len() as u32).to_le_bytes());
+    }
+    table.extend((data.chunks(1024).count() as u32).to_le_bytes());
+    table.push(0);
+    table.extend(0x8F92_EAB1_u32.to_le_bytes());
+    compressed.extend(0x184D_2A5E_u32.to_le_bytes());
+    compressed.extend((table.len() as u32).to_le_bytes());
+    compressed.extend(table);
+    compressed
+}
+
+#[test]
+/// Archives in seekable .zst files are listed and extracted like in any .zst, .zip ones being read
+/// in place.
+fn test_seekable_zstd() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+    let input = testing_dir.join("data");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("big.bin"), vec![7; 100_000]).unwrap();
+    fs::write(input.join("small.txt"), "small").unwrap();
+
+    for format in ["tar", "zip"] {
+        let archive = testing_dir.join(format!("data.{}", format));
+        ouch::compress(std::slice::from_ref(&input), &archive, Default::default()).unwrap();
+        let seekable = testing_dir.join(format!("data.{}.zst", format));
+        fs::write(&seekable, seekable_zstd(&fs::read(&archive).unwrap())).unwrap();
+
+        let mut entries: Vec<_> = ouch::list(&seekable, Default::default())
+            .map(|entry| entry.unwrap().path)
+            .filter(|path| path != Path::new("data/"))
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["data/big.bin", "data/small.txt"].map(PathBuf::from), "{}", format);
+
+        let output = testing_dir.join(format!("output-{}", format));
+        ouch::decompress(&[seekable], Some(&output), Default::default()).unwrap();
+        assert_eq!(fs::read(output.join("data/big.bin")).unwrap(), vec![7; 100_000]);
+        assert_eq!(fs::read_to_string(output.join("data/small.txt")).unwrap(), "small");
+    }
+}
+
+#[test]
+/// Modification times go through .tar archives to the nanosecond, in PAX records, and in whole
+/// seconds with `--format-tar gnu`.
+fn test_tar_nanosecond_mtime() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::new(1_700_000_000, 123_456_789);
+    fs::write(testing_dir.join("file.txt"), "contents").unwrap();
+    let file = fs::File::options().write(true).open(testing_dir.join("file.txt")).unwrap();
+    file.set_modified(mtime).unwrap();
+    // Filesystems with coarser times round it
+    let stored_mtime = fs::metadata(testing_dir.join("file.txt")).unwrap().modified().unwrap();
+    let whole_seconds = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+
+    for (format, expected) in [("pax", stored_mtime), ("gnu", whole_seconds)] {
+        let archive = testing_dir.join(format!("{}.tar", format));
+        let output = testing_dir.join(format);
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["compress", "file.txt"])
+            .arg(&archive)
+            .args(["--format-tar", format])
+            .current_dir(&testing_dir)
+            .stdin(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        ouch::decompress(&[archive], Some(&output), Default::default()).unwrap();
+        let extracted = output.join(format).join("file.txt");
+        let extracted = fs::metadata(extracted).unwrap().modified().unwrap();
+        assert_eq!(extracted, expected, "--format-tar {}", format);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+/// FIFOs are skipped by default, without being opened, and kept with `--special-files store`.
+fn test_tar_special_files() {
+    use std::{
+        ffi::CString,
+        os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+    };
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("source");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("file.txt"), b"contents").unwrap();
+    let fifo_path = CString::new(source.join("fifo").as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o644) }, 0);
+
+    let mut store = oof::Flags::default();
+    store.argument_flags.insert("special-files", "store".into());
+    let mut fail = oof::Flags::default();
+    fail.argument_flags.insert("special-files", "fail".into());
+
+    let compress = |name: &str, flags: &oof::Flags| {
+        let output_path = testing_dir.join(name);
+        let command = Command::Compress { files: vec![source.clone()], output_path };
+        run(command, flags)
+    };
+    let extract = |name: &str, output_name: &str, flags: &oof::Flags| {
+        let output = testing_dir.join(output_name);
+        let command = Command::Decompress {
+            files: vec![testing_dir.join(name)],
+            output_folder: Some(output.clone()),
+        };
+        run(command, flags).expect("Failed to extract");
+        output.join("source")
+    };
+
+    compress("skipped.tar", &oof::Flags::default()).expect("Failed to compress");
+    let output = extract("skipped.tar", "skipped", &store);
+    assert!(output.join("file.txt").exists());
+    assert!(output.join("fifo").symlink_metadata().is_err());
+
+    compress("stored.tar", &store).expect("Failed to compress");
+    let output = extract("stored.tar", "stored", &store);
+    assert!(fs::symlink_metadata(output.join("fifo")).unwrap().file_type().is_fifo());
+
+    let command = Command::Decompress {
+        files: vec![testing_dir.join("stored.tar")],
+        output_folder: Some(testing_dir.join("failed")),
+    };
+    assert!(matches!(run(command, &fail), Err(ouch::Error::SpecialFile { .. })));
+
+    // Extraction needs `--special-files store` too
+    let output = extract("stored.tar", "not-stored", &oof::Flags::default());
+    assert!(output.join("file.txt").exists());
+    assert!(output.join("fifo").symlink_metadata().is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+/// With `--acl`, the POSIX ACLs of files are stored in .tar and restored when extracting.
+fn test_tar_acls() {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    const ACCESS_XATTR: &[u8] = b"system.posix_acl_access\0";
+
+    let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes()).unwrap();
+    let get_acl = |path: &Path| {
+        let mut value = vec![0_u8; 256];
+        let path = c_path(path);
+        let size = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                ACCESS_XATTR.as_ptr().cast(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        value.truncate(size.max(0) as usize);
+        value
+    };
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("source");
+    fs::create_dir(&source).unwrap();
+    let file = source.join("file.txt");
+    fs::write(&file, b"contents").unwrap();
+
+    // user::rw-,user:1000:r--,group::r--,mask::r--,other::r--
+    let mut acl = 2_u32.to_le_bytes().to_vec();
+    let entries = [
+        (0x01_u16, 6_u16, u32::MAX),
+        (0x02, 4, 1000),
+        (0x04, 4, u32::MAX),
+        (0x10, 4, u32::MAX),
+        (0x20, 4, u32::MAX),
+    ];
+    for (tag, perm, id) in entries {
+        acl.extend_from_slice(&tag.to_le_bytes());
+        acl.extend_from_slice(&perm.to_le_bytes());
+        acl.extend_from_slice(&id.to_le_bytes());
+    }
+    let result = unsafe {
+        libc::setxattr(
+            c_path(&file).as_ptr(),
+            ACCESS_XATTR.as_ptr().cast(),
+            acl.as_ptr().cast(),
+            acl.len(),
+            0,
+        )
+    };
+    if result != 0 {
+        // The filesystem of the temporary folder doesn't support ACLs
+        return;
+    }
+
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("acl");
+    let archive_path = testing_dir.join("source.tar");
+    let command = Command::Compress { files: vec![source], output_path: archive_path.clone() };
+    run(command, &flags).expect("Failed to compress");
+
+    let extract = |output_name: &str, flags: &oof::Flags| {
+        let output = testing_dir.join(output_name);
+        let command = Command::Decompress {
+            files: vec![archive_path.clone()],
+            output_folder: Some(output.clone()),
+        };
+        run(command, flags).expect("Failed to extract");
+        output.join("source").join("file.txt")
+    };
+
+    assert_eq!(get_acl(&extract("with-acls", &flags)), acl);
+    // Extraction needs `--acl` too
+    assert!(get_acl(&extract("without-acls", &oof::Flags::default())).is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+/// With `--preserve`, extended attributes are stored in .tar and restored when extracting, and as
+/// root, so are owners.
+fn test_tar_preserve() {
+    use std::{
+        ffi::CString,
+        os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    };
+
+    let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes()).unwrap();
+    let get_xattr = |path: &Path| xattr::get(path, "user.ouch").unwrap().unwrap_or_default();
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("source");
+    fs::create_dir(&source).unwrap();
+    let file = source.join("file.txt");
+    fs::write(&file, b"contents").unwrap();
+    let value = b"value";
+    if xattr::set(&file, "user.ouch", value).is_err() {
+        // The filesystem of the temporary folder doesn't support extended attributes
+        return;
+    }
+    // Changing owners takes root, an ID without a name is restored as it is
+    let is_root = unsafe { libc::geteuid() } == 0;
+    if is_root {
+        assert_eq!(unsafe { libc::chown(c_path(&file).as_ptr(), 54321, 54321) }, 0);
+    }
+
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("preserve", "all".into());
+    let archive_path = testing_dir.join("source.tar");
+    let command = Command::Compress { files: vec![source], output_path: archive_path.clone() };
+    run(command, &flags).expect("Failed to compress");
+
+    let extract = |output_name: &str, flags: &oof::Flags| {
+        let output = testing_dir.join(output_name);
+        let command = Command::Decompress {
+            files: vec![archive_path.clone()],
+            output_folder: Some(output.clone()),
+        };
+        run(command, flags).expect("Failed to extract");
+        output.join("source").join("file.txt")
+    };
+
+    let preserved = extract("preserved", &flags);
+    assert_eq!(get_xattr(&preserved), value);
+    let plain = extract("plain", &oof::Flags::default());
+    assert!(get_xattr(&plain).is_empty());
+    if is_root {
+        assert_eq!(fs::metadata(&preserved).unwrap().uid(), 54321);
+        assert_eq!(fs::metadata(&preserved).unwrap().gid(), 54321);
+        assert_eq!(fs::metadata(&plain).unwrap().uid(), 0);
+    }
+}
+
+#[test]
+/// The name and modification time of a file are stored in the .gz header and restored from it.
+fn test_gzip_header_metadata() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let input = testing_dir.join("notes.txt");
+    fs::write(&input, b"contents").unwrap();
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    fs::File::options().write(true).open(&input).unwrap().set_modified(mtime).unwrap();
+
+    let archive_path = testing_dir.join("notes.txt.gz");
+    let command = Command::Compress { files: vec![input], output_path: archive_path.clone() };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+    let renamed = testing_dir.join("renamed.gz");
+    fs::rename(&archive_path, &renamed).unwrap();
+
+    let extract = |output_name: &str, flags: &oof::Flags| {
+        let output = testing_dir.join(output_name);
+        fs::create_dir(&output).unwrap();
+        let command = Command::Decompress {
+            files: vec![renamed.clone()],
+            output_folder: Some(output.clone()),
+        };
+        run(command, flags).expect("Failed to extract");
+        output
+    };
+    let modified = |path: PathBuf| fs::metadata(path).unwrap().modified().unwrap();
+
+    let mut use_name = oof::Flags::default();
+    use_name.boolean_flags.insert("use-gzip-name");
+    let output = extract("with-name", &use_name);
+    assert_eq!(fs::read(output.join("notes.txt")).unwrap(), b"contents");
+    assert_eq!(modified(output.join("notes.txt")), mtime);
+
+    let output = extract("without-name", &oof::Flags::default());
+    assert_eq!(modified(output.join("renamed")), mtime);
+
+    let mut no_mtime = oof::Flags::default();
+    no_mtime.boolean_flags.insert("no-mtime");
+    let output = extract("without-mtime", &no_mtime);
+    assert_ne!(modified(output.join("renamed")), mtime);
+}
+
+#[cfg(unix)]
+#[test]
+/// A tree laid out like an initramfs goes through .cpio.gz, in the newc format the kernel expects.
+fn test_cpio_initramfs() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("initramfs");
+    fs::create_dir_all(source.join("bin")).unwrap();
+    fs::create_dir_all(source.join("dev")).unwrap();
+    fs::write(source.join("init"), b"#!/bin/sh\nexec /bin/sh\n").unwrap();
+    fs::set_permissions(source.join("init"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(source.join("bin").join("busybox"), b"not really busybox").unwrap();
+    symlink("busybox", source.join("bin").join("sh")).unwrap();
+
+    let archive_path = testing_dir.join("initramfs.cpio.gz");
+    let command =
+        Command::Compress { files: vec![source.clone()], output_path: archive_path.clone() };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+
+    let mut cpio = vec![];
+    flate2::read::GzDecoder::new(fs::File::open(&archive_path).unwrap())
+        .read_to_end(&mut cpio)
+        .unwrap();
+    assert_eq!(&cpio[..6], b"070701");
+    assert_eq!(cpio.len() % 4, 0);
+    assert!(cpio.windows(11).any(|window| window == b"TRAILER!!!\0"));
+
+    let output = testing_dir.join("output");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+
+    let extracted = output.join("initramfs");
+    assert_eq!(list_tree(&source), list_tree(&extracted));
+    assert_eq!(fs::read_link(extracted.join("bin").join("sh")).unwrap(), Path::new("busybox"));
+    let mode = fs::metadata(extracted.join("init")).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o755);
+}
+
+#[test]
+/// `ouch list --json` shows the entries of archives, and the header of single file formats.
+fn test_list_json() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let input = testing_dir.join("notes.txt");
+    fs::write(&input, "contents\n".repeat(1000)).unwrap();
+    for output in &["notes.txt.gz", "notes.tar.gz"] {
+        let command =
+            Command::Compress { files: vec![input.clone()], output_path: testing_dir.join(output) };
+        run(command, &oof::Flags::default()).expect("Failed to compress");
+    }
+
+    let list = |name: &str| -> serde_json::Value {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["list", "--json"])
+            .arg(testing_dir.join(name))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let stream = list("notes.txt.gz");
+    assert_eq!(stream["kind"], "stream");
+    assert_eq!(stream["name"], "notes.txt");
+    assert_eq!(stream["uncompressed_size"], 9000);
+    assert!(stream["mtime"].is_u64());
+
+    let archive = list("notes.tar.gz");
+    assert_eq!(archive["kind"], "archive");
+    assert_eq!(archive["entries"][0]["path"], "notes.txt");
+    assert_eq!(archive["entries"][0]["size"], 9000);
+    let modified = fs::metadata(&input).unwrap().modified().unwrap();
+    let modified = modified.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(archive["entries"][0]["modified"], modified);
+}
+
+#[test]
+/// `--create-tar-index` writes where each entry starts, `ouch list --use-index` reads the entries
+/// from there, and from the whole archive if the index doesn't match it.
+fn test_tar_index() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let input = testing_dir.join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), "first\n".repeat(1000)).unwrap();
+    // Stored with a GNU long name header before its own
+    fs::write(input.join(format!("{}.txt", "b".repeat(120))), "second").unwrap();
+    fs::write(input.join("c.txt"), "third").unwrap();
+
+    let list = |archive: &Path| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["list", "--json", "--use-index"])
+            .arg(archive)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let used_index = !String::from_utf8_lossy(&output.stderr).contains("Not using the index");
+        (json["entries"].clone(), used_index)
+    };
+
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("create-tar-index");
+    for name in ["archive.tar", "archive.tar.gz"] {
+        let archive = testing_dir.join(name);
+        let command =
+            Command::Compress { files: vec![input.clone()], output_path: archive.clone() };
+        run(command, &flags).expect("Failed to compress");
+
+        let index_path = testing_dir.join(format!("{}.idx", name));
+        let index: std::collections::BTreeMap<String, u64> =
+            serde_json::from_slice(&fs::read(&index_path).unwrap()).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.values().min(), Some(&0));
+
+        // The gzip members of every entry are decompressed as one .tar
+        let output_folder = testing_dir.join(format!("{}.out", name));
+        let command = Command::Decompress {
+            files: vec![archive.clone()],
+            output_folder: Some(output_folder.clone()),
+        };
+        run(command, &oof::Flags::default()).expect("Failed to extract");
+        for entry in fs::read_dir(&input).unwrap() {
+            let path = entry.unwrap().path();
+            let extracted = output_folder.join("input").join(path.file_name().unwrap());
+            assert_eq!(fs::read(extracted).unwrap(), fs::read(path).unwrap());
+        }
+
+        let (entries, used_index) = list(&archive);
+        assert!(used_index);
+        let entries = entries.as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+        let first = entries.iter().find(|entry| entry["path"] == "input/a.txt").unwrap();
+        assert_eq!(first["size"], 6000);
+
+        // Stale indexes are left aside
+        fs::write(&index_path, r#"{"input/a.txt": 1, "input/c.txt": 2}"#).unwrap();
+        let (stale_entries, used_index) = list(&archive);
+        assert!(!used_index);
+        assert_eq!(stale_entries.as_array().unwrap().len(), 3);
+    }
+}
+
+#[test]
+/// `--compression-stats --json` gives the sizes of each file, the least compressed first.
+fn test_compression_stats() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let input = testing_dir.join("input");
+    fs::create_dir(&input).unwrap();
+    let mut random = vec![0; 50_000];
+    SmallRng::seed_from_u64(42).fill_bytes(&mut random);
+    fs::write(input.join("random.bin"), &random).unwrap();
+    fs::write(input.join("text.txt"), "contents\n".repeat(5000)).unwrap();
+
+    for name in ["stats.tar.gz", "stats.zip"] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["compress", "-q", "--compression-stats", "--json"])
+            .arg(&input)
+            .arg(testing_dir.join(name))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+        let stats = json["stats"].as_array().unwrap();
+        let paths: Vec<&str> = stats.iter().map(|file| file["path"].as_str().unwrap()).collect();
+        assert_eq!(paths, ["input/random.bin", "input/text.txt"]);
+        assert_eq!(stats[0]["original_size"], 50_000);
+        assert_eq!(stats[1]["original_size"], 45_000);
+        assert!(stats[0]["ratio"].as_f64().unwrap() > 0.99);
+        assert!(stats[1]["ratio"].as_f64().unwrap() < 0.1);
+
+        let compressed: u64 =
+            stats.iter().map(|file| file["compressed_size"].as_u64().unwrap()).sum();
+        assert!(compressed <= fs::metadata(testing_dir.join(name)).unwrap().len());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+/// Read-only folders, even nested ones, get their permissions and modification times once their
+/// files were written into them.
+fn test_read_only_directories() {
+    use std::{os::unix::fs::PermissionsExt, time::UNIX_EPOCH};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let mut builder = tar::Builder::new(vec![]);
+    for path in ["ro/", "ro/file", "ro/inner/", "ro/inner/file"] {
+        let is_dir = path.ends_with('/');
+        let contents: &[u8] = if is_dir { b"" } else { b"file" };
+        let entry_type = if is_dir { tar::EntryType::Directory } else { tar::EntryType::Regular };
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(if is_dir { 0o555 } else { 0o444 });
+        header.set_mtime(1_000_000_000);
+        header.set_size(contents.len() as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+    fs::write(testing_dir.join("archive.tar"), builder.into_inner().unwrap()).unwrap();
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default().unix_permissions(0o555);
+    writer.add_directory("ro/", options).unwrap();
+    writer.add_directory("ro/inner/", options).unwrap();
+    writer.start_file("ro/inner/file", options.unix_permissions(0o444)).unwrap();
+    writer.write_all(b"file").unwrap();
+    fs::write(testing_dir.join("archive.zip"), writer.finish().unwrap().into_inner()).unwrap();
+
+    for archive in ["archive.tar", "archive.zip"] {
+        let output_folder = testing_dir.join(format!("{}.out", archive));
+        let command = Command::Decompress {
+            files: vec![testing_dir.join(archive)],
+            output_folder: Some(output_folder.clone()),
+        };
+        run(command, &oof::Flags::default()).expect("Failed to extract");
+
+        let mode =
+            |path: &str| fs::metadata(output_folder.join(path)).unwrap().permissions().mode();
+        assert_eq!(fs::read(output_folder.join("ro/inner/file")).unwrap(), b"file");
+        assert_eq!(mode("ro") & 0o777, 0o555);
+        assert_eq!(mode("ro/inner") & 0o777, 0o555);
+        if archive == "archive.tar" {
+            let mtime = fs::metadata(output_folder.join("ro")).unwrap().modified().unwrap();
+            assert_eq!(mtime.duration_since(UNIX_EPOCH).unwrap().as_secs(), 1_000_000_000);
+        }
+
+        // Writable again, for the testing folder to be removed
+        for path in ["ro", "ro/inner"] {
+            fs::set_permissions(output_folder.join(path), fs::Permissions::from_mode(0o755))
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+/// With `--no-same-permissions`, the stored permissions are extracted without the bits that the
+/// umask clears, nor the setuid bit.
+fn test_no_same_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+
+    let mut builder = tar::Builder::new(vec![]);
+    for (path, mode) in [("open/", 0o777), ("open/file", 0o4777)] {
+        let mut header = tar::Header::new_gnu();
+        let entry_type =
+            if path.ends_with('/') { tar::EntryType::Directory } else { tar::EntryType::Regular };
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(0);
+        header.set_cksum();
+        builder.append_data(&mut header, path, &[][..]).unwrap();
+    }
+    fs::write(testing_dir.join("archive.tar"), builder.into_inner().unwrap()).unwrap();
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default().unix_permissions(0o777);
+    writer.add_directory("open/", options).unwrap();
+    writer.start_file("open/file", options).unwrap();
+    fs::write(testing_dir.join("archive.zip"), writer.finish().unwrap().into_inner()).unwrap();
+
+    // Folders are created with 0o777 without the bits of the umask
+    fs::create_dir(testing_dir.join("probe")).unwrap();
+    let umasked = fs::metadata(testing_dir.join("probe")).unwrap().permissions().mode() & 0o7777;
+
+    for archive in ["archive.tar", "archive.zip"] {
+        for no_same in [false, true] {
+            let output_folder = testing_dir.join(format!("{}-{}", archive, no_same));
+            let mut flags = oof::Flags::default();
+            if no_same {
+                flags.boolean_flags.insert("no-same-permissions");
+            }
+            let command = Command::Decompress {
+                files: vec![testing_dir.join(archive)],
+                output_folder: Some(output_folder.clone()),
+            };
+            run(command, &flags).expect("Failed to extract");
+
+            let mode = |path: &str| {
+                fs::metadata(output_folder.join(path)).unwrap().permissions().mode() & 0o7777
+            };
+            if no_same {
+                assert_eq!(mode("open"), umasked);
+                assert_eq!(mode("open/file"), umasked);
+            } else {
+                assert_eq!(mode("open"), 0o777);
+                let stored = if archive == "archive.tar" { 0o4777 } else { 0o777 };
+                assert_eq!(mode("open/file"), stored);
+            }
+        }
+    }
+
+    let mut both = oof::Flags::default();
+    both.boolean_flags.insert("same-permissions");
+    both.boolean_flags.insert("no-same-permissions");
+    let command = Command::Decompress {
+        files: vec![testing_dir.join("archive.tar")],
+        output_folder: Some(testing_dir.join("both")),
+    };
+    assert!(matches!(run(command, &both), Err(ouch::Error::ConflictingFlags(..))));
+}
+
+#[test]
+/// `ouch info --json` shows the variant, compression, comment and sizes of archives.
+fn test_info_json() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let tar_gz = create_tar_gz(&[("a.txt", b"contents"), ("b.txt", b"more contents")]);
+    fs::write(testing_dir.join("archive.tar.gz"), tar_gz).unwrap();
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    writer.set_comment("Release 1.0");
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    writer.start_file("a.txt", options).unwrap();
+    writer.write_all(b"contents").unwrap();
+    fs::write(testing_dir.join("archive.zip"), writer.finish().unwrap().into_inner()).unwrap();
+
+    let info = |name: &str| -> serde_json::Value {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["info", "--json"])
+            .arg(testing_dir.join(name))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let tar = info("archive.tar.gz");
+    assert_eq!(tar["format"], ".tar.gz");
+    assert_eq!(tar["compression"], serde_json::json!(["gz"]));
+    assert_eq!(tar["entries"], 2);
+    assert_eq!(tar["uncompressed_size"], 21);
+    assert!(tar["version"].is_string());
+
+    let zip = info("archive.zip");
+    assert_eq!(zip["methods"], serde_json::json!(["Stored"]));
+    assert_eq!(zip["comment"], "Release 1.0");
+    assert_eq!(zip["encrypted"], false);
+    assert_eq!(zip["entries"], 1);
+}
+
+#[test]
+/// Archives are only deleted after extracting them with `--remove-archive`.
+fn test_remove_archive() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let archive_path = testing_dir.join("archive.tar.gz");
+    fs::write(&archive_path, create_tar_gz(&[("file.txt", b"contents")])).unwrap();
+
+    let extract = |output_name: &str, flags: &oof::Flags| {
+        let output = testing_dir.join(output_name);
+        let command = Command::Decompress {
+            files: vec![archive_path.clone()],
+            output_folder: Some(output.clone()),
+        };
+        run(command, flags).expect("Failed to extract");
+        assert_eq!(fs::read(output.join("archive/file.txt")).unwrap(), b"contents");
+    };
+
+    extract("kept", &oof::Flags::default());
+    assert!(archive_path.exists());
+
+    let mut remove_archive = oof::Flags::default();
+    remove_archive.boolean_flags.insert("remove-archive");
+    extract("removed", &remove_archive);
+    assert!(!archive_path.exists());
+}
+
+#[test]
+/// Merged archives hold the entries of all of the inputs, conflicting ones follow `--conflict`.
+fn test_merge_archives() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let first = testing_dir.join("first.tar.gz");
+    let second = testing_dir.join("second.tar.gz");
+    fs::write(&first, create_tar_gz(&[("dir/a.txt", b"first"), ("b.txt", b"b")])).unwrap();
+    fs::write(&second, create_tar_gz(&[("dir/a.txt", b"second"), ("c.txt", b"c")])).unwrap();
+
+    let mut rename = oof::Flags::default();
+    rename.argument_flags.insert("conflict", "rename".into());
+    let merged = testing_dir.join("merged.tar.xz");
+    let command =
+        Command::Merge { archives: vec![first.clone(), second.clone()], output_path: merged.clone() };
+    run(command, &rename).expect("Failed to merge");
+
+    let output = testing_dir.join("output");
+    let command = Command::Decompress { files: vec![merged], output_folder: Some(output.clone()) };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    let output = output.join("merged");
+    assert_eq!(fs::read(output.join("dir/a.txt")).unwrap(), b"first");
+    assert_eq!(fs::read(output.join("dir/a (1).txt")).unwrap(), b"second");
+    assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"b");
+    assert_eq!(fs::read(output.join("c.txt")).unwrap(), b"c");
+
+    let command = Command::Merge {
+        archives: vec![first.clone(), second.clone()],
+        output_path: testing_dir.join("conflict.tar"),
+    };
+    assert_eq!(
+        run(command, &oof::Flags::default()).unwrap_err(),
+        ouch::Error::MergeConflict("dir/a.txt".into())
+    );
+    assert!(!testing_dir.join("conflict.tar").exists());
+
+    let command = Command::Merge {
+        archives: vec![first, second],
+        output_path: testing_dir.join("merged.zip"),
+    };
+    assert!(matches!(
+        run(command, &oof::Flags::default()).unwrap_err(),
+        ouch::Error::MergeFormatMismatch { .. }
+    ));
+
+    // .zip entries are copied without recompressing them
+    let first = testing_dir.join("first.zip");
+    let second = testing_dir.join("second.zip");
+    fs::write(&first, create_zip_with_raw_names(&[(b"a.txt", b"first")])).unwrap();
+    fs::write(&second, create_zip_with_raw_names(&[(b"a.txt", b"second"), (b"b.txt", b"b")]))
+        .unwrap();
+
+    let mut skip = oof::Flags::default();
+    skip.argument_flags.insert("conflict", "skip".into());
+    let merged = testing_dir.join("merged.zip");
+    let command = Command::Merge { archives: vec![first, second], output_path: merged.clone() };
+    run(command, &skip).expect("Failed to merge");
+
+    let output = testing_dir.join("zip-output");
+    let command = Command::Decompress { files: vec![merged], output_folder: Some(output.clone()) };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    let output = output.join("merged");
+    assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"first");
+    assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"b");
+}
+
+#[test]
+/// Converted files are compressed with the formats of the output, what they hold is the same.
+fn test_convert() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let convert = |file: &Path, output_path: &Path| {
+        let command =
+            Command::Convert { file: file.to_path_buf(), output_path: output_path.to_path_buf() };
+        run(command, &oof::Flags::default())
+    };
+
+    let archive = testing_dir.join("archive.tar.gz");
+    let contents = create_tar_gz(&[("dir/a.txt", b"first"), ("b.txt", b"second")]);
+    fs::write(&archive, &contents).unwrap();
+    let converted = testing_dir.join("archive.tar.bz2");
+    convert(&archive, &converted).expect("Failed to convert");
+    assert_eq!(&fs::read(&converted).unwrap()[..3], b"BZh");
+
+    // The .tar is passed through as it is
+    let tar = testing_dir.join("archive.tar");
+    convert(&converted, &tar).expect("Failed to convert");
+    let mut expected = vec![];
+    flate2::read::GzDecoder::new(&contents[..]).read_to_end(&mut expected).unwrap();
+    assert_eq!(fs::read(&tar).unwrap(), expected);
+
+    let notes = testing_dir.join("notes.txt.gz");
+    let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+    encoder.write_all(b"notes").unwrap();
+    fs::write(&notes, encoder.finish().unwrap()).unwrap();
+    let converted = testing_dir.join("notes.txt.xz");
+    convert(&notes, &converted).expect("Failed to convert");
+    let mut decompressed = vec![];
+    xz2::read::XzDecoder::new(fs::File::open(&converted).unwrap())
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, b"notes");
+
+    // Only the compression around the archive can change
+    let zip = testing_dir.join("archive.zip");
+    assert!(matches!(convert(&archive, &zip).unwrap_err(), ouch::Error::Custom { .. }));
+    assert!(convert(&notes, &testing_dir.join("notes.tar.xz")).is_err());
+    assert!(!zip.exists());
+
+    // Nor can the input be overwritten by its own output
+    let before = fs::read(&notes).unwrap();
+    assert!(matches!(convert(&notes, &notes).unwrap_err(), ouch::Error::Custom { .. }));
+    assert_eq!(fs::read(&notes).unwrap(), before);
+
+    // Nothing is left behind when the input is damaged
+    let damaged = testing_dir.join("damaged.tar.gz");
+    fs::write(&damaged, &fs::read(&archive).unwrap()[..40]).unwrap();
+    let output = testing_dir.join("damaged.tar.xz");
+    assert!(matches!(convert(&damaged, &output).unwrap_err(), ouch::Error::CorruptArchive { .. }));
+    assert!(!output.exists());
+}
+
+#[cfg(unix)]
+#[test]
+/// `--gitignore` leaves out ignored files, `--follow-symlinks` stores what links point to.
+fn test_gitignore_and_follow_symlinks() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let project = testing_dir.join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::create_dir_all(project.join("target/debug")).unwrap();
+    fs::write(project.join(".gitignore"), "target/\n*.log\n").unwrap();
+    // The .gitignore files of the folders above the inputs apply too
+    fs::write(testing_dir.join(".gitignore"), "*.tmp\n").unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(project.join("src/scratch.tmp"), "scratch").unwrap();
+    fs::write(project.join("target/debug/binary"), "binary").unwrap();
+    fs::write(project.join("build.log"), "log").unwrap();
+
+    // A folder outside of the project, linked from inside of it, and a link back to the project
+    let shared = testing_dir.join("shared");
+    fs::create_dir(&shared).unwrap();
+    fs::write(shared.join("common.txt"), "common").unwrap();
+    std::os::unix::fs::symlink(&shared, project.join("shared")).unwrap();
+    std::os::unix::fs::symlink(&project, project.join("src/loop")).unwrap();
+
+    let compress_and_list = |name: &str, flags: &oof::Flags| {
+        let archive_path = testing_dir.join(name);
+        let command =
+            Command::Compress { files: vec![project.clone()], output_path: archive_path.clone() };
+        run(command, flags).expect("Failed to compress");
+
+        let output = testing_dir.join(format!("{}-output", name));
+        let command =
+            Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+        run(command, &oof::Flags::default()).expect("Failed to extract");
+        list_tree(&output)
+    };
+
+    let files = compress_and_list("default.tar", &oof::Flags::default());
+    assert!(files.contains(&"project/target/debug/binary".into()));
+    assert!(files.contains(&"project/build.log".into()));
+    assert!(files.contains(&"project/src/scratch.tmp".into()));
+    assert!(!files.contains(&"project/shared/common.txt".into()));
+
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("gitignore");
+    flags.boolean_flags.insert("follow-symlinks");
+    let files = compress_and_list("filtered.tar", &flags);
+    assert!(files.contains(&"project/src/main.rs".into()));
+    assert!(files.contains(&"project/.gitignore".into()));
+    assert!(files.contains(&"project/shared/common.txt".into()));
+    assert!(!files.contains(&"project/target/debug/binary".into()));
+    assert!(!files.contains(&"project/build.log".into()));
+    assert!(!files.contains(&"project/src/scratch.tmp".into()));
+    assert!(!files.iter().any(|path| path.starts_with("project/src/loop")));
+
+    // So does the .git/info/exclude of the repository
+    fs::create_dir_all(project.join(".git/info")).unwrap();
+    fs::write(project.join(".git/HEAD"), "ref: refs/heads/master").unwrap();
+    fs::write(project.join(".git/info/exclude"), "notes.txt\n").unwrap();
+    fs::write(project.join("src/notes.txt"), "notes").unwrap();
+    let mut exclude_vcs = oof::Flags::default();
+    exclude_vcs.boolean_flags.insert("exclude-vcs");
+    let files = compress_and_list("vcs.tar", &exclude_vcs);
+    assert!(files.contains(&"project/src/main.rs".into()));
+    assert!(!files.iter().any(|path| path.starts_with("project/.git")));
+    assert!(!files.contains(&"project/.gitignore".into()));
+    assert!(!files.contains(&"project/build.log".into()));
+    assert!(!files.contains(&"project/src/notes.txt".into()));
+
+    // The sandbox lets ouch read the .gitignore files above its inputs, outside of the folders it
+    // writes to
+    fs::create_dir(testing_dir.join("archives")).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["compress", "project", "archives/sandboxed.tar", "--gitignore"])
+        .current_dir(&testing_dir)
+        .env_remove("OUCH_NO_SANDBOX")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let command = Command::Decompress {
+        files: vec![testing_dir.join("archives/sandboxed.tar")],
+        output_folder: Some(testing_dir.join("sandboxed")),
+    };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    let files = list_tree(&testing_dir.join("sandboxed"));
+    assert!(files.contains(&"project/src/main.rs".into()));
+    assert!(!files.contains(&"project/src/scratch.tmp".into()));
+    assert!(!files.contains(&"project/src/notes.txt".into()));
+}
+
+#[test]
+/// With `--atomic`, a failed extraction leaves nothing behind and existing files are only replaced
+/// after asking.
+fn test_atomic_extraction() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("atomic");
+    // Right into the folders given, to see what is left in them
+    flags.boolean_flags.insert("no-smart-unpack");
+
+    let contents = create_tar_gz(&[("a.txt", b"new a"), ("b.txt", b"new b")]);
+    let archive_path = testing_dir.join("archive.tar.gz");
+    fs::write(&archive_path, &contents).unwrap();
+    let truncated_path = testing_dir.join("truncated.tar.gz");
+    fs::write(&truncated_path, &contents[..contents.len() / 2]).unwrap();
+
+    let extract = |archive_path: &Path, output: &Path, flags: &oof::Flags| {
+        let command = Command::Decompress {
+            files: vec![archive_path.to_path_buf()],
+            output_folder: Some(output.to_path_buf()),
+        };
+        run(command, flags)
+    };
+
+    let output = testing_dir.join("new");
+    extract(&truncated_path, &output, &flags).unwrap_err();
+    assert!(!output.exists());
+    assert_eq!(fs::read_dir(&testing_dir).unwrap().count(), 2);
+
+    extract(&archive_path, &output, &flags).expect("Failed to extract");
+    assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"new a");
+
+    // The existing file is kept with --no, the others are still moved in
+    let existing = testing_dir.join("existing");
+    fs::create_dir(&existing).unwrap();
+    fs::write(existing.join("a.txt"), b"old a").unwrap();
+    flags.boolean_flags.insert("no");
+    extract(&archive_path, &existing, &flags).expect("Failed to extract");
+    assert_eq!(fs::read(existing.join("a.txt")).unwrap(), b"old a");
+    assert_eq!(fs::read(existing.join("b.txt")).unwrap(), b"new b");
+    assert_eq!(fs::read_dir(&existing).unwrap().count(), 2);
+}
+
+#[test]
+/// With `--continue-on-error`, the entries that fail to be extracted are skipped instead of
+/// stopping the extraction, which fails once it's done.
+fn test_continue_on_error() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("continue-on-error");
+
+    let extract = |archive_path: &Path, output: &Path, flags: &oof::Flags| {
+        let command = Command::Decompress {
+            files: vec![archive_path.to_path_buf()],
+            output_folder: Some(output.to_path_buf()),
+        };
+        run(command, flags)
+    };
+    let assert_partial = |result: ouch::Result<()>, failed: &str| match result {
+        Err(err @ ouch::Error::EntriesFailed { .. }) => {
+            assert_eq!(err.exit_code(), ouch::EXIT_PARTIAL_FAILURE);
+            assert_eq!(
+                err,
+                ouch::Error::EntriesFailed {
+                    extracted: 2,
+                    total: 3,
+                    failed: vec![PathBuf::from(failed)]
+                }
+            );
+        },
+        result => panic!("{:?}", result),
+    };
+
+    // The second entry can't be created under the file of the first one
+    let contents = create_tar_gz(&[
+        ("blocker", b"file"),
+        ("blocker/inner.txt", b"inner"),
+        ("after.txt", b"after"),
+    ]);
+    let archive_path = testing_dir.join("archive.tar.gz");
+    fs::write(&archive_path, contents).unwrap();
+    extract(&archive_path, &testing_dir.join("aborted"), &oof::Flags::default()).unwrap_err();
+    assert!(!testing_dir.join("aborted/archive/after.txt").exists());
+    let output = testing_dir.join("tar");
+    assert_partial(extract(&archive_path, &output, &flags), "blocker/inner.txt");
+    assert_eq!(fs::read(output.join("archive/after.txt")).unwrap(), b"after");
+
+    // A bad CRC in the second entry
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+    for (path, contents) in [("a.txt", "first"), ("b.txt", "damaged"), ("c.txt", "third")] {
+        writer.start_file(path, options).unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+    let mut bytes = writer.finish().unwrap().into_inner();
+    let damaged = bytes.windows(7).position(|window| window == b"damaged").unwrap();
+    bytes[damaged] = b'D';
+    let archive_path = testing_dir.join("archive.zip");
+    fs::write(&archive_path, bytes).unwrap();
+    let output = testing_dir.join("zip");
+    assert_partial(extract(&archive_path, &output, &flags), "b.txt");
+    assert_eq!(fs::read(output.join("archive/a.txt")).unwrap(), b"first");
+    assert_eq!(fs::read(output.join("archive/c.txt")).unwrap(), b"third");
+}
+
+#[test]
+/// Compressing, listing and decompressing through the library API, which asks nothing, and
+/// reports its messages and progress to what the options give.
+fn test_library_api() {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    use ouch::logger::{LogSink, Message};
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<String>>);
+    impl LogSink for Recorder {
+        fn log(&self, message: &Message) {
+            self.0.borrow_mut().push(message.message().to_string());
+        }
+    }
+
+    #[derive(Default)]
+    struct Counter(Cell<u64>);
+    impl ouch::ProgressReporter for Counter {
+        fn set_total(&self, _total: u64, _unit: ouch::ProgressUnit) {}
+        fn advance(&self, amount: u64) {
+            self.0.set(self.0.get() + amount);
+        }
+        fn finish(&self) {}
+    }
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let input = testing_dir.join("input");
+    fs::create_dir_all(input.join("folder")).unwrap();
+    fs::write(input.join("a.txt"), b"first").unwrap();
+    fs::write(input.join("folder/b.txt"), b"second").unwrap();
+    let archive_path = testing_dir.join("archive.tar.gz");
+
+    let log = Rc::new(Recorder::default());
+    let mut options = ouch::CompressOptions::default();
+    options.log = Some(log.clone());
+    let report = ouch::compress(std::slice::from_ref(&input), &archive_path, options).unwrap();
+    assert_eq!(report.files, vec![archive_path.clone()]);
+    assert!(!log.0.borrow().is_empty());
+
+    // Never overwritten by default, without asking
+    let err = ouch::compress(&[input], &archive_path, Default::default()).unwrap_err();
+    assert_eq!(err, ouch::Error::OverwriteDeclined(archive_path.clone()));
+
+    let mut paths: Vec<PathBuf> =
+        ouch::list(&archive_path, Default::default()).map(|entry| entry.unwrap().path).collect();
+    paths.sort();
+    assert_eq!(paths, ["input/a.txt", "input/folder/b.txt"].map(PathBuf::from));
+
+    let progress = Rc::new(Counter::default());
+    let mut options = ouch::DecompressOptions::default();
+    options.progress = Some(progress.clone());
+    let output = testing_dir.join("output");
+    let report = ouch::decompress(&[archive_path], Some(&output), options).unwrap();
+    assert!(report.files.contains(&output.join("input/folder/b.txt")));
+    assert_eq!(fs::read(output.join("input/a.txt")).unwrap(), b"first");
+    assert!(progress.0.get() > 0);
+
+    // Errors end the listing, and are described by their Display
+    let not_an_archive = testing_dir.join("notes.txt");
+    fs::write(&not_an_archive, b"notes").unwrap();
+    let entries: Vec<_> = ouch::list(&not_an_archive, Default::default()).collect();
+    assert_eq!(entries.len(), 1);
+    let err = entries.into_iter().next().unwrap().unwrap_err();
+    assert!(err.to_string().starts_with("Cannot list '"), "{}", err);
+}
+
+#[test]
+/// Programs using ouch as a library observe what is done with each entry.
+fn test_events() {
+    use std::rc::Rc;
+
+    use ouch::{extension::CompressionFormat::Tar, ChannelEvents, Event, Phase};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let input = testing_dir.join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"first").unwrap();
+    let archive_path = testing_dir.join("archive.tar");
+
+    let (events, received) = ChannelEvents::new();
+    let mut options = ouch::CompressOptions::default();
+    options.events = Some(Rc::new(events));
+    ouch::compress(&[input], &archive_path, options).unwrap();
+    let entry = PathBuf::from("input/a.txt");
+    assert_eq!(received.try_iter().collect::<Vec<_>>(), [
+        Event::Phase(Phase::Compressing { formats: vec![Tar] }),
+        Event::EntryStarted { entry: "input".into(), size: None },
+        Event::EntryFinished { entry: "input".into(), path: "input".into(), bytes: 0 },
+        Event::EntryStarted { entry: entry.clone(), size: Some(5) },
+        Event::EntryFinished { entry: entry.clone(), path: entry.clone(), bytes: 5 },
+    ]);
+
+    let (events, received) = ChannelEvents::new();
+    let mut options = ouch::DecompressOptions::default();
+    options.events = Some(Rc::new(events));
+    let output = testing_dir.join("output");
+    ouch::decompress(std::slice::from_ref(&archive_path), Some(&output), options).unwrap();
+    assert_eq!(received.try_iter().collect::<Vec<_>>(), [
+        Event::Phase(Phase::Decompressing { input: archive_path.clone(), formats: vec![Tar] }),
+        Event::EntryStarted { entry: entry.clone(), size: Some(5) },
+        Event::EntryFinished { entry: entry.clone(), path: output.join("input/a.txt"), bytes: 5 },
+    ]);
+
+    // Where the entries end up, not where they are staged, with --atomic
+    let (events, received) = ChannelEvents::new();
+    let mut options = ouch::DecompressOptions::default();
+    options.events = Some(Rc::new(events));
+    options.atomic = true;
+    let output = testing_dir.join("atomic");
+    ouch::decompress(std::slice::from_ref(&archive_path), Some(&output), options).unwrap();
+    let finished = received.try_iter().last().unwrap();
+    assert_eq!(finished, Event::EntryFinished {
+        entry,
+        path: output.join("input/a.txt"),
+        bytes: 5
+    });
+}
+
+#[test]
+/// Failures exit with codes that tell them apart.
+fn test_exit_codes() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let ouch = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    assert_eq!(ouch(&["missing.zip"]), Some(3));
+    assert_eq!(ouch(&["--not-a-flag"]), Some(2));
+
+    fs::write(testing_dir.join("file.txt"), b"contents").unwrap();
+    fs::write(testing_dir.join("existing.zip"), b"").unwrap();
+    assert_eq!(ouch(&["c", "file.txt", "existing.zip", "--no"]), Some(5));
+
+    // A zip whose entry is marked as encrypted, ouch can't ask for a password
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+    writer.start_file("secret.txt", options).unwrap();
+    writer.write_all(b"secret").unwrap();
+    let mut bytes = writer.finish().unwrap().into_inner();
+    let central_header = bytes.windows(4).position(|window| window == b"PK\x01\x02").unwrap();
+    bytes[6] |= 1;
+    bytes[central_header + 8] |= 1;
+    fs::write(testing_dir.join("encrypted.zip"), bytes).unwrap();
+    assert_eq!(ouch(&["encrypted.zip"]), Some(7));
+}
+
+#[test]
+/// With `--question-timeout`, a question nobody answers is a no instead of a hang.
+fn test_question_timeout() {
+    use std::process;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    fs::write(testing_dir.join("file.txt"), b"contents").unwrap();
+    fs::write(testing_dir.join("existing.zip"), b"old").unwrap();
+
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["c", "file.txt", "existing.zip", "--question-timeout", "1"])
+        .current_dir(&testing_dir)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Kept open without writing to it, like an unattended terminal
+    let _stdin = child.stdin.take().unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("No response, defaulting to No."), "{}", stdout);
+    assert_eq!(fs::read(testing_dir.join("existing.zip")).unwrap(), b"old");
+
+    // An empty stdin doesn't answer either, it isn't taken for the empty line of a yes
+    let output = process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["c", "file.txt", "existing.zip", "--question-timeout", "60"])
+        .current_dir(&testing_dir)
+        .stdin(process::Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("No response, defaulting to No."), "{}", stdout);
+    assert_eq!(fs::read(testing_dir.join("existing.zip")).unwrap(), b"old");
+}
+
+#[test]
+/// Defaults from the environment, which the command line overrides, and the color variables.
+fn test_env_defaults() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let ouch = |args: &[&str], vars: &[(&str, &str)]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE")
+            .envs(vars.iter().copied())
+            // Away from any real config file
+            .env("XDG_CONFIG_HOME", &testing_dir)
+            .env("HOME", &testing_dir)
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap()
+    };
+
+    fs::write(testing_dir.join("file.txt"), b"contents").unwrap();
+    fs::write(testing_dir.join("existing.zip"), b"old").unwrap();
+    let compress = ["c", "file.txt", "existing.zip"];
+
+    let output = ouch(&compress, &[("OUCH_OVERWRITE", "no")]);
+    assert_eq!(output.status.code(), Some(5));
+    let output = ouch(&[&compress[..], &["--no"]].concat(), &[("OUCH_OPTS", "--yes --charset gbk")]);
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(fs::read(testing_dir.join("existing.zip")).unwrap(), b"old");
+
+    let output = ouch(&[&compress[..], &["-v"]].concat(), &[("OUCH_OPTS", "--yes")]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Using --yes, from OUCH_OPTS."), "{}", stderr);
+    assert!(stderr.contains("Using --verbose, from the command line."), "{}", stderr);
+
+    let output = ouch(&compress, &[("OUCH_OVERWRITE", "maybe")]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("OUCH_OVERWRITE"));
+
+    // The sandbox is only there on Linux
+    #[cfg(target_os = "linux")]
+    {
+        let sandbox_disabled = |args: &[&str], vars: &[(&str, &str)]| {
+            let output = ouch(&[&compress[..], &["-y", "-v"], args].concat(), vars);
+            String::from_utf8(output.stderr).unwrap().contains("Filesystem sandbox disabled.")
+        };
+        assert!(sandbox_disabled(&["--no-sandbox"], &[]));
+        assert!(sandbox_disabled(&[], &[("OUCH_NO_SANDBOX", "1")]));
+        assert!(!sandbox_disabled(&[], &[("OUCH_NO_SANDBOX", "0")]));
+        assert_eq!(ouch(&compress, &[("OUCH_NO_SANDBOX", "yes")]).status.code(), Some(2));
+        assert!(sandbox_disabled(&["--sandbox", "off"], &[]));
+        // The command line takes precedence
+        assert!(!sandbox_disabled(&["--sandbox", "normal"], &[("OUCH_NO_SANDBOX", "1")]));
+        let output = ouch(&[&compress[..], &["--sandbox", "lax"]].concat(), &[]);
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    // Not a terminal, so colors are only there when forced
+    let has_colors = |vars: &[(&str, &str)]| ouch(&["missing.zip"], vars).stderr.contains(&0x1b);
+    assert!(!has_colors(&[]));
+    assert!(has_colors(&[("CLICOLOR_FORCE", "1")]));
+    assert!(!has_colors(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]));
+}
+
+#[test]
+/// With `--log-format json`, every line on stderr is a JSON message, the final error included.
+fn test_json_log_format() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let ouch = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .arg("--log-format")
+            .arg("json")
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(!stderr.contains('\u{1b}'), "colors in {:?}", stderr);
+        let messages: Vec<serde_json::Value> =
+            stderr.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        (output.status.code(), messages)
+    };
+
+    fs::write(testing_dir.join("file.txt"), b"contents").unwrap();
+    let (code, messages) = ouch(&["c", "file.txt", "archive.tar.gz"]);
+    assert_eq!(code, Some(0));
+    let last = messages.last().unwrap();
+    assert_eq!(last["level"], "info");
+    assert_eq!(last["path"], "archive.tar.gz");
+
+    let (code, messages) = ouch(&["archive.tar.gz", "-o", "out", "--verbose"]);
+    assert_eq!(code, Some(0));
+    let extracted = messages.iter().find(|message| message["entry"] == "file.txt").unwrap();
+    assert!(extracted["path"].as_str().unwrap().ends_with("out/archive/file.txt"));
+
+    let (code, messages) = ouch(&["missing.zip"]);
+    assert_eq!(code, Some(3));
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["level"], "error");
+    assert!(messages[0]["title"].as_str().unwrap().contains("missing.zip"));
+    assert!(messages[0]["details"].is_array());
+    assert!(messages[0]["hints"].is_array());
+}
+
+#[test]
+/// `OUCH_LOG` picks the messages shown for each module.
+fn test_log_filter() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    let archive = create_tar_gz(&[("file.txt", b"contents")]);
+    fs::write(testing_dir.join("archive.tar.gz"), archive).unwrap();
+
+    let ouch = |filter: &str| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["archive.tar.gz", "-o", "out", "--yes"])
+            .env("OUCH_LOG", filter)
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+    };
+
+    let output = ouch("");
+    assert!(output.contains("[INFO]"));
+    assert!(!output.contains("[DEBUG]") && !output.contains("[TRACE]"));
+
+    assert_eq!(ouch("off"), "");
+
+    let output = ouch("error,ouch::archive::tar=trace");
+    assert!(output.contains("[TRACE]") && output.contains("file.txt"));
+    // Logged from ouch::commands
+    assert!(!output.contains("Successfully uncompressed"));
+
+    let output = ouch("not a level=at all");
+    assert!(output.contains("Ignoring 'not a level=at all' in OUCH_LOG"));
+}
+
+#[test]
+/// `--quiet` only prints errors, `--verbose` prints every file too.
+fn test_quiet_and_verbose() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    fs::write(testing_dir.join("file.txt"), b"contents").unwrap();
+
+    let ouch = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .env_remove("OUCH_LOG")
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap();
+        let text =
+            String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap();
+        (output.status.code(), text)
+    };
+
+    let (code, output) = ouch(&["c", "file.txt", "archive.tar", "--quiet"]);
+    assert_eq!((code, output.as_str()), (Some(0), ""));
+
+    let (_, output) = ouch(&["archive.tar", "-o", "default"]);
+    assert!(output.contains("Successfully uncompressed"));
+    assert!(!output.contains("file.txt"));
+
+    let (_, output) = ouch(&["archive.tar", "-o", "verbose", "-v"]);
+    assert!(output.contains("file.txt\" extracted. (8.00 B)"));
+
+    // Errors are still shown
+    let (code, output) = ouch(&["missing.zip", "-q"]);
+    assert_eq!(code, Some(3));
+    assert!(output.contains("missing.zip"));
+
+    let (code, output) = ouch(&["archive.tar", "-q", "-v"]);
+    assert_eq!(code, Some(2));
+    assert!(output.contains("--quiet and --verbose can't be used together"));
+}
+
+#[test]
+/// `--log-file` appends the messages shown, the final error included, with the time.
+fn test_log_file() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    fs::write(testing_dir.join("file.txt"), b"contents").unwrap();
+
+    let ouch = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .env_remove("OUCH_LOG")
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap()
+    };
+
+    // The parent folders are created
+    let log_args = ["--log-file", "logs/today/ouch.log"];
+    assert!(ouch(&[&["c", "file.txt", "archive.tar"], &log_args[..]].concat()).status.success());
+    assert_eq!(ouch(&[&["missing.zip"], &log_args[..]].concat()).status.code(), Some(3));
+
+    let log = fs::read_to_string(testing_dir.join("logs/today/ouch.log")).unwrap();
+    assert!(!log.contains('\u{1b}'));
+    let lines: Vec<&str> = log.lines().collect();
+    assert!(lines.iter().all(|line| line.len() > 21 && line.as_bytes()[10] == b'T'));
+    let compressed = "[INFO] Successfully compressed 'archive.tar'.";
+    assert!(lines.iter().any(|line| line.ends_with(compressed)));
+    assert!(lines.iter().any(|line| line.contains("[ERROR] ") && line.contains("missing.zip")));
+
+    // Can't be created inside of a file
+    let output = ouch(&["c", "file.txt", "other.tar", "--log-file", "file.txt/ouch.log"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Could not open the log file"));
+    assert!(!testing_dir.join("other.tar").exists());
+}
+
+#[test]
+/// `ouch list --print0` ends every path with a NUL byte, keeping newlines in names intact.
+fn test_list_print0() {
+    let testing_dir = testing_dir();
+    let archive = testing_dir.path().join("archive.tar.gz");
+    fs::write(&archive, create_tar_gz(&[("with space.txt", b"a"), ("new\nline.txt", b"b")]))
+        .unwrap();
+
+    let ouch = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .arg("list")
+            .arg(&archive)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = ouch(&["--print0"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"with space.txt\0new\nline.txt\0");
+
+    let output = ouch(&["--print0", "--json"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("with space.txt"));
+}
+
+#[test]
+/// `ouch list` shows the sizes of the entries with IEC units, or as numbers of bytes with `--bytes`.
+fn test_list_sizes() {
+    let testing_dir = testing_dir();
+    let archive = testing_dir.path().join("archive.tar.gz");
+    let big = vec![b'a'; 1536];
+    fs::write(&archive, create_tar_gz(&[("big.txt", &big), ("small.txt", b"abc")])).unwrap();
+
+    let ouch = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .arg("list")
+            .arg(&archive)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // A format without specifiers, the same in every time zone
+    let stdout = ouch(&["--date-format", "date"]);
+    assert!(stdout.contains("1.5 KiB  date  big.txt\n"));
+    assert!(stdout.contains("    3 B  date  small.txt\n"));
+    assert!(stdout.contains("2 files and 0 folders, 1.5 KiB in total"));
+
+    let stdout = ouch(&["--bytes", "--date-format", "date"]);
+    assert!(stdout.contains("1536  date  big.txt\n"));
+    assert!(stdout.contains("   3  date  small.txt\n"));
+    assert!(stdout.contains("2 files and 0 folders, 1539 in total"));
+
+    // Always in bytes
+    let stdout = ouch(&["--json"]);
+    assert!(stdout.contains(r#""size":1536"#));
+}
+
+#[cfg(unix)]
+#[test]
+/// Warnings are summarized at the end, with `--strict` the first skipped file fails the run.
+fn test_warnings_summary() {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // .zip can't store FIFOs, they are skipped with a warning
+    let source = testing_dir.join("source");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("file.txt"), b"contents").unwrap();
+    for name in ["fifo1", "fifo2"] {
+        let fifo_path = CString::new(source.join(name).as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o644) }, 0);
+    }
+
+    let ouch = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["c", "source"])
+            .args(args)
+            .env_remove("OUCH_LOG")
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap()
+    };
+
+    let output = ouch(&["default.zip"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let summary = stderr.lines().find(|line| line.contains("2 warnings:")).unwrap();
+    assert!(summary.ends_with("special files skipped (2)"));
+    assert!(stderr.contains(" - special files skipped: 'source/fifo"));
+
+    let output = ouch(&["no-summary.zip", "--no-summary"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("2 warnings:"));
+
+    let output = ouch(&["json.zip", "--log-format", "json"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let summary: serde_json::Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert_eq!(summary["summary"]["total"], 2);
+    assert_eq!(summary["summary"]["groups"][0]["category"], "special files skipped");
+    assert_eq!(summary["summary"]["groups"][0]["count"], 2);
+
+    let output = ouch(&["strict.zip", "--strict"]);
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Skipping the FIFO"));
+    assert!(stderr.contains("With --strict, warnings are treated as errors"));
+    assert!(!testing_dir.join("strict.zip").exists());
+}
+
+#[test]
+/// `ouch contents-hash` gives the same hash for the same files in any format.
+fn test_contents_hash() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("source");
+    fs::create_dir_all(source.join("dir")).unwrap();
+    fs::write(source.join("a.txt"), b"first").unwrap();
+    fs::write(source.join("dir").join("b.txt"), b"second").unwrap();
+
+    let compress = |name: &str| {
+        let archive_path = testing_dir.join(name);
+        let command =
+            Command::Compress { files: vec![source.clone()], output_path: archive_path.clone() };
+        run(command, &oof::Flags::default()).expect("Failed to compress");
+        archive_path
+    };
+    let contents_hash = |archive_path: &Path| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .arg("contents-hash")
+            .arg(archive_path)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        stdout.split_whitespace().next().unwrap().to_string()
+    };
+
+    let hash = contents_hash(&compress("source.tar.gz"));
+    assert_eq!(hash.len(), 64);
+    assert_eq!(contents_hash(&compress("source.zip")), hash);
+    assert_eq!(contents_hash(&compress("source.cpio.xz")), hash);
+
+    fs::write(source.join("a.txt"), b"changed").unwrap();
+    assert_ne!(contents_hash(&compress("changed.tar")), hash);
+}
+
+#[test]
+/// `ouch encrypt` and `ouch decrypt` round trip any file, wrong passphrases leave no output.
+fn test_encrypt_and_decrypt() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let ouch = |passphrase: &str, args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .env("OUCH_PASSPHRASE", passphrase)
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    fs::write(testing_dir.join("data.tar.gz"), b"not even an archive").unwrap();
+    assert_eq!(ouch("", &["encrypt", "data.tar.gz"]), Some(2));
+    assert!(!testing_dir.join("data.tar.gz.age").exists());
+
+    assert_eq!(ouch("hunter2", &["encrypt", "data.tar.gz"]), Some(0));
+    let encrypted = fs::read(testing_dir.join("data.tar.gz.age")).unwrap();
+    assert!(encrypted.starts_with(b"age-encryption.org/v1"));
+
+    fs::remove_file(testing_dir.join("data.tar.gz")).unwrap();
+    assert_eq!(ouch("hunter3", &["decrypt", "data.tar.gz.age"]), Some(7));
+    assert!(!testing_dir.join("data.tar.gz").exists());
+
+    assert_eq!(ouch("hunter2", &["decrypt", "data.tar.gz.age"]), Some(0));
+    assert_eq!(fs::read(testing_dir.join("data.tar.gz")).unwrap(), b"not even an archive");
+}
+
+#[test]
+/// `--checksum-file` writes the digest of the archive next to it, `--verify-checksum` checks it.
+fn test_checksum_file() {
+    use sha2::{Digest, Sha256, Sha512};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    fs::write(testing_dir.join("file.txt"), b"contents").unwrap();
+
+    let hex = |digest: &[u8]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    let compress = |name: &str, algorithm: &str| {
+        let mut flags = oof::Flags::default();
+        flags.argument_flags.insert("checksum-file", algorithm.into());
+        let output_path = testing_dir.join(name);
+        let command = Command::Compress { files: vec![testing_dir.join("file.txt")], output_path };
+        run(command, &flags)
+    };
+    let mut verify = oof::Flags::default();
+    verify.boolean_flags.insert("verify-checksum");
+    let extract = |name: &str| {
+        let command = Command::Decompress {
+            files: vec![testing_dir.join(name)],
+            output_folder: Some(testing_dir.join(format!("{}-output", name))),
+        };
+        run(command, &verify)
+    };
+
+    compress("archive.tar.gz", "sha256").expect("Failed to compress");
+    let archive = fs::read(testing_dir.join("archive.tar.gz")).unwrap();
+    assert_eq!(
+        fs::read_to_string(testing_dir.join("archive.tar.gz.sha256")).unwrap(),
+        format!("{}  archive.tar.gz\n", hex(&Sha256::digest(&archive)))
+    );
+    extract("archive.tar.gz").expect("Failed to extract");
+
+    // .zip is written with seeks, its digest is still the one of the finished file
+    compress("archive.zip", "SHA512").expect("Failed to compress");
+    let archive = fs::read(testing_dir.join("archive.zip")).unwrap();
+    let checksum_file = fs::read_to_string(testing_dir.join("archive.zip.sha512")).unwrap();
+    assert!(checksum_file.starts_with(&hex(&Sha512::digest(&archive))));
+    extract("archive.zip").expect("Failed to extract");
+
+    let mut damaged = archive;
+    damaged.push(0);
+    fs::write(testing_dir.join("archive.zip"), damaged).unwrap();
+    assert!(matches!(extract("archive.zip"), Err(ouch::Error::ChecksumMismatch { .. })));
+
+    fs::remove_file(testing_dir.join("archive.tar.gz.sha256")).unwrap();
+    assert!(matches!(extract("archive.tar.gz"), Err(ouch::Error::MissingChecksumFile(_))));
+
+    // BLAKE3 goes to a .b3 file, like b3sum names them
+    compress("archive.tar.xz", "blake3").expect("Failed to compress");
+    let archive = fs::read(testing_dir.join("archive.tar.xz")).unwrap();
+    assert_eq!(
+        fs::read_to_string(testing_dir.join("archive.tar.xz.b3")).unwrap(),
+        format!("{}  archive.tar.xz\n", blake3::hash(&archive).to_hex())
+    );
+    extract("archive.tar.xz").expect("Failed to extract");
+    let mut damaged = archive;
+    damaged.push(0);
+    fs::write(testing_dir.join("archive.tar.xz"), damaged).unwrap();
+    assert!(matches!(extract("archive.tar.xz"), Err(ouch::Error::ChecksumMismatch { .. })));
+
+    assert_eq!(
+        compress("archive.tar", "md5"),
+        Err(ouch::Error::UnknownChecksumAlgorithm("md5".into()))
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+/// The strict sandbox still lets ouch read the checksum file, the tar index and the targets of
+/// followed symlinks, next to or outside of the inputs.
+fn test_strict_sandbox_reads_sidecars() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let ouch = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(args)
+            .args(["--yes", "--sandbox", "strict"])
+            .current_dir(&testing_dir)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(output.status.success(), "{:?}: {}", args, stderr);
+        stderr
+    };
+
+    let project = testing_dir.join("project");
+    let outside = testing_dir.join("outside");
+    fs::create_dir(&project).unwrap();
+    fs::create_dir(&outside).unwrap();
+    fs::write(project.join("file.txt"), b"inside").unwrap();
+    fs::write(outside.join("linked.txt"), b"outside").unwrap();
+    std::os::unix::fs::symlink(&outside, project.join("link")).unwrap();
+    // Where ouch writes, and so can read, away from what it reads
+    fs::create_dir(testing_dir.join("out")).unwrap();
+
+    ouch(&["c", "project", "archive.tar.gz", "--checksum-file", "sha256", "--create-tar-index"]);
+    ouch(&["archive.tar.gz", "-o", "out/archive", "--verify-checksum"]);
+    assert_eq!(fs::read(testing_dir.join("out/archive/project/file.txt")).unwrap(), b"inside");
+    let stderr = ouch(&["list", "archive.tar.gz", "--use-index", "--temp-dir", "out"]);
+    assert!(!stderr.contains("Not using the index"), "{}", stderr);
+
+    ouch(&["c", "project", "out/followed.tar", "--follow-symlinks"]);
+    ouch(&["out/followed.tar", "-o", "out/followed"]);
+    let linked = testing_dir.join("out/followed/project/link/linked.txt");
+    assert_eq!(fs::read(linked).unwrap(), b"outside");
+}
+
+#[test]
+/// `--try-formats` keeps only the smallest compression of the .tar, named after its format.
+fn test_try_formats() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let source = testing_dir.join("source");
+    fs::create_dir(&source).unwrap();
+    let text: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+    fs::write(source.join("text.txt"), text).unwrap();
+
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("try-formats");
+    let command =
+        Command::Compress { files: vec![source.clone()], output_path: testing_dir.join("out.tar") };
+    run(command, &flags).expect("Failed to compress");
+
+    // Only the winner is left, no .tar nor temporary files
+    let mut outputs: Vec<_> = fs::read_dir(&testing_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name != "source")
+        .collect();
+    assert_eq!(outputs.len(), 1);
+    let output = outputs.pop().unwrap();
+    let candidates = ["out.tar.gz", "out.tar.bz2", "out.tar.xz", "out.tar.zst", "out.tar.lz4"];
+    assert!(candidates.contains(&output.as_str()));
+
+    let command = Command::Decompress {
+        files: vec![testing_dir.join(&output)],
+        output_folder: Some(testing_dir.join("extracted")),
+    };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    assert_eq!(
+        fs::read(testing_dir.join("extracted/source/text.txt")).unwrap(),
+        fs::read(source.join("text.txt")).unwrap()
+    );
+}
+
+#[test]
+/// The bash completion script is valid bash, and completes the values of `--each`.
+fn test_bash_completions() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["completions", "bash"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("tar.gz"));
+
+    let script_path = testing_dir.join("ouch.bash");
+    fs::write(&script_path, script).unwrap();
+    let status = std::process::Command::new("bash").arg("-n").arg(&script_path).status().unwrap();
+    assert!(status.success());
+}
+
+#[test]
+/// `ouch list` stops quietly and successfully when its reader goes away, like `| head` does.
+fn test_list_into_closed_pipe() {
+    use std::{io::BufReader, process};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // Enough lines to not fit in the pipe buffer
+    let names: Vec<String> = (0..10_000).map(|i| format!("file_with_a_long_name_{}", i)).collect();
+    let entries: Vec<(&str, &[u8])> = names.iter().map(|name| (name.as_str(), &b""[..])).collect();
+    let archive = testing_dir.join("big.tar.gz");
+    fs::write(&archive, create_tar_gz(&entries)).unwrap();
+
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .arg("list")
+        .arg(&archive)
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut first_line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut first_line).unwrap();
+    assert!(first_line.contains("big.tar.gz"));
+
+    // The reading end was dropped above
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+/// The messages of an extraction whose stdout was closed are dropped, and it still finishes.
+fn test_decompress_into_closed_pipe() {
+    use std::process;
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let archive = testing_dir.join("small.tar.gz");
+    fs::write(&archive, create_tar_gz(&[("a.txt", b"a"), ("b.txt", b"b")])).unwrap();
+    let output_folder = testing_dir.join("output");
+
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .arg(&archive)
+        .arg("-o")
+        .arg(&output_folder)
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Closed before anything was written to it
+    drop(child.stdout.take());
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(output_folder.join("small/b.txt")).unwrap(), b"b");
+}
+
+#[cfg(unix)]
+#[test]
+/// Ctrl-C stops the compression, removes the partial output and exits with 130.
+fn test_interrupted_compression() {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt, process, thread, time::Duration};
+
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    // Reading from a FIFO keeps ouch busy for as long as the test wants
+    let input = testing_dir.join("input");
+    let fifo_path = CString::new(input.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o644) }, 0);
+    let output = testing_dir.join("input.gz");
+
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["c", "-y"])
+        .arg(&input)
+        .arg(&output)
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Opening the FIFO waits for ouch to open it too
+    let mut fifo = fs::OpenOptions::new().write(true).open(&input).unwrap();
+    fifo.write_all(&[b'a'; 4096]).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) }, 0);
+    thread::sleep(Duration::from_millis(100));
+    // Wakes ouch up if it was waiting for more input, ouch may have stopped reading already
+    let _ = fifo.write_all(&[b'a'; 4096]);
+    drop(fifo);
+
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(130));
+    assert!(!output.exists());
+}
+
+/// Every path inside of `root`, relative to it and sorted, like `find` would list them
+fn list_tree(root: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = walkdir::WalkDir::new(root)
+        .into_iter()
+        .map(|entry| entry.unwrap().path().strip_prefix(root).unwrap().to_path_buf())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+/// A folder of archives is decompressed with `--recursive`, a corrupt archive doesn't stop the rest.
+fn test_recursive_decompression() {
+    let testing_dir = testing_dir();
+    let downloads = testing_dir.path().join("downloads");
+    fs::create_dir_all(downloads.join("nested")).unwrap();
+
+    // A valid .tar.gz inside of a nested folder
+    let archive = create_tar_gz(&[("hello.txt", b"hello")]);
+    fs::write(downloads.join("nested").join("good.tar.gz"), archive).unwrap();
+
+    // A corrupt .zip, and a file that is not an archive at all
+    fs::write(downloads.join("bad.zip"), b"this is not a zip file").unwrap();
+    fs::write(downloads.join("notes.txt"), b"ignored").unwrap();
+
+    let output = testing_dir.path().join("extracted");
+    let command =
+        Command::Decompress { files: vec![downloads.clone()], output_folder: Some(output.clone()) };
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("recursive");
+
+    let result = run(command, &flags);
+    assert_eq!(
+        result,
+        Err(ouch::Error::BatchFailed { failed: vec![downloads.join("bad.zip")], total: 2 })
+    );
+    assert_eq!(fs::read(output.join("nested").join("good").join("hello.txt")).unwrap(), b"hello");
+}
+
+#[test]
+/// Archives found inside of the extracted archive are extracted too with `--extract-nested`.
+fn test_nested_archive_extraction() {
+    let testing_dir = testing_dir();
+
+    let inner = create_tar_gz(&[("hello.txt", b"hello")]);
+    let outer = create_tar_gz(&[("inner.tar.gz", &inner), ("readme.txt", b"readme")]);
+    let archive_path = testing_dir.path().join("release.tar.gz");
+    fs::write(&archive_path, outer).unwrap();
+
+    let output = testing_dir.path().join("output");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    let mut flags = oof::Flags::default();
+    flags.boolean_flags.insert("extract-nested");
+    run(command, &flags).expect("Failed to extract");
+    let output = output.join("release");
+
+    // The inner archive was replaced by a folder with its contents
+    assert!(!output.join("inner.tar.gz").exists());
+    assert_eq!(fs::read(output.join("inner").join("hello.txt")).unwrap(), b"hello");
+    assert_eq!(fs::read(output.join("readme.txt")).unwrap(), b"readme");
+
+    // `--max-output-size` applies to everything extracted, not to each of the archives
+    let inner = create_tar_gz(&[("inner.bin", &[0; 6_000])]);
+    let outer = create_tar_gz(&[("inner.tar.gz", &inner), ("outer.bin", &[0; 6_000])]);
+    let archive_path = testing_dir.path().join("bomb.tar.gz");
+    fs::write(&archive_path, outer).unwrap();
+    let output = testing_dir.path().join("bomb");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    flags.argument_flags.insert("max-output-size", "10k".into());
+    let result = run(command, &flags);
+    assert!(matches!(result, Err(ouch::Error::OutputSizeExceeded { limit: 10_000, .. })));
+    assert_eq!(fs::read(output.join("bomb/outer.bin")).unwrap(), [0; 6_000]);
+}
+
+#[test]
+/// Extraction stops once the contents go over `--max-output-size`.
+fn test_output_size_limit() {
+    let testing_dir = testing_dir();
+
+    let archive = create_tar_gz(&[("small.txt", b"small"), ("big.bin", &[0; 100_000])]);
+    let archive_path = testing_dir.path().join("bomb.tar.gz");
+    fs::write(&archive_path, archive).unwrap();
+
+    let output = testing_dir.path().join("output");
+    let command =
+        Command::Decompress { files: vec![archive_path], output_folder: Some(output.clone()) };
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("max-output-size", "10k".into());
+
+    let result = run(command, &flags);
+    assert_eq!(
+        result,
+        Err(ouch::Error::OutputSizeExceeded { limit: 10_000, entry: output.join("bomb/big.bin") })
+    );
+    assert_eq!(fs::read(output.join("bomb/small.txt")).unwrap(), b"small");
+    assert!(!output.join("bomb/big.bin").exists());
+
+    // `--max-ratio` compares the contents to the size of the compressed file
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("max-ratio", "10".into());
+    let output = testing_dir.path().join("ratio");
+    let command = Command::Decompress {
+        files: vec![testing_dir.path().join("bomb.tar.gz")],
+        output_folder: Some(output.clone()),
+    };
+    assert_eq!(
+        run(command, &flags),
+        Err(ouch::Error::RatioExceeded { max_ratio: 10, entry: output.join("bomb/big.bin") })
+    );
+
+    // Single files too, without leaving the incomplete output behind
+    let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+    encoder.write_all(&[0; 100_000]).unwrap();
+    let zeros_path = testing_dir.path().join("zeros.gz");
+    fs::write(&zeros_path, encoder.finish().unwrap()).unwrap();
+    let output = Some(testing_dir.path().to_path_buf());
+    let command =
+        Command::Decompress { files: vec![zeros_path.clone()], output_folder: output.clone() };
+    assert!(matches!(run(command, &flags), Err(ouch::Error::RatioExceeded { .. })));
+    assert!(!testing_dir.path().join("zeros").exists());
+
+    // 0 disables it
+    flags.argument_flags.insert("max-ratio", "0".into());
+    let command = Command::Decompress { files: vec![zeros_path], output_folder: output };
+    run(command, &flags).expect("Failed to extract");
+    assert_eq!(fs::read(testing_dir.path().join("zeros")).unwrap().len(), 100_000);
+}
+
+#[test]
+/// `--max-files` stops extracting and listing archives with more entries than the limit.
+fn test_entry_limit() {
+    let testing_dir = testing_dir();
+
+    let archive = create_tar_gz(&[("a.txt", b"a"), ("b.txt", b"b"), ("c.txt", b"c")]);
+    let tar_path = testing_dir.path().join("many.tar.gz");
+    fs::write(&tar_path, archive).unwrap();
+    let zip_path = testing_dir.path().join("many.zip");
+    let command = Command::Decompress {
+        files: vec![tar_path.clone()],
+        output_folder: Some(testing_dir.path().join("files")),
+    };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    let extracted = testing_dir.path().join("files/many");
+    let files = ["a.txt", "b.txt", "c.txt"].map(|name| extracted.join(name));
+    let command = Command::Compress { files: files.to_vec(), output_path: zip_path.clone() };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("max-files", "2".into());
+    for archive_path in [tar_path, zip_path] {
+        let command = Command::Decompress {
+            files: vec![archive_path.clone()],
+            output_folder: Some(archive_path.with_extension("limited")),
+        };
+        assert_eq!(run(command, &flags), Err(ouch::Error::EntryLimitExceeded { max_files: 2 }));
+        let command = Command::List { archives: vec![archive_path.clone()] };
+        assert_eq!(run(command, &flags), Err(ouch::Error::EntryLimitExceeded { max_files: 2 }));
+
+        // Archives with as many entries as the limit are fine, and 0 disables it
+        for max_files in ["3", "0"] {
+            flags.argument_flags.insert("max-files", max_files.into());
+            let command = Command::List { archives: vec![archive_path.clone()] };
+            run(command, &flags).expect("Failed to list");
+        }
+        flags.argument_flags.insert("max-files", "2".into());
+    }
+}
+
+#[test]
+/// `--transform` renames entries in order, and skips those it would take out of the folder.
+fn test_transform() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+
+    let archive = create_tar_gz(&[
+        ("project-1.2/src/main.rs", b"fn main() {}"),
+        ("project-1.2/README.markdown", b"# project"),
+        ("project-1.2/secret", b"secret"),
+    ]);
+    let tar_path = testing_dir.join("project.tar.gz");
+    fs::write(&tar_path, archive).unwrap();
+    let zip_path = testing_dir.join("project.zip");
+    let command = Command::Decompress {
+        files: vec![tar_path.clone()],
+        output_folder: Some(testing_dir.join("files")),
+    };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    let command = Command::Compress {
+        files: vec![testing_dir.join("files").join("project-1.2")],
+        output_path: zip_path.clone(),
+    };
+    run(command, &oof::Flags::default()).expect("Failed to compress");
+
+    let mut flags = oof::Flags::default();
+    let transforms = ["^project-1\\.2/:project/", "\\.markdown$:.md", "^project/secret$:../secret"];
+    flags.repeated_flags.insert("transform", transforms.iter().map(Into::into).collect());
+    for archive_path in [tar_path, zip_path] {
+        let output_folder = archive_path.with_extension("renamed");
+        let command = Command::Decompress {
+            files: vec![archive_path.clone()],
+            output_folder: Some(output_folder.clone()),
+        };
+        run(command, &flags).expect("Failed to extract");
+
+        assert_eq!(fs::read(output_folder.join("project/src/main.rs")).unwrap(), b"fn main() {}");
+        assert_eq!(fs::read(output_folder.join("project/README.md")).unwrap(), b"# project");
+        assert!(!output_folder.join("project/secret").exists());
+        assert!(!testing_dir.join("secret").exists());
+
+        let command = Command::List { archives: vec![archive_path] };
+        run(command, &flags).expect("Failed to list");
+    }
+
+    let mut flags = oof::Flags::default();
+    flags.repeated_flags.insert("transform", vec!["(unclosed:x".into()]);
+    let command =
+        Command::Decompress { files: vec![testing_dir.join("project.zip")], output_folder: None };
+    let err = run(command, &flags).unwrap_err();
+    assert!(matches!(err, ouch::Error::InvalidTransform { .. }));
+    assert_eq!(err.exit_code(), ouch::EXIT_USAGE);
+}
+
+#[test]
+/// A .zip inside of a .gz is built in `--temp-dir` with `--low-memory`, and copied there to be
+/// read, and the files are removed after.
+fn test_temp_dir() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+
+    let temp_dir = testing_dir.join("scratch").join("ouch");
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("temp-dir", temp_dir.clone().into());
+
+    let file_path = testing_dir.join("file.txt");
+    fs::write(&file_path, b"contents").unwrap();
+    let archive_path = testing_dir.join("archive.zip.gz");
+    let mut compress_flags = oof::Flags::default();
+    compress_flags.argument_flags.insert("temp-dir", temp_dir.clone().into());
+    compress_flags.boolean_flags.insert("low-memory");
+    let command = Command::Compress { files: vec![file_path], output_path: archive_path.clone() };
+    run(command, &compress_flags).expect("Failed to compress");
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+    let output_folder = testing_dir.join("output");
+    let command = Command::Decompress {
+        files: vec![archive_path.clone()],
+        output_folder: Some(output_folder.clone()),
+    };
+    run(command, &flags).expect("Failed to extract");
+    assert_eq!(fs::read(output_folder.join("archive/file.txt")).unwrap(), b"contents");
+    let command = Command::List { archives: vec![archive_path] };
+    run(command, &flags).expect("Failed to list");
+
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+}
+
+#[test]
+/// Without `--temp-dir`, `--low-memory` builds the .zip next to the output, which the sandbox
+/// lets ouch write to, unlike the system's temp folder.
+fn test_low_memory_in_sandbox() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+    fs::create_dir(testing_dir.join("dir")).unwrap();
+    fs::write(testing_dir.join("dir/file.txt"), b"contents").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["compress", "dir", "out.zip.gz", "--low-memory"])
+        .current_dir(&testing_dir)
+        .env_remove("OUCH_NO_SANDBOX")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+        .args(["out.zip.gz", "--output", "output"])
+        .current_dir(&testing_dir)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(testing_dir.join("output/out/dir/file.txt")).unwrap(), b"contents");
+    // Only the output is left next to it
+    let mut names: Vec<_> =
+        fs::read_dir(&testing_dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    names.sort();
+    assert_eq!(names, ["dir", "out.zip.gz", "output"]);
+}
+
+#[test]
+#[cfg(windows)]
+/// Names Windows can't create are renamed, long paths are extracted and read-only files stay so.
+fn test_windows_paths() {
+    let testing_dir = testing_dir();
+    // Canonical paths on Windows have the \\?\ prefix, which lifts the MAX_PATH limit
+    let testing_dir = testing_dir.path().canonicalize().unwrap();
+
+    let long_dirs = vec!["a".repeat(100); 3];
+    let long_name = format!("{}/file.txt", long_dirs.join("/"));
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default();
+    for (name, contents) in
+        [("CON", "con"), ("dir/aux.txt", "aux"), ("notes.", "notes"), (&*long_name, "long")]
+    {
+        writer.start_file(name, options).unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+    writer.start_file("readonly.txt", options.unix_permissions(0o444)).unwrap();
+    writer.write_all(b"readonly").unwrap();
+    let archive_path = testing_dir.join("windows.zip");
+    fs::write(&archive_path, writer.finish().unwrap().into_inner()).unwrap();
+
+    let output_folder = testing_dir.join("output");
+    let command = Command::Decompress {
+        files: vec![archive_path],
+        output_folder: Some(output_folder.clone()),
+    };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+
+    assert_eq!(fs::read(output_folder.join("CON_")).unwrap(), b"con");
+    assert_eq!(fs::read(output_folder.join("dir").join("aux_.txt")).unwrap(), b"aux");
+    assert_eq!(fs::read(output_folder.join("notes._")).unwrap(), b"notes");
+    let long_path: PathBuf =
+        long_dirs.iter().fold(output_folder.clone(), |path, dir| path.join(dir));
+    assert_eq!(fs::read(long_path.join("file.txt")).unwrap(), b"long");
+    let metadata = fs::metadata(output_folder.join("readonly.txt")).unwrap();
+    assert!(metadata.permissions().readonly());
+}
+
+#[test]
+/// Damaged compressed data is reported as a corrupted archive, not as a generic IO error.
+fn test_corrupt_archive_error() {
+    let testing_dir = testing_dir();
+
+    let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+    encoder.write_all(b"hello").unwrap();
+    let mut archive = encoder.finish().unwrap();
+    // The last 8 bytes are the checksum and the size of the uncompressed data
+    let checksum_position = archive.len() - 8;
+    archive[checksum_position] ^= 0xff;
+    let archive_path = testing_dir.path().join("damaged.txt.gz");
+    fs::write(&archive_path, archive).unwrap();
+
+    let output = testing_dir.path().to_path_buf();
+    let command = Command::Decompress { files: vec![archive_path], output_folder: Some(output) };
+    let result = run(command, &oof::Flags::default());
+    assert!(
+        matches!(
+            result,
+            Err(ouch::Error::CorruptArchive {
+                format: ouch::extension::CompressionFormat::Gzip,
+                ..
+            })
+        ),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+/// Truncated archives are reported with the format that failed, how far into it and, for .tar,
+/// the last entry that was read.
+fn test_truncated_archive_errors() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+
+    let mut random = vec![0; 50_000];
+    SmallRng::seed_from_u64(42).fill_bytes(&mut random);
+    let gzip = |data: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+    let decompress = |name: &str, data: &[u8]| {
+        let archive_path = testing_dir.join(name);
+        fs::write(&archive_path, data).unwrap();
+        let output_folder = testing_dir.join(format!("{}.out", name));
+        fs::create_dir(&output_folder).unwrap();
+        let command =
+            Command::Decompress { files: vec![archive_path], output_folder: Some(output_folder) };
+        run(command, &oof::Flags::default())
+    };
+
+    // Cut in the middle of the deflate stream
+    let gzipped = gzip(&random);
+    let result = decompress("random.bin.gz", &gzipped[..gzipped.len() / 2]);
+    match result {
+        Err(ouch::Error::CorruptArchive {
+            format: ouch::extension::CompressionFormat::Gzip,
+            compressed_offset: Some(compressed_offset),
+            entry: None,
+            ..
+        }) => assert!(compressed_offset <= gzipped.len() as u64 / 2),
+        result => panic!("{:?}", result),
+    }
+
+    // Cut in the middle of the second entry
+    let mut builder = tar::Builder::new(vec![]);
+    for (path, contents) in [("first.txt", &b"first"[..]), ("second.bin", &random)] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+    let gzipped = gzip(&builder.into_inner().unwrap());
+    let result = decompress("archive.tar.gz", &gzipped[..gzipped.len() / 2]);
+    match result {
+        Err(ouch::Error::CorruptArchive {
+            format: ouch::extension::CompressionFormat::Gzip,
+            offset: Some(_),
+            compressed_offset: Some(_),
+            entry: Some(entry),
+            ..
+        }) => assert_eq!(entry, Path::new("second.bin")),
+        result => panic!("{:?}", result),
+    }
+
+    // Cut in the central directory, which lists the entries at the end of the archive
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    writer.start_file("random.bin", Default::default()).unwrap();
+    writer.write_all(&random).unwrap();
+    let zipped = writer.finish().unwrap().into_inner();
+    let result = decompress("archive.zip", &zipped[..zipped.len() - 30]);
+    assert!(
+        matches!(
+            result,
+            Err(ouch::Error::CorruptArchive {
+                format: ouch::extension::CompressionFormat::Zip,
+                ..
+            })
+        ),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+/// ISO images can be extracted, but not created.
+fn test_iso_image_extraction() {
+    let testing_dir = testing_dir();
+
+    let image_path = testing_dir.path().join("disk.iso");
+    fs::write(&image_path, create_iso_image()).unwrap();
+
+    let output = testing_dir.path().join("output");
+    let command = Command::Decompress {
+        files: vec![image_path.clone()],
+        output_folder: Some(output.clone()),
+    };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    let output = output.join("disk");
+
+    assert_eq!(fs::read(output.join("README.TXT")).unwrap(), b"readme");
+    assert_eq!(fs::read(output.join("DOCS").join("NOTES.TXT")).unwrap(), b"notes");
+
+    let command = Command::Compress {
+        files: vec![output.join("README.TXT")],
+        output_path: testing_dir.path().join("new.iso"),
+    };
+    let result = run(command, &oof::Flags::default());
+    assert_eq!(result, Err(ouch::Error::UnsupportedFormat(".iso".into())));
+}
+
+/// Build a tiny ISO 9660 image with "README.TXT" and "DOCS/NOTES.TXT"
+fn create_iso_image() -> Vec<u8> {
+    const SECTOR_SIZE: usize = 2048;
+
+    fn record(name: &[u8], sector: u32, size: u32, is_dir: bool) -> Vec<u8> {
+        let padding = (name.len() + 1) % 2;
+        let mut record = vec![0; 33 + name.len() + padding];
+        record[0] = record.len() as u8;
+        // Both-endian fields: little-endian followed by big-endian
+        record[2..6].copy_from_slice(&sector.to_le_bytes());
+        record[6..10].copy_from_slice(&sector.to_be_bytes());
+        record[10..14].copy_from_slice(&size.to_le_bytes());
+        record[14..18].copy_from_slice(&size.to_be_bytes());
+        record[25] = if is_dir { 0b10 } else { 0 };
+        record[32] = name.len() as u8;
+        record[33..33 + name.len()].copy_from_slice(name);
+        record
+    }
+
+    fn descriptor(kind: u8) -> Vec<u8> {
+        let mut descriptor = vec![0; SECTOR_SIZE];
+        descriptor[0] = kind;
+        descriptor[1..6].copy_from_slice(b"CD001");
+        descriptor[6] = 1;
+        descriptor
+    }
+
+    fn directory(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut directory = records.concat();
+        directory.resize(SECTOR_SIZE, 0);
+        directory
+    }
+
+    // Layout: system area, descriptors at 16 and 17, directories at 18 and 19, files at 20 and 21
+    let dir_size = SECTOR_SIZE as u32;
+    let mut primary = descriptor(1);
+    primary[156..190].copy_from_slice(&record(&[0], 18, dir_size, true));
+
+    let root = directory(&[
+        record(&[0], 18, dir_size, true),
+        record(&[1], 18, dir_size, true),
+        record(b"DOCS", 19, dir_size, true),
+        record(b"README.TXT;1", 20, 6, false),
+    ]);
+    let docs = directory(&[
+        record(&[0], 19, dir_size, true),
+        record(&[1], 18, dir_size, true),
+        record(b"NOTES.TXT;1", 21, 5, false),
+    ]);
+
+    let mut image = vec![0; 16 * SECTOR_SIZE];
+    image.extend(primary);
+    image.extend(descriptor(255));
+    image.extend(root);
+    image.extend(docs);
+    image.extend(directory(&[b"readme".to_vec()]));
+    image.extend(directory(&[b"notes".to_vec()]));
+    image
+}
+
+#[test]
+/// Zip entry names that aren't marked as UTF-8 are decoded as CP437, or with `--charset`.
+fn test_zip_legacy_encoded_names() {
+    let testing_dir = testing_dir();
+
+    // "café.txt" in CP437
+    let cp437_zip = testing_dir.path().join("cp437.zip");
+    fs::write(&cp437_zip, create_zip_with_raw_names(&[(b"caf\x82.txt", b"cp437")])).unwrap();
+    // "日本.txt" in Shift-JIS
+    let shift_jis_zip = testing_dir.path().join("shift_jis.zip");
+    fs::write(&shift_jis_zip, create_zip_with_raw_names(&[(b"\x93\xfa\x96\x7b.txt", b"sjis")]))
+        .unwrap();
+
+    let output = testing_dir.path().join("output");
+    let command =
+        Command::Decompress { files: vec![cp437_zip], output_folder: Some(output.clone()) };
+    run(command, &oof::Flags::default()).expect("Failed to extract");
+    assert_eq!(fs::read(output.join("cp437/café.txt")).unwrap(), b"cp437");
+
+    let command = Command::Decompress {
+        files: vec![shift_jis_zip.clone()],
+        output_folder: Some(output.clone()),
+    };
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("charset", "shift_jis".into());
+    run(command, &flags).expect("Failed to extract");
+    assert_eq!(fs::read(output.join("shift_jis/日本.txt")).unwrap(), b"sjis");
+
+    let output = testing_dir.path().join("zip-encoding");
+    let command =
+        Command::Decompress { files: vec![shift_jis_zip], output_folder: Some(output.clone()) };
+    let mut flags = oof::Flags::default();
+    flags.argument_flags.insert("zip-encoding", "shift_jis".into());
+    run(command, &flags).expect("Failed to extract");
+    assert_eq!(fs::read(output.join("shift_jis/日本.txt")).unwrap(), b"sjis");
+}
+
+#[test]
+/// Split .zip archives are extracted from all of their volumes, which have to be next to the .zip.
+fn test_split_zip() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+
+    let entries: [(&str, &[u8]); 3] =
+        [("a.txt", b"first file"), ("dir/b.txt", &[7; 100]), ("c.txt", b"last file")];
+    let volumes = create_split_zip(&entries, 64);
+    assert!(volumes.len() > 2);
+    let archive_path = testing_dir.join("split.zip");
+    for (index, volume) in volumes.iter().enumerate() {
+        let path = if index + 1 == volumes.len() {
+            archive_path.clone()
+        } else {
+            testing_dir.join(format!("split.z{:02}", index + 1))
+        };
+        fs::write(path, volume).unwrap();
+    }
+
+    let extract = |output_name: &str| {
+        let output_folder = Some(testing_dir.join(output_name));
+        let command = Command::Decompress { files: vec![archive_path.clone()], output_folder };
+        run(command, &oof::Flags::default())
+    };
+    extract("output").expect("Failed to extract");
+    for (path, contents) in entries {
+        assert_eq!(fs::read(testing_dir.join("output/split").join(path)).unwrap(), contents);
+    }
+
+    let missing = testing_dir.join("split.z02");
+    fs::remove_file(&missing).unwrap();
+    assert!(matches!(
+        extract("incomplete"),
+        Err(ouch::Error::MissingZipVolumes { missing: paths, .. }) if paths == [missing]
+    ));
+    assert!(!testing_dir.join("incomplete").exists());
+}
+
+#[test]
+/// Files made of several compressed members, like `cat a.gz b.gz > c.gz`, are decompressed whole,
+/// and the data after their last member is ignored.
+fn test_concatenated_members() {
+    let testing_dir = testing_dir();
+    let testing_dir = testing_dir.path();
+
+    let gzip = |data: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+    let bzip = |data: &[u8]| {
+        let mut encoder = bzip2::write::BzEncoder::new(vec![], Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+    let extract = |name: &str, contents: &[u8]| {
+        let archive_path = testing_dir.join(name);
+        fs::write(&archive_path, contents).unwrap();
+        let output_folder = testing_dir.join(format!("{}-output", name));
+        fs::create_dir(&output_folder).unwrap();
+        let command = Command::Decompress {
+            files: vec![archive_path],
+            output_folder: Some(output_folder.clone()),
+        };
+        run(command, &oof::Flags::default()).expect("Failed to extract");
+        output_folder
+    };
+
+    let output = extract("two.txt.gz", &[gzip(b"first "), gzip(b"second")].concat());
+    assert_eq!(fs::read(output.join("two.txt")).unwrap(), b"first second");
+    let output = extract("two.txt.bz", &[bzip(b"first "), bzip(b"second")].concat());
+    assert_eq!(fs::read(output.join("two.txt")).unwrap(), b"first second");
+
+    // A .tar split in the middle of an entry, each half compressed on its own
+    let mut builder = tar::Builder::new(vec![]);
+    for (path, contents) in [("a.txt", vec![1; 3000]), ("b.txt", vec![2; 5000])] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, &contents[..]).unwrap();
+    }
+    let tar = builder.into_inner().unwrap();
+    let (first, second) = tar.split_at(4000);
+    let members = [gzip(first), gzip(second), b"trailing garbage".to_vec()].concat();
+    let output = extract("spanning.tar.gz", &members);
+    assert_eq!(fs::read(output.join("spanning/a.txt")).unwrap(), vec![1; 3000]);
+    assert_eq!(fs::read(output.join("spanning/b.txt")).unwrap(), vec![2; 5000]);
+}
+
+/// Build a .zip with stored entries whose names are raw bytes, not marked as UTF-8
+fn create_zip_with_raw_names(entries: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut archive = vec![];
+    let mut central_directory = vec![];
+
+    for (name, contents) in entries {
+        let mut crc = flate2::Crc::new();
+        crc.update(contents);
+
+        // Fields shared by the local header and the central directory: version needed, flags,
+        // method, time, date, crc, sizes, name length and extra field length
+        let mut fields = vec![];
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&[0; 8]);
+        fields.extend_from_slice(&crc.sum().to_le_bytes());
+        fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        central_directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes());
+        central_directory.extend_from_slice(&fields);
+        // Comment length, disk number, attributes and the offset of the local header
+        central_directory.extend_from_slice(&[0; 10]);
+        central_directory.extend_from_slice(&(archive.len() as u32).to_le_bytes());
+        central_directory.extend_from_slice(name);
+
+        archive.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        archive.extend_from_slice(&fields);
+        archive.extend_from_slice(name);
+        archive.extend_from_slice(contents);
+    }
+
+    let central_directory_offset = archive.len() as u32;
+    archive.extend_from_slice(&central_directory);
+
+    archive.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    archive.extend_from_slice(&[0; 4]);
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&central_directory_offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes());
+    archive
+}
+
+/// Build the volumes of a split .zip with stored entries, split every `volume_size` bytes, in the
+/// order of "archive.z01", "archive.z02", ... and "archive.zip"
+fn create_split_zip(entries: &[(&str, &[u8])], volume_size: usize) -> Vec<Vec<u8>> {
+    // Split archives start with the spanning signature
+    let mut archive = 0x08074b50u32.to_le_bytes().to_vec();
+    let mut central_directory = vec![];
+
+    for (name, contents) in entries {
+        let mut crc = flate2::Crc::new();
+        crc.update(contents);
+
+        // Version needed, flags, method, time, date, crc, sizes, name length and extra field
+        // length, as in `create_zip_with_raw_names`
+        let mut fields = vec![];
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&[0; 8]);
+        fields.extend_from_slice(&crc.sum().to_le_bytes());
+        fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        // Offsets are relative to the start of the volume of the local header
+        let volume = archive.len() / volume_size;
+        let offset = archive.len() % volume_size;
+        central_directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes());
+        central_directory.extend_from_slice(&fields);
+        central_directory.extend_from_slice(&0u16.to_le_bytes());
+        central_directory.extend_from_slice(&(volume as u16).to_le_bytes());
+        central_directory.extend_from_slice(&[0; 6]);
+        central_directory.extend_from_slice(&(offset as u32).to_le_bytes());
+        central_directory.extend_from_slice(name.as_bytes());
+
+        archive.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        archive.extend_from_slice(&fields);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(contents);
+    }
+
+    let mut volumes: Vec<Vec<u8>> = archive.chunks(volume_size).map(<[u8]>::to_vec).collect();
+    let last_volume = volumes.len() as u16 - 1;
+    // Safe unwrap: there is at least the spanning signature
+    let last = volumes.last_mut().unwrap();
+    let central_directory_offset = last.len() as u32;
+    last.extend_from_slice(&central_directory);
+    last.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    last.extend_from_slice(&last_volume.to_le_bytes());
+    last.extend_from_slice(&last_volume.to_le_bytes());
+    last.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    last.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    last.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    last.extend_from_slice(&central_directory_offset.to_le_bytes());
+    last.extend_from_slice(&0u16.to_le_bytes());
+    volumes
+}
+
+/// Build a .tar.gz in memory with the given (path, contents) entries
+fn create_tar_gz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (path, contents) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, *contents).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}